@@ -0,0 +1,217 @@
+#!/usr/bin/env python3
+"""Builds the KDBX 4 fixture databases used by the kdbx tests.
+
+This is an implementation independent of pw's reader and writer: the container is
+assembled here and all cryptography is done by the `openssl` command line tool
+(OpenSSL 3.2 or newer for Argon2). Every fixture uses the password "fixture-password".
+The fixtures are written to src/storage/kdbx/fixtures.
+
+    python3 scripts/make_kdbx_fixtures.py
+
+Since these fixtures share pw's reading of the format, compatibility with databases
+written by KeePassXC itself is checked by the ignored `keepassxc` tests, which need
+keepassxc-cli:
+
+    cargo test keepassxc -- --ignored
+"""
+import base64
+import gzip
+import hashlib
+import hmac
+import struct
+import subprocess
+from pathlib import Path
+
+PASSWORD = b"fixture-password"
+FIXTURES = Path(__file__).resolve().parent.parent / "src" / "storage" / "kdbx" / "fixtures"
+
+AES256 = bytes.fromhex("31c1f2e6bf714350be5805216afc5aff")
+CHACHA20 = bytes.fromhex("d6038a2b8b6f4cb5a524339a31dbb59a")
+ARGON2D = bytes.fromhex("ef636ddf8c29444b91f7a9a403e30a0c")
+ARGON2ID = bytes.fromhex("9e298b1956db4773b23dfc3ec6f0a1e6")
+
+# Fixed "random" values keep the fixtures reproducible.
+SALT = bytes(range(32))
+MASTER_SEED = bytes(range(32, 64))
+INNER_KEY = bytes(range(64, 128))
+RECYCLE_BIN_UUID = base64.b64encode(bytes(range(16))).decode()
+
+
+def openssl(args, data=b""):
+    return subprocess.run(
+        ["openssl"] + args, input=data, capture_output=True, check=True
+    ).stdout
+
+
+def argon2(variant, password, salt, memory_kib, iterations, lanes):
+    return openssl(
+        [
+            "kdf", "-binary", "-keylen", "32",
+            "-kdfopt", f"hexpass:{password.hex()}",
+            "-kdfopt", f"hexsalt:{salt.hex()}",
+            "-kdfopt", f"iter:{iterations}",
+            "-kdfopt", f"memcost:{memory_kib}",
+            "-kdfopt", f"lanes:{lanes}",
+            variant,
+        ]
+    )
+
+
+def chacha20(key, nonce, data):
+    iv = b"\x00\x00\x00\x00" + nonce
+    return openssl(["enc", "-chacha20", "-K", key.hex(), "-iv", iv.hex()], data)
+
+
+def aes256_cbc(key, iv, data):
+    return openssl(["enc", "-aes-256-cbc", "-K", key.hex(), "-iv", iv.hex()], data)
+
+
+def variant_dictionary(items):
+    out = struct.pack("<H", 0x0100)
+    for kind, name, value in items:
+        name = name.encode()
+        out += struct.pack("<Bi", kind, len(name)) + name
+        out += struct.pack("<i", len(value)) + value
+    return out + b"\x00"
+
+
+def field(field_id, data, size_format="<I"):
+    return struct.pack("<B", field_id) + struct.pack(size_format, len(data)) + data
+
+
+def timestamp(unix):
+    return base64.b64encode(struct.pack("<q", unix + 62135596800)).decode()
+
+
+class InnerStream:
+    def __init__(self, key):
+        digest = hashlib.sha512(key).digest()
+        self.key, self.nonce = digest[:32], digest[32:44]
+        self.offset = 0
+
+    def protect(self, value):
+        data = value.encode()
+        stream = chacha20(self.key, self.nonce, bytes(self.offset + len(data)))
+        self.offset += len(data)
+        return base64.b64encode(
+            bytes(a ^ b for a, b in zip(data, stream[-len(data):]))
+        ).decode()
+
+
+def xml_document(stream):
+    p = stream.protect
+    return f"""<?xml version="1.0" encoding="utf-8" standalone="yes"?>
+<KeePassFile>
+\t<Meta>
+\t\t<Generator>make_fixtures.py</Generator>
+\t\t<DatabaseName>fixture</DatabaseName>
+\t\t<RecycleBinEnabled>True</RecycleBinEnabled>
+\t\t<RecycleBinUUID>{RECYCLE_BIN_UUID}</RecycleBinUUID>
+\t</Meta>
+\t<Root>
+\t\t<Group>
+\t\t\t<UUID>AAAAAAAAAAAAAAAAAAAAAQ==</UUID>
+\t\t\t<Name>fixture</Name>
+\t\t\t<Entry>
+\t\t\t\t<UUID>AAAAAAAAAAAAAAAAAAAAAg==</UUID>
+\t\t\t\t<Times>
+\t\t\t\t\t<LastModificationTime>{timestamp(1_700_000_000)}</LastModificationTime>
+\t\t\t\t</Times>
+\t\t\t\t<String><Key>Title</Key><Value>Email</Value></String>
+\t\t\t\t<String><Key>UserName</Key><Value>alice@example.com</Value></String>
+\t\t\t\t<String><Key>Password</Key><Value Protected="True">{p("correct horse")}</Value></String>
+\t\t\t\t<String><Key>URL</Key><Value>https://mail.example.com</Value></String>
+\t\t\t\t<String><Key>Notes</Key><Value>line1
+line2 &amp; more</Value></String>
+\t\t\t\t<String><Key>Recovery codes</Key><Value Protected="True">{p("1234-5678")}</Value></String>
+\t\t\t\t<String><Key>otp</Key><Value Protected="True">{p("otpauth://totp/Example:alice?secret=JBSWY3DPEHPK3PXP&issuer=Example")}</Value></String>
+\t\t\t\t<History>
+\t\t\t\t\t<Entry>
+\t\t\t\t\t\t<UUID>AAAAAAAAAAAAAAAAAAAAAg==</UUID>
+\t\t\t\t\t\t<Times>
+\t\t\t\t\t\t\t<LastModificationTime>{timestamp(1_600_000_000)}</LastModificationTime>
+\t\t\t\t\t\t</Times>
+\t\t\t\t\t\t<String><Key>Title</Key><Value>Email</Value></String>
+\t\t\t\t\t\t<String><Key>Password</Key><Value Protected="True">{p("old password")}</Value></String>
+\t\t\t\t\t</Entry>
+\t\t\t\t</History>
+\t\t\t</Entry>
+\t\t\t<Group>
+\t\t\t\t<UUID>AAAAAAAAAAAAAAAAAAAAAw==</UUID>
+\t\t\t\t<Name>Work</Name>
+\t\t\t\t<Entry>
+\t\t\t\t\t<UUID>AAAAAAAAAAAAAAAAAAAABA==</UUID>
+\t\t\t\t\t<String><Key>Title</Key><Value>GitHub</Value></String>
+\t\t\t\t\t<String><Key>Password</Key><Value Protected="True">{p("gh-secret")}</Value></String>
+\t\t\t\t\t<Binary><Key>notes.txt</Key><Value Ref="0"/></Binary>
+\t\t\t\t</Entry>
+\t\t\t</Group>
+\t\t\t<Group>
+\t\t\t\t<UUID>{RECYCLE_BIN_UUID}</UUID>
+\t\t\t\t<Name>Recycle Bin</Name>
+\t\t\t\t<Entry>
+\t\t\t\t\t<UUID>AAAAAAAAAAAAAAAAAAAABQ==</UUID>
+\t\t\t\t\t<String><Key>Title</Key><Value>Deleted</Value></String>
+\t\t\t\t\t<String><Key>Password</Key><Value Protected="True">{p("gone")}</Value></String>
+\t\t\t\t</Entry>
+\t\t\t</Group>
+\t\t</Group>
+\t\t<DeletedObjects/>
+\t</Root>
+</KeePassFile>
+""".encode()
+
+
+def build(name, cipher, kdf_uuid, variant, compress):
+    memory_kib, iterations, lanes = 1024, 2, 1
+    kdf = variant_dictionary(
+        [
+            (0x42, "$UUID", kdf_uuid),
+            (0x42, "S", SALT),
+            (0x04, "P", struct.pack("<I", lanes)),
+            (0x05, "M", struct.pack("<Q", memory_kib * 1024)),
+            (0x05, "I", struct.pack("<Q", iterations)),
+            (0x04, "V", struct.pack("<I", 0x13)),
+        ]
+    )
+    iv = bytes(range(200, 216)) if cipher == AES256 else bytes(range(200, 212))
+
+    header = struct.pack("<IIHH", 0x9AA2D903, 0xB54BFB67, 0, 4)
+    header += field(2, cipher)
+    header += field(3, struct.pack("<I", 1 if compress else 0))
+    header += field(4, MASTER_SEED)
+    header += field(7, iv)
+    header += field(11, kdf)
+    header += field(0, b"\r\n\r\n")
+
+    composite = hashlib.sha256(hashlib.sha256(PASSWORD).digest()).digest()
+    transformed = argon2(variant, composite, SALT, memory_kib, iterations, lanes)
+    cipher_key = hashlib.sha256(MASTER_SEED + transformed).digest()
+    hmac_base = hashlib.sha512(MASTER_SEED + transformed + b"\x01").digest()
+
+    def block_key(index):
+        return hashlib.sha512(struct.pack("<Q", index) + hmac_base).digest()
+
+    inner = field(1, struct.pack("<I", 3))
+    inner += field(2, INNER_KEY)
+    inner += field(3, b"\x01" + b"attached file contents")
+    inner += field(0, b"")
+    payload = inner + xml_document(InnerStream(INNER_KEY))
+    if compress:
+        payload = gzip.compress(payload, mtime=0)
+    if cipher == AES256:
+        encrypted = aes256_cbc(cipher_key, iv, payload)
+    else:
+        encrypted = chacha20(cipher_key, iv, payload)
+
+    out = header + hashlib.sha256(header).digest()
+    out += hmac.new(block_key(0xFFFFFFFFFFFFFFFF), header, hashlib.sha256).digest()
+    for index, block in enumerate([encrypted, b""]):
+        prefix = struct.pack("<Qi", index, len(block))
+        mac = hmac.new(block_key(index), prefix + block, hashlib.sha256).digest()
+        out += mac + struct.pack("<i", len(block)) + block
+    (FIXTURES / name).write_bytes(out)
+
+
+build("aes_argon2d.kdbx", AES256, ARGON2D, "ARGON2D", compress=True)
+build("chacha20_argon2id.kdbx", CHACHA20, ARGON2ID, "ARGON2ID", compress=False)
@@ -0,0 +1,303 @@
+use sha2::{Digest, Sha256};
+use std::env;
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+/// Override the clipboard commands, e.g. for a clipboard pw does not know about. Both must
+/// be set; each is split on whitespace into a program and its arguments.
+pub const CLIPBOARD_COPY_ENV: &str = "PW_CLIPBOARD_COPY";
+pub const CLIPBOARD_PASTE_ENV: &str = "PW_CLIPBOARD_PASTE";
+
+/// The hidden subcommand the detached clearing helper runs as.
+pub const CLEAR_CLIPBOARD_COMMAND: &str = "clear-clipboard";
+
+/// A clipboard driven through command-line tools: `copy` reads the new contents from
+/// stdin and `paste` writes the current contents to stdout.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClipboardTool {
+    pub copy: Vec<String>,
+    pub paste: Vec<String>,
+}
+
+fn command(words: &[&str]) -> Vec<String> {
+    words.iter().map(|word| word.to_string()).collect()
+}
+
+fn in_path(program: &str) -> bool {
+    env::var_os("PATH")
+        .is_some_and(|path| env::split_paths(&path).any(|dir| dir.join(program).is_file()))
+}
+
+impl ClipboardTool {
+    /// Finds the clipboard of the current session: the commands from the environment, then
+    /// wl-clipboard on Wayland, then xclip or xsel on X11.
+    pub fn detect() -> io::Result<Self> {
+        match (env::var(CLIPBOARD_COPY_ENV), env::var(CLIPBOARD_PASTE_ENV)) {
+            (Ok(copy), Ok(paste)) => {
+                return Ok(Self {
+                    copy: copy.split_whitespace().map(String::from).collect(),
+                    paste: paste.split_whitespace().map(String::from).collect(),
+                })
+            }
+            (Ok(_), Err(_)) | (Err(_), Ok(_)) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "Set both {} and {} to use a custom clipboard",
+                        CLIPBOARD_COPY_ENV, CLIPBOARD_PASTE_ENV
+                    ),
+                ))
+            }
+            (Err(_), Err(_)) => {}
+        }
+
+        let session = |var: &str| env::var_os(var).is_some_and(|value| !value.is_empty());
+        if session("WAYLAND_DISPLAY") && in_path("wl-copy") && in_path("wl-paste") {
+            return Ok(Self {
+                copy: command(&["wl-copy"]),
+                paste: command(&["wl-paste", "--no-newline"]),
+            });
+        }
+        if session("DISPLAY") {
+            if in_path("xclip") {
+                return Ok(Self {
+                    copy: command(&["xclip", "-selection", "clipboard"]),
+                    paste: command(&["xclip", "-selection", "clipboard", "-o"]),
+                });
+            }
+            if in_path("xsel") {
+                return Ok(Self {
+                    copy: command(&["xsel", "--clipboard", "--input"]),
+                    paste: command(&["xsel", "--clipboard", "--output"]),
+                });
+            }
+        }
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!(
+                "No clipboard is available. Install wl-clipboard (Wayland) or xclip or xsel \
+                 (X11), or set {} and {}.",
+                CLIPBOARD_COPY_ENV, CLIPBOARD_PASTE_ENV
+            ),
+        ))
+    }
+
+    pub fn copy(&self, contents: &[u8]) -> io::Result<()> {
+        let (program, args) = split_command(&self.copy)?;
+        // Clipboard tools often keep running to serve the selection, so their output is
+        // not captured: waiting for it to close would wait for the tool to exit.
+        let mut child = Command::new(program)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| io::Error::new(e.kind(), format!("Cannot run {}: {}", program, e)))?;
+        child.stdin.take().unwrap().write_all(contents)?;
+        let status = child.wait()?;
+        if !status.success() {
+            return Err(io::Error::other(format!(
+                "{} failed with {}",
+                program, status
+            )));
+        }
+        Ok(())
+    }
+
+    /// The current contents, or `None` if the clipboard is empty or cannot be read.
+    pub fn paste(&self) -> Option<Vec<u8>> {
+        let (program, args) = split_command(&self.paste).ok()?;
+        let output = Command::new(program)
+            .args(args)
+            .stdin(Stdio::null())
+            .stderr(Stdio::null())
+            .output()
+            .ok()?;
+        (output.status.success() && !output.stdout.is_empty()).then_some(output.stdout)
+    }
+}
+
+fn split_command(words: &[String]) -> io::Result<(&str, &[String])> {
+    match words.split_first() {
+        Some((program, args)) => Ok((program.as_str(), args)),
+        None => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "The clipboard command is empty",
+        )),
+    }
+}
+
+/// What the clearing helper needs to undo a copy: a digest of the copied secret, so a
+/// clipboard the user has since changed is left alone, and the contents to restore.
+#[derive(Debug, PartialEq)]
+pub struct PendingClear {
+    pub digest: [u8; 32],
+    pub previous: Option<Vec<u8>>,
+}
+
+impl PendingClear {
+    /// Encoded as the digest, a flag byte for whether there is something to restore, and
+    /// the previous contents.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut encoded = self.digest.to_vec();
+        match &self.previous {
+            Some(previous) => {
+                encoded.push(1);
+                encoded.extend_from_slice(previous);
+            }
+            None => encoded.push(0),
+        }
+        encoded
+    }
+
+    pub fn decode(encoded: &[u8]) -> io::Result<Self> {
+        if encoded.len() < 33 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "truncated clipboard state",
+            ));
+        }
+        let (digest, rest) = encoded.split_at(32);
+        Ok(Self {
+            digest: digest.try_into().unwrap(),
+            previous: (rest[0] == 1).then(|| rest[1..].to_vec()),
+        })
+    }
+}
+
+/// Copies `secret`, returning what is needed to restore the clipboard afterwards.
+pub fn copy_secret(tool: &ClipboardTool, secret: &str) -> io::Result<PendingClear> {
+    let digest: [u8; 32] = Sha256::digest(secret.as_bytes()).into();
+    let previous = tool
+        .paste()
+        .filter(|previous| Sha256::digest(previous).as_slice() != digest);
+    tool.copy(secret.as_bytes())?;
+    Ok(PendingClear { digest, previous })
+}
+
+/// Restores the previous contents, or clears the clipboard, if it still holds the secret.
+/// Returns whether the clipboard was changed.
+pub fn clear_if_unchanged(tool: &ClipboardTool, pending: &PendingClear) -> io::Result<bool> {
+    let Some(current) = tool.paste() else {
+        return Ok(false);
+    };
+    if Sha256::digest(&current).as_slice() != pending.digest {
+        return Ok(false);
+    }
+    tool.copy(pending.previous.as_deref().unwrap_or_default())?;
+    Ok(true)
+}
+
+/// Starts a detached copy of pw that clears the clipboard after `timeout`, so the user gets
+/// the prompt back right away. The state is passed on stdin to keep it out of `ps`.
+pub fn spawn_clear_helper(timeout: Duration, pending: &PendingClear) -> io::Result<()> {
+    let mut command = Command::new(env::current_exe()?);
+    command
+        .arg(CLEAR_CLIPBOARD_COMMAND)
+        .arg("--timeout")
+        .arg(timeout.as_secs().to_string())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .current_dir(Path::new("/"));
+    // A process group of its own keeps Ctrl-C in the terminal from killing the helper.
+    #[cfg(unix)]
+    std::os::unix::process::CommandExt::process_group(&mut command, 0);
+    let mut child = command.spawn()?;
+    child.stdin.take().unwrap().write_all(&pending.encode())?;
+    Ok(())
+}
+
+/// The body of the clearing helper: reads the state written by `spawn_clear_helper`, waits,
+/// and clears the clipboard.
+pub fn run_clear_helper(timeout: Duration) -> io::Result<()> {
+    let mut encoded = Vec::new();
+    io::stdin().read_to_end(&mut encoded)?;
+    let pending = PendingClear::decode(&encoded)?;
+    std::thread::sleep(timeout);
+    let tool = ClipboardTool::detect()?;
+    clear_if_unchanged(&tool, &pending)?;
+    Ok(())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::{tempdir, TempDir};
+
+    /// A clipboard backed by a file, driven through `sh` like a real clipboard tool.
+    pub(crate) fn file_clipboard() -> (TempDir, ClipboardTool) {
+        let temp_dir = tempdir().unwrap();
+        let file = temp_dir.path().join("clipboard");
+        let file = file.to_str().unwrap();
+        let tool = ClipboardTool {
+            copy: command(&["sh", "-c", &format!("cat > '{}'", file)]),
+            paste: command(&["sh", "-c", &format!("cat '{}' 2>/dev/null", file)]),
+        };
+        (temp_dir, tool)
+    }
+
+    #[test]
+    fn copy_then_clear_restores_previous_contents() -> io::Result<()> {
+        let (_dir, tool) = file_clipboard();
+        tool.copy(b"shopping list")?;
+
+        let pending = copy_secret(&tool, "hunter2")?;
+        assert_eq!(tool.paste(), Some(b"hunter2".to_vec()));
+        assert!(clear_if_unchanged(&tool, &pending)?);
+        assert_eq!(tool.paste(), Some(b"shopping list".to_vec()));
+        Ok(())
+    }
+
+    #[test]
+    fn clear_leaves_a_changed_clipboard_alone() -> io::Result<()> {
+        let (_dir, tool) = file_clipboard();
+        let pending = copy_secret(&tool, "hunter2")?;
+        assert_eq!(pending.previous, None);
+
+        tool.copy(b"something else")?;
+        assert!(!clear_if_unchanged(&tool, &pending)?);
+        assert_eq!(tool.paste(), Some(b"something else".to_vec()));
+        Ok(())
+    }
+
+    #[test]
+    fn clear_empties_the_clipboard_without_previous_contents() -> io::Result<()> {
+        let (dir, tool) = file_clipboard();
+        let pending = copy_secret(&tool, "hunter2")?;
+        assert!(clear_if_unchanged(&tool, &pending)?);
+        assert_eq!(fs::read(dir.path().join("clipboard"))?, b"");
+        Ok(())
+    }
+
+    #[test]
+    fn pending_clear_round_trip() -> io::Result<()> {
+        for previous in [None, Some(Vec::new()), Some(b"\x00\x01binary".to_vec())] {
+            let pending = PendingClear {
+                digest: [9; 32],
+                previous,
+            };
+            assert_eq!(PendingClear::decode(&pending.encode())?, pending);
+        }
+        assert!(PendingClear::decode(&[0; 10]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn failing_copy_command_is_an_error() {
+        let tool = ClipboardTool {
+            copy: command(&["sh", "-c", "exit 3"]),
+            paste: command(&["true"]),
+        };
+        assert!(tool.copy(b"x").is_err());
+        let missing = ClipboardTool {
+            copy: command(&["pw-no-such-clipboard-tool"]),
+            paste: command(&["pw-no-such-clipboard-tool"]),
+        };
+        assert!(missing.copy(b"x").is_err());
+        assert_eq!(missing.paste(), None);
+    }
+}
@@ -1,32 +1,38 @@
-use crate::risk_analyzer::hibp_risk_analyzer::HIBPRiskAnalyzer;
+use crate::error::PwError;
 use crate::risk_analyzer::risk_analyzer_trait::RiskAnalyzer;
 use crate::storage::storage_trait::Storage;
 
+fn report(key: &str, compromised: bool) {
+    if compromised {
+        println!("Password for {} is compromised!", key);
+    } else {
+        println!("Password for {} is safe.", key);
+    }
+}
+
 pub async fn analyze_handler(
     key: Option<String>,
+    analyzer: &(dyn RiskAnalyzer + Sync),
     storage: &dyn Storage,
-) -> Result<(), Box<dyn std::error::Error>> {
+) -> Result<(), PwError> {
     if let Some(value) = key {
         let password_result = storage.get(value.clone())?;
         if let Some(password) = password_result {
-            let analyzer = HIBPRiskAnalyzer {};
             let compromised = analyzer.check_password(&password).await?;
-            if compromised {
-                println!("Password for {} is compromised!", value);
-            } else {
-                println!("Password for {} is safe.", value);
-            }
+            report(&value, compromised);
         } else {
-            println!("Key not found.");
+            return Err(PwError::NotFound(format!("Key '{}' not found", value)));
         }
     } else {
-        let passwords_result = storage.get_all()?;
-        if let Some(passwords) = passwords_result {
-            let password_refs: Vec<&str> = passwords.iter().map(AsRef::as_ref).collect();
-            let analyzer = HIBPRiskAnalyzer {};
-            analyzer.check_all_passwords(password_refs).await?;
-        } else {
+        let Some(passwords) = storage.get_all()? else {
             println!("No passwords to scan.");
+            return Ok(());
+        };
+        let results = analyzer
+            .check_all_passwords(passwords.iter().map(|(_, password)| password).collect())
+            .await?;
+        for ((key, _), compromised) in passwords.iter().zip(results) {
+            report(key, compromised);
         }
     }
     Ok(())
@@ -0,0 +1,203 @@
+use crate::cli::command_handlers::verify::read_every_entry;
+use crate::config::settings::BackupSettings;
+use crate::crypto::kdf::KdfParams;
+use crate::error::PwError;
+use crate::storage::backups::{self, Backup};
+use crate::storage::storage_trait::Storage;
+use crate::storage::vaults::Vault;
+use std::fs;
+use std::path::Path;
+
+/// Takes a backup of the vault into `dir`, then deletes the backups the retention policy
+/// lets go. Returns the new backup and the deleted ones.
+pub fn take_backup(
+    vault: &Vault,
+    dir: &Path,
+    password: &str,
+    kdf: KdfParams,
+    now: u64,
+    retention: &BackupSettings,
+) -> Result<(Backup, Vec<Backup>), PwError> {
+    let backup = backups::create(vault, dir, password, kdf, now)?;
+    let expired = backups::prune(dir, retention.keep_last, retention.keep_daily, now)?;
+    Ok((backup, expired))
+}
+
+pub fn backup_create_handler(
+    vault: &Vault,
+    dir: &Path,
+    password: &str,
+    kdf: KdfParams,
+    now: u64,
+    retention: &BackupSettings,
+) -> Result<(), PwError> {
+    let (backup, expired) = take_backup(vault, dir, password, kdf, now, retention)?;
+    println!("Backed up {} as {}", vault.path.display(), backup.id);
+    for backup in expired {
+        println!("Removed old backup {}", backup.id);
+    }
+    Ok(())
+}
+
+pub fn backup_list_handler(dir: &Path) -> Result<(), PwError> {
+    let backups = backups::list(dir)?;
+    if backups.is_empty() {
+        println!("No backups in {}", dir.display());
+    }
+    for backup in backups {
+        let size = fs::metadata(&backup.path)?.len();
+        println!("{}  {:>8} bytes", backup.id, size);
+    }
+    Ok(())
+}
+
+/// Replaces the vault with backup `id`. The backup is decrypted and opened with `open`, and
+/// every entry is read back, before anything is replaced; the current vault is then saved as
+/// a new backup, so a restore can itself be undone.
+pub fn backup_restore_handler(
+    id: &str,
+    vault: &Vault,
+    dir: &Path,
+    password: &str,
+    kdf: KdfParams,
+    now: u64,
+    open: impl Fn(&Vault) -> Result<Box<dyn Storage>, PwError>,
+) -> Result<(), PwError> {
+    let backup = backups::find(dir, id)?;
+    let snapshot = backups::open(&backup, password)?;
+    if snapshot.backend != vault.backend {
+        return Err(PwError::Invalid(format!(
+            "Backup '{}' is of a {} vault, but this vault uses {}",
+            id,
+            snapshot.backend.name(),
+            vault.backend.name()
+        )));
+    }
+
+    let staged = Vault {
+        backend: vault.backend,
+        path: backups::stage(&snapshot, vault)?,
+    };
+    let count = match open(&staged).and_then(|storage| read_every_entry(storage.as_ref())) {
+        Ok(count) => count,
+        Err(e) => {
+            let _ = fs::remove_file(&staged.path);
+            return Err(PwError::Corrupt(format!(
+                "Backup '{}' failed its integrity check, so the vault was not changed: {}",
+                id, e
+            )));
+        }
+    };
+
+    if vault.path.exists() {
+        let current = backups::create(vault, dir, password, kdf, now)?;
+        println!("Saved the current vault as backup {}", current.id);
+    }
+    fs::rename(&staged.path, &vault.path)?;
+    println!(
+        "Restored backup {} to {}: {} entries",
+        id,
+        vault.path.display(),
+        count
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::kdf::TEST_KDF_PARAMS;
+    use crate::crypto::secret::Secret;
+    use crate::storage::file_storage::FileStorage;
+    use crate::storage::vaults::Backend;
+    use tempfile::tempdir;
+
+    const NOW: u64 = 1_792_449_600;
+
+    fn open_json(vault: &Vault) -> Result<Box<dyn Storage>, PwError> {
+        let storage = FileStorage::new(
+            &vault.path.to_string_lossy(),
+            "master",
+            None,
+            TEST_KDF_PARAMS,
+        )?;
+        Ok(Box::new(storage))
+    }
+
+    #[test]
+    fn restore_replaces_the_vault_and_keeps_the_current_one() -> Result<(), PwError> {
+        let temp_dir = tempdir()?;
+        let vault = Vault {
+            backend: Backend::Json,
+            path: temp_dir.path().join("store.json"),
+        };
+        let dir = temp_dir.path().join("backups");
+        open_json(&vault)?.set("github".to_string(), "old".into())?;
+        let retention = BackupSettings::default();
+        let (backup, _) = take_backup(&vault, &dir, "master", TEST_KDF_PARAMS, NOW, &retention)?;
+
+        open_json(&vault)?.set("github".to_string(), "new".into())?;
+        backup_restore_handler(
+            &backup.id,
+            &vault,
+            &dir,
+            "master",
+            TEST_KDF_PARAMS,
+            NOW + 60,
+            open_json,
+        )?;
+        let storage = open_json(&vault)?;
+        assert_eq!(
+            storage.get("github".to_string())?,
+            Some(Secret::from("old"))
+        );
+        assert_eq!(backups::list(&dir)?.len(), 2);
+        assert!(!temp_dir.path().join("store.json.restore").exists());
+
+        let wrong = backup_restore_handler(
+            &backup.id,
+            &vault,
+            &dir,
+            "wrong",
+            TEST_KDF_PARAMS,
+            NOW + 120,
+            open_json,
+        );
+        assert!(matches!(wrong, Err(PwError::Auth(_))));
+        Ok(())
+    }
+
+    #[test]
+    fn damaged_backup_is_not_restored() -> Result<(), PwError> {
+        let temp_dir = tempdir()?;
+        let vault = Vault {
+            backend: Backend::Json,
+            path: temp_dir.path().join("store.json"),
+        };
+        let dir = temp_dir.path().join("backups");
+        open_json(&vault)?.set("github".to_string(), "hunter2".into())?;
+        let live = fs::read_to_string(&vault.path)?;
+
+        // A backup of a vault file that was already damaged when it was taken.
+        let damaged = Vault {
+            backend: Backend::Json,
+            path: temp_dir.path().join("damaged.json"),
+        };
+        fs::write(&damaged.path, &live[..live.len() / 2])?;
+        let backup = backups::create(&damaged, &dir, "master", TEST_KDF_PARAMS, NOW)?;
+
+        let result = backup_restore_handler(
+            &backup.id,
+            &vault,
+            &dir,
+            "master",
+            TEST_KDF_PARAMS,
+            NOW + 60,
+            open_json,
+        );
+        assert!(matches!(result, Err(PwError::Corrupt(_))));
+        assert_eq!(fs::read_to_string(&vault.path)?, live);
+        assert_eq!(backups::list(&dir)?.len(), 1);
+        Ok(())
+    }
+}
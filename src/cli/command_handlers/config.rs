@@ -0,0 +1,121 @@
+use crate::config::settings::{Config, Settings};
+use crate::error::PwError;
+use std::fs;
+use std::io;
+use std::path::Path;
+use toml_edit::{DocumentMut, Item, Table};
+
+/// Prints every setting in effect, defaults included, one `key = value` per line.
+pub fn config_list_handler(settings: &Settings) -> Result<(), PwError> {
+    for (key, value) in settings.entries() {
+        println!("{} = {}", key, value);
+    }
+    Ok(())
+}
+
+/// Prints the value of one setting. Strings are printed without quotes so the output can be
+/// used in scripts.
+pub fn config_get_handler(key: &str, settings: &Settings) -> Result<(), PwError> {
+    match settings.get(key) {
+        Some(toml::Value::String(value)) => println!("{}", value),
+        Some(toml::Value::Table(table)) => {
+            for (name, value) in table {
+                println!("{}.{} = {}", key, name, value);
+            }
+        }
+        Some(value) => println!("{}", value),
+        None => return Err(PwError::NotFound(format!("Unknown setting '{}'", key))),
+    }
+    Ok(())
+}
+
+/// Sets `key` in the config file, keeping its comments and layout. The value is read as a
+/// TOML value where possible (`16`, `true`, `"text"`) and as a string otherwise. The file
+/// is only written if the result is a valid config.
+pub fn config_set_handler(key: &str, value: &str, config_path: &Path) -> Result<(), PwError> {
+    let contents = match fs::read_to_string(config_path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e.into()),
+    };
+    let mut document: DocumentMut = contents
+        .parse()
+        .map_err(|e| PwError::Invalid(format!("{}: {}", config_path.display(), e)))?;
+
+    let segments: Vec<&str> = key.split('.').collect();
+    if segments.iter().any(|segment| segment.is_empty()) {
+        return Err(PwError::Invalid(format!("Invalid setting name '{}'", key)));
+    }
+    let (last, parents) = segments.split_last().unwrap();
+    let mut table = document.as_table_mut() as &mut dyn toml_edit::TableLike;
+    for (depth, segment) in parents.iter().enumerate() {
+        let item = table.entry(segment).or_insert_with(|| {
+            let mut table = Table::new();
+            table.set_implicit(true);
+            Item::Table(table)
+        });
+        table = item.as_table_like_mut().ok_or_else(|| {
+            PwError::Invalid(format!(
+                "'{}' is a value, not a table",
+                segments[..=depth].join(".")
+            ))
+        })?;
+    }
+    let value = value
+        .parse::<toml_edit::Value>()
+        .unwrap_or_else(|_| value.into());
+    table.insert(last, toml_edit::value(value.clone()));
+
+    let contents = document.to_string();
+    Config::parse(&contents)?;
+    if let Some(parent) = config_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(config_path, contents)?;
+    println!("Set {} to {}", key, value.to_string().trim());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::settings::OutputFormat;
+    use tempfile::tempdir;
+
+    #[test]
+    fn set_keeps_comments_and_validates() -> Result<(), PwError> {
+        let temp_dir = tempdir()?;
+        let config_path = temp_dir.path().join("pw").join("config.toml");
+
+        config_set_handler("generator.length", "16", &config_path)?;
+        config_set_handler("vaults.team.output.format", "json", &config_path)?;
+        let contents = fs::read_to_string(&config_path)?;
+        fs::write(&config_path, format!("# my settings\n{}", contents))?;
+        config_set_handler("generator.length", "20", &config_path)?;
+
+        let config = Config::load(&config_path)?;
+        assert_eq!(config.settings(None).generator.length, 20);
+        assert_eq!(
+            config.settings(Some("team")).output.format,
+            OutputFormat::Json
+        );
+        assert!(fs::read_to_string(&config_path)?.starts_with("# my settings\n"));
+
+        let before = fs::read_to_string(&config_path)?;
+        let error = config_set_handler("generator.length", "two", &config_path).unwrap_err();
+        assert!(error.to_string().starts_with("generator.length: "));
+        assert!(config_set_handler("generator.lenght", "16", &config_path).is_err());
+        assert!(config_set_handler("generator.length.x", "16", &config_path).is_err());
+        assert!(config_set_handler("generator..length", "16", &config_path).is_err());
+        assert_eq!(fs::read_to_string(&config_path)?, before);
+        Ok(())
+    }
+
+    #[test]
+    fn get_rejects_unknown_settings() {
+        let settings = Settings::default();
+        assert!(config_get_handler("clipboard.timeout", &settings).is_ok());
+        assert!(config_get_handler("generator", &settings).is_ok());
+        assert!(config_get_handler("clipboard.nope", &settings).is_err());
+    }
+}
@@ -0,0 +1,32 @@
+use crate::error::PwError;
+use crate::password_generator::derive::derive_password;
+
+pub fn derive_handler(
+    site: &str,
+    login: &str,
+    counter: u32,
+    length: usize,
+    master_password: &str,
+) -> Result<(), PwError> {
+    let password =
+        derive_password(master_password, site, login, counter, length).map_err(PwError::Invalid)?;
+    println!("Password derived: {}", password);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn derive_handler_derives_password() {
+        let result = derive_handler("example.com", "alice", 1, 16, "master");
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn derive_handler_rejects_invalid_length() {
+        let result = derive_handler("example.com", "alice", 1, 2, "master");
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,578 @@
+use crate::config::settings::{Config, Settings};
+use crate::crypto::kdf::{
+    calibrate_with, memory_limit_kib, time_derivation, CryptoError, KdfParams,
+};
+use crate::error::PwError;
+use crate::storage::file_storage::FileStorage;
+use crate::storage::kdbx::format::read_header;
+use crate::storage::pass_storage::GPG_ID_FILE;
+use crate::storage::private_files::{PRIVATE_DIR_MODE, PRIVATE_FILE_MODE};
+use crate::storage::sqlite_storage::SqliteStorage;
+use crate::storage::vaults::{Backend, Vault, VaultRegistry};
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::time::{Duration, SystemTime};
+
+/// A breach database that has not been updated for this long is reported as stale.
+const BREACH_DB_MAX_AGE: Duration = Duration::from_secs(90 * 24 * 60 * 60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Ok,
+    Skipped,
+    Problem,
+}
+
+/// The outcome of one check, with a suggested fix for problems.
+#[derive(Debug, PartialEq)]
+pub struct Finding {
+    pub status: Status,
+    pub subject: String,
+    pub message: String,
+    pub fix: Option<String>,
+}
+
+impl Finding {
+    fn ok(subject: &str, message: String) -> Self {
+        Self {
+            status: Status::Ok,
+            subject: subject.to_string(),
+            message,
+            fix: None,
+        }
+    }
+
+    fn skipped(subject: &str, message: String) -> Self {
+        Self {
+            status: Status::Skipped,
+            subject: subject.to_string(),
+            message,
+            fix: None,
+        }
+    }
+
+    fn problem(subject: &str, message: String, fix: String) -> Self {
+        Self {
+            status: Status::Problem,
+            subject: subject.to_string(),
+            message,
+            fix: Some(fix),
+        }
+    }
+}
+
+/// Checks that `path` belongs to the current user and that nobody else can access it.
+/// `mode` is the expected mode, 0600 for files and 0700 for directories.
+#[cfg(unix)]
+pub fn check_access(subject: &str, path: &Path, mode: u32) -> Vec<Finding> {
+    use std::os::unix::fs::MetadataExt;
+
+    let Ok(metadata) = fs::metadata(path) else {
+        return Vec::new();
+    };
+    let mut findings = Vec::new();
+    // SAFETY: geteuid has no preconditions and cannot fail.
+    let uid = unsafe { libc::geteuid() };
+    if metadata.uid() != uid {
+        findings.push(Finding::problem(
+            subject,
+            format!(
+                "{} is owned by uid {}, not by you",
+                path.display(),
+                metadata.uid()
+            ),
+            format!("chown $(id -u) {}", path.display()),
+        ));
+    }
+    let current = metadata.mode() & 0o777;
+    if current & 0o077 != 0 {
+        findings.push(Finding::problem(
+            subject,
+            format!(
+                "{} is accessible by other users (mode {:o})",
+                path.display(),
+                current
+            ),
+            format!("chmod {:o} {}", mode, path.display()),
+        ));
+    }
+    if findings.is_empty() {
+        findings.push(Finding::ok(
+            subject,
+            format!("{} is private (mode {:o})", path.display(), current),
+        ));
+    }
+    findings
+}
+
+#[cfg(not(unix))]
+pub fn check_access(_: &str, _: &Path, _: u32) -> Vec<Finding> {
+    Vec::new()
+}
+
+/// Checks that a vault exists, is private and can be read. Nothing is decrypted, so no
+/// password is needed; a damaged entry is only noticed when it is read.
+pub fn check_vault(name: &str, vault: &Vault, builtin: bool) -> Vec<Finding> {
+    let subject = format!("vault '{}'", name);
+    let path = vault.path.as_path();
+    if !path.exists() {
+        if builtin {
+            return vec![Finding::skipped(
+                &subject,
+                format!("{} has not been created yet", path.display()),
+            )];
+        }
+        return vec![Finding::problem(
+            &subject,
+            format!("{} does not exist", path.display()),
+            format!(
+                "restore it from a backup, or run 'pw vault remove {}'",
+                name
+            ),
+        )];
+    }
+
+    let mode = match vault.backend {
+        Backend::Pass => PRIVATE_DIR_MODE,
+        _ => PRIVATE_FILE_MODE,
+    };
+    let mut findings = check_access(&subject, path, mode);
+    let integrity = match vault.backend {
+        Backend::Json => FileStorage::check(path).map(|count| format!("{} entries", count)),
+        Backend::Sqlite => SqliteStorage::check(path).map(|count| format!("{} entries", count)),
+        Backend::Kdbx => fs::read(path)
+            .and_then(|data| Ok(read_header(&data)?))
+            .map(|_| "the header is valid".to_string()),
+        Backend::Pass => match path.join(GPG_ID_FILE).is_file() {
+            true => Ok(format!("{} is present", GPG_ID_FILE)),
+            false => Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{} has no {} file", path.display(), GPG_ID_FILE),
+            )),
+        },
+    };
+    findings.push(match integrity {
+        Ok(summary) => Finding::ok(
+            &subject,
+            format!(
+                "{} {} is readable: {}",
+                vault.backend.name(),
+                path.display(),
+                summary
+            ),
+        ),
+        Err(e) => Finding::problem(
+            &subject,
+            format!("{} cannot be read: {}", path.display(), e),
+            match vault.backend {
+                Backend::Pass => "run 'pass init <gpg-id>' in the store".to_string(),
+                _ => "restore the vault from a backup".to_string(),
+            },
+        ),
+    });
+    findings
+}
+
+fn describe_kdf(kdf: &KdfParams) -> String {
+    format!(
+        "Argon2id ({} MiB, {} iterations, {} lanes)",
+        kdf.memory_kib / 1024,
+        kdf.iterations,
+        kdf.parallelism
+    )
+}
+
+/// Times deriving the key of a JSON or SQLite vault with the Argon2id parameters in its
+/// header. Under half of `target`, this machine can afford stronger parameters; over twice
+/// it, unlocking is needlessly slow. Either way, `pw rekey` with calibrated parameters is
+/// suggested. Vaults without such parameters are left to `check_vault`.
+pub fn check_kdf(
+    name: &str,
+    vault: &Vault,
+    target: Duration,
+    measure: &mut dyn FnMut(&KdfParams) -> Result<Duration, CryptoError>,
+) -> Option<Finding> {
+    let path = vault.path.as_path();
+    let kdf = match vault.backend {
+        Backend::Json => FileStorage::kdf_params(path).ok()??,
+        Backend::Sqlite if path.exists() => SqliteStorage::kdf_params(path).ok()??,
+        _ => return None,
+    };
+    let subject = format!("vault '{}'", name);
+    let elapsed = match measure(&kdf) {
+        Ok(elapsed) => elapsed,
+        Err(e) => {
+            return Some(Finding::problem(
+                &subject,
+                format!("the key derivation settings are unusable: {}", e),
+                "restore the vault from a backup".to_string(),
+            ))
+        }
+    };
+    let summary = format!(
+        "{} unlocks in {} ms",
+        describe_kdf(&kdf),
+        elapsed.as_millis()
+    );
+    let problem = if elapsed < target / 2 {
+        "well under"
+    } else if elapsed > target * 2 {
+        "well over"
+    } else {
+        return Some(Finding::ok(
+            &subject,
+            format!("{}, close to the {} ms target", summary, target.as_millis()),
+        ));
+    };
+    let message = format!(
+        "{}, {} the {} ms target (unlock.time_ms)",
+        summary,
+        problem,
+        target.as_millis()
+    );
+    let fix = match calibrate_with(target, memory_limit_kib(), measure) {
+        Ok(calibrated) => format!(
+            "pw --vault {} rekey --memory-kib {} --iterations {} --parallelism {}",
+            name, calibrated.memory_kib, calibrated.iterations, calibrated.parallelism
+        ),
+        Err(e) => format!("change unlock.time_ms; calibration failed: {}", e),
+    };
+    Some(Finding::problem(&subject, message, fix))
+}
+
+/// Checks the config file, returning the settings to use for the other checks.
+pub fn check_config(config_path: &Path) -> (Finding, Settings) {
+    let subject = "config";
+    match Config::load(config_path) {
+        Ok(config) if config_path.exists() => (
+            Finding::ok(subject, format!("{} is valid", config_path.display())),
+            config.settings(None).clone(),
+        ),
+        Ok(config) => (
+            Finding::skipped(
+                subject,
+                format!(
+                    "{} does not exist; defaults are used",
+                    config_path.display()
+                ),
+            ),
+            config.settings(None).clone(),
+        ),
+        Err(e) => (
+            Finding::problem(
+                subject,
+                e.to_string(),
+                format!(
+                    "edit {}, or change the setting with 'pw config set'",
+                    config_path.display()
+                ),
+            ),
+            Settings::default(),
+        ),
+    }
+}
+
+/// pass vaults decrypt through gpg-agent, which asks for the key's passphrase.
+pub fn check_gpg_agent(vaults: &[(String, Vault)]) -> Finding {
+    let subject = "gpg-agent";
+    if !vaults
+        .iter()
+        .any(|(_, vault)| vault.backend == Backend::Pass)
+    {
+        return Finding::skipped(subject, "no pass vault uses it".to_string());
+    }
+    let status = Command::new("gpg-connect-agent")
+        .arg("/bye")
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status();
+    match status {
+        Ok(status) if status.success() => {
+            Finding::ok(subject, "the agent is reachable".to_string())
+        }
+        Ok(status) => Finding::problem(
+            subject,
+            format!("gpg-connect-agent failed with {}", status),
+            "start the agent with 'gpgconf --launch gpg-agent'".to_string(),
+        ),
+        Err(e) => Finding::problem(
+            subject,
+            format!("gpg-connect-agent cannot be run: {}", e),
+            "install GnuPG".to_string(),
+        ),
+    }
+}
+
+/// Fetches one range from the breach database and checks when it was last updated. The
+/// public API is always current; a self-hosted mirror set in `analyzer.hibp_url` may not be.
+pub async fn check_breach_db(api_url: &str, now: SystemTime) -> Finding {
+    let subject = "breach database";
+    let fix = format!(
+        "check the network connection and analyzer.hibp_url ({})",
+        api_url
+    );
+    let response = reqwest::Client::new()
+        .get(format!("{}00000", api_url))
+        .timeout(Duration::from_secs(10))
+        .send()
+        .await
+        .and_then(|response| response.error_for_status());
+    let response = match response {
+        Ok(response) => response,
+        Err(e) => {
+            return Finding::problem(subject, format!("{} is unreachable: {}", api_url, e), fix)
+        }
+    };
+    let last_modified = response
+        .headers()
+        .get(reqwest::header::LAST_MODIFIED)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| httpdate::parse_http_date(value).ok());
+    let Some(last_modified) = last_modified else {
+        return Finding::ok(subject, format!("{} is reachable", api_url));
+    };
+    let age = now.duration_since(last_modified).unwrap_or_default();
+    let days = age.as_secs() / (24 * 60 * 60);
+    if age > BREACH_DB_MAX_AGE {
+        return Finding::problem(
+            subject,
+            format!("{} was last updated {} days ago", api_url, days),
+            format!(
+                "update the mirror, or remove analyzer.hibp_url to use {}",
+                crate::risk_analyzer::hibp_risk_analyzer::HIBP_API_URL
+            ),
+        );
+    }
+    Finding::ok(
+        subject,
+        format!("{} is reachable and was updated {} days ago", api_url, days),
+    )
+}
+
+fn print_finding(finding: &Finding) {
+    let label = match finding.status {
+        Status::Ok => "ok",
+        Status::Skipped => "skipped",
+        Status::Problem => "PROBLEM",
+    };
+    println!("{:8} {}: {}", label, finding.subject, finding.message);
+    if let Some(fix) = &finding.fix {
+        println!("{:8} fix: {}", "", fix);
+    }
+}
+
+/// Runs every check and prints the results. Fails if any problem was found, so the command
+/// can be used in scripts.
+pub async fn doctor_handler(pw_dir: &Path, config_path: &Path) -> Result<(), PwError> {
+    let mut findings = Vec::new();
+    let (config_finding, settings) = check_config(config_path);
+    findings.push(config_finding);
+
+    if pw_dir.exists() {
+        findings.extend(check_access("pw directory", pw_dir, PRIVATE_DIR_MODE));
+    }
+    let builtin = settings.storage.default_vault(pw_dir)?;
+    let registry_path = pw_dir.join("vaults.json");
+    let vaults = match VaultRegistry::load(&registry_path) {
+        Ok(registry) => registry.list(&builtin),
+        Err(e) => {
+            findings.push(Finding::problem(
+                "vault list",
+                format!("{} cannot be read: {}", registry_path.display(), e),
+                format!("fix or remove {}", registry_path.display()),
+            ));
+            vec![("default".to_string(), builtin.clone())]
+        }
+    };
+    let target = Duration::from_millis(settings.unlock.time_ms);
+    for (name, vault) in &vaults {
+        findings.extend(check_vault(name, vault, *vault == builtin));
+        findings.extend(check_kdf(name, vault, target, &mut time_derivation));
+    }
+    findings.push(check_gpg_agent(&vaults));
+    findings.push(check_breach_db(&settings.analyzer.hibp_url, SystemTime::now()).await);
+
+    for finding in &findings {
+        print_finding(finding);
+    }
+    let problems = findings
+        .iter()
+        .filter(|finding| finding.status == Status::Problem)
+        .count();
+    if problems > 0 {
+        return Err(PwError::Io(io::Error::other(format!(
+            "{} problem(s) found",
+            problems
+        ))));
+    }
+    println!("No problems found.");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::kdf::TEST_KDF_PARAMS;
+    use std::path::PathBuf;
+    use tempfile::tempdir;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    fn problems(findings: &[Finding]) -> Vec<&str> {
+        findings
+            .iter()
+            .filter(|finding| finding.status == Status::Problem)
+            .map(|finding| finding.message.as_str())
+            .collect()
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn open_permissions_are_problems() -> io::Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp_dir = tempdir()?;
+        let path = temp_dir.path().join("store.json");
+        FileStorage::new(path.to_str().unwrap(), "master", None, TEST_KDF_PARAMS)?;
+        let vault = Vault {
+            backend: Backend::Json,
+            path: path.clone(),
+        };
+        assert!(problems(&check_vault("default", &vault, true)).is_empty());
+
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644))?;
+        let findings = check_vault("default", &vault, true);
+        assert_eq!(problems(&findings).len(), 1);
+        let fix = findings[0].fix.as_deref().unwrap();
+        assert_eq!(fix, format!("chmod 600 {}", path.display()));
+        Ok(())
+    }
+
+    #[test]
+    fn damaged_and_missing_vaults_are_problems() -> io::Result<()> {
+        let temp_dir = tempdir()?;
+        let json = Vault {
+            backend: Backend::Json,
+            path: temp_dir.path().join("store.json"),
+        };
+        assert_eq!(
+            check_vault("default", &json, true)[0].status,
+            Status::Skipped
+        );
+        assert_eq!(problems(&check_vault("team", &json, false)).len(), 1);
+
+        FileStorage::new(json.path.to_str().unwrap(), "master", None, TEST_KDF_PARAMS)?;
+        fs::write(&json.path, "{ not json")?;
+        assert_eq!(problems(&check_vault("default", &json, true)).len(), 1);
+
+        let sqlite = Vault {
+            backend: Backend::Sqlite,
+            path: temp_dir.path().join("store.db"),
+        };
+        SqliteStorage::open(&sqlite.path, "master", None, TEST_KDF_PARAMS)?;
+        assert!(problems(&check_vault("db", &sqlite, false)).is_empty());
+
+        let pass = Vault {
+            backend: Backend::Pass,
+            path: temp_dir.path().join("pass"),
+        };
+        fs::create_dir(&pass.path)?;
+        assert!(problems(&check_vault("pass", &pass, false))
+            .iter()
+            .any(|message| message.contains(GPG_ID_FILE)));
+        Ok(())
+    }
+
+    #[test]
+    fn key_derivation_is_checked_against_the_target() -> io::Result<()> {
+        let temp_dir = tempdir()?;
+        let vault = Vault {
+            backend: Backend::Sqlite,
+            path: temp_dir.path().join("store.db"),
+        };
+        let target = Duration::from_millis(500);
+        let taking = |millis: u64| move |_: &KdfParams| Ok(Duration::from_millis(millis));
+        assert_eq!(check_kdf("db", &vault, target, &mut taking(500)), None);
+
+        SqliteStorage::open(&vault.path, "master", None, TEST_KDF_PARAMS)?;
+        let finding = check_kdf("db", &vault, target, &mut taking(400)).unwrap();
+        assert_eq!(finding.status, Status::Ok, "{}", finding.message);
+
+        let finding = check_kdf("db", &vault, target, &mut taking(10)).unwrap();
+        assert_eq!(finding.status, Status::Problem);
+        assert!(
+            finding.message.contains("well under"),
+            "{}",
+            finding.message
+        );
+        assert_eq!(
+            finding.fix.as_deref(),
+            Some("pw --vault db rekey --memory-kib 1048576 --iterations 50 --parallelism 1")
+        );
+
+        let finding = check_kdf("db", &vault, target, &mut taking(1500)).unwrap();
+        assert!(finding.message.contains("well over"), "{}", finding.message);
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_config_is_a_problem() -> io::Result<()> {
+        let temp_dir = tempdir()?;
+        let config_path = temp_dir.path().join("config.toml");
+        assert_eq!(check_config(&config_path).0.status, Status::Skipped);
+
+        fs::write(&config_path, "[generator]\nlength = 2\n")?;
+        let (finding, settings) = check_config(&config_path);
+        assert_eq!(finding.status, Status::Problem);
+        assert_eq!(settings, Settings::default());
+        Ok(())
+    }
+
+    #[test]
+    fn gpg_agent_is_only_checked_for_pass_vaults() {
+        let vaults = vec![(
+            "default".to_string(),
+            Vault {
+                backend: Backend::Json,
+                path: PathBuf::from("/nonexistent/store.json"),
+            },
+        )];
+        assert_eq!(check_gpg_agent(&vaults).status, Status::Skipped);
+    }
+
+    /// Serves one HTTP response with the given `Last-Modified` date.
+    async fn serve_once(last_modified: &'static str) -> io::Result<String> {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let url = format!("http://{}/range/", listener.local_addr()?);
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = [0u8; 1024];
+            let _ = socket.read(&mut request).await.unwrap();
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nLast-Modified: {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                last_modified
+            );
+            socket.write_all(response.as_bytes()).await.unwrap();
+        });
+        Ok(url)
+    }
+
+    #[tokio::test]
+    async fn stale_breach_db_is_a_problem() -> io::Result<()> {
+        let now = httpdate::parse_http_date("Mon, 01 Jun 2026 00:00:00 GMT").unwrap();
+
+        let url = serve_once("Fri, 29 May 2026 00:00:00 GMT").await?;
+        let finding = check_breach_db(&url, now).await;
+        assert_eq!(finding.status, Status::Ok, "{}", finding.message);
+        assert!(finding.message.contains("3 days ago"));
+
+        let url = serve_once("Wed, 01 Jan 2025 00:00:00 GMT").await?;
+        assert_eq!(check_breach_db(&url, now).await.status, Status::Problem);
+
+        let finding = check_breach_db("http://127.0.0.1:9/range/", now).await;
+        assert_eq!(finding.status, Status::Problem);
+        Ok(())
+    }
+}
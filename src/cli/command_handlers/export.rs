@@ -0,0 +1,219 @@
+use crate::{
+    crypto::{envelope::SealedBox, kdf::KdfParams},
+    error::PwError,
+    exporters::{
+        encrypted::{EncryptedExport, ENCRYPTED_EXPORT_FORMAT},
+        export_format::{render_entries, ExportFormat},
+    },
+    storage::{
+        private_files::{private_file_options, restrict_to_owner},
+        storage_trait::Storage,
+    },
+};
+use clap::ValueEnum;
+use std::io::Write;
+use std::path::Path;
+use zeroize::Zeroizing;
+
+pub struct ExportOptions<'a> {
+    pub format: ExportFormat,
+    /// Only keys starting with this prefix are exported.
+    pub prefix: Option<&'a str>,
+    /// Where to write the export; standard output when `None`.
+    pub output: Option<&'a Path>,
+    /// Allow writing plaintext somewhere other than a terminal.
+    pub force: bool,
+    /// Encrypt the export under this passphrase.
+    pub passphrase: Option<&'a str>,
+    pub kdf: KdfParams,
+}
+
+pub fn export_handler(
+    options: ExportOptions,
+    stdout_is_terminal: bool,
+    storage: &dyn Storage,
+) -> Result<(), PwError> {
+    if options.passphrase.is_none() && !options.force {
+        if options.output.is_some() {
+            return Err(PwError::Invalid("Refusing to write a plaintext export to a file. Use --encrypt, or --force to write it anyway.".to_string()));
+        }
+        if !stdout_is_terminal {
+            return Err(PwError::Invalid("Refusing to write a plaintext export to a pipe or redirect. Use --encrypt, or --force to write it anyway.".to_string()));
+        }
+    }
+
+    let prefix = options.prefix.unwrap_or_default();
+    let mut entries = Vec::new();
+    for key in storage.list_keys()? {
+        if !key.starts_with(prefix) {
+            continue;
+        }
+        if let Some(entry) = storage.get_entry(key.clone())? {
+            entries.push((key, entry));
+        }
+    }
+    entries.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let mut contents = Zeroizing::new(render_entries(options.format, &entries)?);
+    if let Some(passphrase) = options.passphrase {
+        let sealed = SealedBox::seal(passphrase.as_bytes(), contents.as_bytes(), options.kdf)?;
+        let content_format = options
+            .format
+            .to_possible_value()
+            .map(|value| value.get_name().to_string())
+            .unwrap_or_default();
+        let mut encrypted = serde_json::to_string_pretty(&EncryptedExport {
+            format: ENCRYPTED_EXPORT_FORMAT.to_string(),
+            content_format,
+            sealed,
+        })?;
+        encrypted.push('\n');
+        contents = Zeroizing::new(encrypted);
+    }
+
+    match options.output {
+        Some(path) => {
+            let mut file = private_file_options()
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .open(path)?;
+            restrict_to_owner(&file)?;
+            file.write_all(contents.as_bytes())?;
+            println!("Exported {} entries to {}", entries.len(), path.display());
+        }
+        None => print!("{}", contents.as_str()),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::kdf::TEST_KDF_PARAMS;
+    use crate::crypto::secret::Secret;
+    use crate::storage::entry::Entry;
+    use tempfile::tempdir;
+
+    struct MockStorage;
+
+    impl Storage for MockStorage {
+        fn set(&self, _: String, _: Secret) -> Result<(), std::io::Error> {
+            unimplemented!()
+        }
+
+        fn get(&self, _: String) -> Result<Option<Secret>, std::io::Error> {
+            unimplemented!()
+        }
+
+        fn get_all(&self) -> Result<Option<Vec<(String, Secret)>>, std::io::Error> {
+            unimplemented!()
+        }
+
+        fn get_entry(&self, key: String) -> Result<Option<Entry>, std::io::Error> {
+            Ok(Some(Entry::new(format!("{}-password", key))))
+        }
+
+        fn set_entry(&self, _: String, _: Entry) -> Result<(), std::io::Error> {
+            unimplemented!()
+        }
+
+        fn list_keys(&self) -> Result<Vec<String>, std::io::Error> {
+            Ok(vec!["prod/db".to_string(), "dev/db".to_string()])
+        }
+    }
+
+    fn options<'a>(output: Option<&'a Path>) -> ExportOptions<'a> {
+        ExportOptions {
+            format: ExportFormat::Csv,
+            prefix: None,
+            output,
+            force: false,
+            passphrase: None,
+            kdf: TEST_KDF_PARAMS,
+        }
+    }
+
+    #[test]
+    fn export_handler_refuses_plaintext_file_without_force() {
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path().join("export.csv");
+
+        let result = export_handler(options(Some(&path)), true, &MockStorage);
+        assert!(result.is_err());
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn export_handler_refuses_plaintext_pipe_without_force() {
+        let result = export_handler(options(None), false, &MockStorage);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn export_handler_writes_filtered_plaintext_with_force() {
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path().join("export.csv");
+
+        let result = export_handler(
+            ExportOptions {
+                prefix: Some("prod/"),
+                force: true,
+                ..options(Some(&path))
+            },
+            false,
+            &MockStorage,
+        );
+        assert!(result.is_ok());
+        let contents = std::fs::read_to_string(&path).unwrap();
+        assert!(contents.contains("prod/db-password"));
+        assert!(!contents.contains("dev/db"));
+    }
+
+    #[test]
+    fn export_handler_encrypts_without_force() {
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path().join("export.json");
+
+        let result = export_handler(
+            ExportOptions {
+                passphrase: Some("export passphrase"),
+                ..options(Some(&path))
+            },
+            false,
+            &MockStorage,
+        );
+        assert!(result.is_ok());
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        assert!(!contents.contains("prod/db-password"));
+        let sealed: SealedBox = serde_json::from_str(&contents).unwrap();
+        let plaintext = sealed.open(b"export passphrase").unwrap();
+        assert!(String::from_utf8(plaintext.to_vec())
+            .unwrap()
+            .contains("prod/db-password"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn export_handler_restricts_an_existing_file() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path().join("export.json");
+        std::fs::write(&path, "old").unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o644)).unwrap();
+
+        let result = export_handler(
+            ExportOptions {
+                passphrase: Some("export passphrase"),
+                ..options(Some(&path))
+            },
+            false,
+            &MockStorage,
+        );
+        assert!(result.is_ok());
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+}
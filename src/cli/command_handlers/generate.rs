@@ -1,21 +1,130 @@
 use crate::{
-    password_generator::generator::generate_strong_password, storage::storage_trait::Storage,
+    crypto::secret::Secret,
+    error::PwError,
+    password_generator::{
+        generator::generate_strong_password,
+        mode::GenerateMode,
+        pattern::Pattern,
+        profiles::{find_profile, UserProfiles},
+        pronounceable::generate_pronounceable_password,
+    },
+    storage::storage_trait::Storage,
 };
 
+pub const DEFAULT_LENGTH: usize = 12;
+
+type PasswordSource = Box<dyn FnMut() -> Result<Secret, PwError>>;
+
+/// Resolves `mode` once and returns a source that produces passwords for it.
+pub fn password_source(
+    mode: &GenerateMode,
+    profiles: Option<&UserProfiles>,
+) -> Result<PasswordSource, PwError> {
+    match mode {
+        GenerateMode::Strong { length } => {
+            let length = length.unwrap_or(DEFAULT_LENGTH);
+            Ok(Box::new(move || Ok(generate_strong_password(length)?)))
+        }
+        GenerateMode::Profile { name, length } => {
+            let mut policy = find_profile(name, profiles)?;
+            if let Some(length) = length {
+                policy.length = *length;
+            }
+            let name = name.clone();
+            Ok(Box::new(move || {
+                policy.generate().map_err(|e| {
+                    PwError::Policy(format!(
+                        "Cannot generate a password for profile '{}': {}",
+                        name, e
+                    ))
+                })
+            }))
+        }
+        GenerateMode::Pattern { pattern } => {
+            let pattern = parse_pattern(pattern)?;
+            Ok(Box::new(move || Ok(pattern.generate())))
+        }
+        GenerateMode::Pronounceable(options) => {
+            options.validate().map_err(PwError::Invalid)?;
+            let options = options.clone();
+            Ok(Box::new(move || {
+                generate_pronounceable_password(&options).map_err(PwError::Invalid)
+            }))
+        }
+    }
+}
+
+fn parse_pattern(pattern: &str) -> Result<Pattern, PwError> {
+    Pattern::parse(pattern).map_err(|e| PwError::Invalid(format!("Invalid pattern: {}", e)))
+}
+
+/// Reports the entropy of the modes where it is known.
+fn print_entropy(mode: &GenerateMode) -> Result<(), PwError> {
+    match mode {
+        GenerateMode::Pattern { pattern } => {
+            println!(
+                "Entropy: {:.1} bits",
+                parse_pattern(pattern)?.entropy_bits()
+            );
+        }
+        GenerateMode::Pronounceable(options) => println!(
+            "Entropy: {:.1} bits (a random password of the same length has {:.1} bits)",
+            options.entropy_bits(),
+            options.random_equivalent_bits()
+        ),
+        GenerateMode::Strong { .. } | GenerateMode::Profile { .. } => {}
+    }
+    Ok(())
+}
+
 pub fn generate_handler(
-    key: Option<String>,
-    length: usize,
-    storage: &dyn Storage,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let password = generate_strong_password(length);
-    if let Some(value) = key {
-        storage.set(value.clone(), password.clone())?;
+    mode: GenerateMode,
+    count: usize,
+    profiles: Option<&UserProfiles>,
+    save_to: Option<(&str, &dyn Storage)>,
+) -> Result<(), PwError> {
+    if count == 0 {
+        return Err(PwError::Invalid(
+            "The number of passwords to generate must be at least 1".to_string(),
+        ));
+    }
+    if count > 1 && save_to.is_some() {
+        return Err(PwError::Invalid(
+            "Only a single password can be saved under a key".to_string(),
+        ));
+    }
+
+    let mut next_password = password_source(&mode, profiles)?;
+    print_entropy(&mode)?;
+    if count > 1 {
+        for _ in 0..count {
+            println!("{}", next_password()?.expose());
+        }
+        return Ok(());
+    }
+
+    let password = next_password()?;
+    if let Some((key, storage)) = save_to {
+        let mut entry = storage.get_entry(key.to_string())?.unwrap_or_default();
+        entry.replace_value(password.clone());
+        entry.profile = match &mode {
+            GenerateMode::Profile { name, .. } => Some(name.clone()),
+            _ => None,
+        };
+        entry.generator = Some(match mode {
+            GenerateMode::Strong { length } => GenerateMode::Strong {
+                length: Some(length.unwrap_or(DEFAULT_LENGTH)),
+            },
+            mode => mode,
+        });
+        storage.set_entry(key.to_string(), entry)?;
         println!(
             "Password generated: {}, and saved under key '{}'",
-            password, value
+            password.expose(),
+            key
         );
     } else {
-        println!("Password generated: {}", password);
+        println!("Password generated: {}", password.expose());
     }
     Ok(())
 }
@@ -23,24 +132,43 @@ pub fn generate_handler(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::crypto::secret::Secret;
+    use crate::password_generator::pronounceable::PronounceableOptions;
+    use crate::storage::entry::Entry;
 
     struct MockStorage {
         should_fail: bool,
     }
 
     impl Storage for MockStorage {
-        fn set(&self, _key: String, _value: String) -> Result<(), std::io::Error> {
+        fn set(&self, _key: String, _value: Secret) -> Result<(), std::io::Error> {
             if self.should_fail {
                 Err(std::io::Error::new(std::io::ErrorKind::Other, "Mock Error"))
             } else {
                 Ok(())
             }
         }
-        fn get(&self, _: String) -> Result<Option<String>, std::io::Error> {
+        fn get(&self, _: String) -> Result<Option<Secret>, std::io::Error> {
+            unimplemented!()
+        }
+
+        fn get_all(&self) -> Result<Option<Vec<(String, Secret)>>, std::io::Error> {
             unimplemented!()
         }
 
-        fn get_all(&self) -> Result<Option<Vec<String>>, std::io::Error> {
+        fn get_entry(&self, _: String) -> Result<Option<Entry>, std::io::Error> {
+            Ok(None)
+        }
+
+        fn set_entry(&self, _: String, _: Entry) -> Result<(), std::io::Error> {
+            if self.should_fail {
+                Err(std::io::Error::other("Mock Error"))
+            } else {
+                Ok(())
+            }
+        }
+
+        fn list_keys(&self) -> Result<Vec<String>, std::io::Error> {
             unimplemented!()
         }
     }
@@ -48,29 +176,128 @@ mod tests {
     #[test]
     fn generate_handler_with_key_saves_password() {
         let mock_storage = MockStorage { should_fail: false };
-        let key = Some("test_key".to_string());
-        let length = 10;
+        let key = "test_key";
+        let length = Some(10);
 
-        let result = generate_handler(key, length, &mock_storage);
+        let result = generate_handler(
+            GenerateMode::Strong { length },
+            1,
+            None,
+            Some((key, &mock_storage)),
+        );
         assert!(result.is_ok());
     }
 
     #[test]
     fn generate_handler_without_key_generates_password() {
-        let mock_storage = MockStorage { should_fail: false };
-        let length = 10;
+        let length = Some(10);
 
-        let result = generate_handler(None, length, &mock_storage);
+        let result = generate_handler(GenerateMode::Strong { length }, 1, None, None);
         assert!(result.is_ok());
     }
 
     #[test]
     fn generate_handler_storage_failure() {
         let mock_storage = MockStorage { should_fail: true };
-        let key = Some("test_key".to_string());
-        let length = 10;
+        let key = "test_key";
+        let length = Some(10);
+
+        let result = generate_handler(
+            GenerateMode::Strong { length },
+            1,
+            None,
+            Some((key, &mock_storage)),
+        );
+        assert!(result.is_err());
+    }
 
-        let result = generate_handler(key, length, &mock_storage);
+    #[test]
+    fn generate_handler_rejects_too_short_length() {
+        let mode = GenerateMode::Strong { length: Some(2) };
+        let result = generate_handler(mode, 1, None, None);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn generate_handler_with_builtin_profile() {
+        let mode = GenerateMode::Profile {
+            name: "bank".to_string(),
+            length: None,
+        };
+        let result = generate_handler(mode, 1, None, None);
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn generate_handler_rejects_unsatisfiable_profile_length() {
+        let mode = GenerateMode::Profile {
+            name: "default".to_string(),
+            length: Some(2),
+        };
+        let result = generate_handler(mode, 1, None, None);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn generate_handler_unknown_profile() {
+        let mode = GenerateMode::Profile {
+            name: "missing".to_string(),
+            length: None,
+        };
+        let result = generate_handler(mode, 1, None, None);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn generate_handler_with_pattern() {
+        let mode = GenerateMode::Pattern {
+            pattern: "x{4}-x{4}-x{4}".to_string(),
+        };
+        let result = generate_handler(mode, 1, None, None);
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn generate_handler_pronounceable() {
+        let mode = GenerateMode::Pronounceable(PronounceableOptions {
+            syllables: 5,
+            digits: 1,
+            symbols: 1,
+        });
+        let result = generate_handler(mode, 1, None, None);
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn generate_handler_multiple_passwords() {
+        let mode = GenerateMode::Strong { length: None };
+        let result = generate_handler(mode, 5, None, None);
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn generate_handler_multiple_passwords_with_key_fails() {
+        let mock_storage = MockStorage { should_fail: false };
+        let key = "test_key";
+
+        let mode = GenerateMode::Strong { length: None };
+        let result = generate_handler(mode, 2, None, Some((key, &mock_storage)));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn generate_handler_zero_count_fails() {
+        let mode = GenerateMode::Strong { length: None };
+        let result = generate_handler(mode, 0, None, None);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn generate_handler_invalid_pattern() {
+        let mode = GenerateMode::Pattern {
+            pattern: "x{4".to_string(),
+        };
+        let result = generate_handler(mode, 1, None, None);
         assert!(result.is_err());
     }
 }
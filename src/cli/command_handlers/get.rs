@@ -1,22 +1,102 @@
+use crate::cli::clipboard::{copy_secret, ClipboardTool, PendingClear};
+use crate::config::settings::OutputFormat;
+use crate::crypto::secret::Secret;
+use crate::error::PwError;
 use crate::storage::storage_trait::Storage;
+use clap::ValueEnum;
+use std::io;
+use std::time::Duration;
 
-pub fn get_handler(key: &str, storage: &dyn Storage) -> Result<(), Box<dyn std::error::Error>> {
-    match storage.get(key.to_string()) {
-        Ok(Some(value)) => {
-            println!("Value: {}", value);
-            Ok(())
-        }
-        Ok(None) => {
-            println!("Key not found");
-            Ok(())
+/// The part of an entry `pw get` shows or copies.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum EntryField {
+    Password,
+    Username,
+    Url,
+    Notes,
+}
+
+impl EntryField {
+    fn name(&self) -> &'static str {
+        match self {
+            EntryField::Password => "password",
+            EntryField::Username => "username",
+            EntryField::Url => "URL",
+            EntryField::Notes => "notes",
         }
-        Err(e) => Err(Box::new(e)),
     }
 }
 
+/// The value of `field` in the entry under `key`.
+fn lookup(key: &str, field: EntryField, storage: &dyn Storage) -> Result<Secret, PwError> {
+    let not_found = || PwError::NotFound(format!("Key '{}' not found", key));
+    if field == EntryField::Password {
+        return storage.get(key.to_string())?.ok_or_else(not_found);
+    }
+    let entry = storage.get_entry(key.to_string())?.ok_or_else(not_found)?;
+    let value = match field {
+        EntryField::Password => Some(entry.value),
+        EntryField::Username => entry.username.map(Secret::from),
+        EntryField::Url => entry.url.map(Secret::from),
+        EntryField::Notes => entry.notes.map(Secret::from),
+    };
+    value.ok_or_else(|| PwError::NotFound(format!("No {} stored for key '{}'", field.name(), key)))
+}
+
+pub fn get_handler(
+    key: &str,
+    field: EntryField,
+    format: OutputFormat,
+    storage: &dyn Storage,
+) -> Result<(), PwError> {
+    let value = lookup(key, field, storage)?;
+    match format {
+        OutputFormat::Json => println!(
+            "{}",
+            serde_json::json!({ "key": key, "value": value.expose() })
+        ),
+        OutputFormat::Text => println!("Value: {}", value.expose()),
+    }
+    Ok(())
+}
+
+/// Copies a field of the entry to the clipboard instead of printing it, so it does not end
+/// up in the terminal's scrollback. Unless `timeout` is zero, `schedule_clear` arranges for
+/// the clipboard to be restored once it expires.
+pub fn clip_handler(
+    key: &str,
+    field: EntryField,
+    tool: &ClipboardTool,
+    timeout: u64,
+    schedule_clear: impl FnOnce(Duration, &PendingClear) -> io::Result<()>,
+    storage: &dyn Storage,
+) -> Result<(), PwError> {
+    let value = lookup(key, field, storage)?;
+    let pending = copy_secret(tool, value.expose())?;
+    if timeout == 0 {
+        println!(
+            "Copied the {} for '{}' to the clipboard.",
+            field.name(),
+            key
+        );
+        return Ok(());
+    }
+    schedule_clear(Duration::from_secs(timeout), &pending)?;
+    println!(
+        "Copied the {} for '{}' to the clipboard. It will be cleared in {}s.",
+        field.name(),
+        key,
+        timeout
+    );
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::cli::clipboard::tests::file_clipboard;
+    use crate::storage::entry::Entry;
+    use std::cell::Cell;
 
     struct MockStorage {
         should_fail: bool,
@@ -24,22 +104,38 @@ mod tests {
     }
 
     impl Storage for MockStorage {
-        fn get(&self, _: String) -> Result<Option<String>, std::io::Error> {
+        fn get(&self, _: String) -> Result<Option<Secret>, std::io::Error> {
             if self.should_return_none {
                 return Ok(None);
             }
             if self.should_fail {
                 Err(std::io::Error::new(std::io::ErrorKind::Other, "Mock Error"))
             } else {
-                Ok(Some("Mock Value".to_string()))
+                Ok(Some(Secret::from("Mock Value")))
             }
         }
 
-        fn set(&self, _: String, _: String) -> Result<(), std::io::Error> {
+        fn set(&self, _: String, _: Secret) -> Result<(), std::io::Error> {
             unimplemented!()
         }
 
-        fn get_all(&self) -> Result<Option<Vec<String>>, std::io::Error> {
+        fn get_all(&self) -> Result<Option<Vec<(String, Secret)>>, std::io::Error> {
+            unimplemented!()
+        }
+
+        fn get_entry(&self, key: String) -> Result<Option<Entry>, std::io::Error> {
+            Ok(self.get(key)?.map(|value| Entry {
+                value,
+                username: Some("mock-user".to_string()),
+                ..Default::default()
+            }))
+        }
+
+        fn set_entry(&self, _: String, _: Entry) -> Result<(), std::io::Error> {
+            unimplemented!()
+        }
+
+        fn list_keys(&self) -> Result<Vec<String>, std::io::Error> {
             unimplemented!()
         }
     }
@@ -50,7 +146,27 @@ mod tests {
             should_fail: false,
             should_return_none: false,
         };
-        let result = get_handler("test_key", &storage);
+        let result = get_handler(
+            "test_key",
+            EntryField::Password,
+            OutputFormat::Text,
+            &storage,
+        );
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn get_handler_prints_json() {
+        let storage = MockStorage {
+            should_fail: false,
+            should_return_none: false,
+        };
+        let result = get_handler(
+            "test_key",
+            EntryField::Password,
+            OutputFormat::Json,
+            &storage,
+        );
         assert!(result.is_ok());
     }
 
@@ -60,8 +176,13 @@ mod tests {
             should_fail: false,
             should_return_none: true,
         };
-        let result = get_handler("test_key", &storage);
-        assert!(result.is_ok());
+        let result = get_handler(
+            "test_key",
+            EntryField::Password,
+            OutputFormat::Text,
+            &storage,
+        );
+        assert_eq!(result.unwrap_err().exit_code(), 3);
     }
 
     #[test]
@@ -70,7 +191,62 @@ mod tests {
             should_fail: true,
             should_return_none: false,
         };
-        let result = get_handler("test_key", &storage);
+        let result = get_handler(
+            "test_key",
+            EntryField::Password,
+            OutputFormat::Text,
+            &storage,
+        );
         assert!(result.is_err());
     }
+
+    #[test]
+    fn clip_handler_copies_and_schedules_clearing() -> Result<(), Box<dyn std::error::Error>> {
+        let storage = MockStorage {
+            should_fail: false,
+            should_return_none: false,
+        };
+        let (_dir, tool) = file_clipboard();
+        let scheduled = Cell::new(None);
+
+        let schedule = |timeout: Duration, _: &PendingClear| {
+            scheduled.set(Some(timeout));
+            Ok(())
+        };
+        clip_handler(
+            "test_key",
+            EntryField::Password,
+            &tool,
+            45,
+            schedule,
+            &storage,
+        )?;
+        assert_eq!(tool.paste(), Some(b"Mock Value".to_vec()));
+        assert_eq!(scheduled.get(), Some(Duration::from_secs(45)));
+
+        let never = |_: Duration, _: &PendingClear| -> io::Result<()> { unreachable!() };
+        clip_handler("test_key", EntryField::Username, &tool, 0, never, &storage)?;
+        assert_eq!(tool.paste(), Some(b"mock-user".to_vec()));
+        Ok(())
+    }
+
+    #[test]
+    fn clip_handler_leaves_clipboard_for_missing_values() {
+        let (_dir, tool) = file_clipboard();
+        let never = |_: Duration, _: &PendingClear| -> io::Result<()> { unreachable!() };
+        let storage = MockStorage {
+            should_fail: false,
+            should_return_none: false,
+        };
+        let result = clip_handler("test_key", EntryField::Notes, &tool, 45, never, &storage);
+        assert!(matches!(result, Err(PwError::NotFound(_))));
+
+        let storage = MockStorage {
+            should_fail: false,
+            should_return_none: true,
+        };
+        let result = clip_handler("test_key", EntryField::Password, &tool, 45, never, &storage);
+        assert!(matches!(result, Err(PwError::NotFound(_))));
+        assert_eq!(tool.paste(), None);
+    }
 }
@@ -0,0 +1,274 @@
+use crate::{
+    error::PwError,
+    importers::import_format::{read_entries, ImportFormat, ImportedEntry},
+    storage::{
+        entry::{now, Entry, HistoryItem},
+        storage_trait::Storage,
+    },
+};
+use clap::ValueEnum;
+use std::collections::{BTreeMap, HashSet};
+use std::path::Path;
+
+/// What to do when an imported key already exists in the vault, or appears twice in the
+/// imported file.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictPolicy {
+    /// Keep the existing entry and drop the imported one.
+    Skip,
+    /// Replace the existing entry with the imported one.
+    Overwrite,
+    /// Store the imported entry under a free key such as `key-2`.
+    Rename,
+}
+
+/// All entries are written together at the end, in one step where the backend allows, so
+/// a failed import leaves the vault as it was.
+pub fn import_handler(
+    format: ImportFormat,
+    path: &Path,
+    on_conflict: ConflictPolicy,
+    dry_run: bool,
+    storage: &dyn Storage,
+) -> Result<(), PwError> {
+    let imported = read_entries(format, path)?;
+    let total = imported.len();
+    let mut taken: HashSet<String> = storage.list_keys()?.into_iter().collect();
+
+    let mut added = 0;
+    let mut pending: BTreeMap<String, Entry> = BTreeMap::new();
+    let mut overwritten = Vec::new();
+    let mut renamed = Vec::new();
+    let mut skipped = Vec::new();
+    for ImportedEntry { key, entry } in imported {
+        let target = if taken.contains(&key) {
+            match on_conflict {
+                ConflictPolicy::Skip => {
+                    skipped.push(key);
+                    continue;
+                }
+                ConflictPolicy::Overwrite => {
+                    overwritten.push(key.clone());
+                    key
+                }
+                ConflictPolicy::Rename => {
+                    let free = free_key(&key, &taken);
+                    renamed.push(format!("{} -> {}", key, free));
+                    free
+                }
+            }
+        } else {
+            added += 1;
+            key
+        };
+        taken.insert(target.clone());
+        if dry_run {
+            continue;
+        }
+        let existing = match pending.remove(&target) {
+            Some(entry) => Some(entry),
+            None => storage.get_entry(target.clone())?,
+        };
+        let entry = match existing {
+            Some(existing) => overwrite(existing, entry),
+            None => entry,
+        };
+        pending.insert(target, entry);
+    }
+
+    let imported_count = total - skipped.len();
+    if dry_run {
+        println!(
+            "Dry run: {} of {} entries read from {} would be imported, nothing was written.",
+            imported_count,
+            total,
+            path.display()
+        );
+    } else {
+        storage.set_entries(pending.into_iter().collect())?;
+        println!(
+            "Imported {} of {} entries from {}.",
+            imported_count,
+            total,
+            path.display()
+        );
+    }
+    println!("  added: {}", added);
+    for (label, keys) in [
+        ("overwritten", &overwritten),
+        ("renamed", &renamed),
+        ("skipped", &skipped),
+    ] {
+        println!("  {}: {}", label, keys.len());
+        for key in keys {
+            println!("    {}", key);
+        }
+    }
+    Ok(())
+}
+
+/// The imported entry, with the value it replaces and that value's history kept in its
+/// history.
+fn overwrite(existing: Entry, imported: Entry) -> Entry {
+    let mut history = existing.history;
+    if !existing.value.is_empty() && existing.value != imported.value {
+        history.push(HistoryItem {
+            value: existing.value,
+            replaced_at: now(),
+        });
+    }
+    history.extend(imported.history);
+    history.sort_by_key(|item| item.replaced_at);
+    Entry {
+        history,
+        ..imported
+    }
+}
+
+fn free_key(key: &str, taken: &HashSet<String>) -> String {
+    (2..)
+        .map(|n| format!("{}-{}", key, n))
+        .find(|candidate| !taken.contains(candidate))
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::secret::Secret;
+    use std::cell::{Cell, RefCell};
+    use tempfile::tempdir;
+
+    struct MockStorage {
+        entries: RefCell<BTreeMap<String, Entry>>,
+        writes: Cell<usize>,
+    }
+
+    impl MockStorage {
+        fn with_keys(keys: &[&str]) -> Self {
+            let entries = keys
+                .iter()
+                .map(|key| (key.to_string(), Entry::new("existing".to_string())))
+                .collect();
+            Self {
+                entries: RefCell::new(entries),
+                writes: Cell::new(0),
+            }
+        }
+
+        fn value(&self, key: &str) -> Option<String> {
+            self.entries
+                .borrow()
+                .get(key)
+                .map(|e| e.value.expose().to_string())
+        }
+    }
+
+    impl Storage for MockStorage {
+        fn set(&self, _: String, _: Secret) -> Result<(), std::io::Error> {
+            unimplemented!()
+        }
+
+        fn get(&self, _: String) -> Result<Option<Secret>, std::io::Error> {
+            unimplemented!()
+        }
+
+        fn get_all(&self) -> Result<Option<Vec<(String, Secret)>>, std::io::Error> {
+            unimplemented!()
+        }
+
+        fn get_entry(&self, key: String) -> Result<Option<Entry>, std::io::Error> {
+            Ok(self.entries.borrow().get(&key).cloned())
+        }
+
+        fn set_entry(&self, key: String, entry: Entry) -> Result<(), std::io::Error> {
+            self.set_entries(vec![(key, entry)])
+        }
+
+        fn set_entries(&self, entries: Vec<(String, Entry)>) -> Result<(), std::io::Error> {
+            self.writes.set(self.writes.get() + 1);
+            self.entries.borrow_mut().extend(entries);
+            Ok(())
+        }
+
+        fn list_keys(&self) -> Result<Vec<String>, std::io::Error> {
+            Ok(self.entries.borrow().keys().cloned().collect())
+        }
+    }
+
+    const CHROME_CSV: &str = "name,url,username,password,note\n\
+        github.com,https://github.com/,alice,new-github,\n\
+        example.com,https://example.com/,bob,new-example,\n\
+        example.com,https://example.com/,carol,second-example,\n";
+
+    fn run(storage: &MockStorage, on_conflict: ConflictPolicy, dry_run: bool) {
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path().join("chrome.csv");
+        std::fs::write(&path, CHROME_CSV).unwrap();
+        import_handler(ImportFormat::Chrome, &path, on_conflict, dry_run, storage).unwrap();
+    }
+
+    #[test]
+    fn import_handler_skips_conflicts() {
+        let storage = MockStorage::with_keys(&["github.com"]);
+        run(&storage, ConflictPolicy::Skip, false);
+
+        assert_eq!(storage.value("github.com").as_deref(), Some("existing"));
+        assert_eq!(storage.value("example.com").as_deref(), Some("new-example"));
+        assert_eq!(storage.value("example.com-2"), None);
+    }
+
+    #[test]
+    fn import_handler_overwrites_conflicts() {
+        let storage = MockStorage::with_keys(&["github.com"]);
+        run(&storage, ConflictPolicy::Overwrite, false);
+
+        assert_eq!(storage.value("github.com").as_deref(), Some("new-github"));
+        assert_eq!(
+            storage.value("example.com").as_deref(),
+            Some("second-example")
+        );
+        let github = storage.entries.borrow()["github.com"].clone();
+        assert_eq!(github.username.as_deref(), Some("alice"));
+        let history: Vec<&str> = github.history.iter().map(|h| h.value.expose()).collect();
+        assert_eq!(history, vec!["existing"]);
+        let example = storage.entries.borrow()["example.com"].clone();
+        assert_eq!(example.history[0].value, "new-example");
+        assert_eq!(storage.writes.get(), 1);
+    }
+
+    #[test]
+    fn import_handler_renames_conflicts() {
+        let storage = MockStorage::with_keys(&["github.com", "github.com-2"]);
+        run(&storage, ConflictPolicy::Rename, false);
+
+        assert_eq!(storage.value("github.com").as_deref(), Some("existing"));
+        assert_eq!(storage.value("github.com-3").as_deref(), Some("new-github"));
+        assert_eq!(storage.value("example.com").as_deref(), Some("new-example"));
+        assert_eq!(
+            storage.value("example.com-2").as_deref(),
+            Some("second-example")
+        );
+    }
+
+    #[test]
+    fn import_handler_dry_run_writes_nothing() {
+        let storage = MockStorage::with_keys(&[]);
+        run(&storage, ConflictPolicy::Overwrite, true);
+
+        assert!(storage.entries.borrow().is_empty());
+    }
+
+    #[test]
+    fn import_handler_missing_file() {
+        let storage = MockStorage::with_keys(&[]);
+        let result = import_handler(
+            ImportFormat::Bitwarden,
+            Path::new("/nonexistent/export.json"),
+            ConflictPolicy::Skip,
+            false,
+            &storage,
+        );
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,208 @@
+use crate::crypto::kdf::{calibrate, KdfParams};
+use crate::error::PwError;
+use crate::risk_analyzer::strength::{estimate_bits, MIN_MASTER_PASSWORD_BITS};
+use crate::storage::keyfile::Keyfile;
+use crate::storage::vaults::{Backend, Vault};
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+/// Creates the vault with `create`, and with `keyfile`, first generates a new keyfile there
+/// that the vault will need, along with the master password, from now on. With `migrate`,
+/// the vault must already exist, and `create` protects it instead.
+pub fn init_handler(
+    vault: &Vault,
+    keyfile: Option<&Path>,
+    migrate: bool,
+    create: impl FnOnce(Option<&Keyfile>) -> Result<(), PwError>,
+) -> Result<(), PwError> {
+    match (vault.path.exists(), migrate) {
+        (true, false) => {
+            return Err(PwError::Invalid(format!(
+                "{} already exists",
+                vault.path.display()
+            )))
+        }
+        (false, true) => {
+            return Err(PwError::NotFound(format!(
+                "{} does not exist",
+                vault.path.display()
+            )))
+        }
+        _ => {}
+    }
+    let keyfile = match keyfile {
+        Some(path) => Some((path, generate_keyfile(vault, path)?)),
+        None => None,
+    };
+    if let Err(e) = create(keyfile.as_ref().map(|(_, keyfile)| keyfile)) {
+        // A keyfile for a vault that was never created would only cause confusion later.
+        if let Some((path, _)) = keyfile {
+            let _ = fs::remove_file(path);
+        }
+        return Err(e);
+    }
+    println!(
+        "{} {} vault {}",
+        if migrate { "Protected" } else { "Created" },
+        vault.backend.name(),
+        vault.path.display()
+    );
+    if let Some((path, _)) = keyfile {
+        println!(
+            "Created keyfile {}. Keep it apart from the vault, e.g. on a removable drive, and \
+             keep a copy somewhere safe: the vault cannot be opened without it.",
+            path.display()
+        );
+    }
+    Ok(())
+}
+
+/// Refuses a master password that the strength estimate finds weak, unless `allow_weak`.
+pub fn check_master_password(password: &str, allow_weak: bool) -> Result<(), PwError> {
+    let bits = estimate_bits(password);
+    if bits >= MIN_MASTER_PASSWORD_BITS {
+        return Ok(());
+    }
+    if allow_weak {
+        eprintln!(
+            "Warning: the master password is weak, about {:.0} bits of strength",
+            bits
+        );
+        return Ok(());
+    }
+    Err(PwError::Invalid(format!(
+        "The master password is too weak: about {:.0} bits of strength, where {:.0} are \
+         needed. Choose a longer one, e.g. several random words, or give --weak-password.",
+        bits, MIN_MASTER_PASSWORD_BITS
+    )))
+}
+
+/// Benchmarks Argon2id on this machine for parameters that take about `target` to unlock
+/// the vault.
+pub fn calibrate_kdf(target: Duration) -> Result<KdfParams, PwError> {
+    eprintln!(
+        "Calibrating Argon2id for a {} ms unlock...",
+        target.as_millis()
+    );
+    let kdf = calibrate(target)?;
+    println!(
+        "Argon2id: {} MiB of memory, {} iterations, {} lanes",
+        kdf.memory_kib / 1024,
+        kdf.iterations,
+        kdf.parallelism
+    );
+    Ok(kdf)
+}
+
+/// Generates a keyfile for `vault`, for the backends that support one.
+pub fn generate_keyfile(vault: &Vault, path: &Path) -> Result<Keyfile, PwError> {
+    if !matches!(vault.backend, Backend::Json | Backend::Sqlite) {
+        return Err(PwError::Invalid(format!(
+            "Keyfiles are only supported for JSON and SQLite vaults, not {} vaults",
+            vault.backend.name()
+        )));
+    }
+    Ok(Keyfile::generate(path)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::kdf::TEST_KDF_PARAMS;
+    use crate::crypto::secret::Secret;
+    use crate::storage::file_storage::FileStorage;
+    use crate::storage::sqlite_storage::SqliteStorage;
+    use crate::storage::storage_trait::{MasterKey, Storage};
+    use tempfile::tempdir;
+
+    fn open(
+        path: &Path,
+        password: &str,
+        keyfile: Option<&Keyfile>,
+    ) -> Result<FileStorage, PwError> {
+        let path = path.to_string_lossy();
+        Ok(FileStorage::new(&path, password, keyfile, TEST_KDF_PARAMS)?)
+    }
+
+    #[test]
+    fn keyfile_vault_needs_both_factors() -> Result<(), PwError> {
+        let temp_dir = tempdir()?;
+        let vault = Vault {
+            backend: Backend::Json,
+            path: temp_dir.path().join("store.json"),
+        };
+        let keyfile_path = temp_dir.path().join("store.key");
+        init_handler(&vault, Some(&keyfile_path), false, |keyfile| {
+            open(&vault.path, "master", keyfile)?.set("github".to_string(), "hunter2".into())?;
+            Ok(())
+        })?;
+
+        let keyfile = Keyfile::load(&keyfile_path)?;
+        let storage = open(&vault.path, "master", Some(&keyfile))?;
+        assert_eq!(
+            storage.get("github".to_string())?,
+            Some(Secret::from("hunter2"))
+        );
+
+        let message = |result: Result<FileStorage, PwError>| match result {
+            Err(PwError::Auth(message)) => message,
+            _ => panic!("expected an authentication error"),
+        };
+        assert!(message(open(&vault.path, "master", None)).contains("needs its keyfile"));
+        let other = Keyfile::generate(&temp_dir.path().join("other.key"))?;
+        assert!(message(open(&vault.path, "master", Some(&other))).contains("wrong keyfile"));
+        assert!(message(open(&vault.path, "wrong", Some(&keyfile))).contains("master password"));
+
+        let again = init_handler(&vault, None, false, |_| Ok(()));
+        assert!(matches!(again, Err(PwError::Invalid(_))));
+        let missing = Vault {
+            backend: Backend::Json,
+            path: temp_dir.path().join("missing.json"),
+        };
+        let migrated = init_handler(&missing, None, true, |_| Ok(()));
+        assert!(matches!(migrated, Err(PwError::NotFound(_))));
+
+        let failed = Vault {
+            backend: Backend::Json,
+            path: temp_dir.path().join("failed.json"),
+        };
+        let failed_keyfile = temp_dir.path().join("failed.key");
+        let result = init_handler(&failed, Some(&failed_keyfile), false, |_| {
+            Err(PwError::Invalid("no password".to_string()))
+        });
+        assert!(result.is_err());
+        assert!(!failed_keyfile.exists());
+        Ok(())
+    }
+
+    #[test]
+    fn weak_master_passwords_need_confirmation() {
+        assert!(check_master_password("correct horse battery staple", false).is_ok());
+        let error = check_master_password("hunter22", false).unwrap_err();
+        assert!(matches!(error, PwError::Invalid(ref message) if message.contains("too weak")));
+        assert!(check_master_password("hunter22", true).is_ok());
+    }
+
+    #[test]
+    fn keyfile_is_kept_across_password_changes() -> Result<(), PwError> {
+        let temp_dir = tempdir()?;
+        let path = temp_dir.path().join("store.db");
+        let keyfile = Keyfile::generate(&temp_dir.path().join("store.key"))?;
+        let mut storage = SqliteStorage::open(&path, "old", Some(&keyfile), TEST_KDF_PARAMS)?;
+        storage.set("github".to_string(), "hunter2".into())?;
+        storage.change_password("new")?;
+        storage.rekey("new", TEST_KDF_PARAMS)?;
+        drop(storage);
+
+        let without =
+            SqliteStorage::open(&path, "new", None, TEST_KDF_PARAMS).map_err(PwError::from);
+        assert!(matches!(without, Err(PwError::Auth(_))));
+        let storage = SqliteStorage::open(&path, "new", Some(&keyfile), TEST_KDF_PARAMS)?;
+        assert_eq!(
+            storage.get("github".to_string())?,
+            Some(Secret::from("hunter2"))
+        );
+        Ok(())
+    }
+}
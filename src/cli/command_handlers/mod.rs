@@ -1,4 +1,18 @@
 pub(crate) mod analyze;
+pub(crate) mod backup;
+pub(crate) mod config;
+pub(crate) mod derive;
+pub(crate) mod doctor;
+pub(crate) mod export;
 pub(crate) mod generate;
 pub(crate) mod get;
+pub(crate) mod import;
+pub(crate) mod init;
+pub(crate) mod otp;
+pub(crate) mod passwd;
+pub(crate) mod recover;
+pub(crate) mod recovery;
+pub(crate) mod rotate;
 pub(crate) mod set;
+pub(crate) mod vault;
+pub(crate) mod verify;
@@ -0,0 +1,146 @@
+use crate::error::PwError;
+use crate::{otp::otpauth::OtpParams, storage::storage_trait::Storage};
+
+/// Stores OTP parameters, from an `otpauth://` URI or a base32 secret, on the entry under
+/// `key`. The entry is created if it does not exist yet.
+pub fn set_otp_handler(key: &str, secret: &str, storage: &dyn Storage) -> Result<(), PwError> {
+    let params = OtpParams::parse(secret)
+        .map_err(|e| PwError::Invalid(format!("Invalid OTP secret: {}", e)))?;
+    let mut entry = storage.get_entry(key.to_string())?.unwrap_or_default();
+    entry.otp = Some(params);
+    storage.set_entry(key.to_string(), entry)?;
+    println!("OTP secret set for key '{}'", key);
+    Ok(())
+}
+
+/// Prints the current one-time code for `key`. HOTP counters are advanced and saved before
+/// the code is shown, so a code is never handed out twice.
+pub fn otp_handler(key: &str, unix_time: u64, storage: &dyn Storage) -> Result<(), PwError> {
+    let Some(mut entry) = storage.get_entry(key.to_string())? else {
+        return Err(PwError::NotFound(format!("Key '{}' not found", key)));
+    };
+    let Some(params) = entry.otp.as_mut() else {
+        return Err(PwError::NotFound(format!(
+            "No OTP secret stored for key '{}'",
+            key
+        )));
+    };
+
+    let (code, remaining) = params
+        .next_code(unix_time)
+        .map_err(|e| PwError::Invalid(format!("Cannot compute a code for key '{}': {}", key, e)))?;
+    match remaining {
+        Some(seconds) => println!("{} (expires in {}s)", code, seconds),
+        None => {
+            storage.set_entry(key.to_string(), entry)?;
+            println!("{}", code);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::secret::Secret;
+    use crate::otp::otpauth::OtpKind;
+    use crate::storage::entry::Entry;
+    use std::cell::RefCell;
+
+    struct MockStorage {
+        entry: RefCell<Option<Entry>>,
+    }
+
+    impl Storage for MockStorage {
+        fn set(&self, _: String, _: Secret) -> Result<(), std::io::Error> {
+            unimplemented!()
+        }
+
+        fn get(&self, _: String) -> Result<Option<Secret>, std::io::Error> {
+            unimplemented!()
+        }
+
+        fn get_all(&self) -> Result<Option<Vec<(String, Secret)>>, std::io::Error> {
+            unimplemented!()
+        }
+
+        fn get_entry(&self, _: String) -> Result<Option<Entry>, std::io::Error> {
+            Ok(self.entry.borrow().clone())
+        }
+
+        fn set_entry(&self, _: String, entry: Entry) -> Result<(), std::io::Error> {
+            *self.entry.borrow_mut() = Some(entry);
+            Ok(())
+        }
+
+        fn list_keys(&self) -> Result<Vec<String>, std::io::Error> {
+            unimplemented!()
+        }
+    }
+
+    #[test]
+    fn set_otp_handler_keeps_password() {
+        let storage = MockStorage {
+            entry: RefCell::new(Some(Entry::new("password".to_string()))),
+        };
+        let result = set_otp_handler("key", "JBSWY3DPEHPK3PXP", &storage);
+        assert!(result.is_ok());
+
+        let entry = storage.entry.borrow().clone().unwrap();
+        assert_eq!(entry.value, "password");
+        assert_eq!(entry.otp.unwrap().secret, "JBSWY3DPEHPK3PXP");
+    }
+
+    #[test]
+    fn set_otp_handler_rejects_invalid_secret() {
+        let storage = MockStorage {
+            entry: RefCell::new(None),
+        };
+        let result = set_otp_handler("key", "not base32!", &storage);
+        assert!(result.is_err());
+        assert!(storage.entry.borrow().is_none());
+    }
+
+    #[test]
+    fn otp_handler_advances_hotp_counter() {
+        let storage = MockStorage {
+            entry: RefCell::new(None),
+        };
+        set_otp_handler(
+            "key",
+            "otpauth://hotp/alice?secret=JBSWY3DPEHPK3PXP&counter=5",
+            &storage,
+        )
+        .unwrap();
+
+        let result = otp_handler("key", 0, &storage);
+        assert!(result.is_ok());
+        let otp = storage.entry.borrow().clone().unwrap().otp.unwrap();
+        assert_eq!(otp.kind, OtpKind::Hotp { counter: 6 });
+    }
+
+    #[test]
+    fn otp_handler_totp() {
+        let storage = MockStorage {
+            entry: RefCell::new(None),
+        };
+        set_otp_handler("key", "JBSWY3DPEHPK3PXP", &storage).unwrap();
+
+        let result = otp_handler("key", 1_700_000_000, &storage);
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn otp_handler_key_not_found() {
+        let storage = MockStorage {
+            entry: RefCell::new(None),
+        };
+        let result = otp_handler("key", 0, &storage);
+        assert!(matches!(result, Err(PwError::NotFound(_))));
+
+        set_otp_handler("key", "JBSWY3DPEHPK3PXP", &storage).unwrap();
+        storage.entry.borrow_mut().as_mut().unwrap().otp = None;
+        let result = otp_handler("key", 0, &storage);
+        assert!(matches!(result, Err(PwError::NotFound(_))));
+    }
+}
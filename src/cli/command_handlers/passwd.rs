@@ -0,0 +1,160 @@
+use crate::crypto::kdf::KdfParams;
+use crate::error::PwError;
+use crate::storage::storage_trait::MasterKey;
+
+pub fn passwd_handler(vault: &mut dyn MasterKey, new_password: &str) -> Result<(), PwError> {
+    vault.change_password(new_password)?;
+    println!("Changed the master password");
+    Ok(())
+}
+
+/// The Argon2id parameters to rekey with: the vault's current ones, with any of them
+/// given on the command line replaced.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct KdfOverrides {
+    pub memory_kib: Option<u32>,
+    pub iterations: Option<u32>,
+    pub parallelism: Option<u32>,
+}
+
+/// Re-encrypts the vault under a new data key. The master password stays the same; the
+/// key derived from it uses the vault's current Argon2id parameters, changed by
+/// `overrides`, from now on.
+pub fn rekey_handler(
+    vault: &mut dyn MasterKey,
+    password: &str,
+    overrides: KdfOverrides,
+) -> Result<(), PwError> {
+    let current = vault.kdf_params()?;
+    let kdf = KdfParams {
+        memory_kib: overrides.memory_kib.unwrap_or(current.memory_kib),
+        iterations: overrides.iterations.unwrap_or(current.iterations),
+        parallelism: overrides.parallelism.unwrap_or(current.parallelism),
+    };
+    let count = vault.rekey(password, kdf)?;
+    println!("Re-encrypted {} entries under a new data key", count);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::kdf::TEST_KDF_PARAMS;
+    use crate::crypto::secret::Secret;
+    use crate::storage::entry::Entry;
+    use crate::storage::file_storage::FileStorage;
+    use crate::storage::sqlite_storage::SqliteStorage;
+    use crate::storage::storage_trait::Storage;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn passwd_keeps_the_entries_and_retires_the_old_password() -> Result<(), PwError> {
+        let temp_dir = tempdir()?;
+        let path = temp_dir.path().join("store.json");
+        let path = path.to_str().unwrap();
+        let mut storage = FileStorage::new(path, "old", None, TEST_KDF_PARAMS)?;
+        storage.set("github".to_string(), "hunter2".into())?;
+        let entries = |path: &str| -> Result<serde_json::Value, PwError> {
+            let vault: serde_json::Value = serde_json::from_str(&fs::read_to_string(path)?)?;
+            Ok(vault["entries"].clone())
+        };
+        let before = entries(path)?;
+
+        passwd_handler(&mut storage, "new")?;
+        assert_eq!(entries(path)?, before);
+        assert!(matches!(
+            FileStorage::new(path, "old", None, TEST_KDF_PARAMS).map_err(PwError::from),
+            Err(PwError::Auth(_))
+        ));
+        let reopened = FileStorage::new(path, "new", None, TEST_KDF_PARAMS)?;
+        assert_eq!(
+            reopened.get("github".to_string())?,
+            Some(Secret::from("hunter2"))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn rekey_re_encrypts_every_entry() -> Result<(), PwError> {
+        let temp_dir = tempdir()?;
+        let path = temp_dir.path().join("store.json");
+        let path = path.to_str().unwrap();
+        let mut storage = FileStorage::new(path, "master", None, TEST_KDF_PARAMS)?;
+        storage.set("github".to_string(), "hunter2".into())?;
+        storage.set("github".to_string(), "hunter3".into())?;
+        let before = fs::read_to_string(path)?;
+
+        let overrides = KdfOverrides {
+            iterations: Some(2),
+            ..Default::default()
+        };
+        rekey_handler(&mut storage, "master", overrides)?;
+        let after: serde_json::Value = serde_json::from_str(&fs::read_to_string(path)?)?;
+        let before: serde_json::Value = serde_json::from_str(&before)?;
+        assert_ne!(after["entries"]["github"], before["entries"]["github"]);
+        assert_eq!(after["header"]["kdf"]["iterations"], 2);
+        assert_eq!(
+            after["header"]["kdf"]["memory_kib"],
+            TEST_KDF_PARAMS.memory_kib
+        );
+
+        let reopened = FileStorage::new(path, "master", None, TEST_KDF_PARAMS)?;
+        let entry = reopened.get_entry("github".to_string())?.unwrap();
+        assert_eq!(entry.value, "hunter3");
+        assert_eq!(entry.history.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn sqlite_passwd_and_rekey() -> Result<(), PwError> {
+        let temp_dir = tempdir()?;
+        let path = temp_dir.path().join("store.db");
+        let mut storage = SqliteStorage::open(&path, "old", None, TEST_KDF_PARAMS)?;
+        let mut entry = Entry::new("hunter2".to_string());
+        entry.notes = Some("recovery codes".to_string());
+        storage.set_entry("github".to_string(), entry)?;
+        storage.set("github".to_string(), "hunter3".into())?;
+
+        passwd_handler(&mut storage, "new")?;
+        rekey_handler(&mut storage, "new", KdfOverrides::default())?;
+        assert_eq!(
+            storage.get("github".to_string())?,
+            Some(Secret::from("hunter3"))
+        );
+        drop(storage);
+
+        assert!(matches!(
+            SqliteStorage::open(&path, "old", None, TEST_KDF_PARAMS).map_err(PwError::from),
+            Err(PwError::Auth(_))
+        ));
+        let reopened = SqliteStorage::open(&path, "new", None, TEST_KDF_PARAMS)?;
+        let entry = reopened.get_entry("github".to_string())?.unwrap();
+        assert_eq!(entry.value, "hunter3");
+        assert_eq!(entry.history.len(), 1);
+        assert_eq!(entry.notes.as_deref(), Some("recovery codes"));
+        Ok(())
+    }
+
+    #[test]
+    fn plain_rekey_keeps_the_vault_kdf_params() -> Result<(), PwError> {
+        let temp_dir = tempdir()?;
+        let calibrated = KdfParams {
+            memory_kib: 128,
+            iterations: 3,
+            parallelism: 2,
+        };
+        let path = temp_dir.path().join("store.json");
+        let mut storage = FileStorage::new(path.to_str().unwrap(), "master", None, calibrated)?;
+        rekey_handler(&mut storage, "master", KdfOverrides::default())?;
+        assert_eq!(FileStorage::kdf_params(&path)?, Some(calibrated));
+
+        let path = temp_dir.path().join("store.db");
+        let mut storage = SqliteStorage::open(&path, "master", None, calibrated)?;
+        rekey_handler(&mut storage, "master", KdfOverrides::default())?;
+        assert_eq!(storage.kdf_params()?, calibrated);
+        drop(storage);
+        assert_eq!(SqliteStorage::kdf_params(&path)?, Some(calibrated));
+        Ok(())
+    }
+}
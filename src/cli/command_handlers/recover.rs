@@ -0,0 +1,105 @@
+use crate::crypto::kdf::KdfParams;
+use crate::error::PwError;
+use crate::storage::file_storage::FileStorage;
+use crate::storage::keyfile::Keyfile;
+use crate::storage::storage_trait::Storage;
+use std::path::Path;
+
+/// Copies every entry that can still be read from the damaged JSON vault at `path` into a
+/// new vault at `output`, protected by the same master password and keyfile, and reports
+/// what was lost.
+/// The damaged vault is left untouched.
+pub fn recover_handler(
+    path: &Path,
+    output: &Path,
+    password: &str,
+    keyfile: Option<&Keyfile>,
+    new_kdf: KdfParams,
+) -> Result<(), PwError> {
+    if output.exists() {
+        return Err(PwError::Invalid(format!(
+            "{} already exists. Choose another file with --output.",
+            output.display()
+        )));
+    }
+    let salvage = FileStorage::salvage(path, password, keyfile)?;
+    if salvage.entries.is_empty() {
+        return Err(PwError::Corrupt(format!(
+            "No entries could be recovered from {}",
+            path.display()
+        )));
+    }
+
+    let storage = FileStorage::new(&output.to_string_lossy(), password, keyfile, new_kdf)?;
+    for (key, entry) in &salvage.entries {
+        storage.set_entry(key.clone(), entry.clone())?;
+    }
+    println!(
+        "Recovered {} entries into {}",
+        salvage.entries.len(),
+        output.display()
+    );
+    for (key, reason) in &salvage.lost {
+        println!("Lost '{}': {}", key, reason);
+    }
+    if salvage.truncated {
+        match salvage.entries.last() {
+            Some((last, _)) => println!(
+                "{} is cut off after '{}'. Entries stored after it are lost.",
+                path.display(),
+                last
+            ),
+            None => println!("{} is cut off.", path.display()),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::kdf::TEST_KDF_PARAMS;
+    use crate::crypto::secret::Secret;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn recovers_the_entries_before_a_cut() -> Result<(), PwError> {
+        let temp_dir = tempdir()?;
+        let path = temp_dir.path().join("store.json");
+        let storage = FileStorage::new(path.to_str().unwrap(), "master", None, TEST_KDF_PARAMS)?;
+        for key in ["a", "b", "c"] {
+            storage.set(key.to_string(), format!("secret-{}", key).into())?;
+        }
+        let contents = fs::read_to_string(&path)?;
+        let cut = contents.find(r#""c":"#).unwrap() + 10;
+        fs::write(&path, &contents[..cut])?;
+        assert!(FileStorage::new(path.to_str().unwrap(), "master", None, TEST_KDF_PARAMS).is_err());
+
+        let output = temp_dir.path().join("recovered.json");
+        recover_handler(&path, &output, "master", None, TEST_KDF_PARAMS)?;
+        let recovered =
+            FileStorage::new(output.to_str().unwrap(), "master", None, TEST_KDF_PARAMS)?;
+        assert_eq!(recovered.list_keys()?, vec!["a", "b"]);
+        assert_eq!(
+            recovered.get("b".to_string())?,
+            Some(Secret::from("secret-b"))
+        );
+
+        let again = recover_handler(&path, &output, "master", None, TEST_KDF_PARAMS);
+        assert!(matches!(again, Err(PwError::Invalid(_))));
+        Ok(())
+    }
+
+    #[test]
+    fn nothing_to_recover_is_an_error() -> Result<(), PwError> {
+        let temp_dir = tempdir()?;
+        let path = temp_dir.path().join("store.json");
+        fs::write(&path, "garbage")?;
+        let output = temp_dir.path().join("recovered.json");
+        let result = recover_handler(&path, &output, "master", None, TEST_KDF_PARAMS);
+        assert!(matches!(result, Err(PwError::Corrupt(_))));
+        assert!(!output.exists());
+        Ok(())
+    }
+}
@@ -0,0 +1,146 @@
+use crate::crypto::secret::{LockedKey, Secret};
+use crate::error::PwError;
+use crate::storage::recovery_shares::{
+    combine_shares, format_id, split_data_key, vault_id, RecoveryShare,
+};
+use crate::storage::storage_trait::MasterKey;
+use std::io;
+
+pub fn recovery_split_handler(
+    vault: &dyn MasterKey,
+    count: u8,
+    threshold: u8,
+) -> Result<(), PwError> {
+    let data_key = vault.data_key();
+    let shares = split_data_key(data_key.expose(), threshold, count)?;
+    println!(
+        "Recovery shares for vault {}. Any {} of these {} shares unlock the vault without its \
+         master password, so give each one to a different person and keep them offline.",
+        format_id(&vault_id(data_key.expose())),
+        threshold,
+        count
+    );
+    for share in &shares {
+        println!();
+        println!("Share {} of {}:", share.index, count);
+        println!("{}", share.encode().expose());
+    }
+    Ok(())
+}
+
+/// Reads shares with `read_share`, which is given the number of the share to read and
+/// returns `None` when there are no more, until the threshold written in the shares is
+/// reached. Returns the data key they recover.
+pub fn read_shares(
+    mut read_share: impl FnMut(usize) -> io::Result<Option<Secret>>,
+) -> Result<LockedKey, PwError> {
+    let mut shares: Vec<RecoveryShare> = Vec::new();
+    while shares.len()
+        < shares
+            .first()
+            .map_or(1, |share| usize::from(share.threshold))
+    {
+        let number = shares.len() + 1;
+        let Some(text) = read_share(number)? else {
+            break;
+        };
+        if text.expose().trim().is_empty() {
+            continue;
+        }
+        let share = RecoveryShare::decode(text.expose()).map_err(|e| match e {
+            PwError::Invalid(message) => PwError::Invalid(format!("Share {}: {}", number, message)),
+            e => e,
+        })?;
+        shares.push(share);
+    }
+    combine_shares(&shares)
+}
+
+/// Protects a vault opened with recovery shares with a new master password.
+pub fn recovery_combine_handler(
+    vault: &mut dyn MasterKey,
+    new_password: &str,
+) -> Result<(), PwError> {
+    vault.change_password(new_password)?;
+    println!("Unlocked the vault with the recovery shares and set a new master password");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::kdf::TEST_KDF_PARAMS;
+    use crate::storage::file_storage::FileStorage;
+    use crate::storage::sqlite_storage::SqliteStorage;
+    use crate::storage::storage_trait::Storage;
+    use tempfile::tempdir;
+
+    fn encoded_shares(vault: &dyn MasterKey) -> Result<Vec<Secret>, PwError> {
+        let shares = split_data_key(vault.data_key().expose(), 3, 5)?;
+        Ok(shares.iter().map(RecoveryShare::encode).collect())
+    }
+
+    #[test]
+    fn shares_reset_a_forgotten_password() -> Result<(), PwError> {
+        let temp_dir = tempdir()?;
+        let path = temp_dir.path().join("store.json");
+        let path = path.to_str().unwrap();
+        let storage = FileStorage::new(path, "forgotten", None, TEST_KDF_PARAMS)?;
+        storage.set("github".to_string(), "hunter2".into())?;
+        let shares = encoded_shares(&storage)?;
+
+        let mut given = vec![
+            shares[4].clone(),
+            Secret::default(),
+            shares[1].clone(),
+            shares[2].clone(),
+        ]
+        .into_iter();
+        let data_key = read_shares(|_| Ok(given.next()))?;
+        let mut storage = FileStorage::open_with_data_key(path, data_key, None)?;
+        recovery_combine_handler(&mut storage, "new")?;
+
+        let storage = FileStorage::new(path, "new", None, TEST_KDF_PARAMS)?;
+        assert_eq!(
+            storage.get("github".to_string())?,
+            Some(Secret::from("hunter2"))
+        );
+
+        let mut too_few = vec![shares[0].clone(), shares[1].clone()].into_iter();
+        assert!(matches!(
+            read_shares(|_| Ok(too_few.next())),
+            Err(PwError::Invalid(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn shares_of_another_vault_do_not_open_it() -> Result<(), PwError> {
+        let temp_dir = tempdir()?;
+        let path = temp_dir.path().join("store.db");
+        let storage = SqliteStorage::open(&path, "master", None, TEST_KDF_PARAMS)?;
+        storage.set("github".to_string(), "hunter2".into())?;
+        let other_path = temp_dir.path().join("other.db");
+        let other = SqliteStorage::open(&other_path, "master", None, TEST_KDF_PARAMS)?;
+        let shares = encoded_shares(&other)?;
+
+        let mut given = shares.into_iter();
+        let data_key = read_shares(|_| Ok(given.next()))?;
+        let result = SqliteStorage::open_with_data_key(&path, data_key.clone(), None);
+        assert!(matches!(
+            result.map_err(PwError::from),
+            Err(PwError::Auth(_))
+        ));
+        let storage = SqliteStorage::open_with_data_key(&other_path, data_key, None)?;
+        assert_eq!(storage.list_keys()?, Vec::<String>::new());
+        Ok(())
+    }
+
+    #[test]
+    fn bad_share_is_reported_by_number() -> Result<(), PwError> {
+        let mut given = vec![Secret::from("ABCDE-FGHIJ")].into_iter();
+        let error = read_shares(|_| Ok(given.next())).unwrap_err().to_string();
+        assert!(error.starts_with("Share 1:"), "{}", error);
+        Ok(())
+    }
+}
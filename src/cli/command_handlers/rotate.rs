@@ -0,0 +1,254 @@
+use super::generate::{password_source, DEFAULT_LENGTH};
+use crate::{
+    error::PwError,
+    password_generator::{
+        generator::MAX_PASSWORD_LENGTH, mode::GenerateMode, profiles::UserProfiles,
+    },
+    storage::{entry::Entry, storage_trait::Storage},
+};
+
+/// The mode a new password for `entry` is generated with: the one its value was generated
+/// with, or for entries that did not record one, their profile or otherwise a strong
+/// password as long as the current one, but never shorter than the default length.
+fn rotation_mode(entry: &Entry) -> GenerateMode {
+    if let Some(mode) = &entry.generator {
+        return mode.clone();
+    }
+    match &entry.profile {
+        Some(name) => GenerateMode::Profile {
+            name: name.clone(),
+            length: None,
+        },
+        None => GenerateMode::Strong {
+            length: Some(
+                entry
+                    .value
+                    .expose()
+                    .chars()
+                    .count()
+                    .clamp(DEFAULT_LENGTH, MAX_PASSWORD_LENGTH),
+            ),
+        },
+    }
+}
+
+fn describe(mode: &GenerateMode) -> String {
+    match mode {
+        GenerateMode::Strong { .. } => String::new(),
+        GenerateMode::Profile { name, .. } => format!(" (profile: {})", name),
+        GenerateMode::Pattern { pattern } => format!(" (pattern: {})", pattern),
+        GenerateMode::Pronounceable(_) => " (pronounceable)".to_string(),
+    }
+}
+
+/// Regenerates the password of every key starting with `prefix`, with the mode it was
+/// generated with. Entries without a password, such as those holding only a 2FA seed, are
+/// left alone.
+///
+/// All new passwords are generated before anything is written, and they are written in one
+/// step where the backend allows, so an unusable profile or a failed write leaves the vault
+/// untouched.
+pub fn rotate_handler(
+    prefix: &str,
+    dry_run: bool,
+    profiles: Option<&UserProfiles>,
+    storage: &dyn Storage,
+) -> Result<(), PwError> {
+    let keys: Vec<String> = storage
+        .list_keys()?
+        .into_iter()
+        .filter(|key| key.starts_with(prefix))
+        .collect();
+    if keys.is_empty() {
+        println!("No keys start with '{}'.", prefix);
+        return Ok(());
+    }
+
+    let mut rotations = Vec::with_capacity(keys.len());
+    let mut summary = Vec::with_capacity(keys.len());
+    for key in keys {
+        let Some(mut entry) = storage.get_entry(key.clone())? else {
+            continue;
+        };
+        if entry.value.is_empty() {
+            continue;
+        }
+        let mode = rotation_mode(&entry);
+        let password = password_source(&mode, profiles)?().map_err(|e| match e {
+            PwError::Policy(message) => {
+                PwError::Policy(format!("Cannot rotate '{}': {}", key, message))
+            }
+            e => e,
+        })?;
+        summary.push(format!("  {}{}", key, describe(&mode)));
+        entry.replace_value(password);
+        rotations.push((key, entry));
+    }
+
+    if dry_run {
+        println!("Would rotate {} key(s):", rotations.len());
+    } else {
+        storage.set_entries(rotations)?;
+        println!("Rotated {} key(s):", summary.len());
+    }
+    for line in summary {
+        println!("{}", line);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::secret::Secret;
+    use crate::storage::entry::Entry;
+    use std::cell::RefCell;
+    use std::collections::BTreeMap;
+
+    struct MockStorage {
+        entries: RefCell<BTreeMap<String, Entry>>,
+    }
+
+    impl MockStorage {
+        fn with_entries(entries: &[(&str, &str, Option<&str>)]) -> Self {
+            let entries = entries
+                .iter()
+                .map(|(key, value, profile)| {
+                    let mut entry = Entry::new(value.to_string());
+                    entry.profile = profile.map(str::to_string);
+                    (key.to_string(), entry)
+                })
+                .collect();
+            Self {
+                entries: RefCell::new(entries),
+            }
+        }
+
+        fn entry(&self, key: &str) -> Entry {
+            self.entries.borrow()[key].clone()
+        }
+    }
+
+    impl Storage for MockStorage {
+        fn set(&self, _: String, _: Secret) -> Result<(), std::io::Error> {
+            unimplemented!()
+        }
+
+        fn get(&self, _: String) -> Result<Option<Secret>, std::io::Error> {
+            unimplemented!()
+        }
+
+        fn get_all(&self) -> Result<Option<Vec<(String, Secret)>>, std::io::Error> {
+            unimplemented!()
+        }
+
+        fn get_entry(&self, key: String) -> Result<Option<Entry>, std::io::Error> {
+            Ok(self.entries.borrow().get(&key).cloned())
+        }
+
+        fn set_entry(&self, key: String, entry: Entry) -> Result<(), std::io::Error> {
+            self.entries.borrow_mut().insert(key, entry);
+            Ok(())
+        }
+
+        fn list_keys(&self) -> Result<Vec<String>, std::io::Error> {
+            Ok(self.entries.borrow().keys().cloned().collect())
+        }
+    }
+
+    #[test]
+    fn rotate_handler_rotates_matching_keys() {
+        let storage = MockStorage::with_entries(&[
+            ("prod/db", "old-db", None),
+            ("prod/pin", "1234", Some("pin")),
+            ("staging/db", "old-staging", None),
+        ]);
+
+        let result = rotate_handler("prod/", false, None, &storage);
+        assert!(result.is_ok());
+
+        let db = storage.entry("prod/db");
+        assert_ne!(db.value, "old-db");
+        assert_eq!(db.value.expose().len(), DEFAULT_LENGTH);
+        assert_eq!(db.history[0].value, "old-db");
+
+        let pin = storage.entry("prod/pin");
+        assert_eq!(pin.value.expose().len(), 6);
+        assert!(pin.value.expose().chars().all(|c| c.is_ascii_digit()));
+        assert_eq!(pin.history[0].value, "1234");
+
+        assert_eq!(storage.entry("staging/db").value, "old-staging");
+    }
+
+    #[test]
+    fn rotate_handler_keeps_generation_mode() {
+        let storage = MockStorage::with_entries(&[
+            ("prod/long", "old-long", None),
+            ("prod/pin", "12345678", None),
+        ]);
+        for (key, mode) in [
+            ("prod/long", GenerateMode::Strong { length: Some(30) }),
+            (
+                "prod/pin",
+                GenerateMode::Pattern {
+                    pattern: "d{8}".to_string(),
+                },
+            ),
+        ] {
+            storage.entries.borrow_mut().get_mut(key).unwrap().generator = Some(mode);
+        }
+
+        let result = rotate_handler("prod/", false, None, &storage);
+        assert!(result.is_ok());
+
+        let long = storage.entry("prod/long");
+        assert_eq!(long.value.expose().len(), 30);
+        assert_eq!(
+            long.generator,
+            Some(GenerateMode::Strong { length: Some(30) })
+        );
+
+        let pin = storage.entry("prod/pin");
+        assert_ne!(pin.value, "12345678");
+        assert_eq!(pin.value.expose().len(), 8);
+        assert!(pin.value.expose().chars().all(|c| c.is_ascii_digit()));
+    }
+
+    #[test]
+    fn rotate_handler_skips_entries_without_password() {
+        let storage = MockStorage::with_entries(&[("prod/2fa", "", None)]);
+
+        let result = rotate_handler("prod/", false, None, &storage);
+        assert!(result.is_ok());
+        assert_eq!(storage.entry("prod/2fa"), Entry::new(String::new()));
+    }
+
+    #[test]
+    fn rotate_handler_dry_run_changes_nothing() {
+        let storage = MockStorage::with_entries(&[("prod/db", "old-db", None)]);
+
+        let result = rotate_handler("prod/", true, None, &storage);
+        assert!(result.is_ok());
+        assert_eq!(storage.entry("prod/db"), Entry::new("old-db".to_string()));
+    }
+
+    #[test]
+    fn rotate_handler_unknown_profile_changes_nothing() {
+        let storage = MockStorage::with_entries(&[
+            ("prod/a", "old-a", None),
+            ("prod/b", "old-b", Some("missing")),
+        ]);
+
+        let result = rotate_handler("prod/", false, None, &storage);
+        assert!(result.is_err());
+        assert_eq!(storage.entry("prod/a").value, "old-a");
+    }
+
+    #[test]
+    fn rotate_handler_no_matching_keys() {
+        let storage = MockStorage::with_entries(&[("prod/db", "old-db", None)]);
+
+        let result = rotate_handler("dev/", false, None, &storage);
+        assert!(result.is_ok());
+    }
+}
@@ -1,12 +1,10 @@
+use crate::crypto::secret::Secret;
+use crate::error::PwError;
 use crate::storage::storage_trait::Storage;
 
-pub fn set_handler(
-    key: &str,
-    value: &str,
-    storage: &dyn Storage,
-) -> Result<(), Box<dyn std::error::Error>> {
+pub fn set_handler(key: &str, value: &str, storage: &dyn Storage) -> Result<(), PwError> {
     storage
-        .set(key.to_string(), value.to_string())
+        .set(key.to_string(), Secret::from(value))
         .map_err(|e| e.into())
         .map(|_| println!("Key set successfully"))
 }
@@ -14,24 +12,37 @@ pub fn set_handler(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::storage::entry::Entry;
 
     struct MockStorage {
         should_fail: bool,
     }
 
     impl Storage for MockStorage {
-        fn set(&self, _key: String, _value: String) -> Result<(), std::io::Error> {
+        fn set(&self, _key: String, _value: Secret) -> Result<(), std::io::Error> {
             if self.should_fail {
                 Err(std::io::Error::new(std::io::ErrorKind::Other, "Mock Error"))
             } else {
                 Ok(())
             }
         }
-        fn get(&self, _: String) -> Result<Option<String>, std::io::Error> {
+        fn get(&self, _: String) -> Result<Option<Secret>, std::io::Error> {
             unimplemented!()
         }
 
-        fn get_all(&self) -> Result<Option<Vec<String>>, std::io::Error> {
+        fn get_all(&self) -> Result<Option<Vec<(String, Secret)>>, std::io::Error> {
+            unimplemented!()
+        }
+
+        fn get_entry(&self, _: String) -> Result<Option<Entry>, std::io::Error> {
+            unimplemented!()
+        }
+
+        fn set_entry(&self, _: String, _: Entry) -> Result<(), std::io::Error> {
+            unimplemented!()
+        }
+
+        fn list_keys(&self) -> Result<Vec<String>, std::io::Error> {
             unimplemented!()
         }
     }
@@ -0,0 +1,155 @@
+use crate::error::PwError;
+use crate::storage::vaults::{validate_name, Vault, VaultRegistry, DEFAULT_VAULT};
+use std::path::Path;
+
+/// Prints every vault with its backend and location, marking the one used by default.
+pub fn vault_list_handler(registry_path: &Path, builtin: &Vault) -> Result<(), PwError> {
+    let registry = VaultRegistry::load(registry_path)?;
+    let selected = registry.selected(None);
+    let vaults = registry.list(builtin);
+    let width = vaults.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
+    for (name, vault) in vaults {
+        let marker = if name == selected { '*' } else { ' ' };
+        println!(
+            "{} {:width$}  {:6}  {}",
+            marker,
+            name,
+            vault.backend.name(),
+            vault.path.display(),
+            width = width
+        );
+    }
+    Ok(())
+}
+
+/// Registers a new vault. `init` opens the vault before it is registered, so databases are
+/// created (and their master password chosen) right away and a bad path is caught early.
+pub fn vault_create_handler(
+    name: &str,
+    vault: Vault,
+    make_default: bool,
+    registry_path: &Path,
+    init: impl FnOnce(&Vault) -> Result<(), PwError>,
+) -> Result<(), PwError> {
+    validate_name(name)?;
+    let mut registry = VaultRegistry::load(registry_path)?;
+    if registry.vaults.contains_key(name) {
+        return Err(PwError::Invalid(format!("Vault '{}' already exists", name)));
+    }
+    init(&vault)?;
+
+    println!(
+        "Created {} vault '{}' at {}",
+        vault.backend.name(),
+        name,
+        vault.path.display()
+    );
+    registry.vaults.insert(name.to_string(), vault);
+    if make_default {
+        registry.default = Some(name.to_string());
+    }
+    registry.save(registry_path)?;
+    Ok(())
+}
+
+/// Unregisters a vault. Its data is left in place, so removing a vault by mistake loses
+/// nothing.
+pub fn vault_remove_handler(name: &str, registry_path: &Path) -> Result<(), PwError> {
+    let mut registry = VaultRegistry::load(registry_path)?;
+    let Some(vault) = registry.vaults.remove(name) else {
+        if name == DEFAULT_VAULT {
+            return Err(PwError::Invalid(
+                "The built-in default vault cannot be removed".to_string(),
+            ));
+        }
+        return Err(PwError::NotFound(format!("No vault named '{}'", name)));
+    };
+    if registry.default.as_deref() == Some(name) {
+        registry.default = None;
+    }
+    registry.save(registry_path)?;
+    println!(
+        "Removed vault '{}'. Its data was left at {}",
+        name,
+        vault.path.display()
+    );
+    Ok(())
+}
+
+/// Makes `name` the vault used when neither --vault nor PW_STORE is given.
+pub fn vault_default_handler(
+    name: &str,
+    registry_path: &Path,
+    builtin: &Vault,
+) -> Result<(), PwError> {
+    let mut registry = VaultRegistry::load(registry_path)?;
+    registry.resolve(Some(name), builtin)?;
+    registry.default = Some(name.to_string()).filter(|name| name != DEFAULT_VAULT);
+    registry.save(registry_path)?;
+    println!("Default vault set to '{}'", name);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::PwError;
+    use crate::storage::vaults::Backend;
+    use std::path::PathBuf;
+    use tempfile::tempdir;
+
+    fn team_vault() -> Vault {
+        Vault {
+            backend: Backend::Sqlite,
+            path: PathBuf::from("/srv/team.db"),
+        }
+    }
+
+    fn no_init(_: &Vault) -> Result<(), PwError> {
+        Ok(())
+    }
+
+    #[test]
+    fn create_default_and_remove() -> Result<(), PwError> {
+        let temp_dir = tempdir()?;
+        let registry_path = temp_dir.path().join("vaults.json");
+
+        vault_create_handler("team", team_vault(), true, &registry_path, no_init)?;
+        let registry = VaultRegistry::load(&registry_path)?;
+        assert_eq!(registry.default.as_deref(), Some("team"));
+        assert_eq!(registry.vaults.get("team"), Some(&team_vault()));
+        assert!(
+            vault_create_handler("team", team_vault(), false, &registry_path, no_init).is_err()
+        );
+
+        vault_default_handler("default", &registry_path, &team_vault())?;
+        assert_eq!(VaultRegistry::load(&registry_path)?.default, None);
+        vault_default_handler("team", &registry_path, &team_vault())?;
+        assert!(vault_default_handler("missing", &registry_path, &team_vault()).is_err());
+
+        vault_remove_handler("team", &registry_path)?;
+        assert_eq!(
+            VaultRegistry::load(&registry_path)?,
+            VaultRegistry::default()
+        );
+        assert!(vault_remove_handler("team", &registry_path).is_err());
+        assert!(vault_remove_handler(DEFAULT_VAULT, &registry_path).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn failed_init_does_not_register() -> Result<(), PwError> {
+        let temp_dir = tempdir()?;
+        let registry_path = temp_dir.path().join("vaults.json");
+        let failing = |_: &Vault| -> Result<(), PwError> {
+            Err(PwError::Auth("password mismatch".to_string()))
+        };
+
+        assert!(vault_create_handler("team", team_vault(), true, &registry_path, failing).is_err());
+        assert!(
+            vault_create_handler("bad/name", team_vault(), false, &registry_path, no_init).is_err()
+        );
+        assert!(!registry_path.exists());
+        Ok(())
+    }
+}
@@ -0,0 +1,74 @@
+use crate::error::PwError;
+use crate::storage::file_storage::FileStorage;
+use crate::storage::keyfile::Keyfile;
+use crate::storage::storage_trait::Storage;
+
+/// Reads every entry, so any damage the backend can detect is reported. JSON vaults check
+/// their MAC on every read; the database backends authenticate each entry as it is
+/// decrypted. Returns the number of entries.
+pub fn read_every_entry(storage: &dyn Storage) -> Result<usize, PwError> {
+    let keys = storage.list_keys()?;
+    for key in &keys {
+        if storage.get_entry(key.clone())?.is_none() {
+            return Err(PwError::Corrupt(format!(
+                "Key '{}' is listed but cannot be read",
+                key
+            )));
+        }
+    }
+    Ok(keys.len())
+}
+
+pub fn verify_handler(storage: &dyn Storage) -> Result<(), PwError> {
+    let count = read_every_entry(storage)?;
+    println!("Vault is intact: {} entries verified", count);
+    Ok(())
+}
+
+/// Re-seals a JSON vault that failed its integrity check, after the master password is
+/// confirmed. For edits the user made by hand, or has reviewed.
+pub fn accept_changes_handler(
+    path: &str,
+    password: &str,
+    keyfile: Option<&Keyfile>,
+) -> Result<(), PwError> {
+    let count = FileStorage::accept_changes(path, password, keyfile)?;
+    println!(
+        "Accepted the current contents of {}: {} entries",
+        path, count
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::kdf::TEST_KDF_PARAMS;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn tampered_vault_is_refused_until_accepted() -> Result<(), PwError> {
+        let temp_dir = tempdir()?;
+        let path = temp_dir.path().join("passwords.json");
+        let path = path.to_str().unwrap();
+        let storage = FileStorage::new(path, "master", None, TEST_KDF_PARAMS)?;
+        storage.set("github".to_string(), "hunter2".into())?;
+        storage.set("gitlab".to_string(), "hunter3".into())?;
+        verify_handler(&storage)?;
+
+        let mut vault: serde_json::Value = serde_json::from_str(&fs::read_to_string(path)?)?;
+        vault["entries"].as_object_mut().unwrap().remove("gitlab");
+        fs::write(path, vault.to_string())?;
+        assert!(matches!(verify_handler(&storage), Err(PwError::Corrupt(_))));
+        assert!(matches!(
+            accept_changes_handler(path, "wrong", None),
+            Err(PwError::Auth(_))
+        ));
+
+        accept_changes_handler(path, "master", None)?;
+        verify_handler(&storage)?;
+        assert_eq!(storage.list_keys()?, vec!["github"]);
+        Ok(())
+    }
+}
@@ -1,2 +1,4 @@
+pub(crate) mod clipboard;
 pub(crate) mod command_handlers;
 pub(crate) mod parser;
+pub(crate) mod prompt;
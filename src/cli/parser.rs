@@ -1,8 +1,63 @@
+use crate::cli::clipboard::{
+    run_clear_helper, spawn_clear_helper, ClipboardTool, CLEAR_CLIPBOARD_COMMAND,
+};
 use crate::cli::command_handlers::analyze::analyze_handler;
-use crate::cli::command_handlers::get::get_handler;
+use crate::cli::command_handlers::backup::{
+    backup_create_handler, backup_list_handler, backup_restore_handler, take_backup,
+};
+use crate::cli::command_handlers::config::{
+    config_get_handler, config_list_handler, config_set_handler,
+};
+use crate::cli::command_handlers::derive::derive_handler;
+use crate::cli::command_handlers::doctor::doctor_handler;
+use crate::cli::command_handlers::export::{export_handler, ExportOptions};
+use crate::cli::command_handlers::get::{clip_handler, get_handler, EntryField};
+use crate::cli::command_handlers::import::{import_handler, ConflictPolicy};
+use crate::cli::command_handlers::init::{
+    calibrate_kdf, check_master_password, generate_keyfile, init_handler,
+};
+use crate::cli::command_handlers::otp::{otp_handler, set_otp_handler};
+use crate::cli::command_handlers::passwd::{passwd_handler, rekey_handler, KdfOverrides};
+use crate::cli::command_handlers::recover::recover_handler;
+use crate::cli::command_handlers::recovery::{
+    read_shares, recovery_combine_handler, recovery_split_handler,
+};
+use crate::cli::command_handlers::rotate::rotate_handler;
 use crate::cli::command_handlers::set::set_handler;
+use crate::cli::command_handlers::vault::{
+    vault_create_handler, vault_default_handler, vault_list_handler, vault_remove_handler,
+};
+use crate::cli::command_handlers::verify::{accept_changes_handler, verify_handler};
+use crate::cli::prompt::{
+    read_master_password, read_new_secret, read_secret_line, EXPORT_PASSPHRASE_ENV, KEYFILE_ENV,
+    MASTER_PASSWORD_ENV, NEW_MASTER_PASSWORD_ENV,
+};
+use crate::config::settings::{
+    AnalyzerKind, Config, Settings, MAX_UNLOCK_TIME_MS, MIN_UNLOCK_TIME_MS,
+};
+use crate::crypto::kdf::KdfParams;
+use crate::crypto::secret::{LockedKey, Secret};
+use crate::error::PwError;
+use crate::exporters::export_format::ExportFormat;
+use crate::importers::import_format::ImportFormat;
+use crate::password_generator::mode::GenerateMode;
+use crate::password_generator::profiles::UserProfiles;
+use crate::password_generator::pronounceable::PronounceableOptions;
+use crate::risk_analyzer::hibp_risk_analyzer::HIBPRiskAnalyzer;
+use crate::storage::entry::now;
 use crate::storage::file_storage::FileStorage;
+use crate::storage::kdbx::format::{Argon2Kdf, Argon2Variant};
+use crate::storage::kdbx_storage::KdbxStorage;
+use crate::storage::keyfile::Keyfile;
+use crate::storage::pass_storage::{Gpg, PassStorage};
+use crate::storage::private_files::create_private_dir;
+use crate::storage::sqlite_storage::SqliteStorage;
+use crate::storage::storage_trait::{MasterKey, Storage};
+use crate::storage::vaults::{Backend, Vault, VaultRegistry, STORE_ENV};
 use clap::{Parser, Subcommand};
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 use super::command_handlers::generate::generate_handler;
 
@@ -14,12 +69,85 @@ use super::command_handlers::generate::generate_handler;
 #[derive(Parser, Debug)]
 #[command(author = "Eliran Turgeman", version = "1.0.0", about = "A simple CLI for managing passwords.", long_about = None)]
 struct Cli {
+    #[arg(
+        long,
+        global = true,
+        value_name = "FILE",
+        help = "Use this KeePass (KDBX 4) database instead of the pw vault. It is created if it does not exist."
+    )]
+    kdbx: Option<PathBuf>,
+
+    #[arg(
+        long,
+        global = true,
+        conflicts_with = "kdbx",
+        help = "Use the pass(1) password store in PASSWORD_STORE_DIR or ~/.password-store instead of the pw vault."
+    )]
+    pass: bool,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "FILE",
+        conflicts_with_all = ["kdbx", "pass"],
+        help = "Use this SQLite database instead of the pw vault. It is created if it does not exist."
+    )]
+    sqlite: Option<PathBuf>,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "NAME",
+        conflicts_with_all = ["kdbx", "pass", "sqlite"],
+        help = "Use this named vault. Defaults to PW_STORE, then the default vault."
+    )]
+    vault: Option<String>,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "FILE",
+        help = "The keyfile needed, with the master password, to open the vault. Defaults to PW_KEYFILE, then the 'unlock.keyfile' setting."
+    )]
+    keyfile: Option<PathBuf>,
+
     #[command(subcommand)]
     command: Commands,
 }
 
 #[derive(Subcommand, Debug)]
 enum Commands {
+    /// Creates the vault.
+    ///
+    /// With --keyfile, a new keyfile is generated at the given path, and the vault can only
+    /// be opened with both the master password and that file, like a KeePass composite key.
+    /// Keep the keyfile apart from the vault, e.g. on a removable drive. Keyfiles are
+    /// supported for JSON and SQLite vaults.
+    ///
+    /// The master password is asked for twice and checked for strength. Argon2id is then
+    /// calibrated on this machine so that unlocking the vault takes about --unlock-time-ms,
+    /// and the chosen parameters are stored in the vault. 'pw doctor' checks them again.
+    Init {
+        #[arg(
+            long,
+            value_parser = clap::value_parser!(u64).range(MIN_UNLOCK_TIME_MS..=MAX_UNLOCK_TIME_MS),
+            help = "How long unlocking the vault should take, in milliseconds. Defaults to the 'unlock.time_ms' setting, 500."
+        )]
+        unlock_time_ms: Option<u64>,
+
+        #[arg(
+            long,
+            help = "Accept a master password that the strength check finds weak."
+        )]
+        weak_password: bool,
+
+        #[arg(
+            long,
+            help = "Protect an existing JSON vault written by a version of pw before master passwords. Such a vault has no integrity check, so check its contents first."
+        )]
+        migrate: bool,
+    },
+
     /// Sets a new password for a given key.
     ///
     /// This command allows you to securely store a new password under a specified key.
@@ -43,6 +171,22 @@ enum Commands {
     Get {
         #[arg(short, long, help = "The key for which to retrieve the password.")]
         key: String,
+
+        #[arg(
+            short,
+            long,
+            value_enum,
+            default_value_t = EntryField::Password,
+            help = "The part of the entry to show or copy."
+        )]
+        field: EntryField,
+
+        #[arg(
+            short,
+            long,
+            help = "Copy the value to the clipboard instead of printing it. It is cleared after clipboard.timeout seconds (45 by default)."
+        )]
+        clip: bool,
     },
 
     /// Analyzes passwords for potential compromises.
@@ -63,6 +207,9 @@ enum Commands {
     ///
     /// This command generates a strong password of a specified length. The generated password
     /// is displayed but not stored. Use the 'set' command to store it if desired.
+    /// A named profile (built-in or from ~/.pw/profiles.json) can be used to follow a site's
+    /// password rules, a pattern can be used when a fixed format is required, and a
+    /// pronounceable password can be generated when it has to be read out loud.
     Generate {
         #[arg(
             short,
@@ -74,40 +221,1145 @@ enum Commands {
         #[arg(
             short,
             long,
-            default_value_t = 12,
-            help = "The length of the password to generate. Defaults to 12 characters."
+            help = "The length of the password to generate. Defaults to 12 characters, or the profile's length."
+        )]
+        length: Option<usize>,
+
+        #[arg(
+            short,
+            long,
+            help = "The name of a password policy profile to follow, e.g. 'bank' or 'pin'."
+        )]
+        profile: Option<String>,
+
+        #[arg(
+            short,
+            long,
+            conflicts_with = "key",
+            help = "The number of passwords to generate, printed one per line. Defaults to 1."
+        )]
+        count: Option<usize>,
+
+        #[arg(
+            long,
+            conflicts_with_all = ["length", "profile"],
+            help = "Generate from a template, e.g. 'x{4}-x{4}-x{4}'. Placeholders: l (lowercase), u (uppercase), L (letter), d (digit), s (symbol), x (letter or digit), * (any), [abc] (one of); a count in braces such as d{3} repeats, \\ escapes, other characters are literal."
+        )]
+        pattern: Option<String>,
+
+        #[arg(
+            long,
+            conflicts_with_all = ["length", "profile", "pattern"],
+            help = "Generate a pronounceable password of consonant-vowel syllables, e.g. for reading over the phone."
+        )]
+        pronounceable: bool,
+
+        #[arg(
+            long,
+            requires = "pronounceable",
+            default_value_t = 8,
+            help = "The number of syllables in a pronounceable password."
+        )]
+        syllables: usize,
+
+        #[arg(
+            long,
+            requires = "pronounceable",
+            default_value_t = 0,
+            help = "The number of digits to insert into a pronounceable password."
+        )]
+        digits: usize,
+
+        #[arg(
+            long,
+            requires = "pronounceable",
+            default_value_t = 0,
+            help = "The number of symbols to insert into a pronounceable password."
+        )]
+        symbols: usize,
+    },
+
+    /// Regenerates the passwords of every key starting with a prefix.
+    ///
+    /// Each entry is regenerated with the profile it was generated with, and its previous
+    /// password is kept in the entry's history. Only the rotated keys are printed.
+    Rotate {
+        #[arg(help = "Rotate every key starting with this prefix, e.g. 'prod/'.")]
+        prefix: String,
+
+        #[arg(long, help = "Show which keys would be rotated without changing them.")]
+        dry_run: bool,
+    },
+
+    /// Shows the current one-time code for a key, or stores a 2FA secret for it.
+    ///
+    /// Without --secret, this prints the current TOTP code and the seconds until it expires,
+    /// or the next HOTP code. With --secret, the otpauth:// URI or base32 secret is stored on
+    /// the entry next to its password.
+    Otp {
+        #[arg(help = "The key whose one-time code to show or whose 2FA secret to set.")]
+        key: String,
+
+        #[arg(
+            short,
+            long,
+            help = "An otpauth:// URI or base32 secret to store under the key."
+        )]
+        secret: Option<String>,
+    },
+
+    /// Imports entries from another password manager's export.
+    ///
+    /// Passwords, usernames, URLs, notes and 2FA secrets are mapped onto entries, and folders
+    /// or groups become key prefixes such as 'Work/GitHub'. A summary of added, overwritten,
+    /// renamed and skipped keys is printed.
+    Import {
+        #[arg(short, long, value_enum, help = "The format of the export to import.")]
+        from: ImportFormat,
+
+        #[arg(help = "The export file, or the password-store directory for --from pass.")]
+        path: PathBuf,
+
+        #[arg(
+            long,
+            value_enum,
+            default_value_t = ConflictPolicy::Skip,
+            help = "What to do with imported keys that already exist."
+        )]
+        on_conflict: ConflictPolicy,
+
+        #[arg(long, help = "Show what would be imported without changing the vault.")]
+        dry_run: bool,
+    },
+
+    /// Exports entries for use in another password manager or as a backup.
+    ///
+    /// Plaintext exports are only written to a terminal unless --force is given. With
+    /// --encrypt, the export is encrypted under a separate passphrase instead.
+    Export {
+        #[arg(short, long, value_enum, help = "The format to export to.")]
+        format: ExportFormat,
+
+        #[arg(short, long, help = "Only export keys starting with this prefix.")]
+        prefix: Option<String>,
+
+        #[arg(
+            short,
+            long,
+            help = "Write the export to this file instead of standard output."
+        )]
+        output: Option<PathBuf>,
+
+        #[arg(
+            long,
+            help = "Encrypt the export with a passphrase (prompted for, or read from PW_EXPORT_PASSPHRASE)."
+        )]
+        encrypt: bool,
+
+        #[arg(long, help = "Allow writing a plaintext export to a file or pipe.")]
+        force: bool,
+    },
+
+    /// Derives a password for a site from the master password.
+    ///
+    /// The same master password, site, login and counter always produce the same password,
+    /// so nothing is stored and no vault needs to be synced. Increase the counter to change
+    /// the password for a site.
+    Derive {
+        #[arg(short, long, help = "The site the password is for, e.g. example.com.")]
+        site: String,
+
+        #[arg(long, help = "The login or username used on the site.")]
+        login: String,
+
+        #[arg(
+            short,
+            long,
+            default_value_t = 1,
+            help = "Increase to derive a new password for the same site and login."
+        )]
+        counter: u32,
+
+        #[arg(
+            short,
+            long,
+            default_value_t = 16,
+            help = "The length of the derived password, between 4 and 35 characters."
         )]
         length: usize,
     },
+
+    /// Lists, creates and removes named vaults.
+    ///
+    /// Each vault has its own backend and master password, so personal and team secrets can
+    /// be kept apart. Select one with --vault or PW_STORE.
+    Vault {
+        #[command(subcommand)]
+        command: VaultCommands,
+    },
+
+    /// Shows and changes settings in the config file.
+    ///
+    /// The config file is ~/.config/pw/config.toml, or pw/config.toml under XDG_CONFIG_HOME.
+    /// Settings are dotted keys such as 'generator.length'; prefix a key with `vaults.<name>.`
+    /// to override it for one vault.
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommands,
+    },
+
+    /// Checks the setup for problems and suggests how to fix them.
+    ///
+    /// This checks that the vaults and ~/.pw are private to you, that every vault can be read,
+    /// that the config file is valid, that gpg-agent is reachable for pass vaults, and that
+    /// the breach database used by 'analyze' is reachable and up to date. No password is
+    /// needed. The exit status is non-zero if a problem is found.
+    Doctor,
+
+    /// Checks that the vault has not been modified outside pw or damaged.
+    ///
+    /// Every entry is read back. JSON vaults carry a MAC over all keys, values and metadata,
+    /// and pw refuses to use one that fails it. If you edited the file yourself, or have
+    /// reviewed the changes, --accept-changes makes pw accept its current contents.
+    Verify {
+        #[arg(
+            long,
+            help = "Accept the current contents of a JSON vault that fails its integrity check."
+        )]
+        accept_changes: bool,
+    },
+
+    /// Salvages the readable entries of a damaged JSON vault into a new vault.
+    ///
+    /// Use this when a vault file was cut off, for example by a full disk, or partly
+    /// overwritten. Every entry that can still be read and decrypted is copied into a new
+    /// vault with the same master password, and the entries that were lost are listed. The
+    /// damaged file is not changed.
+    Recover {
+        #[arg(help = "The damaged vault file.")]
+        file: PathBuf,
+
+        #[arg(
+            short,
+            long,
+            help = "Where to write the new vault. Defaults to the damaged file's name with '.recovered.json'."
+        )]
+        output: Option<PathBuf>,
+    },
+
+    /// Changes the master password. A keyfile the vault needs stays the same.
+    ///
+    /// Entries are encrypted with a data key that is stored wrapped under the master
+    /// password, so only the wrapped key is rewritten. JSON and SQLite vaults only; change the
+    /// password of a KeePass database in KeePass. The new password can also be given in
+    /// PW_NEW_MASTER_PASSWORD. A weak password is refused, as by 'pw init'.
+    Passwd {
+        #[arg(
+            long,
+            help = "Accept a master password that the strength check finds weak."
+        )]
+        weak_password: bool,
+    },
+
+    /// Re-encrypts every entry under a new data key, optionally with new key derivation
+    /// settings.
+    ///
+    /// Use this if the data key may have been exposed, or to strengthen the Argon2id settings
+    /// of an older vault. The master password stays the same. The vault is rewritten in one
+    /// step, so an interruption leaves it as it was. JSON and SQLite vaults only.
+    Rekey {
+        #[arg(
+            long,
+            help = "Argon2id memory cost in KiB. Defaults to the vault's current setting."
+        )]
+        memory_kib: Option<u32>,
+
+        #[arg(
+            long,
+            help = "Argon2id iterations. Defaults to the vault's current setting."
+        )]
+        iterations: Option<u32>,
+
+        #[arg(
+            long,
+            help = "Argon2id lanes. Defaults to the vault's current setting."
+        )]
+        parallelism: Option<u32>,
+    },
+
+    /// Takes an encrypted backup of the vault, or lists or restores backups.
+    ///
+    /// Backups are copies of the vault file encrypted with the master password, kept in
+    /// `~/.pw/backups/<vault>` (see the 'backup' settings). Set 'backup.auto' to take one
+    /// before every command that changes the vault. Old backups are deleted according to
+    /// 'backup.keep_last' and 'backup.keep_daily'. pass stores are not supported.
+    Backup {
+        #[command(subcommand)]
+        command: Option<BackupCommands>,
+    },
+
+    /// Splits the vault's key into recovery shares, or unlocks the vault with them.
+    ///
+    /// With Shamir's secret sharing, the key the entries are encrypted with is split into
+    /// shares, any threshold of which unlock the vault without its master password. Shares
+    /// carry a checksum and the vault's id, so typos and shares of another vault are caught.
+    /// JSON and SQLite vaults only.
+    Recovery {
+        #[command(subcommand)]
+        command: RecoveryCommands,
+    },
+
+    /// Clears the clipboard after a delay. Started in the background by 'get --clip'.
+    #[command(name = CLEAR_CLIPBOARD_COMMAND, hide = true)]
+    ClearClipboard {
+        #[arg(long)]
+        timeout: u64,
+    },
 }
 
-fn init_storage() -> Result<FileStorage, Box<dyn std::error::Error>> {
-    let storage_path = dirs::home_dir();
-    match storage_path {
-        Some(value) => {
-            let path = format!("{}/.pw/store.json", value.display());
-            Ok(FileStorage::new(&path))
+#[derive(Subcommand, Debug)]
+enum ConfigCommands {
+    /// Lists every setting in effect, including defaults.
+    List,
+
+    /// Prints the value of a setting.
+    Get {
+        #[arg(help = "The setting to show, e.g. 'generator.length'.")]
+        key: String,
+    },
+
+    /// Sets a setting in the config file.
+    Set {
+        #[arg(
+            help = "The setting to change, e.g. 'generator.length' or 'vaults.team.output.format'."
+        )]
+        key: String,
+
+        #[arg(help = "The new value, e.g. '16', 'true' or 'json'.")]
+        value: String,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum RecoveryCommands {
+    /// Prints the vault's key as recovery shares.
+    ///
+    /// Give each share to a different person. Shares stay valid until 'pw rekey'; changing
+    /// the master password does not affect them.
+    Split {
+        #[arg(long, default_value_t = 5, help = "How many shares to print.")]
+        shares: u8,
+
+        #[arg(
+            long,
+            default_value_t = 3,
+            help = "How many shares are needed to unlock the vault."
+        )]
+        threshold: u8,
+    },
+
+    /// Unlocks the vault with recovery shares and sets a new master password.
+    ///
+    /// The shares are prompted for one at a time, or read one per line from standard input.
+    /// A keyfile given with --keyfile is required from then on; without one, the vault no
+    /// longer needs a keyfile. A weak new password is refused, as by 'pw init'.
+    Combine {
+        #[arg(
+            long,
+            help = "Accept a master password that the strength check finds weak."
+        )]
+        weak_password: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum BackupCommands {
+    /// Lists the backups of the vault, oldest first.
+    List,
+
+    /// Replaces the vault with a backup, after checking that the backup is intact.
+    ///
+    /// The backup is decrypted and every entry in it is read back before the vault is
+    /// replaced. The current vault is saved as a new backup first.
+    Restore {
+        #[arg(help = "The backup to restore, as shown by 'pw backup list'.")]
+        id: String,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum VaultCommands {
+    /// Lists every vault, marking the default one with '*'.
+    List,
+
+    /// Creates a vault, or registers an existing database or store under a name.
+    Create {
+        #[arg(help = "The name of the vault, made of letters, digits, '-' and '_'.")]
+        name: String,
+
+        #[arg(
+            short,
+            long,
+            value_enum,
+            default_value_t = Backend::Json,
+            help = "The storage backend of the vault."
+        )]
+        backend: Backend,
+
+        #[arg(
+            long,
+            help = "Where the vault is stored. Defaults to ~/.pw/vaults/<name> with the backend's extension."
+        )]
+        path: Option<PathBuf>,
+
+        #[arg(long, help = "Also make this the default vault.")]
+        default: bool,
+
+        #[arg(
+            long,
+            value_parser = clap::value_parser!(u64).range(MIN_UNLOCK_TIME_MS..=MAX_UNLOCK_TIME_MS),
+            help = "How long unlocking the vault should take, in milliseconds. Defaults to the 'unlock.time_ms' setting, 500."
+        )]
+        unlock_time_ms: Option<u64>,
+
+        #[arg(
+            long,
+            help = "Accept a master password that the strength check finds weak."
+        )]
+        weak_password: bool,
+    },
+
+    /// Removes a vault from the list, leaving its data in place.
+    Remove {
+        #[arg(help = "The name of the vault to remove.")]
+        name: String,
+    },
+
+    /// Sets the vault used when neither --vault nor PW_STORE is given.
+    Default {
+        #[arg(help = "The name of the vault to use by default.")]
+        name: String,
+    },
+}
+
+impl Commands {
+    /// Whether the command changes the vault, so an automatic backup is taken first.
+    fn modifies_vault(&self) -> bool {
+        match self {
+            Commands::Set { .. } => true,
+            Commands::Generate { key, .. } => key.is_some(),
+            Commands::Rotate { dry_run, .. } | Commands::Import { dry_run, .. } => !dry_run,
+            Commands::Otp { secret, .. } => secret.is_some(),
+            Commands::Passwd { .. } | Commands::Rekey { .. } => true,
+            Commands::Recovery {
+                command: RecoveryCommands::Combine { .. },
+            } => true,
+            Commands::Init { migrate, .. } => *migrate,
+            Commands::Verify { accept_changes } => *accept_changes,
+            _ => false,
         }
-        None => {
-            eprintln!("Could not find the home directory");
-            Err(Box::new(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                "Could not find the home directory",
-            )))
+    }
+}
+
+/// The generator `pw generate` runs with its options, falling back to the configured
+/// profile and length.
+fn generate_mode(command: &Commands, settings: &Settings) -> GenerateMode {
+    let Commands::Generate {
+        length,
+        profile,
+        pattern,
+        pronounceable,
+        syllables,
+        digits,
+        symbols,
+        ..
+    } = command
+    else {
+        unreachable!("only pw generate has generator options")
+    };
+    match (profile, pattern) {
+        _ if *pronounceable => GenerateMode::Pronounceable(PronounceableOptions {
+            syllables: *syllables,
+            digits: *digits,
+            symbols: *symbols,
+        }),
+        (_, Some(pattern)) => GenerateMode::Pattern {
+            pattern: pattern.clone(),
+        },
+        (Some(name), None) => GenerateMode::Profile {
+            name: name.clone(),
+            length: *length,
+        },
+        (None, None) => match settings.generator.profile.as_str() {
+            "" => GenerateMode::Strong {
+                length: length.or(Some(settings.generator.length)),
+            },
+            name => GenerateMode::Profile {
+                name: name.to_string(),
+                length: *length,
+            },
+        },
+    }
+}
+
+fn pw_dir() -> Result<PathBuf, PwError> {
+    dirs::home_dir()
+        .map(|home| home.join(".pw"))
+        .ok_or_else(PwError::no_home_dir)
+}
+
+/// Picks the vault from the backend flags, --vault, PW_STORE or the default vault, in that
+/// order. The name is `None` when a backend flag names the vault's location directly.
+fn select_vault(
+    cli: &Cli,
+    pw_dir: &Path,
+    builtin: &Vault,
+) -> Result<(Option<String>, Vault), PwError> {
+    if let Some(path) = &cli.sqlite {
+        let vault = Vault {
+            backend: Backend::Sqlite,
+            path: path.clone(),
+        };
+        return Ok((None, vault));
+    }
+    if cli.pass {
+        let root = PassStorage::default_root().ok_or_else(PwError::no_home_dir)?;
+        let vault = Vault {
+            backend: Backend::Pass,
+            path: root,
+        };
+        return Ok((None, vault));
+    }
+    if let Some(path) = &cli.kdbx {
+        let vault = Vault {
+            backend: Backend::Kdbx,
+            path: path.clone(),
+        };
+        return Ok((None, vault));
+    }
+    let requested = cli.vault.clone().or_else(|| {
+        std::env::var(STORE_ENV)
+            .ok()
+            .filter(|name| !name.is_empty())
+    });
+    let registry = VaultRegistry::load(&pw_dir.join("vaults.json"))?;
+    let vault = registry.resolve(requested.as_deref(), builtin)?;
+    let name = registry.selected(requested.as_deref()).to_string();
+    Ok((Some(name), vault))
+}
+
+/// Asks for the password that opens `vault`. pass stores are unlocked by gpg instead, so
+/// they have none. Only `pw init` and `pw vault create` make new vaults, so a missing vault
+/// is an error rather than a prompt for a new password.
+fn vault_password(vault: &Vault) -> Result<Option<Secret>, PwError> {
+    let path = vault.path.as_path();
+    if vault.backend != Backend::Pass && !path.exists() {
+        return Err(PwError::NotFound(format!(
+            "{} does not exist. Create it with 'pw init', or 'pw vault create' for a named \
+             vault.",
+            path.display()
+        )));
+    }
+    let password = match vault.backend {
+        Backend::Json | Backend::Sqlite => read_master_password("Master password: ")?,
+        Backend::Kdbx => read_master_password("Database password: ")?,
+        Backend::Pass => return Ok(None),
+    };
+    Ok(Some(password))
+}
+
+/// Asks twice for the password of a new vault, refusing a weak one unless `allow_weak`.
+fn new_vault_password(vault: &Vault, allow_weak: bool) -> Result<Option<Secret>, PwError> {
+    let prompt = match vault.backend {
+        Backend::Json | Backend::Sqlite => "New master password: ",
+        Backend::Kdbx => "New database password: ",
+        Backend::Pass => return Ok(None),
+    };
+    let password = read_new_secret(prompt, MASTER_PASSWORD_ENV)?;
+    check_master_password(password.expose(), allow_weak)?;
+    Ok(Some(password))
+}
+
+/// The Argon2id parameters in the header of a JSON or SQLite vault, or the defaults for
+/// vaults without them.
+fn vault_kdf_params(vault: &Vault) -> Result<KdfParams, PwError> {
+    let path = vault.path.as_path();
+    let kdf = match vault.backend {
+        Backend::Json => FileStorage::kdf_params(path)?,
+        Backend::Sqlite if path.exists() => SqliteStorage::kdf_params(path)?,
+        _ => None,
+    };
+    Ok(kdf.unwrap_or_default())
+}
+
+/// Takes the automatic backup, if 'backup.auto' is set and `command` changes the vault. It
+/// is sealed with `password` and the Argon2id parameters of the vault itself.
+fn auto_backup(
+    command: &Commands,
+    vault: &Vault,
+    backup_dir: &Path,
+    password: &str,
+    settings: &Settings,
+) -> Result<(), PwError> {
+    if settings.backup.auto && command.modifies_vault() {
+        let kdf = vault_kdf_params(vault)?;
+        take_backup(vault, backup_dir, password, kdf, now(), &settings.backup)?;
+    }
+    Ok(())
+}
+
+/// Asks twice for the new master password of an existing vault, refusing a weak one
+/// unless `allow_weak`.
+fn new_master_password(allow_weak: bool) -> Result<Secret, PwError> {
+    let password = read_new_secret("New master password: ", NEW_MASTER_PASSWORD_ENV)?;
+    check_master_password(password.expose(), allow_weak)?;
+    Ok(password)
+}
+
+/// Creates `vault` with a new password and Argon2id parameters calibrated to unlock it in
+/// about `target`.
+fn initialize_vault(
+    vault: &Vault,
+    keyfile: Option<&Keyfile>,
+    allow_weak: bool,
+    target: Duration,
+) -> Result<(), PwError> {
+    let Some(password) = new_vault_password(vault, allow_weak)? else {
+        return open_vault(vault, None, keyfile).map(|_| ());
+    };
+    let kdf = calibrate_kdf(target)?;
+    create_vault(vault, password.expose(), keyfile, kdf)
+}
+
+/// The keyfile given with --keyfile, PW_KEYFILE or the 'unlock.keyfile' setting, in that
+/// order. `settings` is `None` for commands that run before the config file is read.
+fn keyfile_path(cli: &Cli, settings: Option<&Settings>) -> Result<Option<PathBuf>, PwError> {
+    if let Some(path) = &cli.keyfile {
+        return Ok(Some(path.clone()));
+    }
+    if let Some(path) = std::env::var_os(KEYFILE_ENV).filter(|path| !path.is_empty()) {
+        return Ok(Some(PathBuf::from(path)));
+    }
+    match settings {
+        Some(settings) => settings.unlock.keyfile_path(),
+        None => Ok(None),
+    }
+}
+
+fn load_keyfile(path: Option<&Path>) -> Result<Option<Keyfile>, PwError> {
+    Ok(path.map(Keyfile::load).transpose()?)
+}
+
+/// Opens the existing `vault` with the password from `vault_password`.
+fn open_vault(
+    vault: &Vault,
+    password: Option<&str>,
+    keyfile: Option<&Keyfile>,
+) -> Result<Box<dyn Storage>, PwError> {
+    let path = vault.path.as_path();
+    let password = password.unwrap_or_default();
+    if keyfile.is_some() && !matches!(vault.backend, Backend::Json | Backend::Sqlite) {
+        return Err(PwError::Invalid(format!(
+            "Keyfiles are only supported for JSON and SQLite vaults, not {} vaults",
+            vault.backend.name()
+        )));
+    }
+    match vault.backend {
+        Backend::Json => Ok(Box::new(FileStorage::new(
+            &path.to_string_lossy(),
+            password,
+            keyfile,
+            KdfParams::default(),
+        )?)),
+        Backend::Sqlite => Ok(Box::new(SqliteStorage::open(
+            path,
+            password,
+            keyfile,
+            KdfParams::default(),
+        )?)),
+        Backend::Pass => Ok(Box::new(PassStorage::new(path, Gpg::default())?)),
+        Backend::Kdbx => Ok(Box::new(KdbxStorage::open(path, password)?)),
+    }
+}
+
+/// Opens an existing JSON or SQLite vault to change its master password or data key.
+fn open_master_key(
+    vault: &Vault,
+    password: &str,
+    keyfile: Option<&Keyfile>,
+) -> Result<Box<dyn MasterKey>, PwError> {
+    let path = vault.path.as_path();
+    match vault.backend {
+        Backend::Json => Ok(Box::new(FileStorage::new(
+            &path.to_string_lossy(),
+            password,
+            keyfile,
+            KdfParams::default(),
+        )?)),
+        Backend::Sqlite => Ok(Box::new(SqliteStorage::open(
+            path,
+            password,
+            keyfile,
+            KdfParams::default(),
+        )?)),
+        Backend::Kdbx | Backend::Pass => Err(PwError::Invalid(format!(
+            "{} vaults manage their own keys; only JSON and SQLite vaults are supported",
+            vault.backend.name()
+        ))),
+    }
+}
+
+/// Creates a new vault protected by `password`, deriving its key with `kdf`. pass stores
+/// have no password and are created by `open_vault`.
+fn create_vault(
+    vault: &Vault,
+    password: &str,
+    keyfile: Option<&Keyfile>,
+    kdf: KdfParams,
+) -> Result<(), PwError> {
+    let path = vault.path.as_path();
+    match vault.backend {
+        Backend::Json => {
+            FileStorage::new(&path.to_string_lossy(), password, keyfile, kdf)?;
+        }
+        Backend::Sqlite => {
+            SqliteStorage::open(path, password, keyfile, kdf)?;
+        }
+        Backend::Kdbx => {
+            let kdf = Argon2Kdf::new(
+                Argon2Variant::Argon2id,
+                kdf.memory_kib,
+                kdf.iterations,
+                kdf.parallelism,
+            );
+            KdbxStorage::create(path, password, kdf)?;
+        }
+        Backend::Pass => {
+            open_vault(vault, None, keyfile)?;
+        }
+    }
+    Ok(())
+}
+
+/// Opens an existing JSON or SQLite vault with a data key recovered from recovery shares.
+fn open_with_data_key(
+    vault: &Vault,
+    data_key: LockedKey,
+    keyfile: Option<&Keyfile>,
+) -> Result<Box<dyn MasterKey>, PwError> {
+    match vault.backend {
+        Backend::Json => Ok(Box::new(FileStorage::open_with_data_key(
+            &vault.path.to_string_lossy(),
+            data_key,
+            keyfile,
+        )?)),
+        Backend::Sqlite => Ok(Box::new(SqliteStorage::open_with_data_key(
+            &vault.path,
+            data_key,
+            keyfile,
+        )?)),
+        Backend::Kdbx | Backend::Pass => Err(PwError::Invalid(format!(
+            "{} vaults manage their own keys; only JSON and SQLite vaults are supported",
+            vault.backend.name()
+        ))),
+    }
+}
+
+fn vault_command(
+    command: VaultCommands,
+    pw_dir: &Path,
+    config: &Config,
+    builtin: &Vault,
+    keyfile: Option<&Path>,
+) -> Result<(), PwError> {
+    let registry_path = pw_dir.join("vaults.json");
+    match command {
+        VaultCommands::List => vault_list_handler(&registry_path, builtin),
+        VaultCommands::Create {
+            name,
+            backend,
+            path,
+            default,
+            unlock_time_ms,
+            weak_password,
+        } => {
+            let path = path.unwrap_or_else(|| Vault::default_path(pw_dir, &name, backend));
+            if let Some(parent) = path.parent() {
+                create_private_dir(parent)?;
+            }
+            let vault = Vault { backend, path };
+            let time_ms = config.settings(Some(&name)).unlock.time_ms;
+            let target = Duration::from_millis(unlock_time_ms.unwrap_or(time_ms));
+            let init = |vault: &Vault| {
+                // An existing vault is only registered, after checking that it opens.
+                if vault.path.exists() {
+                    let keyfile = load_keyfile(keyfile)?;
+                    let password = vault_password(vault)?;
+                    return open_vault(
+                        vault,
+                        password.as_ref().map(Secret::expose),
+                        keyfile.as_ref(),
+                    )
+                    .map(|_| ());
+                }
+                let keyfile = match keyfile {
+                    Some(path) => Some(generate_keyfile(vault, path)?),
+                    None => None,
+                };
+                initialize_vault(vault, keyfile.as_ref(), weak_password, target)
+            };
+            vault_create_handler(&name, vault, default, &registry_path, init)
         }
+        VaultCommands::Remove { name } => vault_remove_handler(&name, &registry_path),
+        VaultCommands::Default { name } => vault_default_handler(&name, &registry_path, builtin),
     }
 }
 
 #[tokio::main]
-pub(crate) async fn main() -> Result<(), Box<dyn std::error::Error>> {
+pub(crate) async fn main() -> Result<(), PwError> {
     let cli = Cli::parse();
-    let storage = init_storage()?;
+    if let Commands::ClearClipboard { timeout } = cli.command {
+        return Ok(run_clear_helper(Duration::from_secs(timeout))?);
+    }
+    let pw_dir = pw_dir()?;
+    let config_path = Config::default_path().ok_or_else(PwError::no_home_dir)?;
+    if let Commands::Doctor = cli.command {
+        return doctor_handler(&pw_dir, &config_path).await;
+    }
+    if let Commands::Recover { file, output } = &cli.command {
+        let output = output
+            .clone()
+            .unwrap_or_else(|| file.with_extension("recovered.json"));
+        let keyfile = load_keyfile(keyfile_path(&cli, None)?.as_deref())?;
+        let password = read_master_password("Master password: ")?;
+        return recover_handler(
+            file,
+            &output,
+            password.expose(),
+            keyfile.as_ref(),
+            KdfParams::default(),
+        );
+    }
+    if let Commands::Config {
+        command: ConfigCommands::Set { key, value },
+    } = &cli.command
+    {
+        return config_set_handler(key, value, &config_path);
+    }
+    let config = Config::load(&config_path)?;
+    let builtin = config.settings(None).storage.default_vault(&pw_dir)?;
+    if let Commands::Vault { command } = cli.command {
+        return vault_command(command, &pw_dir, &config, &builtin, cli.keyfile.as_deref());
+    }
+    let (vault_name, vault) = select_vault(&cli, &pw_dir, &builtin)?;
+    let settings = config.settings(vault_name.as_deref());
+    match &cli.command {
+        Commands::Config {
+            command: ConfigCommands::List,
+        } => return config_list_handler(settings),
+        Commands::Config {
+            command: ConfigCommands::Get { key },
+        } => return config_get_handler(key, settings),
+        _ => {}
+    }
+
+    // Neither needs a vault, so they work on machines without one.
+    let user_profiles = UserProfiles {
+        file_path: Some(pw_dir.join("profiles.json")),
+        configured: settings.generator.profiles.clone(),
+    };
+    match &cli.command {
+        Commands::Derive {
+            site,
+            login,
+            counter,
+            length,
+        } => {
+            let master_password = read_master_password("Master password: ")?;
+            return derive_handler(site, login, *counter, *length, master_password.expose());
+        }
+        Commands::Generate {
+            key: None, count, ..
+        } => {
+            let mode = generate_mode(&cli.command, settings);
+            return generate_handler(mode, count.unwrap_or(1), Some(&user_profiles), None);
+        }
+        _ => {}
+    }
+
+    let backup_label = match &vault_name {
+        Some(name) => name.clone(),
+        None => vault
+            .path
+            .file_name()
+            .map_or("default".to_string(), |name| {
+                name.to_string_lossy().into_owned()
+            }),
+    };
+    let backup_dir = settings.backup.vault_dir(&pw_dir, &backup_label)?;
+    let keyfile_path = keyfile_path(&cli, Some(settings))?;
+    if let Commands::Init {
+        unlock_time_ms,
+        weak_password,
+        migrate,
+    } = cli.command
+    {
+        if migrate && vault.backend != Backend::Json {
+            return Err(PwError::Invalid(format!(
+                "--migrate only applies to JSON vaults, not {} vaults",
+                vault.backend.name()
+            )));
+        }
+        let target = Duration::from_millis(unlock_time_ms.unwrap_or(settings.unlock.time_ms));
+        return init_handler(&vault, keyfile_path.as_deref(), migrate, |keyfile| {
+            if !migrate {
+                return initialize_vault(&vault, keyfile, weak_password, target);
+            }
+            let password = new_vault_password(&vault, weak_password)?.unwrap_or_default();
+            auto_backup(
+                &cli.command,
+                &vault,
+                &backup_dir,
+                password.expose(),
+                settings,
+            )?;
+            let kdf = calibrate_kdf(target)?;
+            FileStorage::migrate(
+                &vault.path.to_string_lossy(),
+                password.expose(),
+                keyfile,
+                kdf,
+            )?;
+            Ok(())
+        });
+    }
+    let keyfile = load_keyfile(keyfile_path.as_deref())?;
+
+    if let Commands::Verify {
+        accept_changes: true,
+    } = cli.command
+    {
+        if vault.backend != Backend::Json {
+            return Err(PwError::Invalid(format!(
+                "--accept-changes only applies to JSON vaults, not {} vaults",
+                vault.backend.name()
+            )));
+        }
+        let password = read_master_password("Master password: ")?;
+        auto_backup(
+            &cli.command,
+            &vault,
+            &backup_dir,
+            password.expose(),
+            settings,
+        )?;
+        return accept_changes_handler(
+            &vault.path.to_string_lossy(),
+            password.expose(),
+            keyfile.as_ref(),
+        );
+    }
+
+    match &cli.command {
+        Commands::Backup {
+            command: Some(BackupCommands::List),
+        } => return backup_list_handler(&backup_dir),
+        Commands::Backup {
+            command: Some(BackupCommands::Restore { id }),
+        } => {
+            let password = match vault.backend {
+                Backend::Kdbx => read_master_password("Database password: ")?,
+                _ => read_master_password("Master password: ")?,
+            };
+            let open = |vault: &Vault| open_vault(vault, Some(password.expose()), keyfile.as_ref());
+            return backup_restore_handler(
+                id,
+                &vault,
+                &backup_dir,
+                password.expose(),
+                KdfParams::default(),
+                now(),
+                open,
+            );
+        }
+        _ => {}
+    }
 
+    if let Commands::Passwd { .. } | Commands::Rekey { .. } | Commands::Recovery { .. } =
+        cli.command
+    {
+        if !vault.path.exists() {
+            return Err(PwError::NotFound(format!(
+                "{} does not exist",
+                vault.path.display()
+            )));
+        }
+        if let Commands::Recovery {
+            command: RecoveryCommands::Combine { weak_password },
+        } = cli.command
+        {
+            let data_key = read_shares(|number| read_secret_line(&format!("Share {}: ", number)))?;
+            let mut master_key = open_with_data_key(&vault, data_key, keyfile.as_ref())?;
+            let new_password = new_master_password(weak_password)?;
+            // The backup is sealed with the new password, as the old one is not known.
+            auto_backup(
+                &cli.command,
+                &vault,
+                &backup_dir,
+                new_password.expose(),
+                settings,
+            )?;
+            return recovery_combine_handler(master_key.as_mut(), new_password.expose());
+        }
+        let password = match vault.backend {
+            Backend::Json | Backend::Sqlite => read_master_password("Master password: ")?,
+            _ => Secret::default(),
+        };
+        let mut master_key = open_master_key(&vault, password.expose(), keyfile.as_ref())?;
+        auto_backup(
+            &cli.command,
+            &vault,
+            &backup_dir,
+            password.expose(),
+            settings,
+        )?;
+        return match cli.command {
+            Commands::Recovery {
+                command: RecoveryCommands::Split { shares, threshold },
+            } => recovery_split_handler(master_key.as_ref(), shares, threshold),
+            Commands::Rekey {
+                memory_kib,
+                iterations,
+                parallelism,
+            } => {
+                let overrides = KdfOverrides {
+                    memory_kib,
+                    iterations,
+                    parallelism,
+                };
+                rekey_handler(master_key.as_mut(), password.expose(), overrides)
+            }
+            Commands::Passwd { weak_password } => {
+                let new_password = new_master_password(weak_password)?;
+                passwd_handler(master_key.as_mut(), new_password.expose())
+            }
+            _ => unreachable!("recovery combine is handled above"),
+        };
+    }
+
+    let password = vault_password(&vault)?;
+    let storage = open_vault(
+        &vault,
+        password.as_ref().map(Secret::expose),
+        keyfile.as_ref(),
+    )?;
+    let storage = storage.as_ref();
+    if let Some(password) = &password {
+        auto_backup(
+            &cli.command,
+            &vault,
+            &backup_dir,
+            password.expose(),
+            settings,
+        )?;
+    }
     match cli.command {
-        Commands::Set { key, value } => set_handler(&key, &value, &storage)?,
-        Commands::Get { key } => get_handler(&key, &storage)?,
-        Commands::Analyze { key } => analyze_handler(key, &storage).await?,
-        Commands::Generate { key, length } => generate_handler(key, length, &storage)?,
+        Commands::Set { key, value } => set_handler(&key, &value, storage)?,
+        Commands::Get { key, field, clip } => {
+            if clip {
+                let tool = ClipboardTool::detect()?;
+                let timeout = settings.clipboard.timeout;
+                clip_handler(&key, field, &tool, timeout, spawn_clear_helper, storage)?
+            } else {
+                get_handler(&key, field, settings.output.format, storage)?
+            }
+        }
+        Commands::Analyze { key } => {
+            let analyzer = match settings.analyzer.kind {
+                AnalyzerKind::Hibp => HIBPRiskAnalyzer::new(&settings.analyzer.hibp_url),
+            };
+            analyze_handler(key, &analyzer, storage).await?
+        }
+        Commands::Generate {
+            key: Some(ref key),
+            count,
+            ..
+        } => {
+            let mode = generate_mode(&cli.command, settings);
+            let save_to = Some((key.as_str(), storage));
+            generate_handler(mode, count.unwrap_or(1), Some(&user_profiles), save_to)?
+        }
+        Commands::Rotate { prefix, dry_run } => {
+            rotate_handler(&prefix, dry_run, Some(&user_profiles), storage)?
+        }
+        Commands::Otp { key, secret } => match secret {
+            Some(secret) => set_otp_handler(&key, &secret, storage)?,
+            None => otp_handler(&key, now(), storage)?,
+        },
+        Commands::Import {
+            from,
+            path,
+            on_conflict,
+            dry_run,
+        } => import_handler(from, &path, on_conflict, dry_run, storage)?,
+        Commands::Export {
+            format,
+            prefix,
+            output,
+            encrypt,
+            force,
+        } => {
+            let passphrase = if encrypt {
+                Some(read_new_secret(
+                    "Export passphrase: ",
+                    EXPORT_PASSPHRASE_ENV,
+                )?)
+            } else {
+                None
+            };
+            let options = ExportOptions {
+                format,
+                prefix: prefix.as_deref(),
+                output: output.as_deref(),
+                force,
+                passphrase: passphrase.as_ref().map(Secret::expose),
+                kdf: KdfParams::default(),
+            };
+            export_handler(options, std::io::stdout().is_terminal(), storage)?
+        }
+        Commands::Verify { .. } => verify_handler(storage)?,
+        Commands::Backup { .. } => {
+            let Some(password) = &password else {
+                return Err(PwError::Invalid(
+                    "pass stores are backed up with 'pass git', not pw backup".to_string(),
+                ));
+            };
+            let kdf = KdfParams::default();
+            backup_create_handler(
+                &vault,
+                &backup_dir,
+                password.expose(),
+                kdf,
+                now(),
+                &settings.backup,
+            )?
+        }
+        Commands::Init { .. }
+        | Commands::Derive { .. }
+        | Commands::Generate { key: None, .. }
+        | Commands::Vault { .. }
+        | Commands::Config { .. }
+        | Commands::Doctor
+        | Commands::Recover { .. }
+        | Commands::Passwd { .. }
+        | Commands::Rekey { .. }
+        | Commands::Recovery { .. }
+        | Commands::ClearClipboard { .. } => {
+            unreachable!(
+                "init, derive, keyless generate, vault, config, doctor, recover, key and \
+                 clipboard commands are handled above"
+            )
+        }
     }
     Ok(())
 }
@@ -0,0 +1,82 @@
+use crate::crypto::secret::Secret;
+use std::env;
+use std::io::{self, BufRead, IsTerminal};
+
+/// Scripts and tests can supply the master password through this variable instead of the
+/// interactive prompt.
+pub const MASTER_PASSWORD_ENV: &str = "PW_MASTER_PASSWORD";
+/// The keyfile of vaults that need one, when --keyfile is not given.
+pub const KEYFILE_ENV: &str = "PW_KEYFILE";
+/// Like `MASTER_PASSWORD_ENV`, for the new master password set by `pw passwd`.
+pub const NEW_MASTER_PASSWORD_ENV: &str = "PW_NEW_MASTER_PASSWORD";
+/// Like `MASTER_PASSWORD_ENV`, for the passphrase protecting an encrypted export.
+pub const EXPORT_PASSPHRASE_ENV: &str = "PW_EXPORT_PASSPHRASE";
+
+pub fn read_master_password(prompt: &str) -> io::Result<Secret> {
+    let password = read_env_or_prompt(MASTER_PASSWORD_ENV, prompt)?;
+    if password.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "The master password must not be empty",
+        ));
+    }
+    Ok(password)
+}
+
+/// Reads an existing secret, such as the passphrase of an encrypted export. `env_var` takes
+/// precedence over the prompt.
+pub fn read_secret(prompt: &str, env_var: &str) -> io::Result<Secret> {
+    let secret = read_env_or_prompt(env_var, prompt)?;
+    if secret.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "The passphrase must not be empty",
+        ));
+    }
+    Ok(secret)
+}
+
+/// Reads a new secret, asking twice so a typo does not lock the user out. `env_var` takes
+/// precedence over the prompt.
+pub fn read_new_secret(prompt: &str, env_var: &str) -> io::Result<Secret> {
+    if let Ok(secret) = env::var(env_var) {
+        return Ok(secret.into());
+    }
+    let secret = Secret::from(rpassword::prompt_password(prompt)?);
+    if secret.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "The passphrase must not be empty",
+        ));
+    }
+    let confirmation = Secret::from(rpassword::prompt_password("Repeat to confirm: ")?);
+    if secret != confirmation {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "The passphrases do not match",
+        ));
+    }
+    Ok(secret)
+}
+
+/// Reads one secret: from a hidden prompt on a terminal, or otherwise a line of standard
+/// input, returning `None` at the end of the input.
+pub fn read_secret_line(prompt: &str) -> io::Result<Option<Secret>> {
+    let stdin = io::stdin();
+    if stdin.is_terminal() {
+        return rpassword::prompt_password(prompt).map(|line| Some(line.into()));
+    }
+    let mut line = String::new();
+    if stdin.lock().read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    Ok(Some(line.into()))
+}
+
+/// `env_var` if it is set, or else what is typed at a hidden prompt.
+fn read_env_or_prompt(env_var: &str, prompt: &str) -> io::Result<Secret> {
+    match env::var(env_var) {
+        Ok(secret) => Ok(secret.into()),
+        Err(_) => Ok(rpassword::prompt_password(prompt)?.into()),
+    }
+}
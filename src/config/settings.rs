@@ -0,0 +1,553 @@
+use crate::cli::command_handlers::generate::DEFAULT_LENGTH;
+use crate::error::PwError;
+use crate::password_generator::generator::{MAX_PASSWORD_LENGTH, MIN_PASSWORD_LENGTH};
+use crate::password_generator::policy::PasswordPolicy;
+use crate::risk_analyzer::hibp_risk_analyzer::HIBP_API_URL;
+use crate::storage::pass_storage::PassStorage;
+use crate::storage::vaults::{Backend, Vault};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Bounds for `unlock.time_ms`: faster than this is too weak, slower too annoying.
+pub const MIN_UNLOCK_TIME_MS: u64 = 100;
+pub const MAX_UNLOCK_TIME_MS: u64 = 10_000;
+
+/// Every setting, with its default. The config file may set any of them, globally or in a
+/// `[vaults.<name>]` section that overrides them for one vault.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+    pub storage: StorageSettings,
+    pub generator: GeneratorSettings,
+    pub analyzer: AnalyzerSettings,
+    pub clipboard: ClipboardSettings,
+    pub output: OutputSettings,
+    pub backup: BackupSettings,
+    pub unlock: UnlockSettings,
+}
+
+/// Where the built-in default vault is kept. Named vaults are set up with `pw vault create`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct StorageSettings {
+    pub backend: Backend,
+    /// Empty means the backend's usual location under `~/.pw`.
+    pub path: String,
+}
+
+impl Default for StorageSettings {
+    fn default() -> Self {
+        Self {
+            backend: Backend::Json,
+            path: String::new(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct GeneratorSettings {
+    /// The length of `pw generate` passwords when neither --length nor a profile is given.
+    pub length: usize,
+    /// The profile `pw generate` follows when no other mode is chosen. Empty means none.
+    pub profile: String,
+    /// Profiles defined in the config file, taking precedence over `~/.pw/profiles.json`.
+    pub profiles: BTreeMap<String, PasswordPolicy>,
+}
+
+impl Default for GeneratorSettings {
+    fn default() -> Self {
+        Self {
+            length: DEFAULT_LENGTH,
+            profile: String::new(),
+            profiles: BTreeMap::new(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum AnalyzerKind {
+    /// The "Have I Been Pwned" range API.
+    Hibp,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct AnalyzerSettings {
+    pub kind: AnalyzerKind,
+    /// The range API to query, e.g. a self-hosted mirror.
+    pub hibp_url: String,
+}
+
+impl Default for AnalyzerSettings {
+    fn default() -> Self {
+        Self {
+            kind: AnalyzerKind::Hibp,
+            hibp_url: HIBP_API_URL.to_string(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ClipboardSettings {
+    /// Seconds before a copied password is cleared from the clipboard. 0 never clears it.
+    pub timeout: u64,
+}
+
+impl Default for ClipboardSettings {
+    fn default() -> Self {
+        Self { timeout: 45 }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct OutputSettings {
+    pub format: OutputFormat,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct BackupSettings {
+    /// Where backups are kept, in a directory per vault. Empty means `~/.pw/backups`.
+    pub dir: String,
+    /// Whether to take a backup before every command that changes the vault.
+    pub auto: bool,
+    /// How many of the most recent backups to keep.
+    pub keep_last: usize,
+    /// For how many days to also keep the last backup of each day. 0 keeps none.
+    pub keep_daily: u64,
+}
+
+impl Default for BackupSettings {
+    fn default() -> Self {
+        Self {
+            dir: String::new(),
+            auto: false,
+            keep_last: 10,
+            keep_daily: 30,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct UnlockSettings {
+    /// The keyfile needed along with the master password, usually set for one vault in
+    /// `[vaults.<name>.unlock]`. Empty means none.
+    pub keyfile: String,
+    /// How long deriving the key from the master password should take, in milliseconds.
+    /// `pw init` calibrates Argon2id to it and `pw doctor` checks vaults against it.
+    pub time_ms: u64,
+}
+
+impl Default for UnlockSettings {
+    fn default() -> Self {
+        Self {
+            keyfile: String::new(),
+            time_ms: 500,
+        }
+    }
+}
+
+/// A config file that could not be used. `key` is the dotted path of the offending
+/// setting, when the problem is with a single setting.
+#[derive(Debug, PartialEq)]
+pub struct ConfigError {
+    pub file: Option<PathBuf>,
+    pub key: Option<String>,
+    pub message: String,
+}
+
+impl ConfigError {
+    fn at(key: &str, message: impl Into<String>) -> Self {
+        Self {
+            file: None,
+            key: Some(key.to_string()).filter(|key| !key.is_empty()),
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(file) = &self.file {
+            write!(f, "{}: ", file.display())?;
+        }
+        if let Some(key) = &self.key {
+            write!(f, "{}: ", key)?;
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// A parsed and validated config file.
+#[derive(Debug, PartialEq, Default)]
+pub struct Config {
+    settings: Settings,
+    vaults: BTreeMap<String, Settings>,
+}
+
+impl Config {
+    /// `$XDG_CONFIG_HOME/pw/config.toml`, or `~/.config/pw/config.toml`.
+    pub fn default_path() -> Option<PathBuf> {
+        let config_home = std::env::var_os("XDG_CONFIG_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| dirs::home_dir().map(|home| home.join(".config")))?;
+        Some(config_home.join("pw").join("config.toml"))
+    }
+
+    /// Reads the config file. A missing file means every setting has its default.
+    pub fn load(file_path: &Path) -> Result<Self, ConfigError> {
+        let contents = match fs::read_to_string(file_path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => {
+                return Err(ConfigError {
+                    file: Some(file_path.to_path_buf()),
+                    key: None,
+                    message: e.to_string(),
+                })
+            }
+        };
+        Self::parse(&contents).map_err(|e| ConfigError {
+            file: Some(file_path.to_path_buf()),
+            ..e
+        })
+    }
+
+    pub fn parse(contents: &str) -> Result<Self, ConfigError> {
+        let mut table: toml::Table = contents
+            .parse()
+            .map_err(|e: toml::de::Error| ConfigError::at("", e.to_string().trim_end()))?;
+
+        let overrides = match table.remove("vaults") {
+            None => toml::Table::new(),
+            Some(toml::Value::Table(overrides)) => overrides,
+            Some(_) => return Err(ConfigError::at("vaults", "expected a table of vaults")),
+        };
+        let settings = deserialize_settings(table.clone(), "")?;
+        let mut vaults = BTreeMap::new();
+        for (name, section) in overrides {
+            let prefix = format!("vaults.{}", name);
+            let toml::Value::Table(section) = section else {
+                return Err(ConfigError::at(&prefix, "expected a table"));
+            };
+            if section.contains_key("storage") {
+                return Err(ConfigError::at(
+                    &format!("{}.storage", prefix),
+                    "the storage of a named vault is set with 'pw vault create'",
+                ));
+            }
+            let mut merged = table.clone();
+            merge(&mut merged, section);
+            vaults.insert(name, deserialize_settings(merged, &prefix)?);
+        }
+
+        Ok(Self { settings, vaults })
+    }
+
+    /// The settings in effect for `vault`, or the global settings for `None`.
+    pub fn settings(&self, vault: Option<&str>) -> &Settings {
+        vault
+            .and_then(|name| self.vaults.get(name))
+            .unwrap_or(&self.settings)
+    }
+}
+
+/// Deep-merges `overrides` into `base`, so a vault section only needs the keys it changes.
+fn merge(base: &mut toml::Table, overrides: toml::Table) {
+    for (key, value) in overrides {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base)), toml::Value::Table(value)) => merge(base, value),
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+fn join_key(prefix: &str, key: &str) -> String {
+    match (prefix.is_empty(), key.is_empty() || key == ".") {
+        (_, true) => prefix.to_string(),
+        (true, false) => key.to_string(),
+        (false, false) => format!("{}.{}", prefix, key),
+    }
+}
+
+fn deserialize_settings(table: toml::Table, prefix: &str) -> Result<Settings, ConfigError> {
+    let settings: Settings =
+        serde_path_to_error::deserialize(toml::Value::Table(table)).map_err(|e| {
+            ConfigError::at(
+                &join_key(prefix, &e.path().to_string()),
+                e.inner().to_string(),
+            )
+        })?;
+    settings
+        .validate()
+        .map_err(|(key, message)| ConfigError::at(&join_key(prefix, &key), message))?;
+    Ok(settings)
+}
+
+impl Settings {
+    /// Checks what the types alone cannot, returning the offending key and the problem.
+    fn validate(&self) -> Result<(), (String, String)> {
+        let length = self.generator.length;
+        if !(MIN_PASSWORD_LENGTH..=MAX_PASSWORD_LENGTH).contains(&length) {
+            return Err((
+                "generator.length".to_string(),
+                format!(
+                    "must be between {} and {}, got {}",
+                    MIN_PASSWORD_LENGTH, MAX_PASSWORD_LENGTH, length
+                ),
+            ));
+        }
+        for (name, policy) in &self.generator.profiles {
+            policy.validate().map_err(|e| {
+                (
+                    format!("generator.profiles.{}", name),
+                    format!("profile is not satisfiable: {}", e),
+                )
+            })?;
+        }
+        if !(MIN_UNLOCK_TIME_MS..=MAX_UNLOCK_TIME_MS).contains(&self.unlock.time_ms) {
+            return Err((
+                "unlock.time_ms".to_string(),
+                format!(
+                    "must be between {} and {}, got {}",
+                    MIN_UNLOCK_TIME_MS, MAX_UNLOCK_TIME_MS, self.unlock.time_ms
+                ),
+            ));
+        }
+        if self.backup.keep_last == 0 {
+            return Err((
+                "backup.keep_last".to_string(),
+                "must be at least 1".to_string(),
+            ));
+        }
+        match url::Url::parse(&self.analyzer.hibp_url) {
+            Ok(url) if matches!(url.scheme(), "http" | "https") => {}
+            _ => {
+                return Err((
+                    "analyzer.hibp_url".to_string(),
+                    format!("'{}' is not an http(s) URL", self.analyzer.hibp_url),
+                ))
+            }
+        }
+        Ok(())
+    }
+
+    /// Every setting as a dotted key and its value, sorted by key.
+    pub fn entries(&self) -> Vec<(String, toml::Value)> {
+        let mut entries = Vec::new();
+        if let Ok(value) = toml::Value::try_from(self) {
+            flatten("", value, &mut entries);
+        }
+        entries
+    }
+
+    /// Looks up a dotted key. Tables are returned whole.
+    pub fn get(&self, key: &str) -> Option<toml::Value> {
+        let mut value = toml::Value::try_from(self).ok()?;
+        for segment in key.split('.') {
+            value = match value {
+                toml::Value::Table(mut table) => table.remove(segment)?,
+                _ => return None,
+            };
+        }
+        Some(value)
+    }
+}
+
+fn flatten(prefix: &str, value: toml::Value, entries: &mut Vec<(String, toml::Value)>) {
+    match value {
+        toml::Value::Table(table) => {
+            for (key, value) in table {
+                flatten(&join_key(prefix, &key), value, entries);
+            }
+        }
+        value => entries.push((prefix.to_string(), value)),
+    }
+}
+
+impl StorageSettings {
+    /// The built-in default vault: `~/.pw/store.json` unless the config says otherwise.
+    pub fn default_vault(&self, pw_dir: &Path) -> Result<Vault, PwError> {
+        let path = if self.path.is_empty() {
+            match self.backend {
+                Backend::Json => pw_dir.join("store.json"),
+                Backend::Sqlite => pw_dir.join("store.db"),
+                Backend::Kdbx => pw_dir.join("store.kdbx"),
+                Backend::Pass => PassStorage::default_root().ok_or_else(PwError::no_home_dir)?,
+            }
+        } else {
+            expand_home(&self.path)?
+        };
+        Ok(Vault {
+            backend: self.backend,
+            path,
+        })
+    }
+}
+
+impl BackupSettings {
+    /// The directory holding the backups of the vault called `label`.
+    pub fn vault_dir(&self, pw_dir: &Path, label: &str) -> Result<PathBuf, PwError> {
+        let dir = if self.dir.is_empty() {
+            pw_dir.join("backups")
+        } else {
+            expand_home(&self.dir)?
+        };
+        Ok(dir.join(label))
+    }
+}
+
+impl UnlockSettings {
+    pub fn keyfile_path(&self) -> Result<Option<PathBuf>, PwError> {
+        if self.keyfile.is_empty() {
+            return Ok(None);
+        }
+        expand_home(&self.keyfile).map(Some)
+    }
+}
+
+/// Resolves a leading `~/` in a configured path to the home directory.
+fn expand_home(path: &str) -> Result<PathBuf, PwError> {
+    match path.strip_prefix("~/") {
+        Some(rest) => Ok(dirs::home_dir()
+            .ok_or_else(PwError::no_home_dir)?
+            .join(rest)),
+        None => Ok(PathBuf::from(path)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_settings_use_defaults() {
+        let config = Config::parse("").unwrap();
+        assert_eq!(config.settings(None), &Settings::default());
+        assert_eq!(config.settings(None).generator.length, DEFAULT_LENGTH);
+    }
+
+    #[test]
+    fn vault_sections_override_global_settings() {
+        let config = Config::parse(
+            r#"
+            [generator]
+            length = 20
+            profile = "bank"
+
+            [vaults.team.generator]
+            length = 32
+
+            [vaults.team.output]
+            format = "json"
+
+            [vaults.team.unlock]
+            keyfile = "/media/usb/team.key"
+            "#,
+        )
+        .unwrap();
+
+        let global = config.settings(None);
+        assert_eq!(global.generator.length, 20);
+        assert_eq!(global.output.format, OutputFormat::Text);
+
+        let team = config.settings(Some("team"));
+        assert_eq!(team.generator.length, 32);
+        assert_eq!(team.generator.profile, "bank");
+        assert_eq!(team.output.format, OutputFormat::Json);
+        assert_eq!(
+            team.unlock.keyfile_path().unwrap(),
+            Some(PathBuf::from("/media/usb/team.key"))
+        );
+        assert_eq!(global.unlock.keyfile_path().unwrap(), None);
+        assert_eq!(config.settings(Some("other")), global);
+    }
+
+    #[test]
+    fn errors_point_at_the_bad_key() {
+        let cases = [
+            ("[generator]\nlength = \"long\"", "generator.length"),
+            ("[generator]\nlength = 2", "generator.length"),
+            ("[generator]\nlenght = 16", "generator.lenght"),
+            ("[output]\nformat = \"xml\"", "output.format"),
+            ("[analyzer]\nhibp_url = \"ftp://x\"", "analyzer.hibp_url"),
+            ("[backup]\nkeep_last = 0", "backup.keep_last"),
+            ("[unlock]\ntime_ms = 10", "unlock.time_ms"),
+            (
+                "[generator.profiles.tiny]\nlength = 2",
+                "generator.profiles.tiny",
+            ),
+            (
+                "[vaults.team.clipboard]\ntimeout = -1",
+                "vaults.team.clipboard.timeout",
+            ),
+            (
+                "[vaults.team.storage]\nbackend = \"pass\"",
+                "vaults.team.storage",
+            ),
+        ];
+        for (contents, key) in cases {
+            let error = Config::parse(contents).unwrap_err();
+            assert_eq!(error.key.as_deref(), Some(key), "{}", contents);
+        }
+
+        let error = Config::parse("[generator\n").unwrap_err();
+        assert_eq!(error.key, None);
+    }
+
+    #[test]
+    fn entries_and_get_use_dotted_keys() {
+        let config = Config::parse("[clipboard]\ntimeout = 10").unwrap();
+        let settings = config.settings(None);
+        assert_eq!(
+            settings.get("clipboard.timeout"),
+            Some(toml::Value::Integer(10))
+        );
+        assert_eq!(settings.get("clipboard.nope"), None);
+
+        let keys: Vec<String> = settings.entries().into_iter().map(|(k, _)| k).collect();
+        assert!(keys.contains(&"generator.length".to_string()));
+        assert!(keys.contains(&"storage.backend".to_string()));
+    }
+
+    #[test]
+    fn default_vault_follows_storage_settings() {
+        let pw_dir = Path::new("/home/user/.pw");
+        let default = StorageSettings::default().default_vault(pw_dir).unwrap();
+        assert_eq!(default.path, pw_dir.join("store.json"));
+
+        let sqlite = StorageSettings {
+            backend: Backend::Sqlite,
+            path: "/srv/pw.db".to_string(),
+        };
+        assert_eq!(
+            sqlite.default_vault(pw_dir).unwrap(),
+            Vault {
+                backend: Backend::Sqlite,
+                path: PathBuf::from("/srv/pw.db"),
+            }
+        );
+    }
+}
@@ -0,0 +1,181 @@
+use super::kdf::{derive_key, CryptoError, KdfParams, KEY_LEN, SALT_LEN};
+use chacha20poly1305::aead::{Aead, Payload};
+use chacha20poly1305::{AeadCore, KeyInit, XChaCha20Poly1305, XNonce};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use zeroize::Zeroizing;
+
+const SEALED_BOX_VERSION: u32 = 1;
+const SEALED_BOX_AAD: &[u8] = b"pw-sealed-box-v1";
+pub(crate) const NONCE_LEN: usize = 24;
+
+/// Data encrypted under a passphrase, with everything but the passphrase needed to
+/// decrypt it: Argon2id parameters and salt, and an XChaCha20-Poly1305 nonce.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SealedBox {
+    pub version: u32,
+    pub kdf: KdfParams,
+    #[serde(with = "base64_bytes")]
+    pub salt: Vec<u8>,
+    #[serde(with = "base64_bytes")]
+    pub nonce: Vec<u8>,
+    #[serde(with = "base64_bytes")]
+    pub ciphertext: Vec<u8>,
+}
+
+impl SealedBox {
+    pub fn seal(passphrase: &[u8], plaintext: &[u8], kdf: KdfParams) -> Result<Self, CryptoError> {
+        let mut salt = vec![0u8; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+        let key = derive_key(passphrase, &salt, &kdf)?;
+        let (nonce, ciphertext) = encrypt(key.expose(), plaintext, SEALED_BOX_AAD)?;
+        Ok(Self {
+            version: SEALED_BOX_VERSION,
+            kdf,
+            salt,
+            nonce,
+            ciphertext,
+        })
+    }
+
+    pub fn open(&self, passphrase: &[u8]) -> Result<Zeroizing<Vec<u8>>, CryptoError> {
+        if self.version != SEALED_BOX_VERSION {
+            return Err(CryptoError::InvalidFormat(format!(
+                "unsupported version {}",
+                self.version
+            )));
+        }
+        let key = derive_key(passphrase, &self.salt, &self.kdf)?;
+        decrypt(key.expose(), &self.nonce, &self.ciphertext, SEALED_BOX_AAD)
+    }
+}
+
+/// Encrypts with XChaCha20-Poly1305 under a fresh random nonce, returning the nonce and
+/// the ciphertext.
+pub(crate) fn encrypt(
+    key: &[u8; KEY_LEN],
+    plaintext: &[u8],
+    aad: &[u8],
+) -> Result<(Vec<u8>, Vec<u8>), CryptoError> {
+    let cipher = XChaCha20Poly1305::new(key.into());
+    let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let ciphertext = cipher
+        .encrypt(
+            &nonce,
+            Payload {
+                msg: plaintext,
+                aad,
+            },
+        )
+        .map_err(|_| CryptoError::InvalidFormat("encryption failed".to_string()))?;
+    Ok((nonce.to_vec(), ciphertext))
+}
+
+pub(crate) fn decrypt(
+    key: &[u8; KEY_LEN],
+    nonce: &[u8],
+    ciphertext: &[u8],
+    aad: &[u8],
+) -> Result<Zeroizing<Vec<u8>>, CryptoError> {
+    if nonce.len() != NONCE_LEN {
+        return Err(CryptoError::InvalidFormat(format!(
+            "nonce must be {} bytes",
+            NONCE_LEN
+        )));
+    }
+    XChaCha20Poly1305::new(key.into())
+        .decrypt(
+            XNonce::from_slice(nonce),
+            Payload {
+                msg: ciphertext,
+                aad,
+            },
+        )
+        .map(Zeroizing::new)
+        .map_err(|_| CryptoError::Decryption)
+}
+
+/// Encrypts like `encrypt`, returning the nonce followed by the ciphertext.
+pub(crate) fn seal_blob(
+    key: &[u8; KEY_LEN],
+    plaintext: &[u8],
+    aad: &str,
+) -> Result<Vec<u8>, CryptoError> {
+    let (mut blob, ciphertext) = encrypt(key, plaintext, aad.as_bytes())?;
+    blob.extend_from_slice(&ciphertext);
+    Ok(blob)
+}
+
+/// Decrypts a blob written by `seal_blob`.
+pub(crate) fn open_blob(
+    key: &[u8; KEY_LEN],
+    blob: &[u8],
+    aad: &str,
+) -> Result<Zeroizing<Vec<u8>>, CryptoError> {
+    if blob.len() < NONCE_LEN {
+        return Err(CryptoError::InvalidFormat(
+            "encrypted data is too short".to_string(),
+        ));
+    }
+    let (nonce, ciphertext) = blob.split_at(NONCE_LEN);
+    decrypt(key, nonce, ciphertext, aad.as_bytes())
+}
+
+/// Serializes byte fields as standard base64 strings.
+pub(crate) mod base64_bytes {
+    use base64::engine::general_purpose::STANDARD;
+    use base64::Engine;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&STANDARD.encode(bytes))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let encoded = String::deserialize(deserializer)?;
+        STANDARD
+            .decode(encoded.as_bytes())
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::kdf::TEST_KDF_PARAMS;
+
+    #[test]
+    fn seal_and_open() {
+        let sealed = SealedBox::seal(b"passphrase", b"secret data", TEST_KDF_PARAMS).unwrap();
+        assert_ne!(sealed.ciphertext, b"secret data");
+        assert_eq!(
+            sealed.open(b"passphrase").unwrap().as_slice(),
+            b"secret data"
+        );
+    }
+
+    #[test]
+    fn wrong_passphrase_fails() {
+        let sealed = SealedBox::seal(b"passphrase", b"secret data", TEST_KDF_PARAMS).unwrap();
+        assert_eq!(sealed.open(b"wrong"), Err(CryptoError::Decryption));
+    }
+
+    #[test]
+    fn tampering_is_detected() {
+        let mut sealed = SealedBox::seal(b"passphrase", b"secret data", TEST_KDF_PARAMS).unwrap();
+        sealed.ciphertext[0] ^= 1;
+        assert_eq!(sealed.open(b"passphrase"), Err(CryptoError::Decryption));
+    }
+
+    #[test]
+    fn serializes_as_json() {
+        let sealed = SealedBox::seal(b"passphrase", b"secret data", TEST_KDF_PARAMS).unwrap();
+        let json = serde_json::to_string(&sealed).unwrap();
+        let parsed: SealedBox = serde_json::from_str(&json).unwrap();
+        assert_eq!(
+            parsed.open(b"passphrase").unwrap().as_slice(),
+            b"secret data"
+        );
+    }
+}
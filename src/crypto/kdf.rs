@@ -0,0 +1,265 @@
+use super::memory;
+use super::secret::LockedKey;
+use argon2::{Algorithm, Argon2, Params, Version};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+pub const KEY_LEN: usize = 32;
+pub const SALT_LEN: usize = 16;
+
+/// Argon2id cost parameters. They are stored next to whatever they protect so the key can be
+/// derived again after the defaults change.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KdfParams {
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+}
+
+impl Default for KdfParams {
+    fn default() -> Self {
+        Self {
+            memory_kib: 64 * 1024,
+            iterations: 3,
+            parallelism: 1,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum CryptoError {
+    InvalidKdfParams(String),
+    /// The ciphertext did not authenticate: a wrong passphrase or tampered data.
+    Decryption,
+    InvalidFormat(String),
+}
+
+impl fmt::Display for CryptoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CryptoError::InvalidKdfParams(reason) => {
+                write!(f, "invalid KDF parameters: {}", reason)
+            }
+            CryptoError::Decryption => write!(
+                f,
+                "decryption failed: wrong passphrase or the data was modified"
+            ),
+            CryptoError::InvalidFormat(reason) => write!(f, "invalid encrypted data: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for CryptoError {}
+
+impl From<CryptoError> for std::io::Error {
+    fn from(e: CryptoError) -> Self {
+        std::io::Error::new(std::io::ErrorKind::InvalidData, e)
+    }
+}
+
+/// Derives a 256-bit key from a password with Argon2id.
+pub(crate) fn derive_key(
+    password: &[u8],
+    salt: &[u8],
+    params: &KdfParams,
+) -> Result<LockedKey, CryptoError> {
+    let argon_params = Params::new(
+        params.memory_kib,
+        params.iterations,
+        params.parallelism,
+        Some(KEY_LEN),
+    )
+    .map_err(|e| CryptoError::InvalidKdfParams(e.to_string()))?;
+    let mut key = LockedKey::zeroed();
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, argon_params)
+        .hash_password_into(password, salt, key.expose_mut())
+        .map_err(|e| CryptoError::InvalidKdfParams(e.to_string()))?;
+    Ok(key)
+}
+
+/// Calibration never goes below the OWASP minimum for Argon2id, 19 MiB and 2 iterations,
+/// however slow the machine, and stops adding memory at 1 GiB or half the available memory,
+/// whichever is less.
+const MIN_MEMORY_KIB: u32 = 19 * 1024;
+const MAX_MEMORY_KIB: u32 = 1024 * 1024;
+const MIN_ITERATIONS: u32 = 2;
+/// Past this many iterations, memory is doubled instead: it costs an attacker more than time.
+const MAX_ITERATIONS: u32 = 4;
+
+/// Times one key derivation with `params`.
+pub fn time_derivation(params: &KdfParams) -> Result<Duration, CryptoError> {
+    let start = Instant::now();
+    derive_key(b"calibration", &[0u8; SALT_LEN], params)?;
+    Ok(start.elapsed())
+}
+
+/// Picks Argon2id parameters that take about `target` to derive a key on this machine.
+pub fn calibrate(target: Duration) -> Result<KdfParams, CryptoError> {
+    calibrate_with(target, memory_limit_kib(), &mut time_derivation)
+}
+
+/// The most memory calibration gives a derivation on this machine.
+pub fn memory_limit_kib() -> u32 {
+    let available = memory::available_kib().map_or(u64::MAX, |kib| kib / 2);
+    let limit = available.min(u64::from(MAX_MEMORY_KIB)) as u32;
+    limit.max(MIN_MEMORY_KIB)
+}
+
+/// Like `calibrate`, with `measure` timing a derivation. Starting from the default memory
+/// cost, capped at `max_memory_kib`, one iteration is timed and the iterations that fit in
+/// `target` are worked out; memory is doubled while more than `MAX_ITERATIONS` would fit,
+/// and halved while fewer than `MIN_ITERATIONS` do. Should that lead back to a memory cost
+/// already timed, time grows faster than memory there, e.g. because the machine swaps, and
+/// the smaller of the two is taken.
+pub(crate) fn calibrate_with(
+    target: Duration,
+    max_memory_kib: u32,
+    measure: &mut dyn FnMut(&KdfParams) -> Result<Duration, CryptoError>,
+) -> Result<KdfParams, CryptoError> {
+    let max_memory_kib = max_memory_kib.clamp(MIN_MEMORY_KIB, MAX_MEMORY_KIB);
+    let mut params = KdfParams {
+        iterations: 1,
+        memory_kib: KdfParams::default().memory_kib.min(max_memory_kib),
+        ..KdfParams::default()
+    };
+    let mut timed: BTreeMap<u32, u32> = BTreeMap::new();
+    loop {
+        let elapsed = measure(&params)?.max(Duration::from_micros(1));
+        let fit = (target.as_secs_f64() / elapsed.as_secs_f64()).round();
+        let iterations = fit.min(f64::from(u32::MAX)) as u32;
+        timed.insert(params.memory_kib, iterations);
+        let next = if iterations > MAX_ITERATIONS && params.memory_kib < max_memory_kib {
+            (params.memory_kib * 2).min(max_memory_kib)
+        } else if iterations < MIN_ITERATIONS && params.memory_kib > MIN_MEMORY_KIB {
+            (params.memory_kib / 2).max(MIN_MEMORY_KIB)
+        } else {
+            params.iterations = iterations.max(MIN_ITERATIONS);
+            return Ok(params);
+        };
+        if let Some(&next_iterations) = timed.get(&next) {
+            if next < params.memory_kib {
+                params.memory_kib = next;
+                params.iterations = next_iterations.max(MIN_ITERATIONS);
+            } else {
+                params.iterations = iterations.max(MIN_ITERATIONS);
+            }
+            return Ok(params);
+        }
+        params.memory_kib = next;
+    }
+}
+
+#[cfg(test)]
+pub(crate) const TEST_KDF_PARAMS: KdfParams = KdfParams {
+    memory_kib: 64,
+    iterations: 1,
+    parallelism: 1,
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn derive_key_is_deterministic() {
+        let salt = [7u8; SALT_LEN];
+        let first = derive_key(b"password", &salt, &TEST_KDF_PARAMS).unwrap();
+        let second = derive_key(b"password", &salt, &TEST_KDF_PARAMS).unwrap();
+        assert_eq!(first.expose(), second.expose());
+        assert_ne!(
+            first.expose(),
+            derive_key(b"other", &salt, &TEST_KDF_PARAMS)
+                .unwrap()
+                .expose()
+        );
+    }
+
+    /// A machine on which one iteration over each MiB takes `per_mib`.
+    fn machine(per_mib: Duration) -> impl FnMut(&KdfParams) -> Result<Duration, CryptoError> {
+        move |params| Ok(per_mib * (params.memory_kib / 1024) * params.iterations)
+    }
+
+    #[test]
+    fn calibration_scales_to_the_machine() {
+        let target = Duration::from_millis(500);
+        // 64 MiB takes 128 ms: 4 iterations fit, so the default memory is kept.
+        let params = calibrate_with(
+            target,
+            MAX_MEMORY_KIB,
+            &mut machine(Duration::from_millis(2)),
+        )
+        .unwrap();
+        assert_eq!((params.memory_kib, params.iterations), (64 * 1024, 4));
+
+        // A fast machine gets more memory rather than many iterations.
+        let params = calibrate_with(
+            target,
+            MAX_MEMORY_KIB,
+            &mut machine(Duration::from_micros(250)),
+        )
+        .unwrap();
+        assert_eq!((params.memory_kib, params.iterations), (512 * 1024, 4));
+        let params = calibrate_with(
+            target,
+            MAX_MEMORY_KIB,
+            &mut machine(Duration::from_nanos(1)),
+        )
+        .unwrap();
+        assert_eq!(params.memory_kib, MAX_MEMORY_KIB);
+
+        // A slow one gets less memory, but never less than the minimum.
+        let params = calibrate_with(
+            target,
+            MAX_MEMORY_KIB,
+            &mut machine(Duration::from_millis(10)),
+        )
+        .unwrap();
+        assert_eq!((params.memory_kib, params.iterations), (32 * 1024, 2));
+        let params =
+            calibrate_with(target, MAX_MEMORY_KIB, &mut machine(Duration::from_secs(1))).unwrap();
+        assert_eq!(
+            (params.memory_kib, params.iterations),
+            (MIN_MEMORY_KIB, MIN_ITERATIONS)
+        );
+    }
+
+    #[test]
+    fn calibration_stops_when_time_grows_faster_than_memory() {
+        let target = Duration::from_millis(500);
+        // 64 MiB fits 5 iterations, but 128 MiB swaps and fits none: without remembering
+        // what was timed, this would go back and forth between the two forever.
+        let mut swapping = |params: &KdfParams| {
+            Ok(match params.memory_kib {
+                kib if kib <= 64 * 1024 => Duration::from_millis(100),
+                _ => Duration::from_secs(10),
+            })
+        };
+        let params = calibrate_with(target, MAX_MEMORY_KIB, &mut swapping).unwrap();
+        assert_eq!((params.memory_kib, params.iterations), (64 * 1024, 5));
+    }
+
+    #[test]
+    fn calibration_stays_within_the_memory_limit() {
+        let target = Duration::from_millis(500);
+        let fast = Duration::from_nanos(1);
+        let params = calibrate_with(target, 48 * 1024, &mut machine(fast)).unwrap();
+        assert_eq!(params.memory_kib, 48 * 1024);
+        let params = calibrate_with(target, 1024, &mut machine(fast)).unwrap();
+        assert_eq!(params.memory_kib, MIN_MEMORY_KIB);
+        assert!(memory_limit_kib() >= MIN_MEMORY_KIB && memory_limit_kib() <= MAX_MEMORY_KIB);
+    }
+
+    #[test]
+    fn invalid_params_are_rejected() {
+        let params = KdfParams {
+            memory_kib: 1,
+            ..TEST_KDF_PARAMS
+        };
+        assert!(matches!(
+            derive_key(b"password", &[0u8; SALT_LEN], &params),
+            Err(CryptoError::InvalidKdfParams(_))
+        ));
+    }
+}
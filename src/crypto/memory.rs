@@ -0,0 +1,54 @@
+//! Keeping key material out of swap and core dumps. Both are best effort: pw works, less
+//! safely, where the system does not allow them.
+
+/// Keeps the process from writing a core dump, which would contain the master password
+/// and keys, and on Linux from being attached to by other processes of the same user.
+#[cfg(unix)]
+pub fn disable_core_dumps() {
+    let limit = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    // SAFETY: setrlimit only reads the limit it is given.
+    unsafe { libc::setrlimit(libc::RLIMIT_CORE, &limit) };
+    #[cfg(target_os = "linux")]
+    // SAFETY: PR_SET_DUMPABLE takes a plain integer and affects only this process.
+    unsafe {
+        libc::prctl(libc::PR_SET_DUMPABLE, 0, 0, 0, 0)
+    };
+}
+
+#[cfg(not(unix))]
+pub fn disable_core_dumps() {}
+
+/// Locks the pages holding `bytes` into memory, so they are never written to swap. They
+/// stay locked until the process exits: another key may share the page, and pw does not
+/// run for long.
+#[cfg(unix)]
+pub fn lock(bytes: &[u8]) {
+    if bytes.is_empty() {
+        return;
+    }
+    // SAFETY: the range is a valid allocation; mlock does not access it. Failure, e.g.
+    // over RLIMIT_MEMLOCK, leaves the memory as it was.
+    unsafe { libc::mlock(bytes.as_ptr().cast(), bytes.len()) };
+}
+
+#[cfg(not(unix))]
+pub fn lock(_: &[u8]) {}
+
+/// The memory the system can give to new allocations without swapping, in KiB, or `None`
+/// where it is not known.
+#[cfg(target_os = "linux")]
+pub fn available_kib() -> Option<u64> {
+    let meminfo = std::fs::read_to_string("/proc/meminfo").ok()?;
+    meminfo
+        .lines()
+        .find_map(|line| line.strip_prefix("MemAvailable:"))
+        .and_then(|value| value.trim().trim_end_matches("kB").trim().parse().ok())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn available_kib() -> Option<u64> {
+    None
+}
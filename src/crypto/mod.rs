@@ -0,0 +1,5 @@
+pub(crate) mod envelope;
+pub(crate) mod kdf;
+pub(crate) mod memory;
+pub(crate) mod secret;
+pub(crate) mod shamir;
@@ -0,0 +1,164 @@
+use super::kdf::KEY_LEN;
+use super::memory;
+use rand::rngs::OsRng;
+use rand::RngCore;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use zeroize::Zeroize;
+
+const REDACTED: &str = "[redacted]";
+
+/// A password or other secret value. It is wiped from memory when dropped, and prints as
+/// `[redacted]` so it cannot end up in output or error messages by accident; `expose`
+/// gives the value where it is really needed.
+#[derive(Clone, PartialEq, Eq, Default)]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl PartialEq<str> for Secret {
+    fn eq(&self, other: &str) -> bool {
+        self.0 == other
+    }
+}
+
+impl PartialEq<&str> for Secret {
+    fn eq(&self, other: &&str) -> bool {
+        self.0 == *other
+    }
+}
+
+/// Serialized as the plain string, so stored entries keep their format.
+impl Serialize for Secret {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for Secret {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer).map(Self)
+    }
+}
+
+impl From<String> for Secret {
+    fn from(value: String) -> Self {
+        Self(value)
+    }
+}
+
+impl From<&str> for Secret {
+    fn from(value: &str) -> Self {
+        Self(value.to_string())
+    }
+}
+
+impl Drop for Secret {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Secret({})", REDACTED)
+    }
+}
+
+impl fmt::Display for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(REDACTED)
+    }
+}
+
+/// A 256-bit key, on the heap so it does not move, locked into memory so it is never
+/// swapped out, and wiped when dropped. Keys are written into place with `expose_mut`, so
+/// no copy is left behind on the stack.
+pub struct LockedKey(Box<[u8; KEY_LEN]>);
+
+impl LockedKey {
+    /// An all-zero key, locked before anything is written to it.
+    pub fn zeroed() -> Self {
+        let key = Self(Box::new([0u8; KEY_LEN]));
+        memory::lock(key.0.as_slice());
+        key
+    }
+
+    pub fn random() -> Self {
+        let mut key = Self::zeroed();
+        OsRng.fill_bytes(key.expose_mut());
+        key
+    }
+
+    /// Copies a key out of `bytes`, e.g. a decrypted buffer the caller wipes, or `None` if
+    /// it is not `KEY_LEN` bytes long.
+    pub fn from_slice(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != KEY_LEN {
+            return None;
+        }
+        let mut key = Self::zeroed();
+        key.expose_mut().copy_from_slice(bytes);
+        Some(key)
+    }
+
+    pub fn expose(&self) -> &[u8; KEY_LEN] {
+        &self.0
+    }
+
+    pub fn expose_mut(&mut self) -> &mut [u8; KEY_LEN] {
+        &mut self.0
+    }
+}
+
+impl Clone for LockedKey {
+    fn clone(&self) -> Self {
+        let mut key = Self::zeroed();
+        key.expose_mut().copy_from_slice(self.expose());
+        key
+    }
+}
+
+impl Drop for LockedKey {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl fmt::Debug for LockedKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "LockedKey({})", REDACTED)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn secrets_are_redacted() {
+        let secret = Secret::from("hunter2");
+        assert_eq!(secret.expose(), "hunter2");
+        assert_eq!(secret.to_string(), "[redacted]");
+        assert_eq!(format!("{:?}", Some(&secret)), "Some(Secret([redacted]))");
+        let key = LockedKey::from_slice(&[7; KEY_LEN]).unwrap();
+        assert_eq!(key.clone().expose(), &[7; KEY_LEN]);
+        assert!(LockedKey::from_slice(&[7; KEY_LEN - 1]).is_none());
+        assert_eq!(format!("{:?}", key), "LockedKey([redacted])");
+    }
+
+    #[test]
+    fn secrets_serialize_as_plain_strings() {
+        let json = serde_json::to_string(&Secret::from("hunter2")).unwrap();
+        assert_eq!(json, r#""hunter2""#);
+        let secret: Secret = serde_json::from_str(&json).unwrap();
+        assert_eq!(secret, "hunter2");
+    }
+}
@@ -0,0 +1,128 @@
+use rand::rngs::OsRng;
+use rand::RngCore;
+
+/// Multiplies in GF(2^8) with the AES polynomial, x^8 + x^4 + x^3 + x + 1.
+fn mul(mut a: u8, mut b: u8) -> u8 {
+    let mut product = 0;
+    while b != 0 {
+        if b & 1 != 0 {
+            product ^= a;
+        }
+        let carry = a & 0x80;
+        a <<= 1;
+        if carry != 0 {
+            a ^= 0x1b;
+        }
+        b >>= 1;
+    }
+    product
+}
+
+/// The multiplicative inverse, a^254, of a non-zero element.
+fn inverse(a: u8) -> u8 {
+    let mut result = 1;
+    let mut base = a;
+    let mut exponent = 254u8;
+    while exponent != 0 {
+        if exponent & 1 != 0 {
+            result = mul(result, base);
+        }
+        base = mul(base, base);
+        exponent >>= 1;
+    }
+    result
+}
+
+fn evaluate(coefficients: &[u8], x: u8) -> u8 {
+    coefficients
+        .iter()
+        .rev()
+        .fold(0, |value, &coefficient| mul(value, x) ^ coefficient)
+}
+
+/// Splits `secret` into `count` shares, any `threshold` of which give it back. Each byte is
+/// the constant term of its own random polynomial of degree `threshold - 1`, and share `x`
+/// holds the value of every polynomial at `x`, for `x` from 1 to `count`.
+pub(crate) fn split(secret: &[u8], threshold: u8, count: u8) -> Vec<(u8, Vec<u8>)> {
+    assert!(
+        (1..=count).contains(&threshold),
+        "the threshold must be between 1 and the number of shares"
+    );
+    let mut shares: Vec<(u8, Vec<u8>)> = (1..=count)
+        .map(|x| (x, Vec::with_capacity(secret.len())))
+        .collect();
+    let mut coefficients = vec![0u8; usize::from(threshold)];
+    for &byte in secret {
+        coefficients[0] = byte;
+        OsRng.fill_bytes(&mut coefficients[1..]);
+        for (x, values) in &mut shares {
+            values.push(evaluate(&coefficients, *x));
+        }
+    }
+    coefficients.fill(0);
+    shares
+}
+
+/// Recovers the secret from shares with distinct, non-zero `x`, by Lagrange interpolation
+/// at 0. With fewer shares than the threshold the result is unrelated to the secret.
+pub(crate) fn combine(shares: &[(u8, &[u8])]) -> Vec<u8> {
+    let len = shares.first().map_or(0, |(_, values)| values.len());
+    (0..len)
+        .map(|i| {
+            shares.iter().fold(0, |secret, &(x, values)| {
+                let basis = shares
+                    .iter()
+                    .filter(|&&(other, _)| other != x)
+                    .fold(1, |basis, &(other, _)| {
+                        mul(basis, mul(other, inverse(other ^ x)))
+                    });
+                secret ^ mul(values[i], basis)
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn field_arithmetic() {
+        // The example from FIPS 197, section 4.2.
+        assert_eq!(mul(0x57, 0x83), 0xc1);
+        for a in 1..=255u8 {
+            assert_eq!(mul(a, inverse(a)), 1, "{}", a);
+        }
+    }
+
+    #[test]
+    fn any_threshold_of_shares_recovers_the_secret() {
+        let secret: Vec<u8> = (0..32).collect();
+        let shares = split(&secret, 3, 5);
+        assert_eq!(shares.len(), 5);
+        for a in 0..5 {
+            for b in a + 1..5 {
+                for c in b + 1..5 {
+                    let subset: Vec<(u8, &[u8])> = [a, b, c]
+                        .iter()
+                        .map(|&i| (shares[i].0, shares[i].1.as_slice()))
+                        .collect();
+                    assert_eq!(combine(&subset), secret);
+                }
+            }
+        }
+
+        let too_few: Vec<(u8, &[u8])> = shares[..2]
+            .iter()
+            .map(|(x, values)| (*x, values.as_slice()))
+            .collect();
+        assert_ne!(combine(&too_few), secret);
+    }
+
+    #[test]
+    fn threshold_of_one_copies_the_secret() {
+        for (_, values) in split(b"secret", 1, 3) {
+            assert_eq!(values, b"secret");
+        }
+    }
+}
@@ -0,0 +1,197 @@
+use crate::config::settings::ConfigError;
+use crate::crypto::kdf::CryptoError;
+use crate::password_generator::generator::GeneratorError;
+use crate::password_generator::policy::PolicyError;
+use crate::storage::kdbx::format::KdbxError;
+use std::fmt;
+use std::io;
+
+/// Why a pw command failed. Each kind of failure has its own exit status, so scripts can
+/// tell a missing key from a wrong password or an unreachable server. The statuses are
+/// listed in the README and must not change.
+#[derive(Debug)]
+pub enum PwError {
+    /// A file could not be read or written, or a helper program failed. Exit status 1.
+    Io(io::Error),
+    /// An argument, input file or setting is not valid. Exit status 2, like usage errors.
+    Invalid(String),
+    /// A key, field, vault or profile does not exist. Exit status 3.
+    NotFound(String),
+    /// A wrong master password, database password or passphrase. Exit status 4.
+    Auth(String),
+    /// A vault or encrypted file is damaged or in an unknown format. Exit status 5.
+    Corrupt(String),
+    /// A remote service could not be reached or gave a bad answer. Exit status 6.
+    Network(String),
+    /// No password can satisfy the requested length or rules. Exit status 7.
+    Policy(String),
+}
+
+impl PwError {
+    pub fn no_home_dir() -> Self {
+        PwError::Io(io::Error::other("Could not find the home directory"))
+    }
+
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            PwError::Io(_) => 1,
+            PwError::Invalid(_) => 2,
+            PwError::NotFound(_) => 3,
+            PwError::Auth(_) => 4,
+            PwError::Corrupt(_) => 5,
+            PwError::Network(_) => 6,
+            PwError::Policy(_) => 7,
+        }
+    }
+}
+
+impl fmt::Display for PwError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PwError::Io(e) => write!(f, "{}", e),
+            PwError::Invalid(message)
+            | PwError::NotFound(message)
+            | PwError::Auth(message)
+            | PwError::Corrupt(message)
+            | PwError::Network(message)
+            | PwError::Policy(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for PwError {}
+
+/// Storage backends report errors as `io::Error`, so the error kind, or a more specific
+/// error carried inside, decides the variant. A missing file is an I/O error: backends
+/// report a missing key or vault with a `PwError::NotFound` inside, so a missing import or
+/// config file does not look like a missing key.
+impl From<io::Error> for PwError {
+    fn from(e: io::Error) -> Self {
+        if e.get_ref().is_some_and(|inner| inner.is::<PwError>()) {
+            return *e.into_inner().unwrap().downcast::<PwError>().unwrap();
+        }
+        if let Some(inner) = e
+            .get_ref()
+            .and_then(|inner| inner.downcast_ref::<CryptoError>())
+        {
+            return PwError::from(inner);
+        }
+        if let Some(inner) = e
+            .get_ref()
+            .and_then(|inner| inner.downcast_ref::<KdbxError>())
+        {
+            return PwError::from(inner);
+        }
+        match e.kind() {
+            io::ErrorKind::InvalidData => PwError::Corrupt(e.to_string()),
+            io::ErrorKind::InvalidInput => PwError::Invalid(e.to_string()),
+            _ => PwError::Io(e),
+        }
+    }
+}
+
+/// Lets storage backends, which return `io::Error`, report a specific kind of failure.
+impl From<PwError> for io::Error {
+    fn from(e: PwError) -> Self {
+        match e {
+            PwError::Io(e) => e,
+            PwError::NotFound(_) => io::Error::new(io::ErrorKind::NotFound, e),
+            PwError::Invalid(_) => io::Error::new(io::ErrorKind::InvalidInput, e),
+            PwError::Corrupt(_) => io::Error::new(io::ErrorKind::InvalidData, e),
+            _ => io::Error::other(e),
+        }
+    }
+}
+
+impl From<&CryptoError> for PwError {
+    fn from(e: &CryptoError) -> Self {
+        match e {
+            CryptoError::Decryption => PwError::Auth(e.to_string()),
+            CryptoError::InvalidKdfParams(_) => PwError::Invalid(e.to_string()),
+            CryptoError::InvalidFormat(_) => PwError::Corrupt(e.to_string()),
+        }
+    }
+}
+
+impl From<CryptoError> for PwError {
+    fn from(e: CryptoError) -> Self {
+        PwError::from(&e)
+    }
+}
+
+impl From<&KdbxError> for PwError {
+    fn from(e: &KdbxError) -> Self {
+        match e {
+            KdbxError::WrongKey => PwError::Auth(e.to_string()),
+            _ => PwError::Corrupt(e.to_string()),
+        }
+    }
+}
+
+impl From<GeneratorError> for PwError {
+    fn from(e: GeneratorError) -> Self {
+        PwError::Policy(e.to_string())
+    }
+}
+
+impl From<PolicyError> for PwError {
+    fn from(e: PolicyError) -> Self {
+        PwError::Policy(e.to_string())
+    }
+}
+
+impl From<ConfigError> for PwError {
+    fn from(e: ConfigError) -> Self {
+        PwError::Invalid(e.to_string())
+    }
+}
+
+impl From<serde_json::Error> for PwError {
+    fn from(e: serde_json::Error) -> Self {
+        PwError::Invalid(format!("invalid JSON: {}", e))
+    }
+}
+
+impl From<csv::Error> for PwError {
+    fn from(e: csv::Error) -> Self {
+        PwError::Invalid(format!("invalid CSV: {}", e))
+    }
+}
+
+impl From<quick_xml::Error> for PwError {
+    fn from(e: quick_xml::Error) -> Self {
+        PwError::Invalid(format!("invalid XML: {}", e))
+    }
+}
+
+impl From<reqwest::Error> for PwError {
+    fn from(e: reqwest::Error) -> Self {
+        PwError::Network(e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn io_errors_keep_the_specific_failure() {
+        let auth: io::Error = PwError::Auth("wrong master password".to_string()).into();
+        assert_eq!(PwError::from(auth).exit_code(), 4);
+
+        let kdbx: io::Error = KdbxError::WrongKey.into();
+        assert_eq!(PwError::from(kdbx).exit_code(), 4);
+        let crypto: io::Error = CryptoError::InvalidFormat("short".to_string()).into();
+        assert_eq!(PwError::from(crypto).exit_code(), 5);
+
+        let vault: io::Error = PwError::NotFound("no vault".to_string()).into();
+        assert_eq!(vault.kind(), io::ErrorKind::NotFound);
+        assert_eq!(PwError::from(vault).exit_code(), 3);
+        let missing = io::Error::new(io::ErrorKind::NotFound, "no such file");
+        assert_eq!(PwError::from(missing).exit_code(), 1);
+        let denied = io::Error::new(io::ErrorKind::PermissionDenied, "denied");
+        let error = PwError::from(denied);
+        assert_eq!(error.exit_code(), 1);
+        assert_eq!(error.to_string(), "denied");
+    }
+}
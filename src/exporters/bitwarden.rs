@@ -0,0 +1,78 @@
+use super::export_format::split_key;
+use crate::error::PwError;
+use crate::storage::entry::Entry;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use uuid::Uuid;
+
+/// Renders a Bitwarden unencrypted JSON export. Key prefixes become folders.
+pub(crate) fn render_bitwarden(entries: &[(String, Entry)]) -> Result<String, PwError> {
+    let mut folders: BTreeMap<String, String> = BTreeMap::new();
+    let mut items = Vec::new();
+    for (key, entry) in entries {
+        let (path, name) = split_key(key);
+        let folder_id = if path.is_empty() {
+            Value::Null
+        } else {
+            let id = folders
+                .entry(path.join("/"))
+                .or_insert_with(|| Uuid::new_v4().to_string());
+            Value::String(id.clone())
+        };
+        let uris: Vec<Value> = entry
+            .url
+            .iter()
+            .map(|url| json!({"match": null, "uri": url}))
+            .collect();
+        items.push(json!({
+            "id": Uuid::new_v4().to_string(),
+            "folderId": folder_id,
+            "type": 1,
+            "name": name,
+            "notes": entry.notes,
+            "favorite": false,
+            "login": {
+                "username": entry.username,
+                "password": entry.value,
+                "totp": entry.otp.as_ref().map(|otp| otp.to_uri()),
+                "uris": uris,
+            },
+        }));
+    }
+    let folders: Vec<Value> = folders
+        .into_iter()
+        .map(|(name, id)| json!({"id": id, "name": name}))
+        .collect();
+    Ok(serde_json::to_string_pretty(&json!({
+        "encrypted": false,
+        "folders": folders,
+        "items": items,
+    }))?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::importers::bitwarden::parse_bitwarden;
+    use crate::otp::otpauth::OtpParams;
+
+    #[test]
+    fn round_trips_through_importer() {
+        let mut entry = Entry::new("hunter2".to_string());
+        entry.username = Some("alice".to_string());
+        entry.url = Some("https://github.com".to_string());
+        entry.otp = Some(OtpParams::parse("JBSWY3DPEHPK3PXP").unwrap());
+        let entries = vec![
+            ("Work/Code/GitHub".to_string(), entry),
+            ("plain".to_string(), Entry::new("pw".to_string())),
+        ];
+
+        let export = render_bitwarden(&entries).unwrap();
+        let imported = parse_bitwarden(&export).unwrap();
+        assert_eq!(imported.len(), 2);
+        for ((key, entry), imported) in entries.iter().zip(&imported) {
+            assert_eq!(&imported.key, key);
+            assert_eq!(&imported.entry, entry);
+        }
+    }
+}
@@ -0,0 +1,42 @@
+use crate::error::PwError;
+use crate::storage::entry::Entry;
+
+pub(crate) fn render_csv(entries: &[(String, Entry)]) -> Result<String, PwError> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer.write_record(["key", "username", "password", "url", "notes", "otpauth"])?;
+    for (key, entry) in entries {
+        let otpauth = entry
+            .otp
+            .as_ref()
+            .map(|otp| otp.to_uri())
+            .unwrap_or_default();
+        writer.write_record([
+            key.as_str(),
+            entry.username.as_deref().unwrap_or_default(),
+            entry.value.expose(),
+            entry.url.as_deref().unwrap_or_default(),
+            entry.notes.as_deref().unwrap_or_default(),
+            otpauth.expose(),
+        ])?;
+    }
+    let contents = writer.into_inner().map_err(|e| e.into_error())?;
+    String::from_utf8(contents).map_err(|e| PwError::Invalid(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_and_quotes_fields() {
+        let mut entry = Entry::new("p,w\"1".to_string());
+        entry.notes = Some("line1\nline2".to_string());
+        let csv = render_csv(&[("work/db".to_string(), entry)]).unwrap();
+
+        let mut reader = csv::Reader::from_reader(csv.as_bytes());
+        let record = reader.records().next().unwrap().unwrap();
+        assert_eq!(&record[0], "work/db");
+        assert_eq!(&record[2], "p,w\"1");
+        assert_eq!(&record[4], "line1\nline2");
+    }
+}
@@ -0,0 +1,14 @@
+use crate::crypto::envelope::SealedBox;
+use serde::{Deserialize, Serialize};
+
+pub(crate) const ENCRYPTED_EXPORT_FORMAT: &str = "pw-encrypted-export";
+
+/// An encrypted export: the rendered export, sealed under a passphrase. `content_format`
+/// is the name of the export format the plaintext is in, such as `json`.
+#[derive(Serialize, Deserialize, Debug)]
+pub(crate) struct EncryptedExport {
+    pub format: String,
+    pub content_format: String,
+    #[serde(flatten)]
+    pub sealed: SealedBox,
+}
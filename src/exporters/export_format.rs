@@ -0,0 +1,51 @@
+use super::{bitwarden, csv_file, keepass_xml, pw_json};
+use crate::error::PwError;
+use crate::storage::entry::Entry;
+use clap::ValueEnum;
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// pw's own JSON format, including history and 2FA secrets.
+    Json,
+    /// CSV with key, username, password, url, notes and otpauth columns.
+    Csv,
+    /// Bitwarden unencrypted JSON, importable by Bitwarden and Vaultwarden.
+    Bitwarden,
+    /// KeePass 2 XML, importable by KeePass and KeePassXC.
+    KeepassXml,
+}
+
+/// Renders `entries`, sorted by key, in the given format.
+pub fn render_entries(
+    format: ExportFormat,
+    entries: &[(String, Entry)],
+) -> Result<String, PwError> {
+    match format {
+        ExportFormat::Json => pw_json::render_json(entries),
+        ExportFormat::Csv => csv_file::render_csv(entries),
+        ExportFormat::Bitwarden => bitwarden::render_bitwarden(entries),
+        ExportFormat::KeepassXml => Ok(keepass_xml::render_keepass_xml(entries)),
+    }
+}
+
+/// Splits a key such as `Work/Email/Gmail` into its folder path and name, the inverse of
+/// how imports build keys.
+pub(crate) fn split_key(key: &str) -> (Vec<&str>, &str) {
+    let mut parts: Vec<&str> = key.split('/').collect();
+    let name = parts.pop().unwrap_or_default();
+    (parts, name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_key_into_folders_and_name() {
+        assert_eq!(
+            split_key("Work/Email/Gmail"),
+            (vec!["Work", "Email"], "Gmail")
+        );
+        assert_eq!(split_key("github"), (vec![], "github"));
+    }
+}
@@ -0,0 +1,122 @@
+use super::export_format::split_key;
+use crate::crypto::secret::Secret;
+use crate::storage::entry::Entry;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use quick_xml::escape::escape;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use uuid::Uuid;
+
+/// The name of the root group holding everything that was exported.
+const ROOT_GROUP: &str = "pw";
+
+#[derive(Default)]
+struct Group<'a> {
+    groups: BTreeMap<&'a str, Group<'a>>,
+    entries: Vec<(&'a str, &'a Entry)>,
+}
+
+/// Renders a KeePass 2 XML export. Key prefixes become nested groups under a root group.
+pub(crate) fn render_keepass_xml(entries: &[(String, Entry)]) -> String {
+    let mut root = Group::default();
+    for (key, entry) in entries {
+        let (path, name) = split_key(key);
+        let group = path.into_iter().fold(&mut root, |group, part| {
+            group.groups.entry(part).or_default()
+        });
+        group.entries.push((name, entry));
+    }
+
+    let mut xml = String::from(
+        "<?xml version=\"1.0\" encoding=\"utf-8\" standalone=\"yes\"?>\n\
+         <KeePassFile>\n\t<Meta>\n\t\t<Generator>pw</Generator>\n\t</Meta>\n\t<Root>\n",
+    );
+    write_group(&mut xml, ROOT_GROUP, &root, 2);
+    xml.push_str("\t</Root>\n</KeePassFile>\n");
+    xml
+}
+
+fn write_group(xml: &mut String, name: &str, group: &Group, depth: usize) {
+    let indent = "\t".repeat(depth);
+    let _ = writeln!(xml, "{}<Group>", indent);
+    let _ = writeln!(xml, "{}\t<UUID>{}</UUID>", indent, new_uuid());
+    let _ = writeln!(xml, "{}\t<Name>{}</Name>", indent, escape(name));
+    for (name, entry) in &group.entries {
+        write_entry(xml, name, entry, depth + 1);
+    }
+    for (name, child) in &group.groups {
+        write_group(xml, name, child, depth + 1);
+    }
+    let _ = writeln!(xml, "{}</Group>", indent);
+}
+
+fn write_entry(xml: &mut String, name: &str, entry: &Entry, depth: usize) {
+    let indent = "\t".repeat(depth);
+    let otp = entry.otp.as_ref().map(|otp| otp.to_uri());
+    let fields = [
+        ("Title", Some(name), false),
+        ("UserName", entry.username.as_deref(), false),
+        ("Password", Some(entry.value.expose()), true),
+        ("URL", entry.url.as_deref(), false),
+        ("Notes", entry.notes.as_deref(), false),
+        ("otp", otp.as_ref().map(Secret::expose), true),
+    ];
+
+    let _ = writeln!(xml, "{}<Entry>", indent);
+    let _ = writeln!(xml, "{}\t<UUID>{}</UUID>", indent, new_uuid());
+    for (key, value, protected) in fields {
+        let Some(value) = value else {
+            continue;
+        };
+        let attribute = if protected {
+            " ProtectInMemory=\"True\""
+        } else {
+            ""
+        };
+        let _ = writeln!(
+            xml,
+            "{}\t<String><Key>{}</Key><Value{}>{}</Value></String>",
+            indent,
+            key,
+            attribute,
+            escape(value)
+        );
+    }
+    let _ = writeln!(xml, "{}</Entry>", indent);
+}
+
+fn new_uuid() -> String {
+    STANDARD.encode(Uuid::new_v4().as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::importers::keepass_xml::parse_keepass_xml;
+    use crate::otp::otpauth::OtpParams;
+
+    #[test]
+    fn round_trips_through_importer() {
+        let mut entry = Entry::new("<&\"pw'>".to_string());
+        entry.username = Some("alice".to_string());
+        entry.notes = Some("line1\nline2".to_string());
+        entry.otp = Some(OtpParams::parse("JBSWY3DPEHPK3PXP").unwrap());
+        let entries = vec![
+            ("top".to_string(), Entry::new("pw".to_string())),
+            ("Work/Email/Gmail".to_string(), entry),
+            ("Work/VPN".to_string(), Entry::new("vpn".to_string())),
+        ];
+
+        let xml = render_keepass_xml(&entries);
+        let mut imported = parse_keepass_xml(&xml).unwrap();
+        imported.sort_by(|a, b| a.key.cmp(&b.key));
+        let mut expected = entries.clone();
+        expected.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(imported.len(), 3);
+        for ((key, entry), imported) in expected.iter().zip(&imported) {
+            assert_eq!(&imported.key, key);
+            assert_eq!(&imported.entry, entry);
+        }
+    }
+}
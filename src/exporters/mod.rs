@@ -0,0 +1,6 @@
+pub(crate) mod bitwarden;
+pub(crate) mod csv_file;
+pub(crate) mod encrypted;
+pub(crate) mod export_format;
+pub(crate) mod keepass_xml;
+pub(crate) mod pw_json;
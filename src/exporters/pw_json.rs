@@ -0,0 +1,27 @@
+use crate::error::PwError;
+use crate::storage::entry::Entry;
+use std::collections::BTreeMap;
+
+/// Renders entries as a map of keys to entries, with their history and metadata, so nothing
+/// is lost. `pw import --from pw` reads it back.
+pub(crate) fn render_json(entries: &[(String, Entry)]) -> Result<String, PwError> {
+    let map: BTreeMap<&str, &Entry> = entries.iter().map(|(k, e)| (k.as_str(), e)).collect();
+    Ok(serde_json::to_string_pretty(&map)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_round_trip() {
+        let mut entry = Entry::new("pw".to_string());
+        entry.replace_value("new".to_string());
+        entry.username = Some("alice".to_string());
+        let entries = vec![("key".to_string(), entry)];
+
+        let json = render_json(&entries).unwrap();
+        let parsed: BTreeMap<String, Entry> = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed["key"], entries[0].1);
+    }
+}
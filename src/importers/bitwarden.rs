@@ -0,0 +1,159 @@
+use super::import_format::{ImportFields, ImportedEntry};
+use crate::error::PwError;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+#[derive(Deserialize)]
+struct BitwardenExport {
+    #[serde(default)]
+    encrypted: bool,
+    #[serde(default)]
+    folders: Vec<Folder>,
+    #[serde(default)]
+    items: Vec<Item>,
+}
+
+#[derive(Deserialize)]
+struct Folder {
+    id: String,
+    name: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Item {
+    #[serde(default)]
+    folder_id: Option<String>,
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    notes: Option<String>,
+    #[serde(default)]
+    login: Option<Login>,
+}
+
+#[derive(Deserialize)]
+struct Login {
+    #[serde(default)]
+    username: Option<String>,
+    #[serde(default)]
+    password: Option<String>,
+    #[serde(default)]
+    totp: Option<String>,
+    #[serde(default)]
+    uris: Option<Vec<Uri>>,
+}
+
+#[derive(Deserialize)]
+struct Uri {
+    #[serde(default)]
+    uri: Option<String>,
+}
+
+/// Reads a Bitwarden JSON export. Folders become key prefixes; items without a login, such
+/// as secure notes, are imported with an empty password and their notes.
+pub(crate) fn read_bitwarden(path: &Path) -> Result<Vec<ImportedEntry>, PwError> {
+    let contents = fs::read_to_string(path)?;
+    parse_bitwarden(&contents)
+}
+
+pub(crate) fn parse_bitwarden(contents: &str) -> Result<Vec<ImportedEntry>, PwError> {
+    let export: BitwardenExport = serde_json::from_str(contents)?;
+    if export.encrypted {
+        return Err(PwError::Invalid(
+            "Encrypted Bitwarden exports are not supported; export as unencrypted JSON".to_string(),
+        ));
+    }
+    let folders: HashMap<&str, &str> = export
+        .folders
+        .iter()
+        .map(|folder| (folder.id.as_str(), folder.name.as_str()))
+        .collect();
+
+    Ok(export
+        .items
+        .iter()
+        .map(|item| {
+            let login = item.login.as_ref();
+            let url = login
+                .and_then(|login| login.uris.as_ref())
+                .and_then(|uris| uris.iter().find_map(|uri| uri.uri.as_deref()));
+            let folder = item
+                .folder_id
+                .as_deref()
+                .and_then(|id| folders.get(id).copied());
+            ImportFields {
+                path: folder.into_iter().collect(),
+                name: &item.name,
+                password: login
+                    .and_then(|l| l.password.as_deref())
+                    .unwrap_or_default(),
+                username: login
+                    .and_then(|l| l.username.as_deref())
+                    .unwrap_or_default(),
+                url: url.unwrap_or_default(),
+                notes: item.notes.as_deref().unwrap_or_default(),
+                totp: login.and_then(|l| l.totp.as_deref()).unwrap_or_default(),
+            }
+            .into_imported()
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXPORT: &str = r#"{
+        "encrypted": false,
+        "folders": [{"id": "f1", "name": "Work"}],
+        "items": [
+            {
+                "type": 1,
+                "folderId": "f1",
+                "name": "GitHub",
+                "notes": null,
+                "login": {
+                    "username": "alice",
+                    "password": "hunter2",
+                    "totp": "otpauth://totp/GitHub:alice?secret=JBSWY3DPEHPK3PXP",
+                    "uris": [{"match": null, "uri": "https://github.com"}]
+                }
+            },
+            {
+                "type": 2,
+                "folderId": null,
+                "name": "Wifi",
+                "notes": "password is on the router",
+                "secureNote": {"type": 0}
+            }
+        ]
+    }"#;
+
+    #[test]
+    fn parses_logins_and_notes() {
+        let entries = parse_bitwarden(EXPORT).unwrap();
+        assert_eq!(entries.len(), 2);
+
+        assert_eq!(entries[0].key, "Work/GitHub");
+        assert_eq!(entries[0].entry.value, "hunter2");
+        assert_eq!(entries[0].entry.username.as_deref(), Some("alice"));
+        assert_eq!(entries[0].entry.url.as_deref(), Some("https://github.com"));
+        assert!(entries[0].entry.otp.is_some());
+
+        assert_eq!(entries[1].key, "Wifi");
+        assert_eq!(entries[1].entry.value, "");
+        assert_eq!(
+            entries[1].entry.notes.as_deref(),
+            Some("password is on the router")
+        );
+    }
+
+    #[test]
+    fn rejects_encrypted_export() {
+        let result = parse_bitwarden(r#"{"encrypted": true, "items": []}"#);
+        assert!(result.is_err());
+    }
+}
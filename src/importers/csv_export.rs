@@ -0,0 +1,226 @@
+use super::import_format::{ImportFields, ImportFormat, ImportedEntry};
+use crate::error::PwError;
+use std::io::Read;
+use std::path::Path;
+
+/// Which header names a CSV export uses for each field. Headers are matched
+/// case-insensitively and the first alias present wins.
+struct CsvLayout {
+    name: &'static [&'static str],
+    group: &'static [&'static str],
+    url: &'static [&'static str],
+    username: &'static [&'static str],
+    password: &'static [&'static str],
+    notes: &'static [&'static str],
+    totp: &'static [&'static str],
+}
+
+fn layout(format: ImportFormat) -> CsvLayout {
+    match format {
+        ImportFormat::KeepassCsv => CsvLayout {
+            name: &["title", "account"],
+            group: &["group"],
+            url: &["url", "web site"],
+            username: &["username", "login name", "user name"],
+            password: &["password"],
+            notes: &["notes", "comments"],
+            totp: &["totp"],
+        },
+        ImportFormat::OnePassword => CsvLayout {
+            name: &["title", "name"],
+            group: &[],
+            url: &["url", "website", "urls"],
+            username: &["username"],
+            password: &["password"],
+            notes: &["notes", "notesplain"],
+            totp: &["otpauth", "one-time password"],
+        },
+        ImportFormat::Lastpass => CsvLayout {
+            name: &["name"],
+            group: &["grouping"],
+            url: &["url"],
+            username: &["username"],
+            password: &["password"],
+            notes: &["extra"],
+            totp: &["totp"],
+        },
+        ImportFormat::Chrome => CsvLayout {
+            name: &["name"],
+            group: &[],
+            url: &["url"],
+            username: &["username"],
+            password: &["password"],
+            notes: &["note"],
+            totp: &[],
+        },
+        ImportFormat::Firefox => CsvLayout {
+            name: &[],
+            group: &[],
+            url: &["url"],
+            username: &["username"],
+            password: &["password"],
+            notes: &[],
+            totp: &[],
+        },
+        ImportFormat::Pw => CsvLayout {
+            name: &["key"],
+            group: &[],
+            url: &["url"],
+            username: &["username"],
+            password: &["password"],
+            notes: &["notes"],
+            totp: &["otpauth"],
+        },
+        _ => unreachable!("{:?} is not a CSV format", format),
+    }
+}
+
+/// LastPass exports secure notes with this placeholder URL.
+const LASTPASS_NOTE_URL: &str = "http://sn";
+
+pub(crate) fn read_csv(format: ImportFormat, path: &Path) -> Result<Vec<ImportedEntry>, PwError> {
+    parse_csv(format, std::fs::File::open(path)?)
+}
+
+pub(crate) fn parse_csv(
+    format: ImportFormat,
+    input: impl Read,
+) -> Result<Vec<ImportedEntry>, PwError> {
+    let layout = layout(format);
+    let mut reader = csv::ReaderBuilder::new().flexible(true).from_reader(input);
+    let headers: Vec<String> = reader
+        .headers()?
+        .iter()
+        .map(|h| h.trim().trim_start_matches('\u{feff}').to_ascii_lowercase())
+        .collect();
+    let column = |aliases: &[&str]| {
+        aliases
+            .iter()
+            .find_map(|alias| headers.iter().position(|h| h == alias))
+    };
+    let Some(password_column) = column(layout.password) else {
+        return Err(PwError::Invalid(format!(
+            "The CSV file has no password column (expected one of: {})",
+            layout.password.join(", ")
+        )));
+    };
+    let name_column = column(layout.name);
+    let group_column = column(layout.group);
+    let url_column = column(layout.url);
+    let username_column = column(layout.username);
+    let notes_column = column(layout.notes);
+    let totp_column = column(layout.totp);
+
+    let mut entries = Vec::new();
+    for record in reader.records() {
+        let record = record?;
+        let field = |column: Option<usize>| column.and_then(|i| record.get(i)).unwrap_or_default();
+
+        let group = field(group_column);
+        let mut path: Vec<&str> = group.split(['/', '\\']).collect();
+        // KeePass puts every entry under the database's root group.
+        if format == ImportFormat::KeepassCsv && path.len() > 1 {
+            path.remove(0);
+        } else if format == ImportFormat::KeepassCsv {
+            path.clear();
+        }
+        let mut url = field(url_column);
+        if format == ImportFormat::Lastpass && url == LASTPASS_NOTE_URL {
+            url = "";
+        }
+
+        entries.push(
+            ImportFields {
+                path,
+                name: field(name_column),
+                password: field(Some(password_column)),
+                username: field(username_column),
+                url,
+                notes: field(notes_column),
+                totp: field(totp_column),
+            }
+            .into_imported(),
+        );
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(format: ImportFormat, csv: &str) -> Vec<ImportedEntry> {
+        parse_csv(format, csv.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn keepass_csv() {
+        let entries = parse(
+            ImportFormat::KeepassCsv,
+            "\"Group\",\"Title\",\"Username\",\"Password\",\"URL\",\"Notes\",\"TOTP\"\n\
+             \"Root/Email\",\"Gmail\",\"alice\",\"pw1\",\"https://mail.google.com\",\"multi\nline\",\"\"\n\
+             \"Root\",\"Top\",\"bob\",\"pw2\",\"\",\"\",\"\"\n",
+        );
+        assert_eq!(entries[0].key, "Email/Gmail");
+        assert_eq!(entries[0].entry.notes.as_deref(), Some("multi\nline"));
+        assert_eq!(entries[1].key, "Top");
+        assert_eq!(entries[1].entry.username.as_deref(), Some("bob"));
+    }
+
+    #[test]
+    fn one_password_csv() {
+        let entries = parse(
+            ImportFormat::OnePassword,
+            "Title,Url,Username,Password,OTPAuth,Favorite,Archived,Tags,Notes\n\
+             GitHub,https://github.com,alice,pw,otpauth://totp/GitHub:alice?secret=JBSWY3DPEHPK3PXP,false,false,,note\n",
+        );
+        assert_eq!(entries[0].key, "GitHub");
+        assert!(entries[0].entry.otp.is_some());
+        assert_eq!(entries[0].entry.notes.as_deref(), Some("note"));
+    }
+
+    #[test]
+    fn lastpass_csv() {
+        let entries = parse(
+            ImportFormat::Lastpass,
+            "url,username,password,totp,extra,name,grouping,fav\n\
+             https://example.com,alice,pw,,,Example,Shopping\\Online,0\n\
+             http://sn,,,,the note,Note,,0\n",
+        );
+        assert_eq!(entries[0].key, "Shopping/Online/Example");
+        assert_eq!(entries[1].key, "Note");
+        assert_eq!(entries[1].entry.url, None);
+        assert_eq!(entries[1].entry.notes.as_deref(), Some("the note"));
+    }
+
+    #[test]
+    fn chrome_csv() {
+        let entries = parse(
+            ImportFormat::Chrome,
+            "name,url,username,password,note\n\
+             example.com,https://example.com/,alice,pw,\n",
+        );
+        assert_eq!(entries[0].key, "example.com");
+        assert_eq!(entries[0].entry.value, "pw");
+    }
+
+    #[test]
+    fn firefox_csv_uses_host_as_key() {
+        let entries = parse(
+            ImportFormat::Firefox,
+            "\"url\",\"username\",\"password\",\"httpRealm\",\"formActionOrigin\",\"guid\",\"timeCreated\",\"timeLastUsed\",\"timePasswordChanged\"\n\
+             \"https://login.example.org\",\"alice\",\"pw\",,\"https://login.example.org\",\"{1}\",\"1\",\"1\",\"1\"\n",
+        );
+        assert_eq!(entries[0].key, "login.example.org");
+        assert_eq!(entries[0].entry.username.as_deref(), Some("alice"));
+    }
+
+    #[test]
+    fn missing_password_column_is_an_error() {
+        let result = parse_csv(ImportFormat::Chrome, "name,url\nx,y\n".as_bytes());
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("no password column"));
+    }
+}
@@ -0,0 +1,183 @@
+use super::{bitwarden, csv_export, keepass_xml, password_store, pw_export};
+use crate::cli::prompt::{read_secret, EXPORT_PASSPHRASE_ENV};
+use crate::error::PwError;
+use crate::otp::otpauth::OtpParams;
+use crate::storage::entry::Entry;
+use crate::storage::pass_storage::Gpg;
+use clap::ValueEnum;
+use std::path::Path;
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportFormat {
+    /// Bitwarden unencrypted JSON export.
+    Bitwarden,
+    /// KeePass 2 / KeePassXC XML export.
+    KeepassXml,
+    /// KeePass 2 / KeePassXC CSV export.
+    KeepassCsv,
+    /// 1Password CSV export.
+    #[value(name = "1password")]
+    OnePassword,
+    /// LastPass CSV export.
+    Lastpass,
+    /// Chrome (and other Chromium browsers) CSV export.
+    Chrome,
+    /// Firefox CSV export.
+    Firefox,
+    /// A pass(1) password-store directory, decrypted with gpg.
+    Pass,
+    /// A JSON or CSV export written by pw, encrypted or not.
+    Pw,
+}
+
+/// An entry read from another password manager, with the key it will be stored under.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImportedEntry {
+    pub key: String,
+    pub entry: Entry,
+}
+
+/// The fields other password managers have in common, before they are mapped to an entry.
+#[derive(Debug, Default)]
+pub(crate) struct ImportFields<'a> {
+    pub path: Vec<&'a str>,
+    pub name: &'a str,
+    pub password: &'a str,
+    pub username: &'a str,
+    pub url: &'a str,
+    pub notes: &'a str,
+    pub totp: &'a str,
+}
+
+impl ImportFields<'_> {
+    /// Builds the entry and its key. Entries without a name are keyed by their URL's host.
+    /// A TOTP secret that cannot be parsed is kept in the notes rather than dropped.
+    pub(crate) fn into_imported(self) -> ImportedEntry {
+        let mut notes = non_empty(self.notes);
+        let otp = match non_empty(self.totp) {
+            Some(totp) => match OtpParams::parse(&totp) {
+                Ok(params) => Some(params),
+                Err(_) => {
+                    let line = format!("otp: {}", totp);
+                    notes = Some(match notes {
+                        Some(notes) => format!("{}\n{}", notes, line),
+                        None => line,
+                    });
+                    None
+                }
+            },
+            None => None,
+        };
+
+        let name = non_empty(self.name)
+            .or_else(|| url_host(self.url))
+            .unwrap_or_else(|| "untitled".to_string());
+        let key = self
+            .path
+            .iter()
+            .map(|part| part.trim())
+            .filter(|part| !part.is_empty())
+            .chain(std::iter::once(name.as_str()))
+            .collect::<Vec<_>>()
+            .join("/");
+
+        ImportedEntry {
+            key,
+            entry: Entry {
+                value: self.password.into(),
+                username: non_empty(self.username),
+                url: non_empty(self.url),
+                notes,
+                otp,
+                ..Default::default()
+            },
+        }
+    }
+}
+
+pub fn read_entries(format: ImportFormat, path: &Path) -> Result<Vec<ImportedEntry>, PwError> {
+    match format {
+        ImportFormat::Bitwarden => bitwarden::read_bitwarden(path),
+        ImportFormat::KeepassXml => keepass_xml::read_keepass_xml(path),
+        ImportFormat::Pass => {
+            password_store::read_password_store(path, &|file| Gpg::default().decrypt(file))
+        }
+        ImportFormat::Pw => pw_export::read_pw_export(path, &|| {
+            read_secret("Export passphrase: ", EXPORT_PASSPHRASE_ENV)
+        }),
+        csv_format => csv_export::read_csv(csv_format, path),
+    }
+}
+
+pub(crate) fn non_empty(value: &str) -> Option<String> {
+    let value = value.trim();
+    if value.is_empty() {
+        None
+    } else {
+        Some(value.to_string())
+    }
+}
+
+fn url_host(url: &str) -> Option<String> {
+    url::Url::parse(url.trim())
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_string))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn key_joins_path_and_name() {
+        let imported = ImportFields {
+            path: vec!["Work", " ", "Email "],
+            name: "Gmail",
+            password: "secret",
+            ..Default::default()
+        }
+        .into_imported();
+        assert_eq!(imported.key, "Work/Email/Gmail");
+        assert_eq!(imported.entry.value, "secret");
+    }
+
+    #[test]
+    fn missing_name_falls_back_to_host() {
+        let imported = ImportFields {
+            url: "https://accounts.example.com/login",
+            ..Default::default()
+        }
+        .into_imported();
+        assert_eq!(imported.key, "accounts.example.com");
+
+        let untitled = ImportFields::default().into_imported();
+        assert_eq!(untitled.key, "untitled");
+    }
+
+    #[test]
+    fn invalid_totp_is_kept_in_notes() {
+        let imported = ImportFields {
+            name: "site",
+            notes: "note",
+            totp: "not a secret!",
+            ..Default::default()
+        }
+        .into_imported();
+        assert!(imported.entry.otp.is_none());
+        assert_eq!(
+            imported.entry.notes.as_deref(),
+            Some("note\notp: not a secret!")
+        );
+    }
+
+    #[test]
+    fn valid_totp_is_parsed() {
+        let imported = ImportFields {
+            name: "site",
+            totp: "JBSWY3DPEHPK3PXP",
+            ..Default::default()
+        }
+        .into_imported();
+        assert_eq!(imported.entry.otp.unwrap().secret, "JBSWY3DPEHPK3PXP");
+    }
+}
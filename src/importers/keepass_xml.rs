@@ -0,0 +1,182 @@
+use super::import_format::{ImportFields, ImportedEntry};
+use crate::error::PwError;
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use std::collections::HashMap;
+use std::path::Path;
+
+const RECYCLE_BIN: &str = "Recycle Bin";
+
+/// Reads a KeePass XML export. Group names below the root group become key prefixes;
+/// entry history and the recycle bin are skipped.
+pub(crate) fn read_keepass_xml(path: &Path) -> Result<Vec<ImportedEntry>, PwError> {
+    let contents = std::fs::read_to_string(path)?;
+    parse_keepass_xml(&contents)
+}
+
+pub(crate) fn parse_keepass_xml(xml: &str) -> Result<Vec<ImportedEntry>, PwError> {
+    let mut reader = Reader::from_str(xml);
+    let mut elements: Vec<String> = Vec::new();
+    let mut groups: Vec<String> = Vec::new();
+    let mut history_depth = 0;
+    let mut fields: Option<HashMap<String, String>> = None;
+    let mut key = String::new();
+    let mut value = String::new();
+    let mut entries = Vec::new();
+
+    loop {
+        match reader.read_event()? {
+            Event::Start(e) => {
+                let name = String::from_utf8_lossy(e.local_name().as_ref()).into_owned();
+                match name.as_str() {
+                    "Group" => groups.push(String::new()),
+                    "History" => history_depth += 1,
+                    "Entry" if history_depth == 0 => fields = Some(HashMap::new()),
+                    "Key" => key.clear(),
+                    "Value" => value.clear(),
+                    _ => {}
+                }
+                elements.push(name);
+            }
+            Event::Empty(e) if e.local_name().as_ref() == b"Value" => value.clear(),
+            Event::Text(e) => {
+                append_text(&elements, &mut groups, &mut key, &mut value, &e.unescape()?)
+            }
+            Event::CData(e) => append_text(
+                &elements,
+                &mut groups,
+                &mut key,
+                &mut value,
+                &String::from_utf8_lossy(&e),
+            ),
+            Event::End(e) => {
+                elements.pop();
+                match e.local_name().as_ref() {
+                    b"Group" => {
+                        groups.pop();
+                    }
+                    b"History" => history_depth -= 1,
+                    b"String" if history_depth == 0 => {
+                        if let Some(fields) = fields.as_mut() {
+                            fields.insert(std::mem::take(&mut key), std::mem::take(&mut value));
+                        }
+                    }
+                    b"Entry" if history_depth == 0 => {
+                        if let Some(fields) = fields.take() {
+                            if groups.iter().any(|g| g == RECYCLE_BIN) {
+                                continue;
+                            }
+                            entries.push(entry_from_fields(&groups, &fields));
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(entries)
+}
+
+fn append_text(
+    elements: &[String],
+    groups: &mut [String],
+    key: &mut String,
+    value: &mut String,
+    text: &str,
+) {
+    let parent = elements.len().checked_sub(2).map(|i| elements[i].as_str());
+    match (parent, elements.last().map(String::as_str)) {
+        (Some("Group"), Some("Name")) => {
+            if let Some(group) = groups.last_mut() {
+                group.push_str(text);
+            }
+        }
+        (Some("String"), Some("Key")) => key.push_str(text),
+        (Some("String"), Some("Value")) => value.push_str(text),
+        _ => {}
+    }
+}
+
+fn entry_from_fields(groups: &[String], fields: &HashMap<String, String>) -> ImportedEntry {
+    let field = |name: &str| fields.get(name).map(String::as_str).unwrap_or_default();
+    let totp = match field("otp") {
+        "" => field("TOTP Seed"),
+        otp => otp,
+    };
+    ImportFields {
+        // The first group is the database's root group.
+        path: groups.iter().skip(1).map(String::as_str).collect(),
+        name: field("Title"),
+        password: field("Password"),
+        username: field("UserName"),
+        url: field("URL"),
+        notes: field("Notes"),
+        totp,
+    }
+    .into_imported()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXPORT: &str = r#"<?xml version="1.0" encoding="utf-8" standalone="yes"?>
+<KeePassFile>
+  <Meta><DatabaseName>Passwords</DatabaseName></Meta>
+  <Root>
+    <Group>
+      <Name>Passwords</Name>
+      <Entry>
+        <String><Key>Title</Key><Value>Top &amp; Level</Value></String>
+        <String><Key>Password</Key><Value>pw1</Value></String>
+        <String><Key>UserName</Key><Value/></String>
+      </Entry>
+      <Group>
+        <Name>Email</Name>
+        <Entry>
+          <String><Key>Title</Key><Value>Gmail</Value></String>
+          <String><Key>UserName</Key><Value>alice</Value></String>
+          <String><Key>Password</Key><Value>current</Value></String>
+          <String><Key>URL</Key><Value>https://mail.google.com</Value></String>
+          <String><Key>otp</Key><Value>otpauth://totp/Gmail:alice?secret=JBSWY3DPEHPK3PXP</Value></String>
+          <History>
+            <Entry>
+              <String><Key>Title</Key><Value>Gmail</Value></String>
+              <String><Key>Password</Key><Value>old</Value></String>
+            </Entry>
+          </History>
+        </Entry>
+      </Group>
+      <Group>
+        <Name>Recycle Bin</Name>
+        <Entry>
+          <String><Key>Title</Key><Value>Deleted</Value></String>
+          <String><Key>Password</Key><Value>gone</Value></String>
+        </Entry>
+      </Group>
+    </Group>
+  </Root>
+</KeePassFile>"#;
+
+    #[test]
+    fn parses_groups_and_entries() {
+        let entries = parse_keepass_xml(EXPORT).unwrap();
+        assert_eq!(entries.len(), 2);
+
+        assert_eq!(entries[0].key, "Top & Level");
+        assert_eq!(entries[0].entry.value, "pw1");
+        assert_eq!(entries[0].entry.username, None);
+
+        assert_eq!(entries[1].key, "Email/Gmail");
+        assert_eq!(entries[1].entry.value, "current");
+        assert_eq!(entries[1].entry.username.as_deref(), Some("alice"));
+        assert!(entries[1].entry.otp.is_some());
+    }
+
+    #[test]
+    fn malformed_xml_is_an_error() {
+        assert!(parse_keepass_xml("<KeePassFile><Root></Group>").is_err());
+    }
+}
@@ -0,0 +1,6 @@
+pub(crate) mod bitwarden;
+pub(crate) mod csv_export;
+pub(crate) mod import_format;
+pub(crate) mod keepass_xml;
+pub(crate) mod password_store;
+pub(crate) mod pw_export;
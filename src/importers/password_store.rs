@@ -0,0 +1,149 @@
+use super::import_format::{ImportFields, ImportedEntry};
+use crate::error::PwError;
+use std::fs;
+use std::io;
+use std::path::Path;
+use zeroize::Zeroizing;
+
+/// Reads every `.gpg` file below `root`, keyed by its path relative to `root`.
+pub(crate) fn read_password_store(
+    root: &Path,
+    decrypt: &dyn Fn(&Path) -> io::Result<Zeroizing<String>>,
+) -> Result<Vec<ImportedEntry>, PwError> {
+    if !root.is_dir() {
+        return Err(PwError::Invalid(format!(
+            "{} is not a password-store directory",
+            root.display()
+        )));
+    }
+    let mut entries = Vec::new();
+    visit(root, root, decrypt, &mut entries)?;
+    entries.sort_by(|a, b| a.key.cmp(&b.key));
+    Ok(entries)
+}
+
+fn visit(
+    root: &Path,
+    dir: &Path,
+    decrypt: &dyn Fn(&Path) -> io::Result<Zeroizing<String>>,
+    entries: &mut Vec<ImportedEntry>,
+) -> io::Result<()> {
+    for dir_entry in fs::read_dir(dir)? {
+        let path = dir_entry?.path();
+        let hidden = path
+            .file_name()
+            .is_some_and(|name| name.to_string_lossy().starts_with('.'));
+        if hidden {
+            continue;
+        }
+        if path.is_dir() {
+            visit(root, &path, decrypt, entries)?;
+        } else if path.extension().is_some_and(|ext| ext == "gpg") {
+            let relative = path.strip_prefix(root).unwrap_or(&path).with_extension("");
+            let components: Vec<String> = relative
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy().into_owned())
+                .collect();
+            let contents = decrypt(&path)?;
+            entries.push(parse_pass_contents(&components, &contents));
+        }
+    }
+    Ok(())
+}
+
+/// Splits a decrypted pass file using the usual conventions: the password on the first
+/// line, then `login:`/`url:` style fields, an `otpauth://` line, and free-form notes.
+pub(crate) fn parse_pass_contents(components: &[String], contents: &str) -> ImportedEntry {
+    let mut lines = contents.lines();
+    let password = lines.next().unwrap_or_default();
+    let mut username = "";
+    let mut url = "";
+    let mut totp = "";
+    let mut notes = Vec::new();
+    for line in lines {
+        if line.starts_with("otpauth://") {
+            totp = line;
+            continue;
+        }
+        match line.split_once(':') {
+            Some((field, value)) => match field.trim().to_ascii_lowercase().as_str() {
+                "login" | "username" | "user" | "email" if username.is_empty() => {
+                    username = value.trim()
+                }
+                "url" | "website" if url.is_empty() => url = value.trim(),
+                _ => notes.push(line),
+            },
+            None => notes.push(line),
+        }
+    }
+    let notes = notes.join("\n");
+
+    let (name, path) = components
+        .split_last()
+        .map(|(name, path)| (name.as_str(), path))
+        .unwrap_or_default();
+    ImportFields {
+        path: path.iter().map(String::as_str).collect(),
+        name,
+        password,
+        username,
+        url,
+        notes: &notes,
+        totp,
+    }
+    .into_imported()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn parses_pass_conventions() {
+        let imported = parse_pass_contents(
+            &["work".to_string(), "github".to_string()],
+            "hunter2\nlogin: alice\nurl: https://github.com\notpauth://totp/x?secret=JBSWY3DPEHPK3PXP\nrecovery codes below\n1234-5678\n",
+        );
+        assert_eq!(imported.key, "work/github");
+        assert_eq!(imported.entry.value, "hunter2");
+        assert_eq!(imported.entry.username.as_deref(), Some("alice"));
+        assert_eq!(imported.entry.url.as_deref(), Some("https://github.com"));
+        assert!(imported.entry.otp.is_some());
+        assert_eq!(
+            imported.entry.notes.as_deref(),
+            Some("recovery codes below\n1234-5678")
+        );
+    }
+
+    #[test]
+    fn walks_store_directory() -> io::Result<()> {
+        let temp_dir = tempdir()?;
+        let root = temp_dir.path();
+        fs::create_dir_all(root.join("work"))?;
+        fs::create_dir_all(root.join(".git"))?;
+        fs::write(root.join(".gpg-id"), "alice@example.com\n")?;
+        fs::write(root.join("email.gpg"), "pw-email")?;
+        fs::write(root.join("work/github.gpg"), "pw-github\nlogin: alice")?;
+        fs::write(root.join(".git/ignored.gpg"), "nope")?;
+
+        // Stand in for gpg: the "encrypted" files hold plaintext.
+        let entries =
+            read_password_store(root, &|path| fs::read_to_string(path).map(Zeroizing::new))
+                .unwrap();
+        let keys: Vec<&str> = entries.iter().map(|e| e.key.as_str()).collect();
+        assert_eq!(keys, vec!["email", "work/github"]);
+        assert_eq!(entries[1].entry.username.as_deref(), Some("alice"));
+        Ok(())
+    }
+
+    #[test]
+    fn decrypt_errors_abort_import() -> io::Result<()> {
+        let temp_dir = tempdir()?;
+        fs::write(temp_dir.path().join("email.gpg"), "x")?;
+
+        let result = read_password_store(temp_dir.path(), &|_| Err(io::Error::other("no key")));
+        assert!(result.is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,141 @@
+use super::bitwarden::parse_bitwarden;
+use super::csv_export::parse_csv;
+use super::import_format::{ImportFormat, ImportedEntry};
+use super::keepass_xml::parse_keepass_xml;
+use crate::crypto::secret::Secret;
+use crate::error::PwError;
+use crate::exporters::encrypted::{EncryptedExport, ENCRYPTED_EXPORT_FORMAT};
+use crate::storage::entry::Entry;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Reads a file written by `pw export`: JSON or CSV, or any format encrypted with
+/// `--encrypt`. `passphrase` is only called for encrypted exports.
+pub(crate) fn read_pw_export(
+    path: &Path,
+    passphrase: &dyn Fn() -> io::Result<Secret>,
+) -> Result<Vec<ImportedEntry>, PwError> {
+    let contents = fs::read_to_string(path)?;
+    parse_pw_export(&contents, passphrase)
+}
+
+fn parse_pw_export(
+    contents: &str,
+    passphrase: &dyn Fn() -> io::Result<Secret>,
+) -> Result<Vec<ImportedEntry>, PwError> {
+    if !contents.trim_start().starts_with('{') {
+        return parse_csv(ImportFormat::Pw, contents.as_bytes());
+    }
+    let value: Value = serde_json::from_str(contents)?;
+    if value.get("format").and_then(Value::as_str) != Some(ENCRYPTED_EXPORT_FORMAT) {
+        return parse_json(value);
+    }
+
+    let export: EncryptedExport = serde_json::from_value(value)?;
+    let plaintext = export.sealed.open(passphrase()?.expose().as_bytes())?;
+    let plaintext = std::str::from_utf8(&plaintext)
+        .map_err(|_| PwError::Corrupt("The decrypted export is not valid UTF-8".to_string()))?;
+    match export.content_format.as_str() {
+        "json" => parse_json(serde_json::from_str(plaintext)?),
+        "csv" => parse_csv(ImportFormat::Pw, plaintext.as_bytes()),
+        "bitwarden" => parse_bitwarden(plaintext),
+        "keepass-xml" => parse_keepass_xml(plaintext),
+        other => Err(PwError::Invalid(format!(
+            "The export contains '{}' data, which this version of pw cannot import",
+            other
+        ))),
+    }
+}
+
+/// pw's JSON export maps keys to complete entries, so history and generator settings are
+/// kept.
+fn parse_json(value: Value) -> Result<Vec<ImportedEntry>, PwError> {
+    let entries: BTreeMap<String, Entry> = serde_json::from_value(value)?;
+    Ok(entries
+        .into_iter()
+        .map(|(key, entry)| ImportedEntry { key, entry })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::envelope::SealedBox;
+    use crate::crypto::kdf::TEST_KDF_PARAMS;
+    use crate::exporters::export_format::{render_entries, ExportFormat};
+    use crate::otp::otpauth::OtpParams;
+
+    fn entries() -> Vec<(String, Entry)> {
+        let mut entry = Entry::new("old".to_string());
+        entry.replace_value("hunter2".to_string());
+        entry.username = Some("alice".to_string());
+        entry.otp = Some(OtpParams::parse("JBSWY3DPEHPK3PXP").unwrap());
+        vec![
+            ("Work/GitHub".to_string(), entry),
+            ("bank".to_string(), Entry::new("p,w\"1".to_string())),
+        ]
+    }
+
+    fn encrypt(format: ExportFormat, content_format: &str) -> String {
+        let plaintext = render_entries(format, &entries()).unwrap();
+        let export = EncryptedExport {
+            format: ENCRYPTED_EXPORT_FORMAT.to_string(),
+            content_format: content_format.to_string(),
+            sealed: SealedBox::seal(b"passphrase", plaintext.as_bytes(), TEST_KDF_PARAMS).unwrap(),
+        };
+        serde_json::to_string(&export).unwrap()
+    }
+
+    fn no_passphrase() -> io::Result<Secret> {
+        panic!("the passphrase is only needed for encrypted exports")
+    }
+
+    #[test]
+    fn json_export_round_trips() {
+        let json = render_entries(ExportFormat::Json, &entries()).unwrap();
+        let imported = parse_pw_export(&json, &no_passphrase).unwrap();
+        let expected: Vec<ImportedEntry> = entries()
+            .into_iter()
+            .map(|(key, entry)| ImportedEntry { key, entry })
+            .collect();
+        assert_eq!(imported, expected);
+    }
+
+    #[test]
+    fn csv_export_keeps_keys() {
+        let csv = render_entries(ExportFormat::Csv, &entries()).unwrap();
+        let imported = parse_pw_export(&csv, &no_passphrase).unwrap();
+        assert_eq!(imported[0].key, "Work/GitHub");
+        assert_eq!(imported[0].entry.value, "hunter2");
+        assert_eq!(imported[0].entry.username.as_deref(), Some("alice"));
+        assert!(imported[0].entry.otp.is_some());
+        assert_eq!(imported[1].entry.value, "p,w\"1");
+    }
+
+    #[test]
+    fn encrypted_exports_are_decrypted() {
+        for (format, name) in [
+            (ExportFormat::Json, "json"),
+            (ExportFormat::Csv, "csv"),
+            (ExportFormat::Bitwarden, "bitwarden"),
+            (ExportFormat::KeepassXml, "keepass-xml"),
+        ] {
+            let export = encrypt(format, name);
+            let mut imported = parse_pw_export(&export, &|| Ok("passphrase".into())).unwrap();
+            imported.sort_by(|a, b| a.key.cmp(&b.key));
+            let keys: Vec<&str> = imported.iter().map(|i| i.key.as_str()).collect();
+            assert_eq!(keys, vec!["Work/GitHub", "bank"], "{}", name);
+            assert_eq!(imported[0].entry.value, "hunter2", "{}", name);
+        }
+    }
+
+    #[test]
+    fn wrong_passphrase_is_refused() {
+        let export = encrypt(ExportFormat::Json, "json");
+        let result = parse_pw_export(&export, &|| Ok("wrong".into()));
+        assert!(matches!(result, Err(PwError::Auth(_))));
+    }
+}
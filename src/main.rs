@@ -1,8 +1,23 @@
 mod cli;
+mod config;
+mod crypto;
+mod error;
+mod exporters;
+mod importers;
+mod otp;
 mod password_generator;
 mod risk_analyzer;
 mod storage;
 
-fn main() {
-    let _ = cli::parser::main();
+use std::process::ExitCode;
+
+fn main() -> ExitCode {
+    crypto::memory::disable_core_dumps();
+    match cli::parser::main() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::from(e.exit_code())
+        }
+    }
 }
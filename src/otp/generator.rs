@@ -0,0 +1,133 @@
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha1::Sha1;
+use sha2::{Sha256, Sha512};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum Algorithm {
+    #[default]
+    Sha1,
+    Sha256,
+    Sha512,
+}
+
+impl Algorithm {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_ascii_uppercase().replace('-', "").as_str() {
+            "SHA1" => Some(Algorithm::Sha1),
+            "SHA256" => Some(Algorithm::Sha256),
+            "SHA512" => Some(Algorithm::Sha512),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Algorithm::Sha1 => "SHA1",
+            Algorithm::Sha256 => "SHA256",
+            Algorithm::Sha512 => "SHA512",
+        }
+    }
+
+    fn mac(&self, key: &[u8], message: &[u8]) -> Vec<u8> {
+        // HMAC accepts keys of any length, so `new_from_slice` cannot fail.
+        match self {
+            Algorithm::Sha1 => {
+                let mut mac = Hmac::<Sha1>::new_from_slice(key).unwrap();
+                mac.update(message);
+                mac.finalize().into_bytes().to_vec()
+            }
+            Algorithm::Sha256 => {
+                let mut mac = Hmac::<Sha256>::new_from_slice(key).unwrap();
+                mac.update(message);
+                mac.finalize().into_bytes().to_vec()
+            }
+            Algorithm::Sha512 => {
+                let mut mac = Hmac::<Sha512>::new_from_slice(key).unwrap();
+                mac.update(message);
+                mac.finalize().into_bytes().to_vec()
+            }
+        }
+    }
+}
+
+/// Computes an RFC 4226 HOTP code, zero-padded to `digits` digits.
+pub(crate) fn hotp(secret: &[u8], counter: u64, digits: u32, algorithm: Algorithm) -> String {
+    let hash = algorithm.mac(secret, &counter.to_be_bytes());
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let truncated = u32::from_be_bytes([
+        hash[offset] & 0x7f,
+        hash[offset + 1],
+        hash[offset + 2],
+        hash[offset + 3],
+    ]);
+    let code = u64::from(truncated) % 10u64.pow(digits);
+    format!("{:0width$}", code, width = digits as usize)
+}
+
+/// Computes an RFC 6238 TOTP code for `unix_time` and the number of seconds it stays valid.
+pub(crate) fn totp(
+    secret: &[u8],
+    unix_time: u64,
+    period: u64,
+    digits: u32,
+    algorithm: Algorithm,
+) -> (String, u64) {
+    let code = hotp(secret, unix_time / period, digits, algorithm);
+    (code, period - unix_time % period)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHA1_SEED: &[u8] = b"12345678901234567890";
+    const SHA256_SEED: &[u8] = b"12345678901234567890123456789012";
+    const SHA512_SEED: &[u8] = b"1234567890123456789012345678901234567890123456789012345678901234";
+
+    // RFC 4226, Appendix D.
+    #[test]
+    fn hotp_rfc4226_vectors() {
+        let expected = [
+            "755224", "287082", "359152", "969429", "338314", "254676", "287922", "162583",
+            "399871", "520489",
+        ];
+        for (counter, code) in expected.iter().enumerate() {
+            assert_eq!(hotp(SHA1_SEED, counter as u64, 6, Algorithm::Sha1), *code);
+        }
+    }
+
+    // RFC 6238, Appendix B.
+    #[test]
+    fn totp_rfc6238_vectors() {
+        let vectors = [
+            (59, "94287082", "46119246", "90693936"),
+            (1111111109, "07081804", "68084774", "25091201"),
+            (1111111111, "14050471", "67062674", "99943326"),
+            (1234567890, "89005924", "91819424", "93441116"),
+            (2000000000, "69279037", "90698825", "38618901"),
+            (20000000000, "65353130", "77737706", "47863826"),
+        ];
+        for (time, sha1, sha256, sha512) in vectors {
+            assert_eq!(totp(SHA1_SEED, time, 30, 8, Algorithm::Sha1).0, sha1);
+            assert_eq!(totp(SHA256_SEED, time, 30, 8, Algorithm::Sha256).0, sha256);
+            assert_eq!(totp(SHA512_SEED, time, 30, 8, Algorithm::Sha512).0, sha512);
+        }
+    }
+
+    #[test]
+    fn totp_reports_remaining_seconds() {
+        assert_eq!(totp(SHA1_SEED, 59, 30, 6, Algorithm::Sha1).1, 1);
+        assert_eq!(totp(SHA1_SEED, 60, 30, 6, Algorithm::Sha1).1, 30);
+        assert_eq!(totp(SHA1_SEED, 65, 60, 6, Algorithm::Sha1).1, 55);
+    }
+
+    #[test]
+    fn parse_algorithm_names() {
+        assert_eq!(Algorithm::parse("sha1"), Some(Algorithm::Sha1));
+        assert_eq!(Algorithm::parse("SHA-256"), Some(Algorithm::Sha256));
+        assert_eq!(Algorithm::parse("SHA512"), Some(Algorithm::Sha512));
+        assert_eq!(Algorithm::parse("MD5"), None);
+    }
+}
@@ -0,0 +1,393 @@
+use super::generator::{hotp, totp, Algorithm};
+use crate::crypto::secret::Secret;
+use data_encoding::BASE32_NOPAD;
+use percent_encoding::{percent_decode_str, utf8_percent_encode, NON_ALPHANUMERIC};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use url::Url;
+use zeroize::Zeroizing;
+
+pub const DEFAULT_DIGITS: u32 = 6;
+pub const DEFAULT_PERIOD: u64 = 30;
+
+/// Everything needed to compute one-time codes for an entry, as found in an
+/// `otpauth://` URI.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct OtpParams {
+    /// The shared secret, base32-encoded without padding.
+    pub secret: Secret,
+    #[serde(default)]
+    pub algorithm: Algorithm,
+    pub digits: u32,
+    pub kind: OtpKind,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub issuer: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub account: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum OtpKind {
+    Totp { period: u64 },
+    Hotp { counter: u64 },
+}
+
+#[derive(Debug, PartialEq)]
+pub enum OtpError {
+    InvalidSecret,
+    InvalidUri(String),
+    UnsupportedType(String),
+    UnsupportedAlgorithm(String),
+    InvalidDigits(String),
+    InvalidPeriod(String),
+    InvalidCounter(String),
+    CounterExhausted,
+}
+
+impl fmt::Display for OtpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OtpError::InvalidSecret => write!(f, "the secret is not valid base32"),
+            OtpError::InvalidUri(reason) => write!(f, "invalid otpauth URI: {}", reason),
+            OtpError::UnsupportedType(kind) => {
+                write!(f, "'{}' is not a supported OTP type (totp or hotp)", kind)
+            }
+            OtpError::UnsupportedAlgorithm(name) => write!(
+                f,
+                "'{}' is not a supported algorithm (SHA1, SHA256 or SHA512)",
+                name
+            ),
+            OtpError::InvalidDigits(digits) => {
+                write!(f, "'{}' is not a valid number of digits (6 to 8)", digits)
+            }
+            OtpError::InvalidPeriod(period) => {
+                write!(f, "'{}' is not a valid period in seconds", period)
+            }
+            OtpError::InvalidCounter(counter) => write!(f, "'{}' is not a valid counter", counter),
+            OtpError::CounterExhausted => write!(f, "the HOTP counter cannot be advanced further"),
+        }
+    }
+}
+
+impl std::error::Error for OtpError {}
+
+impl OtpParams {
+    /// Parses either an `otpauth://` URI or a bare base32 secret. A bare secret gets the
+    /// usual authenticator defaults: TOTP, SHA1, 6 digits, 30 second period.
+    pub fn parse(input: &str) -> Result<Self, OtpError> {
+        if input.trim_start().starts_with("otpauth://") {
+            Self::parse_uri(input.trim())
+        } else {
+            Self::from_secret(input)
+        }
+    }
+
+    pub fn from_secret(secret: &str) -> Result<Self, OtpError> {
+        Ok(Self {
+            secret: normalize_secret(secret)?,
+            algorithm: Algorithm::default(),
+            digits: DEFAULT_DIGITS,
+            kind: OtpKind::Totp {
+                period: DEFAULT_PERIOD,
+            },
+            issuer: None,
+            account: None,
+        })
+    }
+
+    fn parse_uri(uri: &str) -> Result<Self, OtpError> {
+        let url = Url::parse(uri).map_err(|e| OtpError::InvalidUri(e.to_string()))?;
+        let kind = url.host_str().unwrap_or_default().to_ascii_lowercase();
+
+        let mut secret = None;
+        let mut issuer = None;
+        let mut algorithm = Algorithm::default();
+        let mut digits = DEFAULT_DIGITS;
+        let mut period = DEFAULT_PERIOD;
+        let mut counter = None;
+        for (name, value) in url.query_pairs() {
+            match name.as_ref() {
+                "secret" => secret = Some(normalize_secret(&value)?),
+                "issuer" => issuer = Some(value.to_string()),
+                "algorithm" => {
+                    algorithm = Algorithm::parse(&value)
+                        .ok_or_else(|| OtpError::UnsupportedAlgorithm(value.to_string()))?
+                }
+                "digits" => {
+                    digits = value
+                        .parse()
+                        .ok()
+                        .filter(|d| (6..=8).contains(d))
+                        .ok_or_else(|| OtpError::InvalidDigits(value.to_string()))?
+                }
+                "period" => {
+                    period = value
+                        .parse()
+                        .ok()
+                        .filter(|p| *p > 0)
+                        .ok_or_else(|| OtpError::InvalidPeriod(value.to_string()))?
+                }
+                "counter" => {
+                    counter = Some(
+                        value
+                            .parse()
+                            .map_err(|_| OtpError::InvalidCounter(value.to_string()))?,
+                    )
+                }
+                _ => {}
+            }
+        }
+
+        let kind = match kind.as_str() {
+            "totp" => OtpKind::Totp { period },
+            "hotp" => OtpKind::Hotp {
+                counter: counter.ok_or_else(|| {
+                    OtpError::InvalidUri("hotp URIs need a counter parameter".to_string())
+                })?,
+            },
+            other => return Err(OtpError::UnsupportedType(other.to_string())),
+        };
+
+        // The label is "issuer:account" or just "account", percent-encoded.
+        let label = url.path().trim_start_matches('/');
+        let label = percent_decode_str(label).decode_utf8_lossy();
+        let (label_issuer, account) = match label.split_once(':') {
+            Some((issuer, account)) => (Some(issuer.trim().to_string()), account.trim()),
+            None => (None, label.trim()),
+        };
+
+        Ok(Self {
+            secret: secret
+                .ok_or_else(|| OtpError::InvalidUri("missing secret parameter".to_string()))?,
+            algorithm,
+            digits,
+            kind,
+            issuer: issuer.or(label_issuer).filter(|i| !i.is_empty()),
+            account: Some(account.to_string()).filter(|a| !a.is_empty()),
+        })
+    }
+
+    /// Renders the params as an `otpauth://` URI, the format authenticator apps and other
+    /// password managers import. The URI holds the secret, so it is a `Secret` too.
+    pub fn to_uri(&self) -> Secret {
+        let encode = |value: &str| utf8_percent_encode(value, NON_ALPHANUMERIC).to_string();
+        let account = self.account.as_deref().unwrap_or_default();
+        let label = match &self.issuer {
+            Some(issuer) => format!("{}:{}", encode(issuer), encode(account)),
+            None => encode(account),
+        };
+        let (kind, moving_factor) = match self.kind {
+            OtpKind::Totp { period } => ("totp", format!("period={}", period)),
+            OtpKind::Hotp { counter } => ("hotp", format!("counter={}", counter)),
+        };
+        let issuer = self
+            .issuer
+            .as_ref()
+            .map(|issuer| format!("&issuer={}", encode(issuer)))
+            .unwrap_or_default();
+        format!(
+            "otpauth://{}/{}?secret={}&algorithm={}&digits={}&{}{}",
+            kind,
+            label,
+            self.secret.expose(),
+            self.algorithm.name(),
+            self.digits,
+            moving_factor,
+            issuer
+        )
+        .into()
+    }
+
+    /// `secret` is validated whenever params are parsed, so decoding only fails for
+    /// hand-edited vaults. Those get an error rather than codes from an empty key.
+    fn secret_bytes(&self) -> Result<Zeroizing<Vec<u8>>, OtpError> {
+        match BASE32_NOPAD.decode(self.secret.expose().as_bytes()) {
+            Ok(bytes) if !bytes.is_empty() => Ok(Zeroizing::new(bytes)),
+            _ => Err(OtpError::InvalidSecret),
+        }
+    }
+
+    /// `parse` only accepts 6 to 8 digits and a non-zero period, but params read from a
+    /// vault or a pw export are deserialized without it, so they are checked again here.
+    fn check_settings(&self) -> Result<(), OtpError> {
+        if !(6..=8).contains(&self.digits) {
+            return Err(OtpError::InvalidDigits(self.digits.to_string()));
+        }
+        if let OtpKind::Totp { period: 0 } = self.kind {
+            return Err(OtpError::InvalidPeriod("0".to_string()));
+        }
+        Ok(())
+    }
+
+    /// Computes the current code. For TOTP the seconds until it expires are returned too.
+    /// For HOTP the counter is advanced, so the params must be saved afterwards.
+    pub fn next_code(&mut self, unix_time: u64) -> Result<(String, Option<u64>), OtpError> {
+        self.check_settings()?;
+        let secret = self.secret_bytes()?;
+        match &mut self.kind {
+            OtpKind::Totp { period } => {
+                let (code, remaining) =
+                    totp(&secret, unix_time, *period, self.digits, self.algorithm);
+                Ok((code, Some(remaining)))
+            }
+            OtpKind::Hotp { counter } => {
+                let next = counter.checked_add(1).ok_or(OtpError::CounterExhausted)?;
+                let code = hotp(&secret, *counter, self.digits, self.algorithm);
+                *counter = next;
+                Ok((code, None))
+            }
+        }
+    }
+}
+
+fn normalize_secret(secret: &str) -> Result<Secret, OtpError> {
+    let normalized: Secret = secret
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != '-' && *c != '=')
+        .map(|c| c.to_ascii_uppercase())
+        .collect::<String>()
+        .into();
+    match BASE32_NOPAD
+        .decode(normalized.expose().as_bytes())
+        .map(Zeroizing::new)
+    {
+        Ok(bytes) if !bytes.is_empty() => Ok(normalized),
+        _ => Err(OtpError::InvalidSecret),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_bare_secret() {
+        let params = OtpParams::parse("jbsw y3dp ehpk 3pxp").unwrap();
+        assert_eq!(params.secret, "JBSWY3DPEHPK3PXP");
+        assert_eq!(params.digits, 6);
+        assert_eq!(params.kind, OtpKind::Totp { period: 30 });
+        assert_eq!(params.algorithm, Algorithm::Sha1);
+        assert!(!format!("{:?}", params).contains("JBSWY3DPEHPK3PXP"));
+    }
+
+    #[test]
+    fn parse_totp_uri() {
+        let params = OtpParams::parse(
+            "otpauth://totp/ACME%20Co:john.doe@email.com?secret=HXDMVJECJJWSRB3HWIZR4IFUGFTMXBOZ&issuer=ACME%20Co&algorithm=SHA256&digits=8&period=60",
+        )
+        .unwrap();
+        assert_eq!(params.secret, "HXDMVJECJJWSRB3HWIZR4IFUGFTMXBOZ");
+        assert_eq!(params.algorithm, Algorithm::Sha256);
+        assert_eq!(params.digits, 8);
+        assert_eq!(params.kind, OtpKind::Totp { period: 60 });
+        assert_eq!(params.issuer.as_deref(), Some("ACME Co"));
+        assert_eq!(params.account.as_deref(), Some("john.doe@email.com"));
+    }
+
+    #[test]
+    fn parse_hotp_uri() {
+        let params = OtpParams::parse(
+            "otpauth://hotp/alice?secret=GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ&counter=3",
+        )
+        .unwrap();
+        assert_eq!(params.kind, OtpKind::Hotp { counter: 3 });
+        assert_eq!(params.account.as_deref(), Some("alice"));
+    }
+
+    #[test]
+    fn hotp_advances_counter() {
+        // base32 of the RFC 4226 secret "12345678901234567890".
+        let mut params = OtpParams::parse(
+            "otpauth://hotp/alice?secret=GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ&counter=0",
+        )
+        .unwrap();
+        assert_eq!(params.next_code(0), Ok(("755224".to_string(), None)));
+        assert_eq!(params.next_code(0), Ok(("287082".to_string(), None)));
+        assert_eq!(params.kind, OtpKind::Hotp { counter: 2 });
+    }
+
+    #[test]
+    fn totp_code_from_params() {
+        let mut params = OtpParams::parse("GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ").unwrap();
+        params.digits = 8;
+        assert_eq!(params.next_code(59), Ok(("94287082".to_string(), Some(1))));
+    }
+
+    #[test]
+    fn damaged_params_give_no_code() {
+        let mut params = OtpParams::parse("JBSWY3DPEHPK3PXP").unwrap();
+        params.secret = "not base32!".into();
+        assert_eq!(params.next_code(0), Err(OtpError::InvalidSecret));
+
+        let mut params = OtpParams::parse(&format!(
+            "otpauth://hotp/alice?secret=JBSWY3DPEHPK3PXP&counter={}",
+            u64::MAX
+        ))
+        .unwrap();
+        assert_eq!(params.next_code(0), Err(OtpError::CounterExhausted));
+        assert_eq!(params.kind, OtpKind::Hotp { counter: u64::MAX });
+    }
+
+    #[test]
+    fn deserialized_params_with_bad_settings_give_no_code() {
+        let params = |digits: u32, kind: &str| -> OtpParams {
+            serde_json::from_str(&format!(
+                r#"{{"secret": "JBSWY3DPEHPK3PXP", "digits": {}, "kind": {}}}"#,
+                digits, kind
+            ))
+            .unwrap()
+        };
+        assert_eq!(
+            params(20, r#"{"type": "totp", "period": 30}"#).next_code(0),
+            Err(OtpError::InvalidDigits("20".to_string()))
+        );
+        assert_eq!(
+            params(0, r#"{"type": "hotp", "counter": 0}"#).next_code(0),
+            Err(OtpError::InvalidDigits("0".to_string()))
+        );
+        assert_eq!(
+            params(6, r#"{"type": "totp", "period": 0}"#).next_code(0),
+            Err(OtpError::InvalidPeriod("0".to_string()))
+        );
+        let mut hotp = params(6, r#"{"type": "hotp", "counter": 4}"#);
+        hotp.digits = 9;
+        assert!(hotp.next_code(0).is_err());
+        assert_eq!(hotp.kind, OtpKind::Hotp { counter: 4 });
+    }
+
+    #[test]
+    fn uri_round_trip() {
+        let uris = [
+            "otpauth://totp/ACME%20Co:john.doe@email.com?secret=HXDMVJECJJWSRB3HWIZR4IFUGFTMXBOZ&issuer=ACME%20Co&algorithm=SHA512&digits=8&period=60",
+            "otpauth://hotp/alice?secret=JBSWY3DPEHPK3PXP&counter=7",
+        ];
+        for uri in uris {
+            let params = OtpParams::parse(uri).unwrap();
+            assert_eq!(OtpParams::parse(params.to_uri().expose()).unwrap(), params);
+        }
+    }
+
+    #[test]
+    fn invalid_inputs() {
+        assert_eq!(
+            OtpParams::parse("not base32!"),
+            Err(OtpError::InvalidSecret)
+        );
+        assert_eq!(OtpParams::parse(""), Err(OtpError::InvalidSecret));
+        assert_eq!(
+            OtpParams::parse("otpauth://totp/a?secret=JBSWY3DPEHPK3PXP&digits=5"),
+            Err(OtpError::InvalidDigits("5".to_string()))
+        );
+        assert_eq!(
+            OtpParams::parse("otpauth://totp/a?secret=JBSWY3DPEHPK3PXP&algorithm=MD5"),
+            Err(OtpError::UnsupportedAlgorithm("MD5".to_string()))
+        );
+        assert_eq!(
+            OtpParams::parse("otpauth://steam/a?secret=JBSWY3DPEHPK3PXP"),
+            Err(OtpError::UnsupportedType("steam".to_string()))
+        );
+        assert!(OtpParams::parse("otpauth://hotp/a?secret=JBSWY3DPEHPK3PXP").is_err());
+        assert!(OtpParams::parse("otpauth://totp/a").is_err());
+    }
+}
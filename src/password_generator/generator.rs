@@ -1,36 +1,78 @@
-use rand::prelude::*;
+use crate::crypto::secret::Secret;
+use rand::rngs::OsRng;
 use rand::seq::SliceRandom;
+use rand::Rng;
+use std::fmt;
+use zeroize::Zeroize;
 
-pub(crate) fn generate_strong_password(length: usize) -> String {
-    let lowercase_letters = "abcdefghijklmnopqrstuvwxyz";
-    let uppercase_letters = "ABCDEFGHIJKLMNOPQRSTUVWXYZ";
-    let digits = "0123456789";
-    let special_chars = "!@#$%^&*()_-+=<>?";
-    let all_chars = format!(
-        "{}{}{}{}",
-        lowercase_letters, uppercase_letters, digits, special_chars
-    );
+pub(crate) const LOWERCASE_LETTERS: &str = "abcdefghijklmnopqrstuvwxyz";
+pub(crate) const UPPERCASE_LETTERS: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+pub(crate) const DIGITS: &str = "0123456789";
+pub(crate) const SPECIAL_CHARS: &str = "!@#$%^&*()_-+=<>?";
+const ALL_CHARS: &[u8] =
+    b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789!@#$%^&*()_-+=<>?";
+
+const REQUIRED_CLASSES: [&str; 4] = [LOWERCASE_LETTERS, UPPERCASE_LETTERS, DIGITS, SPECIAL_CHARS];
 
-    let mut rng = thread_rng();
+/// One character from each required class has to fit.
+pub const MIN_PASSWORD_LENGTH: usize = REQUIRED_CLASSES.len();
+pub const MAX_PASSWORD_LENGTH: usize = 1024;
+
+#[derive(Debug, PartialEq)]
+pub enum GeneratorError {
+    TooShort { length: usize, min: usize },
+    TooLong { length: usize, max: usize },
+}
 
-    let mut password: Vec<char> = vec![
-        lowercase_letters.chars().choose(&mut rng).unwrap(),
-        uppercase_letters.chars().choose(&mut rng).unwrap(),
-        digits.chars().choose(&mut rng).unwrap(),
-        special_chars.chars().choose(&mut rng).unwrap(),
-    ];
+impl fmt::Display for GeneratorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GeneratorError::TooShort { length, min } => write!(
+                f,
+                "a password of length {} cannot contain all {} required character classes",
+                length, min
+            ),
+            GeneratorError::TooLong { length, max } => write!(
+                f,
+                "a password of length {} exceeds the maximum length of {}",
+                length, max
+            ),
+        }
+    }
+}
 
-    password.extend((password.len()..length).map(|_| {
-        *all_chars
-            .chars()
-            .collect::<Vec<char>>()
-            .choose(&mut rng)
-            .unwrap()
-    }));
+impl std::error::Error for GeneratorError {}
 
+/// Generates a password of exactly `length` characters containing at least one lowercase
+/// letter, uppercase letter, digit and symbol, using the operating system's CSPRNG.
+pub(crate) fn generate_strong_password(length: usize) -> Result<Secret, GeneratorError> {
+    if length < MIN_PASSWORD_LENGTH {
+        return Err(GeneratorError::TooShort {
+            length,
+            min: MIN_PASSWORD_LENGTH,
+        });
+    }
+    if length > MAX_PASSWORD_LENGTH {
+        return Err(GeneratorError::TooLong {
+            length,
+            max: MAX_PASSWORD_LENGTH,
+        });
+    }
+
+    let mut rng = OsRng;
+    let mut password = Vec::with_capacity(length);
+    for class in REQUIRED_CLASSES {
+        let class = class.as_bytes();
+        password.push(class[rng.gen_range(0..class.len())]);
+    }
+    password.extend(
+        (MIN_PASSWORD_LENGTH..length).map(|_| ALL_CHARS[rng.gen_range(0..ALL_CHARS.len())]),
+    );
     password.shuffle(&mut rng);
 
-    password.into_iter().collect()
+    let secret = Secret::from(password.iter().copied().map(char::from).collect::<String>());
+    password.zeroize();
+    Ok(secret)
 }
 
 #[cfg(test)]
@@ -40,39 +82,96 @@ mod tests {
     #[test]
     fn password_length() {
         let length = 12;
-        let password = generate_strong_password(length);
+        let password = generate_strong_password(length).unwrap();
+        let password = password.expose();
         assert_eq!(password.len(), length);
     }
 
     #[test]
     fn password_contains_lowercase() {
-        let password = generate_strong_password(12);
+        let password = generate_strong_password(12).unwrap();
+        let password = password.expose();
         assert!(password.chars().any(|c| c.is_lowercase()));
     }
 
     #[test]
     fn password_contains_uppercase() {
-        let password = generate_strong_password(12);
+        let password = generate_strong_password(12).unwrap();
+        let password = password.expose();
         assert!(password.chars().any(|c| c.is_uppercase()));
     }
 
     #[test]
     fn password_contains_digit() {
-        let password = generate_strong_password(12);
+        let password = generate_strong_password(12).unwrap();
+        let password = password.expose();
         assert!(password.chars().any(|c| c.is_ascii_digit()));
     }
 
     #[test]
     fn password_contains_special_character() {
-        let password = generate_strong_password(12);
+        let password = generate_strong_password(12).unwrap();
+        let password = password.expose();
         let special_chars = "!@#$%^&*()_-+=<>?";
         assert!(password.chars().any(|c| special_chars.contains(c)));
     }
 
     #[test]
     fn passwords_are_random() {
-        let password1 = generate_strong_password(12);
-        let password2 = generate_strong_password(12);
+        let password1 = generate_strong_password(12).unwrap();
+        let password1 = password1.expose();
+        let password2 = generate_strong_password(12).unwrap();
+        let password2 = password2.expose();
         assert_ne!(password1, password2);
     }
+
+    #[test]
+    fn all_chars_is_union_of_classes() {
+        assert_eq!(ALL_CHARS, REQUIRED_CLASSES.concat().as_bytes());
+    }
+
+    #[test]
+    fn zero_length_is_rejected() {
+        assert_eq!(
+            generate_strong_password(0),
+            Err(GeneratorError::TooShort { length: 0, min: 4 })
+        );
+    }
+
+    #[test]
+    fn length_below_required_classes_is_rejected() {
+        assert_eq!(
+            generate_strong_password(2),
+            Err(GeneratorError::TooShort { length: 2, min: 4 })
+        );
+        assert!(generate_strong_password(MIN_PASSWORD_LENGTH - 1).is_err());
+    }
+
+    #[test]
+    fn minimum_length_contains_every_class() {
+        let password = generate_strong_password(MIN_PASSWORD_LENGTH).unwrap();
+        let password = password.expose();
+        assert_eq!(password.len(), MIN_PASSWORD_LENGTH);
+        for class in REQUIRED_CLASSES {
+            assert!(password.chars().any(|c| class.contains(c)));
+        }
+    }
+
+    #[test]
+    fn maximum_length_is_accepted() {
+        let password = generate_strong_password(MAX_PASSWORD_LENGTH).unwrap();
+        let password = password.expose();
+        assert_eq!(password.len(), MAX_PASSWORD_LENGTH);
+    }
+
+    #[test]
+    fn length_above_maximum_is_rejected() {
+        assert_eq!(
+            generate_strong_password(MAX_PASSWORD_LENGTH + 1),
+            Err(GeneratorError::TooLong {
+                length: MAX_PASSWORD_LENGTH + 1,
+                max: MAX_PASSWORD_LENGTH
+            })
+        );
+    }
 }
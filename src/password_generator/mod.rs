@@ -1 +1,7 @@
+pub(crate) mod derive;
 pub(crate) mod generator;
+pub(crate) mod mode;
+pub(crate) mod pattern;
+pub(crate) mod policy;
+pub(crate) mod profiles;
+pub(crate) mod pronounceable;
@@ -0,0 +1,54 @@
+use super::pronounceable::PronounceableOptions;
+use serde::{Deserialize, Serialize};
+
+/// Which generator produces a password. Entries remember the mode their value was generated
+/// with, so rotating them produces a password of the same kind.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum GenerateMode {
+    Strong {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        length: Option<usize>,
+    },
+    Profile {
+        name: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        length: Option<usize>,
+    },
+    Pattern {
+        pattern: String,
+    },
+    Pronounceable(PronounceableOptions),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn modes_round_trip_through_json() {
+        let modes = [
+            GenerateMode::Strong { length: Some(20) },
+            GenerateMode::Profile {
+                name: "bank".to_string(),
+                length: None,
+            },
+            GenerateMode::Pattern {
+                pattern: "x{4}-d{4}".to_string(),
+            },
+            GenerateMode::Pronounceable(PronounceableOptions {
+                syllables: 4,
+                digits: 2,
+                symbols: 1,
+            }),
+        ];
+        for mode in modes {
+            let json = serde_json::to_string(&mode).unwrap();
+            assert_eq!(serde_json::from_str::<GenerateMode>(&json).unwrap(), mode);
+        }
+        assert_eq!(
+            serde_json::to_string(&GenerateMode::Strong { length: Some(20) }).unwrap(),
+            r#"{"mode":"strong","length":20}"#
+        );
+    }
+}
@@ -0,0 +1,232 @@
+use super::generator::{DIGITS, LOWERCASE_LETTERS, SPECIAL_CHARS, UPPERCASE_LETTERS};
+use crate::crypto::secret::Secret;
+use rand::prelude::*;
+use rand::rngs::OsRng;
+use std::fmt;
+
+/// Upper bound for a single `{n}` repetition, to keep typos like `x{10000000}` harmless.
+const MAX_REPEAT: usize = 1024;
+
+/// A parsed password template.
+///
+/// Placeholders draw one random character from a class:
+///
+/// | placeholder | characters                      |
+/// |-------------|---------------------------------|
+/// | `l`         | lowercase letters               |
+/// | `u`         | uppercase letters               |
+/// | `L`         | letters of either case          |
+/// | `d`         | digits                          |
+/// | `s`         | symbols                         |
+/// | `x`         | letters of either case + digits |
+/// | `*`         | any of the above                |
+/// | `[...]`     | one of the listed characters    |
+///
+/// `{n}` repeats the preceding placeholder or literal `n` times, `\` escapes the next
+/// character, and every other character is copied literally. For example
+/// `x{4}-x{4}-x{4}` or `l{3}d{3}-l{3}d{3}-l{3}d{3}`.
+#[derive(Debug, PartialEq)]
+pub struct Pattern {
+    tokens: Vec<Token>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Literal(char),
+    Class(Vec<char>),
+}
+
+#[derive(Debug, PartialEq)]
+pub enum PatternError {
+    Empty,
+    DanglingEscape,
+    UnclosedSet,
+    EmptySet,
+    UnclosedRepeat,
+    InvalidRepeat(String),
+    RepeatWithoutToken,
+}
+
+impl fmt::Display for PatternError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PatternError::Empty => write!(f, "pattern is empty"),
+            PatternError::DanglingEscape => write!(f, "pattern ends with an unfinished escape"),
+            PatternError::UnclosedSet => write!(f, "'[' is never closed"),
+            PatternError::EmptySet => write!(f, "'[]' does not contain any characters"),
+            PatternError::UnclosedRepeat => write!(f, "'{{' is never closed"),
+            PatternError::InvalidRepeat(count) => write!(
+                f,
+                "'{{{}}}' is not a repetition count between 1 and {}",
+                count, MAX_REPEAT
+            ),
+            PatternError::RepeatWithoutToken => {
+                write!(f, "'{{n}}' must follow a placeholder or literal")
+            }
+        }
+    }
+}
+
+impl std::error::Error for PatternError {}
+
+impl Pattern {
+    pub fn parse(template: &str) -> Result<Self, PatternError> {
+        let mut tokens = Vec::new();
+        let mut chars = template.chars();
+
+        while let Some(c) = chars.next() {
+            let token = match c {
+                'l' => Token::Class(LOWERCASE_LETTERS.chars().collect()),
+                'u' => Token::Class(UPPERCASE_LETTERS.chars().collect()),
+                'L' => Token::Class(class_chars(&[LOWERCASE_LETTERS, UPPERCASE_LETTERS])),
+                'd' => Token::Class(DIGITS.chars().collect()),
+                's' => Token::Class(SPECIAL_CHARS.chars().collect()),
+                'x' => Token::Class(class_chars(&[LOWERCASE_LETTERS, UPPERCASE_LETTERS, DIGITS])),
+                '*' => Token::Class(class_chars(&[
+                    LOWERCASE_LETTERS,
+                    UPPERCASE_LETTERS,
+                    DIGITS,
+                    SPECIAL_CHARS,
+                ])),
+                '[' => {
+                    let mut set = Vec::new();
+                    loop {
+                        match chars.next() {
+                            Some(']') => break,
+                            Some('\\') => set.push(chars.next().ok_or(PatternError::UnclosedSet)?),
+                            Some(c) => set.push(c),
+                            None => return Err(PatternError::UnclosedSet),
+                        }
+                    }
+                    set.sort_unstable();
+                    set.dedup();
+                    if set.is_empty() {
+                        return Err(PatternError::EmptySet);
+                    }
+                    Token::Class(set)
+                }
+                '{' => {
+                    let mut count = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => count.push(c),
+                            None => return Err(PatternError::UnclosedRepeat),
+                        }
+                    }
+                    let repeat = count
+                        .trim()
+                        .parse::<usize>()
+                        .ok()
+                        .filter(|n| (1..=MAX_REPEAT).contains(n))
+                        .ok_or(PatternError::InvalidRepeat(count))?;
+                    let previous = tokens
+                        .last()
+                        .cloned()
+                        .ok_or(PatternError::RepeatWithoutToken)?;
+                    tokens.extend(std::iter::repeat(previous).take(repeat - 1));
+                    continue;
+                }
+                '\\' => Token::Literal(chars.next().ok_or(PatternError::DanglingEscape)?),
+                c => Token::Literal(c),
+            };
+            tokens.push(token);
+        }
+
+        if tokens.is_empty() {
+            return Err(PatternError::Empty);
+        }
+        Ok(Self { tokens })
+    }
+
+    pub fn generate(&self) -> Secret {
+        let mut rng = OsRng;
+        let mut password = String::with_capacity(self.tokens.len() * 4);
+        for token in &self.tokens {
+            password.push(match token {
+                Token::Literal(c) => *c,
+                Token::Class(chars) => *chars.choose(&mut rng).unwrap(),
+            });
+        }
+        Secret::from(password)
+    }
+
+    /// The entropy of a password drawn from this pattern, in bits. Literals are known to an
+    /// attacker who knows the pattern and contribute nothing.
+    pub fn entropy_bits(&self) -> f64 {
+        self.tokens
+            .iter()
+            .map(|token| match token {
+                Token::Literal(_) => 0.0,
+                Token::Class(chars) => (chars.len() as f64).log2(),
+            })
+            .sum()
+    }
+}
+
+fn class_chars(classes: &[&str]) -> Vec<char> {
+    classes.iter().flat_map(|class| class.chars()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grouped_pattern_shape() {
+        let pattern = Pattern::parse("x{4}-x{4}-x{4}").unwrap();
+        let password = pattern.generate();
+        let password = password.expose();
+        let groups: Vec<&str> = password.split('-').collect();
+        assert_eq!(groups.len(), 3);
+        assert!(groups
+            .iter()
+            .all(|g| g.len() == 4 && g.chars().all(|c| c.is_ascii_alphanumeric())));
+    }
+
+    #[test]
+    fn apple_style_pattern_shape() {
+        let pattern = Pattern::parse("l{3}d{3}-l{3}d{3}-l{3}d{3}").unwrap();
+        let password = pattern.generate();
+        let password = password.expose();
+        for group in password.split('-') {
+            let (letters, digits) = group.split_at(3);
+            assert!(letters.chars().all(|c| c.is_ascii_lowercase()));
+            assert!(digits.chars().all(|c| c.is_ascii_digit()));
+        }
+    }
+
+    #[test]
+    fn escapes_and_sets() {
+        let pattern = Pattern::parse(r"\l[ab]{2}").unwrap();
+        let password = pattern.generate();
+        let password = password.expose();
+        assert!(password.starts_with('l'));
+        assert!(password[1..].chars().all(|c| c == 'a' || c == 'b'));
+        assert_eq!(password.len(), 3);
+    }
+
+    #[test]
+    fn entropy_counts_only_placeholders() {
+        let pattern = Pattern::parse("d{4}-d{4}").unwrap();
+        let expected = 8.0 * 10f64.log2();
+        assert!((pattern.entropy_bits() - expected).abs() < 1e-9);
+
+        let literal = Pattern::parse(r"abc-\d").unwrap();
+        assert_eq!(literal.entropy_bits(), 0.0);
+    }
+
+    #[test]
+    fn invalid_patterns() {
+        assert_eq!(Pattern::parse(""), Err(PatternError::Empty));
+        assert_eq!(Pattern::parse("{3}"), Err(PatternError::RepeatWithoutToken));
+        assert_eq!(Pattern::parse("x{3"), Err(PatternError::UnclosedRepeat));
+        assert_eq!(
+            Pattern::parse("x{0}"),
+            Err(PatternError::InvalidRepeat("0".to_string()))
+        );
+        assert_eq!(Pattern::parse("[ab"), Err(PatternError::UnclosedSet));
+        assert_eq!(Pattern::parse("[]"), Err(PatternError::EmptySet));
+        assert_eq!(Pattern::parse("x\\"), Err(PatternError::DanglingEscape));
+    }
+}
@@ -0,0 +1,495 @@
+use super::generator::{
+    DIGITS, LOWERCASE_LETTERS, MAX_PASSWORD_LENGTH, SPECIAL_CHARS, UPPERCASE_LETTERS,
+};
+use crate::crypto::secret::Secret;
+use rand::rngs::OsRng;
+use rand::seq::SliceRandom;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CharClass {
+    Lowercase,
+    Uppercase,
+    Digit,
+    Symbol,
+}
+
+impl CharClass {
+    pub const ALL: [CharClass; 4] = [
+        CharClass::Lowercase,
+        CharClass::Uppercase,
+        CharClass::Digit,
+        CharClass::Symbol,
+    ];
+
+    fn contains(&self, c: char, symbols: &str) -> bool {
+        match self {
+            CharClass::Lowercase => c.is_ascii_lowercase(),
+            CharClass::Uppercase => c.is_ascii_uppercase(),
+            CharClass::Digit => c.is_ascii_digit(),
+            CharClass::Symbol => symbols.contains(c),
+        }
+    }
+}
+
+impl fmt::Display for CharClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            CharClass::Lowercase => "lowercase",
+            CharClass::Uppercase => "uppercase",
+            CharClass::Digit => "digit",
+            CharClass::Symbol => "symbol",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// The rules a site imposes on its passwords.
+///
+/// Every field except `length` has a default, so a profile in `profiles.json`
+/// only needs to spell out what differs from a plain strong password.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct PasswordPolicy {
+    pub length: usize,
+    /// Character classes the password may be drawn from.
+    pub allowed: Vec<CharClass>,
+    /// Character classes that must appear at least once.
+    pub required: Vec<CharClass>,
+    /// The symbols used for `CharClass::Symbol`.
+    pub symbols: String,
+    /// Classes the first character must belong to. Empty means any allowed class.
+    pub first_char: Vec<CharClass>,
+    /// Maximum number of identical characters in a row.
+    pub max_repeats: Option<usize>,
+}
+
+impl Default for PasswordPolicy {
+    fn default() -> Self {
+        Self {
+            length: 12,
+            allowed: CharClass::ALL.to_vec(),
+            required: CharClass::ALL.to_vec(),
+            symbols: SPECIAL_CHARS.to_string(),
+            first_char: Vec::new(),
+            max_repeats: None,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum PolicyError {
+    ZeroLength,
+    TooLong(usize),
+    NoAllowedClasses,
+    RequiredNotAllowed(CharClass),
+    TooManyRequiredClasses { required: usize, length: usize },
+    EmptySymbols,
+    InvalidSymbol(char),
+    FirstCharNotAllowed(CharClass),
+    ZeroMaxRepeats,
+    RepeatsUnsatisfiable,
+}
+
+impl fmt::Display for PolicyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PolicyError::ZeroLength => write!(f, "length must be greater than zero"),
+            PolicyError::TooLong(length) => write!(
+                f,
+                "length {} exceeds the maximum length of {}",
+                length, MAX_PASSWORD_LENGTH
+            ),
+            PolicyError::NoAllowedClasses => write!(f, "no character classes are allowed"),
+            PolicyError::RequiredNotAllowed(class) => {
+                write!(f, "required class '{}' is not an allowed class", class)
+            }
+            PolicyError::TooManyRequiredClasses { required, length } => write!(
+                f,
+                "{} required classes do not fit in a password of length {}",
+                required, length
+            ),
+            PolicyError::EmptySymbols => {
+                write!(f, "symbols are allowed but the symbol set is empty")
+            }
+            PolicyError::InvalidSymbol(c) => {
+                write!(
+                    f,
+                    "'{}' is not a valid symbol (must be ASCII punctuation)",
+                    c
+                )
+            }
+            PolicyError::FirstCharNotAllowed(class) => write!(
+                f,
+                "first character class '{}' is not an allowed class",
+                class
+            ),
+            PolicyError::ZeroMaxRepeats => write!(f, "max_repeats must be at least 1"),
+            PolicyError::RepeatsUnsatisfiable => write!(
+                f,
+                "max_repeats cannot be met with a single-character alphabet"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for PolicyError {}
+
+impl PasswordPolicy {
+    /// Checks that at least one password can satisfy every rule of the policy.
+    pub fn validate(&self) -> Result<(), PolicyError> {
+        if self.length == 0 {
+            return Err(PolicyError::ZeroLength);
+        }
+        if self.length > MAX_PASSWORD_LENGTH {
+            return Err(PolicyError::TooLong(self.length));
+        }
+        if self.allowed.is_empty() {
+            return Err(PolicyError::NoAllowedClasses);
+        }
+        if let Some(class) = self.required.iter().find(|c| !self.allowed.contains(c)) {
+            return Err(PolicyError::RequiredNotAllowed(*class));
+        }
+        if self.allowed.contains(&CharClass::Symbol) {
+            if self.symbols.is_empty() {
+                return Err(PolicyError::EmptySymbols);
+            }
+            if let Some(c) = self.symbols.chars().find(|c| !c.is_ascii_punctuation()) {
+                return Err(PolicyError::InvalidSymbol(c));
+            }
+        }
+        if let Some(class) = self.first_char.iter().find(|c| !self.allowed.contains(c)) {
+            return Err(PolicyError::FirstCharNotAllowed(*class));
+        }
+
+        // The first character can only satisfy a required class it belongs to, so when the
+        // first character is restricted to classes outside `required` it takes a slot away.
+        let required = self.required_classes();
+        let first_covers_required =
+            self.first_char.is_empty() || self.first_char.iter().any(|c| required.contains(c));
+        let slots_needed = required.len() + usize::from(!first_covers_required);
+        if slots_needed > self.length {
+            return Err(PolicyError::TooManyRequiredClasses {
+                required: required.len(),
+                length: self.length,
+            });
+        }
+
+        match self.max_repeats {
+            Some(0) => return Err(PolicyError::ZeroMaxRepeats),
+            Some(max) if max < self.length && self.alphabet().len() < 2 => {
+                return Err(PolicyError::RepeatsUnsatisfiable)
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Generates a password that satisfies the policy.
+    ///
+    /// Like `generate_strong_password`, each required class gets a random position of its
+    /// own. The characters are then drawn uniformly from left to right, leaving out only
+    /// the one that would make a run longer than `max_repeats`, so any policy that passes
+    /// `validate` is met on the first try, however long the password.
+    pub fn generate(&self) -> Result<Secret, PolicyError> {
+        self.validate()?;
+
+        let alphabet = self.alphabet();
+        let first_alphabet = if self.first_char.is_empty() {
+            alphabet.clone()
+        } else {
+            self.class_alphabet(&self.first_char)
+        };
+
+        let mut rng = OsRng;
+        let mut positions: Vec<usize> = (0..self.length).collect();
+        positions.shuffle(&mut rng);
+        let mut slots: Vec<Option<CharClass>> = vec![None; self.length];
+        for (class, position) in self.required_classes().into_iter().zip(positions) {
+            slots[position] = Some(class);
+        }
+        // The first character can only stand for a required class it may start with.
+        // `validate` made sure another such class or a free position is available.
+        if let Some(class) = slots[0] {
+            if !self.first_char.is_empty() && !self.first_char.contains(&class) {
+                let other = slots
+                    .iter()
+                    .position(|slot| slot.is_some_and(|c| self.first_char.contains(&c)))
+                    .or_else(|| slots.iter().position(Option::is_none))
+                    .expect("validate leaves room for the first character");
+                slots.swap(0, other);
+            }
+        }
+
+        let mut password = String::with_capacity(self.length);
+        let mut previous = None;
+        let mut run = 0;
+        for (i, slot) in slots.into_iter().enumerate() {
+            let mut candidates = if i == 0 {
+                first_alphabet.clone()
+            } else {
+                alphabet.clone()
+            };
+            // A class met earlier in the password does not need its position any more.
+            if let Some(class) =
+                slot.filter(|class| !password.chars().any(|c| class.contains(c, &self.symbols)))
+            {
+                candidates.retain(|&c| class.contains(c, &self.symbols));
+            }
+            if self.max_repeats == Some(run) {
+                candidates.retain(|&c| Some(c) != previous);
+            }
+            let c = *candidates
+                .choose(&mut rng)
+                .expect("validate leaves a character to choose");
+            run = if previous == Some(c) { run + 1 } else { 1 };
+            previous = Some(c);
+            password.push(c);
+        }
+        debug_assert!(self.accepts(&password));
+        Ok(Secret::from(password))
+    }
+
+    /// Returns whether `password` follows every rule of the policy.
+    pub fn accepts(&self, password: &str) -> bool {
+        if password.chars().count() != self.length {
+            return false;
+        }
+        if !password.chars().all(|c| {
+            self.allowed
+                .iter()
+                .any(|class| class.contains(c, &self.symbols))
+        }) {
+            return false;
+        }
+        if !self
+            .required
+            .iter()
+            .all(|class| password.chars().any(|c| class.contains(c, &self.symbols)))
+        {
+            return false;
+        }
+        if let Some(first) = password.chars().next() {
+            if !self.first_char.is_empty()
+                && !self
+                    .first_char
+                    .iter()
+                    .any(|class| class.contains(first, &self.symbols))
+            {
+                return false;
+            }
+        }
+        if let Some(max) = self.max_repeats {
+            let mut run = 0;
+            let mut previous = None;
+            for c in password.chars() {
+                run = if previous == Some(c) { run + 1 } else { 1 };
+                if run > max {
+                    return false;
+                }
+                previous = Some(c);
+            }
+        }
+        true
+    }
+
+    /// The distinct required classes, in a fixed order.
+    fn required_classes(&self) -> Vec<CharClass> {
+        CharClass::ALL
+            .into_iter()
+            .filter(|c| self.required.contains(c))
+            .collect()
+    }
+
+    fn alphabet(&self) -> Vec<char> {
+        self.class_alphabet(&self.allowed)
+    }
+
+    fn class_alphabet(&self, classes: &[CharClass]) -> Vec<char> {
+        let mut alphabet = Vec::new();
+        for class in CharClass::ALL.iter().filter(|c| classes.contains(c)) {
+            let chars = match class {
+                CharClass::Lowercase => LOWERCASE_LETTERS,
+                CharClass::Uppercase => UPPERCASE_LETTERS,
+                CharClass::Digit => DIGITS,
+                CharClass::Symbol => self.symbols.as_str(),
+            };
+            alphabet.extend(chars.chars());
+        }
+        alphabet.sort_unstable();
+        alphabet.dedup();
+        alphabet
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_policy_generates_accepted_password() {
+        let policy = PasswordPolicy::default();
+        let password = policy.generate().unwrap();
+        let password = password.expose();
+        assert_eq!(password.len(), 12);
+        assert!(policy.accepts(password));
+    }
+
+    #[test]
+    fn restricted_symbols_and_first_letter() {
+        let policy = PasswordPolicy {
+            length: 16,
+            symbols: "!@#".to_string(),
+            first_char: vec![CharClass::Lowercase, CharClass::Uppercase],
+            ..Default::default()
+        };
+        for _ in 0..50 {
+            let password = policy.generate().unwrap();
+            let password = password.expose();
+            assert!(password.chars().next().unwrap().is_ascii_alphabetic());
+            assert!(password
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "!@#".contains(c)));
+        }
+    }
+
+    #[test]
+    fn max_repeats_is_respected() {
+        let policy = PasswordPolicy {
+            length: 32,
+            allowed: vec![CharClass::Digit],
+            required: vec![CharClass::Digit],
+            max_repeats: Some(1),
+            ..Default::default()
+        };
+        let password = policy.generate().unwrap();
+        let password = password.expose();
+        assert!(password
+            .as_bytes()
+            .windows(2)
+            .all(|pair| pair[0] != pair[1]));
+    }
+
+    #[test]
+    fn long_passwords_with_few_characters_and_no_repeats() {
+        let policy = PasswordPolicy {
+            length: MAX_PASSWORD_LENGTH,
+            allowed: vec![CharClass::Digit],
+            required: vec![CharClass::Digit],
+            max_repeats: Some(1),
+            ..Default::default()
+        };
+        let password = policy.generate().unwrap();
+        assert!(policy.accepts(password.expose()));
+
+        let policy = PasswordPolicy {
+            length: 200,
+            allowed: vec![CharClass::Symbol],
+            required: vec![CharClass::Symbol],
+            symbols: "!?".to_string(),
+            max_repeats: Some(1),
+            ..Default::default()
+        };
+        let password = policy.generate().unwrap();
+        assert!(policy.accepts(password.expose()));
+    }
+
+    #[test]
+    fn tight_policies_are_always_met() {
+        let policies = [
+            PasswordPolicy {
+                length: 4,
+                symbols: "!".to_string(),
+                max_repeats: Some(1),
+                ..Default::default()
+            },
+            PasswordPolicy {
+                length: 4,
+                symbols: "!".to_string(),
+                first_char: vec![CharClass::Symbol],
+                ..Default::default()
+            },
+            PasswordPolicy {
+                length: 5,
+                allowed: vec![CharClass::Lowercase, CharClass::Digit, CharClass::Symbol],
+                required: vec![CharClass::Digit, CharClass::Symbol],
+                symbols: "!".to_string(),
+                first_char: vec![CharClass::Lowercase],
+                max_repeats: Some(1),
+            },
+            PasswordPolicy {
+                length: 2,
+                allowed: vec![CharClass::Lowercase, CharClass::Digit],
+                required: vec![CharClass::Digit],
+                first_char: vec![CharClass::Lowercase],
+                ..Default::default()
+            },
+        ];
+        for policy in policies {
+            for _ in 0..200 {
+                let password = policy.generate().unwrap();
+                assert!(policy.accepts(password.expose()), "{:?}", policy);
+            }
+        }
+    }
+
+    #[test]
+    fn too_many_required_classes_is_rejected() {
+        let policy = PasswordPolicy {
+            length: 3,
+            ..Default::default()
+        };
+        assert_eq!(
+            policy.validate(),
+            Err(PolicyError::TooManyRequiredClasses {
+                required: 4,
+                length: 3
+            })
+        );
+    }
+
+    #[test]
+    fn first_char_outside_required_needs_extra_slot() {
+        let policy = PasswordPolicy {
+            length: 1,
+            allowed: vec![CharClass::Lowercase, CharClass::Digit],
+            required: vec![CharClass::Digit],
+            first_char: vec![CharClass::Lowercase],
+            ..Default::default()
+        };
+        assert!(policy.validate().is_err());
+    }
+
+    #[test]
+    fn required_class_must_be_allowed() {
+        let policy = PasswordPolicy {
+            allowed: vec![CharClass::Lowercase],
+            required: vec![CharClass::Digit],
+            ..Default::default()
+        };
+        assert_eq!(
+            policy.validate(),
+            Err(PolicyError::RequiredNotAllowed(CharClass::Digit))
+        );
+    }
+
+    #[test]
+    fn empty_symbol_set_is_rejected() {
+        let policy = PasswordPolicy {
+            symbols: String::new(),
+            ..Default::default()
+        };
+        assert_eq!(policy.validate(), Err(PolicyError::EmptySymbols));
+    }
+
+    #[test]
+    fn zero_length_is_rejected() {
+        let policy = PasswordPolicy {
+            length: 0,
+            ..Default::default()
+        };
+        assert_eq!(policy.validate(), Err(PolicyError::ZeroLength));
+    }
+}
@@ -0,0 +1,191 @@
+use super::policy::{CharClass, PasswordPolicy};
+use crate::error::PwError;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Profiles that are always available. A profile with the same name in
+/// `profiles.json` takes precedence.
+pub(crate) fn builtin_profiles() -> HashMap<String, PasswordPolicy> {
+    let mut profiles = HashMap::new();
+    profiles.insert("default".to_string(), PasswordPolicy::default());
+    profiles.insert(
+        "bank".to_string(),
+        PasswordPolicy {
+            length: 16,
+            symbols: "!@#".to_string(),
+            first_char: vec![CharClass::Lowercase, CharClass::Uppercase],
+            max_repeats: Some(2),
+            ..Default::default()
+        },
+    );
+    profiles.insert(
+        "alphanumeric".to_string(),
+        PasswordPolicy {
+            length: 20,
+            allowed: vec![CharClass::Lowercase, CharClass::Uppercase, CharClass::Digit],
+            required: vec![CharClass::Lowercase, CharClass::Uppercase, CharClass::Digit],
+            ..Default::default()
+        },
+    );
+    profiles.insert(
+        "pin".to_string(),
+        PasswordPolicy {
+            length: 6,
+            allowed: vec![CharClass::Digit],
+            required: vec![CharClass::Digit],
+            max_repeats: Some(2),
+            ..Default::default()
+        },
+    );
+    profiles.insert(
+        "max".to_string(),
+        PasswordPolicy {
+            length: 64,
+            ..Default::default()
+        },
+    );
+    profiles
+}
+
+/// Reads user-defined profiles from a JSON object mapping profile names to policies.
+/// A missing file means no user-defined profiles.
+pub(crate) fn load_profiles(file_path: &Path) -> io::Result<HashMap<String, PasswordPolicy>> {
+    match fs::read_to_string(file_path) {
+        Ok(contents) => Ok(serde_json::from_str(&contents)?),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(HashMap::new()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Where user-defined profiles come from. Profiles in the config file take precedence over
+/// the profiles file.
+#[derive(Debug, Default)]
+pub(crate) struct UserProfiles {
+    pub file_path: Option<PathBuf>,
+    pub configured: BTreeMap<String, PasswordPolicy>,
+}
+
+/// Looks up a profile by name, preferring user-defined profiles over built-in ones.
+pub(crate) fn find_profile(
+    name: &str,
+    user_profiles: Option<&UserProfiles>,
+) -> Result<PasswordPolicy, PwError> {
+    let mut profiles = builtin_profiles();
+    if let Some(user_profiles) = user_profiles {
+        if let Some(path) = &user_profiles.file_path {
+            profiles.extend(load_profiles(path)?);
+        }
+        profiles.extend(user_profiles.configured.clone());
+    }
+    match profiles.remove(name) {
+        Some(policy) => {
+            policy.validate().map_err(|e| {
+                PwError::Policy(format!("Profile '{}' is not satisfiable: {}", name, e))
+            })?;
+            Ok(policy)
+        }
+        None => {
+            let mut names: Vec<&String> = profiles.keys().collect();
+            names.sort();
+            Err(PwError::NotFound(format!(
+                "Unknown profile '{}'. Available profiles: {}",
+                name,
+                names
+                    .iter()
+                    .map(|n| n.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tempfile::tempdir;
+
+    #[test]
+    fn builtin_profiles_are_satisfiable() {
+        for (name, policy) in builtin_profiles() {
+            assert!(policy.validate().is_ok(), "profile '{}' is invalid", name);
+        }
+    }
+
+    #[test]
+    fn user_profile_overrides_builtin() -> io::Result<()> {
+        let temp_dir = tempdir()?;
+        let file_path = temp_dir.path().join("profiles.json");
+        let mut file = fs::File::create(&file_path)?;
+        file.write_all(br#"{"bank": {"length": 10, "symbols": "!"}}"#)?;
+
+        let user_profiles = UserProfiles {
+            file_path: Some(file_path),
+            ..Default::default()
+        };
+        let policy = find_profile("bank", Some(&user_profiles)).unwrap();
+        assert_eq!(policy.length, 10);
+        assert_eq!(policy.symbols, "!");
+        assert_eq!(policy.allowed, CharClass::ALL.to_vec());
+        Ok(())
+    }
+
+    #[test]
+    fn configured_profile_overrides_profiles_file() -> io::Result<()> {
+        let temp_dir = tempdir()?;
+        let file_path = temp_dir.path().join("profiles.json");
+        fs::write(&file_path, r#"{"bank": {"length": 10}}"#)?;
+
+        let mut configured = BTreeMap::new();
+        configured.insert(
+            "bank".to_string(),
+            PasswordPolicy {
+                length: 30,
+                ..Default::default()
+            },
+        );
+        let user_profiles = UserProfiles {
+            file_path: Some(file_path),
+            configured,
+        };
+        assert_eq!(
+            find_profile("bank", Some(&user_profiles)).unwrap().length,
+            30
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn unsatisfiable_user_profile_is_rejected() -> io::Result<()> {
+        let temp_dir = tempdir()?;
+        let file_path = temp_dir.path().join("profiles.json");
+        fs::write(&file_path, r#"{"tiny": {"length": 2}}"#)?;
+
+        let user_profiles = UserProfiles {
+            file_path: Some(file_path),
+            ..Default::default()
+        };
+        let result = find_profile("tiny", Some(&user_profiles));
+        assert!(result.unwrap_err().to_string().contains("not satisfiable"));
+        Ok(())
+    }
+
+    #[test]
+    fn unknown_profile_lists_available() {
+        let result = find_profile("nope", None);
+        let message = result.unwrap_err().to_string();
+        assert!(message.contains("Unknown profile 'nope'"));
+        assert!(message.contains("bank"));
+    }
+
+    #[test]
+    fn missing_profiles_file_is_empty() -> io::Result<()> {
+        let temp_dir = tempdir()?;
+        let profiles = load_profiles(&temp_dir.path().join("profiles.json"))?;
+        assert!(profiles.is_empty());
+        Ok(())
+    }
+}
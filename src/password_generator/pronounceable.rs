@@ -0,0 +1,205 @@
+use super::generator::{
+    DIGITS, LOWERCASE_LETTERS, MAX_PASSWORD_LENGTH, SPECIAL_CHARS, UPPERCASE_LETTERS,
+};
+use crate::crypto::secret::Secret;
+use rand::prelude::*;
+use rand::rngs::OsRng;
+use serde::{Deserialize, Serialize};
+use zeroize::Zeroize;
+
+/// Consonants that are hard to mishear when spelled out; c, q, w, x and y are left out.
+const CONSONANTS: &[u8] = b"bdfghjklmnprstvz";
+const VOWELS: &[u8] = b"aeiou";
+/// Symbols with short, unambiguous spoken names.
+const PHONE_SYMBOLS: &[u8] = b"!#%+=?@";
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PronounceableOptions {
+    /// Number of consonant-vowel syllables.
+    pub syllables: usize,
+    /// Number of digits inserted between syllables.
+    pub digits: usize,
+    /// Number of symbols inserted between syllables.
+    pub symbols: usize,
+}
+
+impl Default for PronounceableOptions {
+    fn default() -> Self {
+        Self {
+            syllables: 8,
+            digits: 0,
+            symbols: 0,
+        }
+    }
+}
+
+impl PronounceableOptions {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.syllables == 0 {
+            return Err("a pronounceable password needs at least one syllable".to_string());
+        }
+        match self.password_length() {
+            Some(length) if length <= MAX_PASSWORD_LENGTH => Ok(()),
+            _ => Err(format!(
+                "a pronounceable password can be at most {} characters long",
+                MAX_PASSWORD_LENGTH
+            )),
+        }
+    }
+
+    /// The length of the generated password, or `None` if it does not fit in a `usize`.
+    pub fn password_length(&self) -> Option<usize> {
+        self.syllables
+            .checked_mul(2)?
+            .checked_add(self.digits)?
+            .checked_add(self.symbols)
+    }
+
+    /// Entropy of the generated password in bits.
+    ///
+    /// Only the character choices are counted. The positions of inserted digits and
+    /// symbols are ignored, so this is a lower bound rather than an optimistic estimate.
+    pub fn entropy_bits(&self) -> f64 {
+        let syllable = ((CONSONANTS.len() * VOWELS.len()) as f64).log2();
+        self.syllables as f64 * syllable
+            + self.digits as f64 * (DIGITS.len() as f64).log2()
+            + self.symbols as f64 * (PHONE_SYMBOLS.len() as f64).log2()
+    }
+
+    /// Entropy of a fully random password of the same length drawn from the alphabet used
+    /// by `generate_strong_password`, for comparison.
+    pub fn random_equivalent_bits(&self) -> f64 {
+        let alphabet =
+            LOWERCASE_LETTERS.len() + UPPERCASE_LETTERS.len() + DIGITS.len() + SPECIAL_CHARS.len();
+        let length = self.syllables as f64 * 2.0 + self.digits as f64 + self.symbols as f64;
+        length * (alphabet as f64).log2()
+    }
+}
+
+/// Generates a password of alternating consonants and vowels, e.g. `tobagumi`, with any
+/// requested digits and symbols placed between syllables.
+pub(crate) fn generate_pronounceable_password(
+    options: &PronounceableOptions,
+) -> Result<Secret, String> {
+    options.validate()?;
+
+    let mut rng = OsRng;
+    let mut groups: Vec<String> = (0..options.syllables)
+        .map(|_| {
+            let mut syllable = String::with_capacity(2);
+            syllable.push(*CONSONANTS.choose(&mut rng).unwrap() as char);
+            syllable.push(*VOWELS.choose(&mut rng).unwrap() as char);
+            syllable
+        })
+        .collect();
+
+    for i in 0..options.digits + options.symbols {
+        let extra = if i < options.digits {
+            *DIGITS.as_bytes().choose(&mut rng).unwrap()
+        } else {
+            *PHONE_SYMBOLS.choose(&mut rng).unwrap()
+        };
+        let position = rng.gen_range(0..=groups.len());
+        groups.insert(position, (extra as char).to_string());
+    }
+
+    let password = Secret::from(groups.concat());
+    groups.iter_mut().for_each(Zeroize::zeroize);
+    Ok(password)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn alternates_consonants_and_vowels() {
+        let options = PronounceableOptions::default();
+        let password = generate_pronounceable_password(&options).unwrap();
+        let password = password.expose();
+        assert_eq!(password.len(), 16);
+        for (i, c) in password.bytes().enumerate() {
+            if i % 2 == 0 {
+                assert!(CONSONANTS.contains(&c));
+            } else {
+                assert!(VOWELS.contains(&c));
+            }
+        }
+    }
+
+    #[test]
+    fn inserts_digits_and_symbols() {
+        let options = PronounceableOptions {
+            syllables: 4,
+            digits: 2,
+            symbols: 1,
+        };
+        let password = generate_pronounceable_password(&options).unwrap();
+        let password = password.expose();
+        assert_eq!(Some(password.len()), options.password_length());
+        assert_eq!(password.chars().filter(|c| c.is_ascii_digit()).count(), 2);
+        assert_eq!(
+            password
+                .bytes()
+                .filter(|c| PHONE_SYMBOLS.contains(c))
+                .count(),
+            1
+        );
+    }
+
+    #[test]
+    fn entropy_is_lower_than_random_password() {
+        let options = PronounceableOptions {
+            syllables: 6,
+            digits: 2,
+            symbols: 0,
+        };
+        let expected = 6.0 * 80f64.log2() + 2.0 * 10f64.log2();
+        assert!((options.entropy_bits() - expected).abs() < 1e-9);
+        assert!(options.entropy_bits() < options.random_equivalent_bits());
+    }
+
+    #[test]
+    fn zero_syllables_is_rejected() {
+        let options = PronounceableOptions {
+            syllables: 0,
+            ..Default::default()
+        };
+        assert!(generate_pronounceable_password(&options).is_err());
+    }
+
+    #[test]
+    fn maximum_length_is_accepted() {
+        let options = PronounceableOptions {
+            syllables: MAX_PASSWORD_LENGTH / 2 - 1,
+            digits: 1,
+            symbols: 1,
+        };
+        let password = generate_pronounceable_password(&options).unwrap();
+        assert_eq!(password.expose().len(), MAX_PASSWORD_LENGTH);
+    }
+
+    #[test]
+    fn length_above_maximum_is_rejected() {
+        let options = PronounceableOptions {
+            syllables: MAX_PASSWORD_LENGTH / 2,
+            digits: 1,
+            symbols: 0,
+        };
+        assert!(options.validate().is_err());
+        assert!(generate_pronounceable_password(&options).is_err());
+
+        let overflowing = PronounceableOptions {
+            syllables: usize::MAX,
+            ..Default::default()
+        };
+        assert_eq!(overflowing.password_length(), None);
+        assert!(overflowing.validate().is_err());
+        let overflowing = PronounceableOptions {
+            syllables: 1,
+            digits: usize::MAX - 1,
+            symbols: 0,
+        };
+        assert!(overflowing.validate().is_err());
+    }
+}
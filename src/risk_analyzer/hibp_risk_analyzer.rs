@@ -1,14 +1,28 @@
 use super::risk_analyzer_trait::RiskAnalyzer;
-use anyhow::Result;
+use crate::crypto::secret::Secret;
+use crate::error::PwError;
 use async_trait::async_trait;
 use sha1::{Digest, Sha1};
 
-pub struct HIBPRiskAnalyzer;
+/// The "Have I Been Pwned" range API. Only the first five characters of the SHA-1 hash
+/// are sent.
+pub const HIBP_API_URL: &str = "https://api.pwnedpasswords.com/range/";
+
+pub struct HIBPRiskAnalyzer {
+    api_url: String,
+}
 
 impl HIBPRiskAnalyzer {
-    fn hash_password(&self, password: &str) -> String {
+    /// Queries the range API at `api_url`, which the hash prefix is appended to.
+    pub fn new(api_url: &str) -> Self {
+        Self {
+            api_url: api_url.to_string(),
+        }
+    }
+
+    fn hash_password(&self, password: &Secret) -> String {
         let mut hasher = Sha1::new();
-        hasher.update(password.as_bytes());
+        hasher.update(password.expose().as_bytes());
         let result = hasher.finalize();
         format!("{:X}", result)
     }
@@ -16,21 +30,21 @@ impl HIBPRiskAnalyzer {
 
 #[async_trait]
 impl RiskAnalyzer for HIBPRiskAnalyzer {
-    async fn check_password(&self, password: &str) -> Result<bool, anyhow::Error> {
+    async fn check_password(&self, password: &Secret) -> Result<bool, PwError> {
         let hashed_password_hex = self.hash_password(password);
         let prefix = &hashed_password_hex[..5];
         let suffix = &hashed_password_hex[5..];
 
-        let url = format!("https://api.pwnedpasswords.com/range/{}", prefix);
+        let url = format!("{}{}", self.api_url, prefix);
         let client = reqwest::Client::new();
-        let resp = client.get(&url).send().await?.text().await?;
+        let resp = client
+            .get(&url)
+            .send()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
 
-        if resp.lines().any(|line| line.ends_with(suffix)) {
-            println!("Password '{}' is compromised!", password);
-            Ok(true)
-        } else {
-            println!("Password '{}' is safe.", password);
-            Ok(false)
-        }
+        Ok(resp.lines().any(|line| line.ends_with(suffix)))
     }
 }
@@ -1,2 +1,3 @@
 pub(crate) mod hibp_risk_analyzer;
 pub(crate) mod risk_analyzer_trait;
+pub(crate) mod strength;
@@ -1,20 +1,24 @@
-use anyhow::{Context, Result};
+use crate::crypto::secret::Secret;
+use crate::error::PwError;
 use async_trait::async_trait;
 use futures::future::try_join_all;
 
 #[async_trait]
 pub trait RiskAnalyzer {
-    async fn check_password(&self, password: &str) -> Result<bool, anyhow::Error>;
+    /// Whether `password` is known to be compromised.
+    async fn check_password(&self, password: &Secret) -> Result<bool, PwError>;
 
-    async fn check_all_passwords(&self, passwords: Vec<&str>) -> Result<(), anyhow::Error> {
+    /// Checks every password at once, returning whether each is compromised, in order.
+    async fn check_all_passwords(&self, passwords: Vec<&Secret>) -> Result<Vec<bool>, PwError> {
         let check_futures = passwords
             .into_iter()
             .map(|password| self.check_password(password))
             .collect::<Vec<_>>();
-        try_join_all(check_futures)
-            .await
-            .context("Failed to check all passwords")?;
-
-        Ok(())
+        try_join_all(check_futures).await.map_err(|e| match e {
+            PwError::Network(message) => {
+                PwError::Network(format!("Failed to check all passwords: {}", message))
+            }
+            e => e,
+        })
     }
 }
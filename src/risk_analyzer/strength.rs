@@ -0,0 +1,464 @@
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+/// `pw init` refuses master passwords estimated below this, unless --weak-password is given.
+pub const MIN_MASTER_PASSWORD_BITS: f64 = 50.0;
+
+/// Word lists, most common first, so a word's line number is its rank.
+const WORD_LISTS: [&str; 3] = [
+    include_str!("wordlists/passwords.txt"),
+    include_str!("wordlists/english.txt"),
+    include_str!("wordlists/names.txt"),
+];
+
+/// Guesses per character for the parts of a password that match no pattern. As in zxcvbn,
+/// this is well below the size of the alphabet, since what people type is far from random.
+const BRUTEFORCE_CARDINALITY: f64 = 10.0;
+
+/// The fewest guesses a pattern inside a longer password counts for, so that cutting a
+/// password into many tiny patterns does not make it look weaker than it is.
+const MIN_SINGLE_CHAR_GUESSES: f64 = 10.0;
+const MIN_MULTI_CHAR_GUESSES: f64 = 50.0;
+
+/// Added for every pattern after the first, since an attacker also has to guess how many
+/// patterns there are.
+const MIN_GUESSES_BEFORE_GROWING_SEQUENCE: f64 = 10_000.0;
+
+/// Characters beyond this are not matched against patterns and count as bruteforce, which
+/// keeps the estimate fast for very long passphrases.
+const MAX_ANALYZED_CHARS: usize = 100;
+
+/// Years are guessed outwards from this one.
+const REFERENCE_YEAR: i64 = 2020;
+const MIN_YEAR_SPACE: f64 = 20.0;
+
+/// Runs along a keyboard row, in either direction. A run can start on any of the keys,
+/// and each key has a few neighbours it could continue to.
+const KEYBOARD_ROWS: [&str; 4] = ["1234567890-=", "qwertyuiop[]", "asdfghjkl;'", "zxcvbnm,./"];
+const KEYBOARD_KEYS: f64 = 94.0;
+const KEYBOARD_AVERAGE_DEGREE: f64 = 4.6;
+
+/// Characters commonly substituted for letters.
+const LEET: [(char, &[char]); 18] = [
+    ('4', &['a']),
+    ('@', &['a']),
+    ('8', &['b']),
+    ('(', &['c']),
+    ('{', &['c']),
+    ('<', &['c']),
+    ('3', &['e']),
+    ('6', &['g']),
+    ('9', &['g']),
+    ('1', &['i', 'l']),
+    ('!', &['i']),
+    ('|', &['i', 'l']),
+    ('0', &['o']),
+    ('$', &['s']),
+    ('5', &['s']),
+    ('7', &['t', 'l']),
+    ('+', &['t']),
+    ('2', &['z']),
+];
+
+/// Words with more ambiguous leet characters than this are not looked up, which keeps the
+/// number of readings of a word small.
+const MAX_AMBIGUOUS_LEET: usize = 4;
+
+/// A part of the password, `start..end`, and the log2 of the guesses it takes to find it.
+struct Match {
+    start: usize,
+    end: usize,
+    bits: f64,
+}
+
+/// An estimate of a password's strength in bits: the log2 of the guesses an attacker who
+/// tries common passwords, words, names, years, keyboard runs, sequences and repeats first
+/// would need, in the manner of zxcvbn. Capitalised, reversed and leet-spelled words count
+/// for little more than the words themselves.
+pub fn estimate_bits(password: &str) -> f64 {
+    let chars: Vec<char> = password.chars().collect();
+    let analyzed = chars.len().min(MAX_ANALYZED_CHARS);
+    let rest = (chars.len() - analyzed) as f64 * BRUTEFORCE_CARDINALITY.log2();
+    guesses_bits(&chars[..analyzed]) + rest
+}
+
+/// The cheapest way to cover `chars` with patterns and bruteforce, zxcvbn's "most guessable
+/// match sequence".
+fn guesses_bits(chars: &[char]) -> f64 {
+    let n = chars.len();
+    if n == 0 {
+        return 0.0;
+    }
+    let mut matches = pattern_matches(chars);
+    for start in 0..n {
+        for end in start + 1..=n {
+            matches.push(Match {
+                start,
+                end,
+                bits: (end - start) as f64 * BRUTEFORCE_CARDINALITY.log2(),
+            });
+        }
+    }
+    for m in &mut matches {
+        if m.end - m.start < n {
+            let min = match m.end - m.start {
+                1 => MIN_SINGLE_CHAR_GUESSES,
+                _ => MIN_MULTI_CHAR_GUESSES,
+            };
+            m.bits = m.bits.max(min.log2());
+        }
+    }
+
+    // best[end][count]: the fewest bits for `count` matches covering `chars[..end]`.
+    let mut best = vec![vec![f64::INFINITY; n + 1]; n + 1];
+    best[0][0] = 0.0;
+    matches.sort_by_key(|m| m.end);
+    for m in &matches {
+        for count in 1..=m.end {
+            let bits = best[m.start][count - 1] + m.bits;
+            if bits < best[m.end][count] {
+                best[m.end][count] = bits;
+            }
+        }
+    }
+    (1..=n)
+        .filter(|&count| best[n][count].is_finite())
+        .map(|count| {
+            let sequence = log2_factorial(count) + best[n][count];
+            let growth = (count - 1) as f64 * MIN_GUESSES_BEFORE_GROWING_SEQUENCE.log2();
+            log2_sum(sequence, growth)
+        })
+        .fold(f64::INFINITY, f64::min)
+}
+
+fn pattern_matches(chars: &[char]) -> Vec<Match> {
+    let lower: Vec<char> = chars.iter().flat_map(|c| c.to_lowercase()).collect();
+    if lower.len() != chars.len() {
+        // A character whose lowercase is several characters would misalign the two.
+        return Vec::new();
+    }
+    let mut matches = Vec::new();
+    dictionary_matches(chars, &lower, &mut matches);
+    sequence_matches(chars, &mut matches);
+    keyboard_matches(chars, &lower, &mut matches);
+    repeat_matches(chars, &mut matches);
+    year_matches(chars, &mut matches);
+    matches
+}
+
+fn ranks() -> &'static HashMap<&'static str, usize> {
+    static RANKS: OnceLock<HashMap<&'static str, usize>> = OnceLock::new();
+    RANKS.get_or_init(|| {
+        let mut ranks = HashMap::new();
+        for list in WORD_LISTS {
+            for (index, word) in list.lines().enumerate() {
+                let rank = ranks.entry(word).or_insert(index + 1);
+                *rank = (*rank).min(index + 1);
+            }
+        }
+        ranks
+    })
+}
+
+fn dictionary_matches(chars: &[char], lower: &[char], matches: &mut Vec<Match>) {
+    let ranks = ranks();
+    let longest = ranks.keys().map(|word| word.chars().count()).max();
+    let longest = longest.unwrap_or_default();
+    for start in 0..chars.len() {
+        for end in start + 3..=chars.len().min(start + longest) {
+            let original = &chars[start..end];
+            let token = &lower[start..end];
+            let case = uppercase_variations(original).log2();
+            let mut push = |rank: usize, extra: f64| {
+                matches.push(Match {
+                    start,
+                    end,
+                    bits: (rank as f64).log2() + case + extra,
+                })
+            };
+
+            let word: String = token.iter().collect();
+            if let Some(&rank) = ranks.get(word.as_str()) {
+                push(rank, 0.0);
+            }
+            let reversed: String = token.iter().rev().collect();
+            if let Some(&rank) = ranks.get(reversed.as_str()) {
+                push(rank, 1.0);
+            }
+            for (word, variations) in unleet(token) {
+                if let Some(&rank) = ranks.get(word.as_str()) {
+                    push(rank, variations.log2());
+                }
+            }
+        }
+    }
+}
+
+/// How many ways the letters of `word` could have been capitalised, given its pattern: an
+/// attacker tries a capital first or last letter, or all capitals, before anything else.
+fn uppercase_variations(word: &[char]) -> f64 {
+    let upper = word.iter().filter(|c| c.is_uppercase()).count();
+    let lower = word.iter().filter(|c| c.is_lowercase()).count();
+    if upper == 0 {
+        return 1.0;
+    }
+    let first_only = word[0].is_uppercase() && upper == 1;
+    let last_only = word[word.len() - 1].is_uppercase() && upper == 1;
+    if first_only || last_only || lower == 0 {
+        return 2.0;
+    }
+    (1..=upper.min(lower))
+        .map(|k| binomial(upper + lower, k))
+        .sum()
+}
+
+/// Every reading of `token` with its leet characters turned back into letters, with the
+/// number of ways the substitutions could have been made.
+fn unleet(token: &[char]) -> Vec<(String, f64)> {
+    let leet = |c: char| LEET.iter().find(|(from, _)| *from == c).map(|(_, to)| *to);
+    if !token.iter().any(|&c| leet(c).is_some()) {
+        return Vec::new();
+    }
+    let ambiguous = token
+        .iter()
+        .filter(|&&c| leet(c).is_some_and(|letters| letters.len() > 1))
+        .count();
+    if ambiguous > MAX_AMBIGUOUS_LEET {
+        return Vec::new();
+    }
+    let mut readings = vec![String::new()];
+    for &c in token {
+        readings = match leet(c) {
+            Some(letters) => readings
+                .iter()
+                .flat_map(|reading| letters.iter().map(move |&l| format!("{}{}", reading, l)))
+                .collect(),
+            None => readings
+                .into_iter()
+                .map(|r| format!("{}{}", r, c))
+                .collect(),
+        };
+    }
+    readings
+        .into_iter()
+        .map(|reading| {
+            let reading_chars: Vec<char> = reading.chars().collect();
+            let variations = leet_variations(token, &reading_chars);
+            (reading, variations)
+        })
+        .collect()
+}
+
+/// For each substituted letter, the ways of choosing which of its occurrences were
+/// substituted, as in zxcvbn.
+fn leet_variations(token: &[char], reading: &[char]) -> f64 {
+    let mut substitutions: Vec<(char, char)> = token
+        .iter()
+        .zip(reading)
+        .filter(|(from, to)| from != to)
+        .map(|(&from, &to)| (from, to))
+        .collect();
+    substitutions.sort_unstable();
+    substitutions.dedup();
+    substitutions
+        .iter()
+        .map(|&(from, to)| {
+            let substituted = token.iter().filter(|&&c| c == from).count();
+            let kept = token.iter().filter(|&&c| c == to).count();
+            match kept {
+                0 => 2.0,
+                _ => (1..=substituted.min(kept))
+                    .map(|k| binomial(substituted + kept, k))
+                    .sum(),
+            }
+        })
+        .product()
+}
+
+/// Runs like `abc`, `13579` or `zyx`, with the same step between each character.
+fn sequence_matches(chars: &[char], matches: &mut Vec<Match>) {
+    let delta = |i: usize| i64::from(u32::from(chars[i + 1])) - i64::from(u32::from(chars[i]));
+    let mut start = 0;
+    while start + 2 < chars.len() {
+        let step = delta(start);
+        let mut end = start + 2;
+        while end < chars.len() && delta(end - 1) == step {
+            end += 1;
+        }
+        if step != 0 && step.abs() <= 5 && end - start >= 3 {
+            let first = chars[start];
+            let base: f64 = if "aAzZ019".contains(first) {
+                4.0
+            } else if first.is_ascii_digit() {
+                10.0
+            } else {
+                26.0
+            };
+            let descending = if step < 0 { 2.0 } else { 1.0 };
+            matches.push(Match {
+                start,
+                end,
+                bits: (base * (end - start) as f64 * descending).log2(),
+            });
+            start = end - 1;
+        } else {
+            start += 1;
+        }
+    }
+}
+
+/// Runs of neighbouring keys along a row, like `qwerty` or `lkjh`.
+fn keyboard_matches(chars: &[char], lower: &[char], matches: &mut Vec<Match>) {
+    let position = |c: char| {
+        KEYBOARD_ROWS
+            .iter()
+            .enumerate()
+            .find_map(|(row, keys)| keys.chars().position(|k| k == c).map(|col| (row, col)))
+    };
+    let neighbours = |a: char, b: char, direction: i64| match (position(a), position(b)) {
+        (Some((row_a, col_a)), Some((row_b, col_b))) => {
+            row_a == row_b && col_b as i64 - col_a as i64 == direction
+        }
+        _ => false,
+    };
+    for direction in [1, -1] {
+        let mut start = 0;
+        while start < lower.len() {
+            let mut end = start + 1;
+            while end < lower.len() && neighbours(lower[end - 1], lower[end], direction) {
+                end += 1;
+            }
+            if end - start >= 3 {
+                let keys = KEYBOARD_KEYS * KEYBOARD_AVERAGE_DEGREE * (end - start - 1) as f64;
+                let case = uppercase_variations(&chars[start..end]);
+                matches.push(Match {
+                    start,
+                    end,
+                    bits: (keys * case).log2(),
+                });
+            }
+            start = end;
+        }
+    }
+}
+
+/// A part repeated over and over, like `aaaa` or `abcabc`, is worth the part once times
+/// the number of repeats. The shortest repeating part is taken, and matching continues
+/// after the repeats.
+fn repeat_matches(chars: &[char], matches: &mut Vec<Match>) {
+    let mut start = 0;
+    while start < chars.len() {
+        let repeat = (1..=(chars.len() - start) / 2).find_map(|unit| {
+            let part = &chars[start..start + unit];
+            let repeats = chars[start..]
+                .chunks_exact(unit)
+                .take_while(|chunk| *chunk == part)
+                .count();
+            (repeats >= 2).then_some((part, repeats))
+        });
+        let Some((part, repeats)) = repeat else {
+            start += 1;
+            continue;
+        };
+        let end = start + part.len() * repeats;
+        matches.push(Match {
+            start,
+            end,
+            bits: guesses_bits(part) + (repeats as f64).log2(),
+        });
+        start = end;
+    }
+}
+
+/// Years from 1900 to 2099; the further from the present, the less likely.
+fn year_matches(chars: &[char], matches: &mut Vec<Match>) {
+    for start in 0..chars.len().saturating_sub(3) {
+        let digits: String = chars[start..start + 4].iter().collect();
+        let Ok(year) = digits.parse::<i64>() else {
+            continue;
+        };
+        if (1900..=2099).contains(&year) && digits.chars().all(|c| c.is_ascii_digit()) {
+            let space = ((year - REFERENCE_YEAR).abs() as f64).max(MIN_YEAR_SPACE);
+            matches.push(Match {
+                start,
+                end: start + 4,
+                bits: space.log2(),
+            });
+        }
+    }
+}
+
+fn binomial(n: usize, k: usize) -> f64 {
+    (0..k).fold(1.0, |acc, i| acc * (n - i) as f64 / (i + 1) as f64)
+}
+
+fn log2_factorial(n: usize) -> f64 {
+    (2..=n).map(|i| (i as f64).log2()).sum()
+}
+
+/// log2(2^a + 2^b), without overflowing for large a or b.
+fn log2_sum(a: f64, b: f64) -> f64 {
+    let (high, low) = if a > b { (a, b) } else { (b, a) };
+    high + (1.0 + (low - high).exp2()).log2()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repeats_and_runs_count_little() {
+        assert_eq!(estimate_bits(""), 0.0);
+        assert!(estimate_bits("aaaaaaaa") < 10.0);
+        assert!(estimate_bits("abcdefgh") < 10.0);
+        assert!(estimate_bits("87654321") < 10.0);
+        assert!(estimate_bits("abcabcabcabc") < 15.0);
+        assert!(estimate_bits("password123") < MIN_MASTER_PASSWORD_BITS);
+        assert!(estimate_bits("qwertyui") < MIN_MASTER_PASSWORD_BITS);
+    }
+
+    #[test]
+    fn common_words_are_weak_however_they_are_spelled() {
+        for password in [
+            "Password1234",
+            "P@ssw0rd2024",
+            "drowssap",
+            "Dragon1987!",
+            "monkeybanana",
+            "Tr0ub4dor&3x",
+            "jennifer2001",
+            "qwerty123456",
+        ] {
+            assert!(
+                estimate_bits(password) < MIN_MASTER_PASSWORD_BITS,
+                "{} is estimated at {:.1} bits",
+                password,
+                estimate_bits(password)
+            );
+        }
+        assert!(estimate_bits("P@ssw0rd") < estimate_bits("x7#Kq9vZ"));
+    }
+
+    #[test]
+    fn long_passphrases_are_strong() {
+        for password in [
+            "correct horse battery staple",
+            "hj4R#mQ9vL2$xW7pZ",
+            "glimmer-quasar-tundra-oboe",
+        ] {
+            assert!(
+                estimate_bits(password) > MIN_MASTER_PASSWORD_BITS,
+                "{} is estimated at {:.1} bits",
+                password,
+                estimate_bits(password)
+            );
+        }
+        assert!(estimate_bits("Tr0ub4dor&3x") > estimate_bits("tr0ub4dor3x"));
+    }
+
+    #[test]
+    fn long_passwords_are_estimated_quickly() {
+        let long = "x".repeat(10_000);
+        assert!(estimate_bits(&long) > MIN_MASTER_PASSWORD_BITS);
+    }
+}
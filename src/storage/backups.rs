@@ -0,0 +1,311 @@
+use super::entry::{civil_from_days, days_from_civil};
+use super::private_files::{create_private_dir, private_file_options};
+use super::vaults::{Backend, Vault};
+use crate::crypto::envelope::{base64_bytes, SealedBox};
+use crate::crypto::kdf::KdfParams;
+use crate::error::PwError;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+const BACKUP_EXTENSION: &str = "pwbak";
+
+const SECONDS_PER_DAY: u64 = 86_400;
+
+/// What a backup file holds, sealed under the vault's master password: the vault file as
+/// it was when the backup was taken.
+#[derive(Serialize, Deserialize)]
+pub struct Snapshot {
+    pub backend: Backend,
+    pub created: u64,
+    #[serde(with = "base64_bytes")]
+    pub data: Vec<u8>,
+}
+
+/// A backup file. Its id is the UTC time it was taken, e.g. `20261019-153000`, with a
+/// `-N` suffix when several are taken in the same second.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Backup {
+    pub id: String,
+    pub path: PathBuf,
+    pub created: u64,
+}
+
+fn backup_id(created: u64) -> String {
+    let (year, month, day) = civil_from_days((created / SECONDS_PER_DAY) as i64);
+    let seconds = created % SECONDS_PER_DAY;
+    format!(
+        "{:04}{:02}{:02}-{:02}{:02}{:02}",
+        year,
+        month,
+        day,
+        seconds / 3_600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+/// The time a backup was taken, read back from its id.
+fn parse_id(id: &str) -> Option<u64> {
+    let stamp = id.get(..15)?;
+    let suffix = &id[15..];
+    if !(suffix.is_empty() || suffix.strip_prefix('-')?.parse::<u32>().is_ok()) {
+        return None;
+    }
+    let (date, time) = stamp.split_once('-')?;
+    if date.len() != 8
+        || time.len() != 6
+        || !stamp.replace('-', "").bytes().all(|b| b.is_ascii_digit())
+    {
+        return None;
+    }
+    let number = |text: &str| text.parse::<i64>().ok();
+    let days = days_from_civil(
+        number(&date[..4])?,
+        number(&date[4..6])?,
+        number(&date[6..])?,
+    );
+    let seconds = number(&time[..2])? * 3_600 + number(&time[2..4])? * 60 + number(&time[4..])?;
+    u64::try_from(days * SECONDS_PER_DAY as i64 + seconds).ok()
+}
+
+/// Seals the vault file into a new backup in `dir`, created with owner-only access.
+pub fn create(
+    vault: &Vault,
+    dir: &Path,
+    password: &str,
+    kdf: KdfParams,
+    now: u64,
+) -> io::Result<Backup> {
+    if vault.backend == Backend::Pass {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "pass stores are backed up with 'pass git', not pw backup",
+        ));
+    }
+    let snapshot = Snapshot {
+        backend: vault.backend,
+        created: now,
+        data: fs::read(&vault.path)?,
+    };
+    let sealed = SealedBox::seal(password.as_bytes(), &serde_json::to_vec(&snapshot)?, kdf)?;
+    let contents = serde_json::to_string(&sealed)?;
+
+    create_private_dir(dir)?;
+    let stamp = backup_id(now);
+    for attempt in 0.. {
+        let id = match attempt {
+            0 => stamp.clone(),
+            n => format!("{}-{}", stamp, n),
+        };
+        let path = dir.join(format!("{}.{}", id, BACKUP_EXTENSION));
+        let mut file = match private_file_options()
+            .write(true)
+            .create_new(true)
+            .open(&path)
+        {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        };
+        file.write_all(contents.as_bytes())?;
+        return Ok(Backup {
+            id,
+            path,
+            created: now,
+        });
+    }
+    unreachable!("there is always a free backup id")
+}
+
+/// Every backup in `dir`, oldest first. Other files are ignored.
+pub fn list(dir: &Path) -> io::Result<Vec<Backup>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let mut backups = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        if path.extension().and_then(|ext| ext.to_str()) != Some(BACKUP_EXTENSION) {
+            continue;
+        }
+        let Some(id) = path.file_stem().and_then(|stem| stem.to_str()) else {
+            continue;
+        };
+        if let Some(created) = parse_id(id) {
+            backups.push(Backup {
+                id: id.to_string(),
+                created,
+                path,
+            });
+        }
+    }
+    backups.sort_by_key(|backup| (backup.created, backup.id.len(), backup.id.clone()));
+    Ok(backups)
+}
+
+pub fn find(dir: &Path, id: &str) -> io::Result<Backup> {
+    list(dir)?
+        .into_iter()
+        .find(|backup| backup.id == id)
+        .ok_or_else(|| {
+            PwError::NotFound(format!(
+                "No backup '{}' in {}. List backups with 'pw backup list'.",
+                id,
+                dir.display()
+            ))
+            .into()
+        })
+}
+
+/// Decrypts a backup. A wrong password and a damaged file both fail here, as the
+/// encryption is authenticated.
+pub fn open(backup: &Backup, password: &str) -> io::Result<Snapshot> {
+    let sealed: SealedBox = serde_json::from_str(&fs::read_to_string(&backup.path)?)?;
+    let plaintext = sealed.open(password.as_bytes())?;
+    Ok(serde_json::from_slice(&plaintext)?)
+}
+
+/// Writes the vault file of `snapshot` next to the live vault, so it can be checked and
+/// then renamed into place.
+pub fn stage(snapshot: &Snapshot, vault: &Vault) -> io::Result<PathBuf> {
+    let mut name = vault.path.as_os_str().to_owned();
+    name.push(".restore");
+    let path = PathBuf::from(name);
+    private_file_options()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(&path)?
+        .write_all(&snapshot.data)?;
+    Ok(path)
+}
+
+/// The backups the retention policy lets go: all but the `keep_last` most recent, and the
+/// last backup of each of the `keep_daily` most recent days. `backups` is oldest first.
+pub fn expired(backups: &[Backup], keep_last: usize, keep_daily: u64, now: u64) -> Vec<Backup> {
+    let today = now / SECONDS_PER_DAY;
+    let mut kept_days = HashSet::new();
+    let mut expired = Vec::new();
+    for (age, backup) in backups.iter().rev().enumerate() {
+        let day = backup.created / SECONDS_PER_DAY;
+        let recent_day = today.saturating_sub(day) < keep_daily;
+        let last_of_day = recent_day && kept_days.insert(day);
+        if age >= keep_last && !last_of_day {
+            expired.push(backup.clone());
+        }
+    }
+    expired.reverse();
+    expired
+}
+
+/// Deletes the backups in `dir` that the retention policy lets go, returning them.
+pub fn prune(dir: &Path, keep_last: usize, keep_daily: u64, now: u64) -> io::Result<Vec<Backup>> {
+    let expired = expired(&list(dir)?, keep_last, keep_daily, now);
+    for backup in &expired {
+        fs::remove_file(&backup.path)?;
+    }
+    Ok(expired)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::kdf::TEST_KDF_PARAMS;
+    use crate::error::PwError;
+    use tempfile::tempdir;
+
+    const NOW: u64 = 1_792_449_600;
+
+    fn backup(created: u64) -> Backup {
+        let id = backup_id(created);
+        Backup {
+            path: PathBuf::from(format!("{}.pwbak", id)),
+            id,
+            created,
+        }
+    }
+
+    #[test]
+    fn ids_are_utc_timestamps() {
+        assert_eq!(backup_id(0), "19700101-000000");
+        assert_eq!(backup_id(NOW), "20261019-224000");
+        assert_eq!(parse_id("20261019-224000"), Some(NOW));
+        assert_eq!(parse_id("20261019-224000-2"), Some(NOW));
+        for id in [
+            "",
+            "2026",
+            "20261019_224000",
+            "20261019-224000x",
+            "2026101a-224000",
+        ] {
+            assert_eq!(parse_id(id), None, "{}", id);
+        }
+    }
+
+    #[test]
+    fn retention_keeps_the_last_backups_and_one_per_day() {
+        let day = SECONDS_PER_DAY;
+        // Three backups today, two yesterday, one a week ago and one two months ago.
+        let backups: Vec<Backup> = [
+            NOW - 60 * day,
+            NOW - 7 * day,
+            NOW - day - 20,
+            NOW - day - 10,
+            NOW - 30,
+            NOW - 20,
+            NOW - 10,
+        ]
+        .into_iter()
+        .map(backup)
+        .collect();
+
+        let ids = |backups: Vec<Backup>| -> Vec<u64> {
+            backups.iter().map(|backup| backup.created).collect()
+        };
+        assert_eq!(
+            ids(expired(&backups, 2, 30, NOW)),
+            vec![NOW - 60 * day, NOW - day - 20, NOW - 30]
+        );
+        assert_eq!(
+            ids(expired(&backups, 2, 0, NOW)),
+            ids(backups[..5].to_vec())
+        );
+        assert!(expired(&backups, 10, 0, NOW).is_empty());
+    }
+
+    #[test]
+    fn backups_round_trip_and_need_the_password() -> Result<(), PwError> {
+        let temp_dir = tempdir()?;
+        let vault = Vault {
+            backend: Backend::Json,
+            path: temp_dir.path().join("store.json"),
+        };
+        fs::write(&vault.path, "vault contents")?;
+        let dir = temp_dir.path().join("backups").join("default");
+
+        let first = create(&vault, &dir, "master", TEST_KDF_PARAMS, NOW)?;
+        let second = create(&vault, &dir, "master", TEST_KDF_PARAMS, NOW)?;
+        assert_eq!(first.id, "20261019-224000");
+        assert_eq!(second.id, "20261019-224000-1");
+        fs::write(dir.join("notes.txt"), "not a backup")?;
+        assert_eq!(list(&dir)?, vec![first.clone(), second]);
+        assert!(!fs::read_to_string(&first.path)?.contains("vault contents"));
+
+        let snapshot = open(&find(&dir, &first.id)?, "master")?;
+        assert_eq!(snapshot.data, b"vault contents");
+        assert_eq!(snapshot.backend, Backend::Json);
+        let wrong = open(&first, "wrong").map_err(PwError::from);
+        assert!(matches!(wrong, Err(PwError::Auth(_))));
+        let missing = find(&dir, "20200101-000000").map_err(PwError::from);
+        assert!(matches!(missing, Err(PwError::NotFound(_))));
+
+        prune(&dir, 1, 0, NOW)?;
+        assert_eq!(list(&dir)?.len(), 1);
+        Ok(())
+    }
+}
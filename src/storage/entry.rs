@@ -0,0 +1,142 @@
+use crate::crypto::secret::Secret;
+use crate::otp::otpauth::OtpParams;
+use crate::password_generator::mode::GenerateMode;
+use serde::{Deserialize, Serialize};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A stored secret together with the metadata kept alongside it.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct Entry {
+    pub value: Secret,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notes: Option<String>,
+    /// The generator profile the value was created with. Entries generated before
+    /// `generator` was recorded are rotated with it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+    /// How the value was generated, used again when rotating.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub generator: Option<GenerateMode>,
+    /// Previous values, oldest first.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub history: Vec<HistoryItem>,
+    /// One-time password parameters, for entries that also hold a 2FA seed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub otp: Option<OtpParams>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct HistoryItem {
+    pub value: Secret,
+    /// When the value was replaced, in seconds since the Unix epoch.
+    pub replaced_at: u64,
+}
+
+impl Entry {
+    pub fn new(value: impl Into<Secret>) -> Self {
+        Self {
+            value: value.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Replaces the value, moving the previous one into the history. Setting the value it
+    /// already has is a no-op.
+    pub fn replace_value(&mut self, value: impl Into<Secret>) {
+        let value = value.into();
+        if value == self.value {
+            return;
+        }
+        let previous = std::mem::replace(&mut self.value, value);
+        if !previous.is_empty() {
+            self.history.push(HistoryItem {
+                value: previous,
+                replaced_at: now(),
+            });
+        }
+    }
+}
+
+pub(crate) fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// Days since the Unix epoch for a proleptic Gregorian date.
+pub(crate) fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month_index = (month + 9) % 12;
+    let day_of_year = (153 * month_index + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// The proleptic Gregorian date of a day counted from the Unix epoch, as year, month, day.
+pub(crate) fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replace_value_keeps_history() {
+        let mut entry = Entry::new("first".to_string());
+        entry.replace_value("second".to_string());
+        entry.replace_value("third".to_string());
+
+        assert_eq!(entry.value, "third");
+        let history: Vec<&str> = entry.history.iter().map(|h| h.value.expose()).collect();
+        assert_eq!(history, vec!["first", "second"]);
+    }
+
+    #[test]
+    fn replace_with_same_value_is_noop() {
+        let mut entry = Entry::new("same".to_string());
+        entry.replace_value("same".to_string());
+        assert!(entry.history.is_empty());
+    }
+
+    #[test]
+    fn default_entry_has_no_history_after_first_value() {
+        let mut entry = Entry::default();
+        entry.replace_value("first".to_string());
+        assert!(entry.history.is_empty());
+    }
+
+    #[test]
+    fn civil_dates_round_trip() {
+        for (date, days) in [
+            ((1970, 1, 1), 0),
+            ((2000, 2, 29), 11_016),
+            ((2026, 10, 19), 20_745),
+            ((1969, 12, 31), -1),
+        ] {
+            assert_eq!(days_from_civil(date.0, date.1, date.2), days);
+            assert_eq!(civil_from_days(days), date);
+        }
+    }
+}
@@ -1,103 +1,795 @@
-use super::storage_trait::Storage;
+use super::entry::Entry;
+use super::json_salvage::read_partial_object;
+use super::keyfile::{composite_secret, keyfile_check, verify_keyfile, Keyfile};
+use super::private_files::{create_private_dir, private_file_options};
+use super::storage_trait::{MasterKey, Storage};
+use crate::crypto::envelope::{base64_bytes, open_blob, seal_blob};
+use crate::crypto::kdf::{derive_key, KdfParams, KEY_LEN, SALT_LEN};
+use crate::crypto::secret::{LockedKey, Secret};
+use crate::error::PwError;
+use hmac::digest::FixedOutput;
+use hmac::{Hmac, Mac};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::Sha256;
+use zeroize::Zeroizing;
 
-use std::collections::HashMap;
-use std::fs::{self, File, OpenOptions};
-use std::io::{self, Read, Write};
-use std::path::Path;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
 
+type HmacSha256 = Hmac<Sha256>;
+
+const VAULT_FORMAT: &str = "pw-vault";
+/// The members of a vault file besides `format`.
+const VAULT_MEMBERS: [&str; 4] = ["version", "header", "entries", "mac"];
+
+/// Version 1 vaults were a bare map of keys to entries, with no header and no MAC, and are
+/// only converted by `FileStorage::migrate`, since anyone can write such a file. Versions 2
+/// and 3 used a key derived straight from the master password, and version 2 kept its
+/// entries in plaintext. Both are upgraded when opened.
+const VAULT_VERSION: u32 = 4;
+const DERIVED_KEY_VERSION: u32 = 3;
+const PLAINTEXT_VERSION: u32 = 2;
+
+/// MACed under the derived key of version 2 and 3 vaults, to tell a wrong master password
+/// apart from a modified vault.
+const KEY_CHECK: &[u8] = b"pw-vault-key-check";
+
+/// The MAC and the records use separate keys derived from the data key with these labels.
+const MAC_KEY_LABEL: &[u8] = b"pw-vault-mac-key";
+const RECORD_KEY_LABEL: &[u8] = b"pw-vault-record-key";
+
+/// Associated data of the wrapped data key.
+const DATA_KEY_AAD: &str = "pw-vault-data-key";
+
+/// Vaults written before entries carried metadata map keys straight to values.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StoredEntry {
+    Legacy(String),
+    Entry(Box<Entry>),
+}
+
+impl From<StoredEntry> for Entry {
+    fn from(stored: StoredEntry) -> Self {
+        match stored {
+            StoredEntry::Legacy(value) => Entry::new(value),
+            StoredEntry::Entry(entry) => *entry,
+        }
+    }
+}
+
+/// The random data key, wrapped under a key derived from the master password and, if the
+/// vault has one, its keyfile. Changing the password only wraps the data key again; the
+/// entries stay as they are.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct VaultHeader {
+    kdf: KdfParams,
+    #[serde(with = "base64_bytes")]
+    salt: Vec<u8>,
+    #[serde(with = "base64_bytes")]
+    wrapped_key: Vec<u8>,
+    /// Present when the vault needs a keyfile, to check the keyfile on its own.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    keyfile: Option<KeyfileCheck>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct KeyfileCheck(#[serde(with = "base64_bytes")] Vec<u8>);
+
+/// The header of version 2 and 3 vaults, whose key was derived from the master password.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct DerivedKeyHeader {
+    kdf: KdfParams,
+    #[serde(with = "base64_bytes")]
+    salt: Vec<u8>,
+    #[serde(with = "base64_bytes")]
+    check: Vec<u8>,
+}
+
+impl DerivedKeyHeader {
+    /// Derives the vault key, failing if `password` is not the vault's master password.
+    fn derive(&self, password: &str) -> io::Result<LockedKey> {
+        let key = derive_key(password.as_bytes(), &self.salt, &self.kdf)?;
+        key_check(key.expose())
+            .verify_slice(&self.check)
+            .map_err(|_| PwError::Auth("wrong master password".to_string()))?;
+        Ok(key)
+    }
+}
+
+/// One entry, encrypted on its own with its key as associated data. A damaged record loses
+/// only its own entry, and records cannot be moved to another key.
+#[derive(Serialize, Deserialize, Clone)]
+struct Record(#[serde(with = "base64_bytes")] Vec<u8>);
+
+/// The vault as written to disk. The MAC covers every other field.
 #[derive(Serialize, Deserialize)]
+struct VaultFile<H, E> {
+    format: String,
+    version: u32,
+    header: H,
+    entries: BTreeMap<String, E>,
+    #[serde(with = "base64_bytes")]
+    mac: Vec<u8>,
+}
+
+/// The input to the MAC. Entries are in key order, so the same content always gives the
+/// same bytes however the file is formatted.
+#[derive(Serialize)]
+struct SignedContent<'a, H, E> {
+    format: &'a str,
+    version: u32,
+    header: &'a H,
+    entries: &'a BTreeMap<String, E>,
+}
+
+/// What a vault file contains, before anything is checked.
+enum Contents {
+    Missing,
+    Legacy(BTreeMap<String, Entry>),
+    Plaintext(VaultFile<DerivedKeyHeader, Entry>),
+    DerivedKey(VaultFile<DerivedKeyHeader, Record>),
+    Sealed(VaultFile<VaultHeader, Record>),
+}
+
+fn read_contents(path: &Path) -> io::Result<Contents> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Contents::Missing),
+        Err(e) => return Err(e),
+    };
+    if contents.trim().is_empty() {
+        return Err(damaged(path, "it is empty"));
+    }
+    let value: Value =
+        serde_json::from_str(&contents).map_err(|e| damaged(path, &e.to_string()))?;
+    if value.get("format").and_then(Value::as_str) != Some(VAULT_FORMAT) {
+        let map: BTreeMap<String, StoredEntry> =
+            serde_json::from_value(value).map_err(|e| damaged(path, &e.to_string()))?;
+        return Ok(Contents::Legacy(
+            map.into_iter().map(|(k, v)| (k, v.into())).collect(),
+        ));
+    }
+    match value.get("version").and_then(Value::as_u64) {
+        Some(version) if version == u64::from(VAULT_VERSION) => {
+            Ok(Contents::Sealed(serde_json::from_value(value)?))
+        }
+        Some(version) if version == u64::from(DERIVED_KEY_VERSION) => {
+            Ok(Contents::DerivedKey(serde_json::from_value(value)?))
+        }
+        Some(version) if version == u64::from(PLAINTEXT_VERSION) => {
+            Ok(Contents::Plaintext(serde_json::from_value(value)?))
+        }
+        version => Err(unknown_version(path, version)),
+    }
+}
+
+fn unknown_version(path: &Path, version: Option<u64>) -> io::Error {
+    let version = version.map_or("no".to_string(), |version| version.to_string());
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!(
+            "{} has vault version {}, which this version of pw cannot read",
+            path.display(),
+            version
+        ),
+    )
+}
+
+fn damaged(path: &Path, reason: &str) -> io::Error {
+    PwError::Corrupt(format!(
+        "{} is not a readable vault: {}. Restore it with 'pw backup restore' or salvage \
+         what is left with 'pw recover'.",
+        path.display(),
+        reason
+    ))
+    .into()
+}
+
+/// A bare map has no MAC, so it is refused rather than trusted: it may be an old vault,
+/// or a sealed vault replaced by someone who does not know the master password.
+fn unprotected(path: &Path) -> io::Error {
+    PwError::Corrupt(format!(
+        "{} has no master password or integrity check. If it is a vault written by a \
+         version of pw before master passwords, check its contents and protect it with \
+         'pw init --migrate'.",
+        path.display()
+    ))
+    .into()
+}
+
+fn tampered(path: &Path, reason: &str) -> io::Error {
+    PwError::Corrupt(format!(
+        "{} failed its integrity check: {}. It was modified outside pw or is damaged. \
+         Restore it with 'pw backup restore', salvage what is left with 'pw recover', or \
+         run 'pw verify --accept-changes' if you made the change yourself.",
+        path.display(),
+        reason
+    ))
+    .into()
+}
+
+fn keyed_hmac(key: &[u8; KEY_LEN]) -> HmacSha256 {
+    HmacSha256::new_from_slice(key).expect("HMAC accepts any key length")
+}
+
+fn compute_mac<H: Serialize, E: Serialize>(
+    key: &[u8; KEY_LEN],
+    file: &VaultFile<H, E>,
+) -> io::Result<HmacSha256> {
+    let mut mac = keyed_hmac(key);
+    mac.update(&serde_json::to_vec(&SignedContent {
+        format: &file.format,
+        version: file.version,
+        header: &file.header,
+        entries: &file.entries,
+    })?);
+    Ok(mac)
+}
+
+fn verify_mac<H: Serialize, E: Serialize>(
+    path: &Path,
+    key: &[u8; KEY_LEN],
+    file: &VaultFile<H, E>,
+) -> io::Result<()> {
+    compute_mac(key, file)?
+        .verify_slice(&file.mac)
+        .map_err(|_| tampered(path, "its contents do not match its MAC"))
+}
+
+fn key_check(key: &[u8; KEY_LEN]) -> HmacSha256 {
+    let mut mac = keyed_hmac(key);
+    mac.update(KEY_CHECK);
+    mac
+}
+
+fn labeled_key(key: &[u8; KEY_LEN], label: &[u8]) -> LockedKey {
+    let mut mac = keyed_hmac(key);
+    mac.update(label);
+    let mut labeled = LockedKey::zeroed();
+    mac.finalize_into(labeled.expose_mut().into());
+    labeled
+}
+
+fn open_record(record_key: &[u8; KEY_LEN], key: &str, record: &Record) -> io::Result<Entry> {
+    let plaintext = open_blob(record_key, &record.0, key).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("the entry for '{}' cannot be decrypted", key),
+        )
+    })?;
+    Ok(serde_json::from_slice(&plaintext)?)
+}
+
+fn salvage_members<T: DeserializeOwned>(
+    members: Vec<(String, Value)>,
+    salvage: &mut Salvage,
+    read: impl Fn(&str, T) -> io::Result<Entry>,
+) {
+    for (key, value) in members {
+        let entry = serde_json::from_value::<T>(value)
+            .map_err(io::Error::from)
+            .and_then(|value| read(&key, value));
+        match entry {
+            Ok(entry) => salvage.entries.push((key, entry)),
+            Err(e) => salvage.lost.push((key, e.to_string())),
+        }
+    }
+}
+
+/// What `FileStorage::salvage` could read from a damaged vault.
+#[derive(Debug, Default)]
+pub struct Salvage {
+    pub entries: Vec<(String, Entry)>,
+    /// Entries known to exist that could not be read, with the reason.
+    pub lost: Vec<(String, String)>,
+    /// Whether the file ends in the middle of the entries, so entries after the cut are
+    /// lost without a trace.
+    pub truncated: bool,
+}
+
+/// Stores every entry in one JSON file, rewritten on each change. Each entry is encrypted
+/// separately with XChaCha20-Poly1305, and the file carries an HMAC-SHA256 over its header
+/// and all records, which is checked on every read. Both keys are derived from a random
+/// data key, which is wrapped under a key derived from the master password with Argon2id.
 pub struct FileStorage {
-    file_path: String,
+    file_path: PathBuf,
+    header: VaultHeader,
+    data_key: LockedKey,
+    mac_key: LockedKey,
+    record_key: LockedKey,
+    keyfile: Option<Keyfile>,
 }
 
 impl FileStorage {
-    pub fn new(file_path: &str) -> Self {
+    /// Opens the vault at `file_path`, creating it and its directory, with owner-only
+    /// access, if needed. `new_kdf` only applies to a new vault, which also needs
+    /// `keyfile`, if one is given, from now on. A vault written before vaults had a master
+    /// password is refused; see `migrate`.
+    pub fn new(
+        file_path: &str,
+        password: &str,
+        keyfile: Option<&Keyfile>,
+        new_kdf: KdfParams,
+    ) -> io::Result<Self> {
         let path = Path::new(file_path);
+        if let Some(parent) = path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+        {
+            create_private_dir(parent)?;
+        }
 
-        if let Some(parent) = path.parent() {
-            if !parent.exists() {
-                match fs::create_dir_all(parent) {
-                    Ok(_) => println!("Directory created: {:?}", parent),
-                    Err(e) => panic!("Failed to create directory: {:?}, error: {}", parent, e),
-                }
+        match read_contents(path)? {
+            Contents::Sealed(file) => {
+                let storage = Self::unlock(path, file.header, password, keyfile)?;
+                storage.read_storage()?;
+                Ok(storage)
+            }
+            Contents::DerivedKey(file) => {
+                verify_keyfile(path, None, &file.header.salt, keyfile)?;
+                let key = file.header.derive(password)?;
+                verify_mac(path, key.expose(), &file)?;
+                let storage = Self::protect(path, key, password, None, file.header.kdf)?;
+                storage.write_records(&file.entries)?;
+                Ok(storage)
             }
+            Contents::Plaintext(file) => {
+                verify_keyfile(path, None, &file.header.salt, keyfile)?;
+                let key = file.header.derive(password)?;
+                verify_mac(path, key.expose(), &file)?;
+                let storage = Self::protect(path, key, password, None, file.header.kdf)?;
+                storage.write_storage(&file.entries)?;
+                Ok(storage)
+            }
+            Contents::Legacy(_) => Err(unprotected(path)),
+            Contents::Missing => Self::create(path, password, keyfile, new_kdf, &BTreeMap::new()),
         }
+    }
 
-        let mut file = match OpenOptions::new()
-            .read(true)
-            .write(true)
-            .create(true)
-            .open(file_path)
-        {
-            Ok(file) => file,
-            Err(e) => panic!(
-                "Failed to open or create file: {:?}, error: {}",
-                file_path, e
+    /// Protects a vault written before vaults had a master password with `password`, and
+    /// `keyfile` if one is given, from now on. Returns the number of entries. Such a vault
+    /// has nothing to verify its contents with, so this is only run when asked for.
+    pub fn migrate(
+        file_path: &str,
+        password: &str,
+        keyfile: Option<&Keyfile>,
+        kdf: KdfParams,
+    ) -> io::Result<usize> {
+        let path = Path::new(file_path);
+        match read_contents(path)? {
+            Contents::Legacy(entries) => {
+                Self::create(path, password, keyfile, kdf, &entries)?;
+                Ok(entries.len())
+            }
+            Contents::Missing => {
+                Err(PwError::NotFound(format!("{} does not exist", path.display())).into())
+            }
+            _ => Err(
+                PwError::Invalid(format!("{} already has a master password", path.display()))
+                    .into(),
             ),
+        }
+    }
+
+    fn create(
+        path: &Path,
+        password: &str,
+        keyfile: Option<&Keyfile>,
+        kdf: KdfParams,
+        entries: &BTreeMap<String, Entry>,
+    ) -> io::Result<Self> {
+        let storage = Self::protect(path, LockedKey::random(), password, keyfile, kdf)?;
+        storage.write_storage(entries)?;
+        Ok(storage)
+    }
+
+    /// Wraps `data_key` under `password` and `keyfile` with a fresh salt.
+    fn protect(
+        path: &Path,
+        data_key: LockedKey,
+        password: &str,
+        keyfile: Option<&Keyfile>,
+        kdf: KdfParams,
+    ) -> io::Result<Self> {
+        let mut salt = vec![0u8; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+        let wrapping_key = derive_key(&composite_secret(password, keyfile), &salt, &kdf)?;
+        let header = VaultHeader {
+            kdf,
+            wrapped_key: seal_blob(wrapping_key.expose(), data_key.expose(), DATA_KEY_AAD)?,
+            keyfile: keyfile_check(keyfile, &salt).map(KeyfileCheck),
+            salt,
         };
+        Ok(Self::with_keys(path, header, data_key, keyfile))
+    }
 
-        match file.metadata() {
-            Ok(metadata) => {
-                if metadata.len() == 0 {
-                    if let Err(e) = file.write_all(b"{}") {
-                        panic!("Failed to write to file: {:?}, error: {}", file_path, e);
-                    }
-                }
-            }
-            Err(e) => panic!("Failed to get file metadata: {:?}, error: {}", file_path, e),
-        }
+    /// Unwraps the data key, failing if the keyfile or `password` is not the vault's.
+    fn unlock(
+        path: &Path,
+        header: VaultHeader,
+        password: &str,
+        keyfile: Option<&Keyfile>,
+    ) -> io::Result<Self> {
+        let check = header.keyfile.as_ref().map(|check| check.0.as_slice());
+        verify_keyfile(path, check, &header.salt, keyfile)?;
+        let secret = composite_secret(password, keyfile);
+        let wrapping_key = derive_key(&secret, &header.salt, &header.kdf)?;
+        let data_key = open_blob(wrapping_key.expose(), &header.wrapped_key, DATA_KEY_AAD)
+            .map_err(|_| PwError::Auth("wrong master password".to_string()))?;
+        let data_key = LockedKey::from_slice(&data_key)
+            .ok_or_else(|| tampered(path, "its data key has the wrong length"))?;
+        Ok(Self::with_keys(path, header, data_key, keyfile))
+    }
 
+    fn with_keys(
+        path: &Path,
+        header: VaultHeader,
+        data_key: LockedKey,
+        keyfile: Option<&Keyfile>,
+    ) -> Self {
         Self {
-            file_path: file_path.to_string(),
+            file_path: path.to_path_buf(),
+            header,
+            mac_key: labeled_key(data_key.expose(), MAC_KEY_LABEL),
+            record_key: labeled_key(data_key.expose(), RECORD_KEY_LABEL),
+            data_key,
+            keyfile: keyfile.cloned(),
         }
     }
 
-    fn read_storage(&self) -> io::Result<HashMap<String, String>> {
-        match File::open(&self.file_path) {
-            Ok(mut file) => {
-                let mut contents = String::new();
-                file.read_to_string(&mut contents)?;
-                let map: HashMap<String, String> = serde_json::from_str(&contents)?;
-                Ok(map)
+    /// Opens the vault with its data key, recovered from recovery shares instead of
+    /// unwrapped with the master password. The key is checked against the vault's MAC.
+    pub fn open_with_data_key(
+        file_path: &str,
+        data_key: LockedKey,
+        keyfile: Option<&Keyfile>,
+    ) -> io::Result<Self> {
+        let path = Path::new(file_path);
+        let file = match read_contents(path)? {
+            Contents::Sealed(file) => file,
+            Contents::Missing => {
+                return Err(PwError::NotFound(format!("{} does not exist", path.display())).into())
             }
-            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(HashMap::new()),
-            Err(e) => Err(e),
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "{} was not split into recovery shares by this version of pw",
+                        path.display()
+                    ),
+                ))
+            }
+        };
+        let storage = Self::with_keys(path, file.header.clone(), data_key, keyfile);
+        verify_mac(path, storage.mac_key.expose(), &file).map_err(|_| {
+            PwError::Auth(format!(
+                "The recovery shares do not open {}",
+                path.display()
+            ))
+        })?;
+        Ok(storage)
+    }
+
+    /// The Argon2id parameters in the header of the vault at `path`, if it has a master
+    /// password.
+    pub fn kdf_params(path: &Path) -> io::Result<Option<KdfParams>> {
+        Ok(match read_contents(path)? {
+            Contents::Missing | Contents::Legacy(_) => None,
+            Contents::Plaintext(file) => Some(file.header.kdf),
+            Contents::DerivedKey(file) => Some(file.header.kdf),
+            Contents::Sealed(file) => Some(file.header.kdf),
+        })
+    }
+
+    /// Reads the vault at `path` without creating it or checking its MAC, returning the
+    /// number of entries.
+    pub fn check(path: &Path) -> io::Result<usize> {
+        match read_contents(path)? {
+            Contents::Missing => {
+                Err(PwError::NotFound(format!("{} does not exist", path.display())).into())
+            }
+            Contents::Legacy(_) => Err(unprotected(path)),
+            Contents::Plaintext(file) => Ok(file.entries.len()),
+            Contents::DerivedKey(file) => Ok(file.entries.len()),
+            Contents::Sealed(file) => Ok(file.entries.len()),
         }
     }
 
-    fn write_storage(&self, map: &HashMap<String, String>) -> io::Result<()> {
-        let contents = serde_json::to_string(map)?;
-        fs::write(&self.file_path, contents)
+    /// Accepts the current contents of a vault that failed its integrity check, by
+    /// computing a new MAC over them. Only the master password is checked, so this is for
+    /// changes the user made or has reviewed. Returns the number of entries.
+    pub fn accept_changes(
+        file_path: &str,
+        password: &str,
+        keyfile: Option<&Keyfile>,
+    ) -> io::Result<usize> {
+        let path = Path::new(file_path);
+        let entries = match read_contents(path)? {
+            Contents::Sealed(file) => {
+                let storage = Self::unlock(path, file.header, password, keyfile)?;
+                let entries = storage.open_records(&file.entries)?;
+                storage.write_storage(&entries)?;
+                entries
+            }
+            Contents::DerivedKey(file) => {
+                let key = file.header.derive(password)?;
+                let storage = Self::protect(path, key, password, None, file.header.kdf)?;
+                let entries = storage.open_records(&file.entries)?;
+                storage.write_storage(&entries)?;
+                entries
+            }
+            Contents::Plaintext(file) => {
+                let key = file.header.derive(password)?;
+                let storage = Self::protect(path, key, password, None, file.header.kdf)?;
+                storage.write_storage(&file.entries)?;
+                file.entries
+            }
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("{} has no integrity check to update", path.display()),
+                ))
+            }
+        };
+        Ok(entries.len())
+    }
+
+    /// Reads every entry that can still be read from a damaged vault: a file cut off part
+    /// way, with broken JSON, or with damaged records. Nothing is written. `password`
+    /// and `keyfile` are only needed to decrypt records.
+    pub fn salvage(path: &Path, password: &str, keyfile: Option<&Keyfile>) -> io::Result<Salvage> {
+        let bytes = fs::read(path)?;
+        let text = String::from_utf8_lossy(&bytes);
+        let (vault, entries) = read_partial_object(&text, Some("entries"));
+        let member = |name: &str| {
+            vault
+                .members
+                .iter()
+                .find(|(member, _)| member == name)
+                .map(|(_, value)| value)
+        };
+
+        // A damaged "format" does not make a vault legacy: restoring its header, records
+        // and MAC as password entries would be worse than restoring nothing.
+        let is_vault_member = |name: &str| name == "format" || VAULT_MEMBERS.contains(&name);
+        let versioned = member("format").and_then(Value::as_str) == Some(VAULT_FORMAT)
+            || entries.is_some()
+            || vault.members.iter().any(|(name, _)| is_vault_member(name))
+            || vault.damaged.as_deref().is_some_and(is_vault_member);
+
+        let mut salvage = Salvage::default();
+        if !versioned {
+            let (map, _) = read_partial_object(&text, None);
+            salvage_members(map.members, &mut salvage, |_, stored: StoredEntry| {
+                Ok(stored.into())
+            });
+            if let Some(key) = map.damaged {
+                salvage.lost.push((key, "cut off or damaged".to_string()));
+            }
+            salvage.truncated = !map.complete;
+            return Ok(salvage);
+        }
+
+        let entries = entries.unwrap_or_default();
+        let version = member("version").and_then(Value::as_u64);
+        let header = |version: u64| {
+            member("header").cloned().ok_or_else(|| {
+                PwError::Corrupt(format!(
+                    "the header of {} (vault version {}) is damaged, so no entry can be decrypted",
+                    path.display(),
+                    version
+                ))
+            })
+        };
+        match version {
+            Some(version) if version == u64::from(PLAINTEXT_VERSION) => {
+                salvage_members(entries.members, &mut salvage, |_, entry: Entry| Ok(entry));
+            }
+            Some(version) if version == u64::from(DERIVED_KEY_VERSION) => {
+                let header: DerivedKeyHeader = serde_json::from_value(header(version)?)?;
+                let record_key = labeled_key(header.derive(password)?.expose(), RECORD_KEY_LABEL);
+                salvage_members(entries.members, &mut salvage, |key, record: Record| {
+                    open_record(record_key.expose(), key, &record)
+                });
+            }
+            Some(version) if version == u64::from(VAULT_VERSION) => {
+                let header: VaultHeader = serde_json::from_value(header(version)?)?;
+                let storage = Self::unlock(path, header, password, keyfile)?;
+                salvage_members(entries.members, &mut salvage, |key, record: Record| {
+                    open_record(storage.record_key.expose(), key, &record)
+                });
+            }
+            Some(version) => return Err(unknown_version(path, Some(version))),
+            None => {
+                return Err(PwError::Corrupt(format!(
+                    "the version of {} is damaged, so its entries cannot be read",
+                    path.display()
+                ))
+                .into())
+            }
+        }
+        if let Some(key) = entries.damaged {
+            salvage.lost.push((key, "cut off or damaged".to_string()));
+        }
+        salvage.truncated = !entries.complete;
+        Ok(salvage)
+    }
+
+    fn open_records(
+        &self,
+        records: &BTreeMap<String, Record>,
+    ) -> io::Result<BTreeMap<String, Entry>> {
+        records
+            .iter()
+            .map(|(key, record)| {
+                Ok((
+                    key.clone(),
+                    open_record(self.record_key.expose(), key, record)?,
+                ))
+            })
+            .collect()
+    }
+
+    /// Reads the records, after checking the header and the MAC.
+    fn read_records(&self) -> io::Result<BTreeMap<String, Record>> {
+        let file = match read_contents(&self.file_path)? {
+            Contents::Sealed(file) => file,
+            _ => return Err(tampered(&self.file_path, "its format was changed")),
+        };
+        if file.header != self.header {
+            return Err(tampered(&self.file_path, "its header was changed"));
+        }
+        verify_mac(&self.file_path, self.mac_key.expose(), &file)?;
+        Ok(file.entries)
+    }
+
+    fn read_storage(&self) -> io::Result<BTreeMap<String, Entry>> {
+        self.open_records(&self.read_records()?)
+    }
+
+    fn write_storage(&self, entries: &BTreeMap<String, Entry>) -> io::Result<()> {
+        let mut records = BTreeMap::new();
+        for (key, entry) in entries {
+            let plaintext = Zeroizing::new(serde_json::to_vec(entry)?);
+            records.insert(
+                key.clone(),
+                Record(seal_blob(self.record_key.expose(), &plaintext, key)?),
+            );
+        }
+        self.write_records(&records)
+    }
+
+    /// Writes to a temporary file next to the vault and renames it into place, so an
+    /// interrupted write cannot leave a truncated or half re-encrypted vault behind.
+    fn write_records(&self, records: &BTreeMap<String, Record>) -> io::Result<()> {
+        let mut file = VaultFile {
+            format: VAULT_FORMAT.to_string(),
+            version: VAULT_VERSION,
+            header: self.header.clone(),
+            entries: records.clone(),
+            mac: Vec::new(),
+        };
+        file.mac = compute_mac(self.mac_key.expose(), &file)?
+            .finalize()
+            .into_bytes()
+            .to_vec();
+        let contents = serde_json::to_string(&file)?;
+
+        let mut temp_name = self.file_path.as_os_str().to_owned();
+        temp_name.push(".tmp");
+        let temp_path = PathBuf::from(temp_name);
+        let mut temp = private_file_options()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&temp_path)?;
+        temp.write_all(contents.as_bytes())?;
+        temp.sync_all()?;
+        fs::rename(&temp_path, &self.file_path)
+    }
+}
+
+impl MasterKey for FileStorage {
+    fn change_password(&mut self, new_password: &str) -> io::Result<()> {
+        let records = self.read_records()?;
+        let storage = Self::protect(
+            &self.file_path,
+            self.data_key.clone(),
+            new_password,
+            self.keyfile.as_ref(),
+            self.header.kdf,
+        )?;
+        storage.write_records(&records)?;
+        *self = storage;
+        Ok(())
+    }
+
+    fn rekey(&mut self, password: &str, kdf: KdfParams) -> io::Result<usize> {
+        let entries = self.read_storage()?;
+        let storage = Self::create(
+            &self.file_path,
+            password,
+            self.keyfile.as_ref(),
+            kdf,
+            &entries,
+        )?;
+        *self = storage;
+        Ok(entries.len())
+    }
+
+    fn kdf_params(&self) -> io::Result<KdfParams> {
+        Ok(self.header.kdf)
+    }
+
+    fn data_key(&self) -> &LockedKey {
+        &self.data_key
     }
 }
 
 impl Storage for FileStorage {
-    fn set(&self, key: String, value: String) -> io::Result<()> {
+    fn set(&self, key: String, value: Secret) -> io::Result<()> {
         let mut map = self.read_storage()?;
-        map.insert(key, value);
+        map.entry(key).or_default().replace_value(value);
         self.write_storage(&map)
     }
 
-    fn get(&self, key: String) -> io::Result<Option<String>> {
-        let map = self.read_storage()?;
-        Ok(map.get(&key).cloned())
+    fn get(&self, key: String) -> io::Result<Option<Secret>> {
+        let mut map = self.read_storage()?;
+        Ok(map.remove(&key).map(|entry| entry.value))
     }
 
-    fn get_all(&self) -> io::Result<Option<Vec<String>>> {
+    fn get_all(&self) -> io::Result<Option<Vec<(String, Secret)>>> {
         let map = self.read_storage()?;
         if map.is_empty() {
             Ok(None)
         } else {
-            let values = map.values().cloned().collect::<Vec<String>>();
+            let values = map
+                .into_iter()
+                .filter(|(_, entry)| !entry.value.is_empty())
+                .map(|(key, entry)| (key, entry.value))
+                .collect::<Vec<(String, Secret)>>();
             Ok(Some(values))
         }
     }
+
+    fn get_entry(&self, key: String) -> io::Result<Option<Entry>> {
+        let mut map = self.read_storage()?;
+        Ok(map.remove(&key))
+    }
+
+    fn set_entry(&self, key: String, entry: Entry) -> io::Result<()> {
+        self.set_entries(vec![(key, entry)])
+    }
+
+    fn set_entries(&self, entries: Vec<(String, Entry)>) -> io::Result<()> {
+        let mut map = self.read_storage()?;
+        map.extend(entries);
+        self.write_storage(&map)
+    }
+
+    fn list_keys(&self) -> io::Result<Vec<String>> {
+        let map = self.read_storage()?;
+        Ok(map.into_keys().collect())
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::crypto::kdf::TEST_KDF_PARAMS;
+    use crate::crypto::secret::Secret;
+    use crate::error::PwError;
+    use crate::storage::entry::Entry;
     use crate::storage::storage_trait::Storage;
+    use base64::engine::general_purpose::STANDARD;
+    use base64::Engine;
+    use serde_json::Value;
     use std::io;
     use tempfile::tempdir;
 
@@ -105,11 +797,12 @@ mod tests {
     fn test_set_and_get() -> io::Result<()> {
         let temp_dir = tempdir()?; // Create a temporary directory
         let file_path = temp_dir.path().join("store.json");
-        let storage = super::FileStorage::new(file_path.to_str().unwrap());
+        let storage =
+            super::FileStorage::new(file_path.to_str().unwrap(), "master", None, TEST_KDF_PARAMS)?;
 
-        storage.set("key".to_string(), "value".to_string())?;
+        storage.set("key".to_string(), "value".into())?;
 
-        assert_eq!(storage.get("key".to_string())?, Some("value".to_string()));
+        assert_eq!(storage.get("key".to_string())?, Some(Secret::from("value")));
         Ok(())
     }
 
@@ -117,7 +810,8 @@ mod tests {
     fn test_get_nonexistent_key() -> io::Result<()> {
         let temp_dir = tempdir()?; // Create a temporary directory
         let file_path = temp_dir.path().join("store.json");
-        let storage = super::FileStorage::new(file_path.to_str().unwrap());
+        let storage =
+            super::FileStorage::new(file_path.to_str().unwrap(), "master", None, TEST_KDF_PARAMS)?;
 
         assert_eq!(storage.get("nonexistent_key".to_string())?, None);
 
@@ -128,15 +822,17 @@ mod tests {
     fn test_persistence() -> io::Result<()> {
         let temp_dir = tempdir()?;
         let file_path = temp_dir.path().join("store.json");
-        let storage = super::FileStorage::new(file_path.to_str().unwrap());
+        let storage =
+            super::FileStorage::new(file_path.to_str().unwrap(), "master", None, TEST_KDF_PARAMS)?;
 
-        storage.set("persisted_key".to_string(), "persisted_value".to_string())?;
+        storage.set("persisted_key".to_string(), "persisted_value".into())?;
         drop(storage);
 
-        let new_storage = super::FileStorage::new(file_path.to_str().unwrap());
+        let new_storage =
+            super::FileStorage::new(file_path.to_str().unwrap(), "master", None, TEST_KDF_PARAMS)?;
         assert_eq!(
             new_storage.get("persisted_key".to_string())?,
-            Some("persisted_value".to_string())
+            Some(Secret::from("persisted_value"))
         );
 
         Ok(())
@@ -146,20 +842,367 @@ mod tests {
     fn test_get_all() -> io::Result<()> {
         let temp_dir = tempdir()?;
         let file_path = temp_dir.path().join("store.json");
-        let storage = super::FileStorage::new(file_path.to_str().unwrap());
+        let storage =
+            super::FileStorage::new(file_path.to_str().unwrap(), "master", None, TEST_KDF_PARAMS)?;
 
-        storage.set("k1".to_string(), "v1".to_string())?;
-        storage.set("k2".to_string(), "v2".to_string())?;
-        storage.set("k3".to_string(), "v3".to_string())?;
+        storage.set("k1".to_string(), "v1".into())?;
+        storage.set("k2".to_string(), "v2".into())?;
+        storage.set("k3".to_string(), "v3".into())?;
 
-        let mut values = storage.get_all()?.unwrap_or_default();
-        values.sort();
-
-        let mut expected = vec!["v1".to_string(), "v2".to_string(), "v3".to_string()];
-        expected.sort();
+        let values = storage.get_all()?.unwrap_or_default();
+        let expected: Vec<(String, Secret)> = [("k1", "v1"), ("k2", "v2"), ("k3", "v3")]
+            .into_iter()
+            .map(|(key, value)| (key.to_string(), Secret::from(value)))
+            .collect();
 
         assert_eq!(values, expected);
 
         Ok(())
     }
+
+    #[test]
+    fn test_set_keeps_history() -> io::Result<()> {
+        let temp_dir = tempdir()?;
+        let file_path = temp_dir.path().join("store.json");
+        let storage =
+            super::FileStorage::new(file_path.to_str().unwrap(), "master", None, TEST_KDF_PARAMS)?;
+
+        storage.set("key".to_string(), "old".into())?;
+        storage.set("key".to_string(), "new".into())?;
+
+        let entry = storage.get_entry("key".to_string())?.unwrap();
+        assert_eq!(entry.value, "new");
+        assert_eq!(entry.history.len(), 1);
+        assert_eq!(entry.history[0].value, "old");
+        Ok(())
+    }
+
+    #[test]
+    fn test_reads_legacy_string_values() -> io::Result<()> {
+        let temp_dir = tempdir()?;
+        let file_path = temp_dir.path().join("store.json");
+        std::fs::write(&file_path, r#"{"legacy": "value"}"#)?;
+        let path = file_path.to_str().unwrap();
+        assert_eq!(
+            super::FileStorage::migrate(path, "master", None, TEST_KDF_PARAMS)?,
+            1
+        );
+        let storage = super::FileStorage::new(path, "master", None, TEST_KDF_PARAMS)?;
+
+        assert_eq!(
+            storage.get("legacy".to_string())?,
+            Some(Secret::from("value"))
+        );
+        storage.set("other".to_string(), "v".into())?;
+        assert_eq!(storage.list_keys()?, vec!["legacy", "other"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_legacy_vault_is_only_protected_when_migrated() -> io::Result<()> {
+        let temp_dir = tempdir()?;
+        let file_path = temp_dir.path().join("store.json");
+        let path = file_path.to_str().unwrap();
+        std::fs::write(&file_path, r#"{"legacy": "value"}"#)?;
+
+        let error = super::FileStorage::new(path, "master", None, TEST_KDF_PARAMS)
+            .err()
+            .unwrap();
+        assert!(matches!(PwError::from(error), PwError::Corrupt(_)));
+        assert!(super::FileStorage::check(&file_path).is_err());
+        assert_eq!(
+            std::fs::read_to_string(&file_path)?,
+            r#"{"legacy": "value"}"#
+        );
+
+        super::FileStorage::migrate(path, "master", None, TEST_KDF_PARAMS)?;
+        assert!(!std::fs::read_to_string(&file_path)?.contains(r#""legacy":"value""#));
+        assert_eq!(super::FileStorage::check(&file_path)?, 1);
+        let error = super::FileStorage::migrate(path, "master", None, TEST_KDF_PARAMS)
+            .err()
+            .unwrap();
+        assert!(matches!(PwError::from(error), PwError::Invalid(_)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_sealed_vault_replaced_by_a_map_is_refused() -> io::Result<()> {
+        let temp_dir = tempdir()?;
+        let file_path = temp_dir.path().join("store.json");
+        let path = file_path.to_str().unwrap();
+        let storage = super::FileStorage::new(path, "master", None, TEST_KDF_PARAMS)?;
+        storage.set("github".to_string(), "hunter2".into())?;
+
+        std::fs::write(&file_path, r#"{"github": "attacker-pw"}"#)?;
+        let error = super::FileStorage::new(path, "master", None, TEST_KDF_PARAMS)
+            .err()
+            .unwrap();
+        assert!(matches!(PwError::from(error), PwError::Corrupt(_)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_empty_or_unparsable_vault_is_corrupt() -> io::Result<()> {
+        let temp_dir = tempdir()?;
+        let file_path = temp_dir.path().join("store.json");
+        let path = file_path.to_str().unwrap();
+        for contents in ["", "  \n", "{\"format\": \"pw-vault\", \"vers", "[1, 2]"] {
+            std::fs::write(&file_path, contents)?;
+            let error = super::FileStorage::new(path, "master", None, TEST_KDF_PARAMS)
+                .err()
+                .unwrap();
+            assert!(
+                matches!(PwError::from(error), PwError::Corrupt(_)),
+                "{:?}",
+                contents
+            );
+            assert_eq!(std::fs::read_to_string(&file_path)?, contents);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_wrong_password_is_not_reported_as_tampering() -> io::Result<()> {
+        let temp_dir = tempdir()?;
+        let file_path = temp_dir.path().join("store.json");
+        super::FileStorage::new(file_path.to_str().unwrap(), "master", None, TEST_KDF_PARAMS)?;
+
+        let error =
+            super::FileStorage::new(file_path.to_str().unwrap(), "wrong", None, TEST_KDF_PARAMS)
+                .err()
+                .unwrap();
+        assert!(matches!(PwError::from(error), PwError::Auth(_)));
+        Ok(())
+    }
+
+    /// Applies `edit` to the vault file as parsed JSON.
+    fn edit_vault(path: &std::path::Path, edit: impl FnOnce(&mut Value)) -> io::Result<()> {
+        let mut vault: Value = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+        edit(&mut vault);
+        std::fs::write(path, vault.to_string())
+    }
+
+    fn flip_record_bit(vault: &mut Value, key: &str) {
+        let record = &mut vault["entries"][key];
+        let mut bytes = STANDARD.decode(record.as_str().unwrap()).unwrap();
+        *bytes.last_mut().unwrap() ^= 1;
+        *record = Value::from(STANDARD.encode(bytes));
+    }
+
+    #[test]
+    fn test_secrets_and_metadata_are_encrypted() -> io::Result<()> {
+        let temp_dir = tempdir()?;
+        let file_path = temp_dir.path().join("store.json");
+        let storage =
+            super::FileStorage::new(file_path.to_str().unwrap(), "master", None, TEST_KDF_PARAMS)?;
+        let mut entry = Entry::new("hunter2".to_string());
+        entry.username = Some("octocat".to_string());
+        storage.set_entry("github".to_string(), entry)?;
+
+        let contents = std::fs::read_to_string(&file_path)?;
+        assert!(contents.contains("github"));
+        assert!(!contents.contains("hunter2"));
+        assert!(!contents.contains("octocat"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_tampering_is_detected() -> io::Result<()> {
+        let temp_dir = tempdir()?;
+        let file_path = temp_dir.path().join("store.json");
+        let storage =
+            super::FileStorage::new(file_path.to_str().unwrap(), "master", None, TEST_KDF_PARAMS)?;
+        storage.set("github".to_string(), "hunter2".into())?;
+        storage.set("gitlab".to_string(), "hunter3".into())?;
+        let original = std::fs::read_to_string(&file_path)?;
+
+        type Edit = fn(&mut Value);
+        let edits: [(&str, Edit); 5] = [
+            ("a damaged record", |vault| flip_record_bit(vault, "github")),
+            ("swapped records", |vault| {
+                let entries = vault["entries"].as_object_mut().unwrap();
+                let github = entries["github"].take();
+                entries["github"] = entries["gitlab"].take();
+                entries["gitlab"] = github;
+            }),
+            ("a removed entry", |vault| {
+                vault["entries"].as_object_mut().unwrap().remove("gitlab");
+            }),
+            ("changed KDF parameters", |vault| {
+                vault["header"]["kdf"]["iterations"] = Value::from(2);
+            }),
+            ("a removed MAC", |vault| {
+                vault.as_object_mut().unwrap().remove("mac");
+            }),
+        ];
+        for (change, edit) in edits {
+            std::fs::write(&file_path, &original)?;
+            edit_vault(&file_path, edit)?;
+            let error = storage.list_keys().unwrap_err();
+            assert!(
+                matches!(PwError::from(error), PwError::Corrupt(_)),
+                "{} went unnoticed",
+                change
+            );
+            let reopened = super::FileStorage::new(
+                file_path.to_str().unwrap(),
+                "master",
+                None,
+                TEST_KDF_PARAMS,
+            );
+            assert!(reopened.is_err());
+        }
+
+        std::fs::write(&file_path, &original)?;
+        assert_eq!(storage.list_keys()?, vec!["github", "gitlab"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_accept_changes_reseals_the_vault() -> io::Result<()> {
+        let temp_dir = tempdir()?;
+        let file_path = temp_dir.path().join("store.json");
+        let path = file_path.to_str().unwrap();
+        let storage = super::FileStorage::new(path, "master", None, TEST_KDF_PARAMS)?;
+        storage.set("keep".to_string(), "1".into())?;
+        storage.set("drop".to_string(), "2".into())?;
+        edit_vault(&file_path, |vault| {
+            vault["entries"].as_object_mut().unwrap().remove("drop");
+        })?;
+        assert!(storage.list_keys().is_err());
+
+        assert!(super::FileStorage::accept_changes(path, "wrong", None).is_err());
+        assert_eq!(super::FileStorage::accept_changes(path, "master", None)?, 1);
+        assert_eq!(storage.list_keys()?, vec!["keep"]);
+
+        // A record that does not decrypt cannot be accepted, only salvaged.
+        edit_vault(&file_path, |vault| flip_record_bit(vault, "keep"))?;
+        assert!(super::FileStorage::accept_changes(path, "master", None).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_salvage_skips_damaged_records() -> io::Result<()> {
+        let temp_dir = tempdir()?;
+        let file_path = temp_dir.path().join("store.json");
+        let storage =
+            super::FileStorage::new(file_path.to_str().unwrap(), "master", None, TEST_KDF_PARAMS)?;
+        for key in ["a", "b", "c"] {
+            storage.set(key.to_string(), key.to_uppercase().into())?;
+        }
+        edit_vault(&file_path, |vault| flip_record_bit(vault, "b"))?;
+
+        let salvage = super::FileStorage::salvage(&file_path, "master", None)?;
+        let keys: Vec<&str> = salvage
+            .entries
+            .iter()
+            .map(|(key, _)| key.as_str())
+            .collect();
+        assert_eq!(keys, vec!["a", "c"]);
+        assert_eq!(salvage.entries[1].1.value, "C");
+        assert_eq!(salvage.lost.len(), 1);
+        assert_eq!(salvage.lost[0].0, "b");
+        assert!(!salvage.truncated);
+
+        let error = super::FileStorage::salvage(&file_path, "wrong", None).unwrap_err();
+        assert!(matches!(PwError::from(error), PwError::Auth(_)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_salvage_does_not_mistake_a_vault_with_a_damaged_format_for_legacy() -> io::Result<()> {
+        let temp_dir = tempdir()?;
+        let file_path = temp_dir.path().join("store.json");
+        let storage =
+            super::FileStorage::new(file_path.to_str().unwrap(), "master", None, TEST_KDF_PARAMS)?;
+        storage.set("a".to_string(), "A".into())?;
+        edit_vault(&file_path, |vault| {
+            vault["format"] = Value::from("pw-vXult")
+        })?;
+
+        let salvage = super::FileStorage::salvage(&file_path, "master", None)?;
+        assert_eq!(salvage.entries.len(), 1);
+        assert_eq!(salvage.entries[0].0, "a");
+        assert_eq!(salvage.entries[0].1.value, "A");
+        assert!(salvage.lost.is_empty());
+
+        edit_vault(&file_path, |vault| {
+            vault.as_object_mut().unwrap().remove("format");
+            vault["version"] = Value::from("four");
+        })?;
+        let error = super::FileStorage::salvage(&file_path, "master", None).unwrap_err();
+        assert!(matches!(PwError::from(error), PwError::Corrupt(_)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_salvage_reads_cut_off_legacy_vaults() -> io::Result<()> {
+        let temp_dir = tempdir()?;
+        let file_path = temp_dir.path().join("store.json");
+        std::fs::write(
+            &file_path,
+            r#"{"old": "value", "new": {"value": "v", "history": []}, "cut": {"val"#,
+        )?;
+
+        let salvage = super::FileStorage::salvage(&file_path, "unused", None)?;
+        assert_eq!(salvage.entries.len(), 2);
+        assert_eq!(
+            salvage.entries[0],
+            ("old".to_string(), Entry::new("value".to_string()))
+        );
+        assert_eq!(salvage.lost[0].0, "cut");
+        assert!(salvage.truncated);
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_entry_round_trip() -> io::Result<()> {
+        let temp_dir = tempdir()?;
+        let file_path = temp_dir.path().join("store.json");
+        let storage =
+            super::FileStorage::new(file_path.to_str().unwrap(), "master", None, TEST_KDF_PARAMS)?;
+
+        let mut entry = Entry::new("value".to_string());
+        entry.profile = Some("bank".to_string());
+        storage.set_entry("key".to_string(), entry.clone())?;
+
+        assert_eq!(storage.get_entry("key".to_string())?, Some(entry));
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_new_creates_private_files() -> io::Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp_dir = tempdir()?;
+        let dir = temp_dir.path().join("pw").join("vaults");
+        let file_path = dir.join("store.json");
+        super::FileStorage::new(file_path.to_str().unwrap(), "master", None, TEST_KDF_PARAMS)?;
+
+        let mode = |path: &std::path::Path| -> io::Result<u32> {
+            Ok(std::fs::metadata(path)?.permissions().mode() & 0o777)
+        };
+        assert_eq!(mode(&file_path)?, 0o600);
+        assert_eq!(mode(&dir)?, 0o700);
+        assert_eq!(mode(dir.parent().unwrap())?, 0o700);
+        assert_eq!(super::FileStorage::check(&file_path)?, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_new_fails_instead_of_panicking() -> io::Result<()> {
+        let temp_dir = tempdir()?;
+        let blocker = temp_dir.path().join("file");
+        std::fs::write(&blocker, "")?;
+        let file_path = blocker.join("store.json");
+        assert!(super::FileStorage::new(
+            file_path.to_str().unwrap(),
+            "master",
+            None,
+            TEST_KDF_PARAMS
+        )
+        .is_err());
+        Ok(())
+    }
 }
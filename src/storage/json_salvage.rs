@@ -0,0 +1,154 @@
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+/// What could be read of a JSON object that may be cut off or damaged.
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct PartialObject {
+    /// The members read in full, in file order.
+    pub members: Vec<(String, Value)>,
+    /// The member where reading stopped, if its name could be read.
+    pub damaged: Option<String>,
+    /// Whether the object was read up to its closing brace.
+    pub complete: bool,
+}
+
+/// Reads as many members as possible of the JSON object at the start of `text`. The value
+/// of the member named `nested` is itself read partially and returned separately, so that
+/// a cut inside it still gives the members before the cut.
+pub(crate) fn read_partial_object(
+    text: &str,
+    nested: Option<&str>,
+) -> (PartialObject, Option<PartialObject>) {
+    let mut scanner = Scanner { text, pos: 0 };
+    scanner.object(nested)
+}
+
+struct Scanner<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl Scanner<'_> {
+    fn skip_whitespace(&mut self) {
+        let rest = &self.text[self.pos..];
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    fn eat(&mut self, byte: u8) -> bool {
+        self.skip_whitespace();
+        if self.text.as_bytes().get(self.pos) == Some(&byte) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Reads one complete JSON value, leaving the position unchanged if there is none.
+    fn value<T: DeserializeOwned>(&mut self) -> Option<T> {
+        self.skip_whitespace();
+        let mut values =
+            serde_json::Deserializer::from_str(&self.text[self.pos..]).into_iter::<T>();
+        let value = values.next()?.ok()?;
+        self.pos += values.byte_offset();
+        Some(value)
+    }
+
+    fn object(&mut self, nested: Option<&str>) -> (PartialObject, Option<PartialObject>) {
+        let mut object = PartialObject::default();
+        let mut inner = None;
+        if !self.eat(b'{') {
+            return (object, inner);
+        }
+        if self.eat(b'}') {
+            object.complete = true;
+            return (object, inner);
+        }
+        while let Some(name) = self.value::<String>() {
+            if !self.eat(b':') {
+                object.damaged = Some(name);
+                break;
+            }
+            if nested == Some(name.as_str()) {
+                let (value, _) = self.object(None);
+                let complete = value.complete;
+                inner = Some(value);
+                if !complete {
+                    object.damaged = Some(name);
+                    break;
+                }
+            } else {
+                let Some(value) = self.value::<Value>() else {
+                    object.damaged = Some(name);
+                    break;
+                };
+                object.members.push((name, value));
+            }
+            if self.eat(b',') {
+                continue;
+            }
+            object.complete = self.eat(b'}');
+            break;
+        }
+        (object, inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn complete_object_is_read_in_full() {
+        let (object, nested) = read_partial_object(r#" {"a": 1, "b": {"c": [true]}} "#, None);
+        assert_eq!(
+            object.members,
+            vec![
+                ("a".to_string(), json!(1)),
+                ("b".to_string(), json!({"c": [true]}))
+            ]
+        );
+        assert!(object.complete);
+        assert_eq!(object.damaged, None);
+        assert_eq!(nested, None);
+    }
+
+    #[test]
+    fn cut_object_keeps_the_members_before_the_cut() {
+        let (object, _) =
+            read_partial_object(r#"{"a": "x", "b": {"value": "y"}, "c": {"val"#, None);
+        assert_eq!(object.members.len(), 2);
+        assert_eq!(object.damaged, Some("c".to_string()));
+        assert!(!object.complete);
+
+        let (object, _) = read_partial_object(r#"{"a": "x", "b"#, None);
+        assert_eq!(object.members.len(), 1);
+        assert_eq!(object.damaged, None);
+    }
+
+    #[test]
+    fn nested_object_is_read_partially() {
+        let text = r#"{"format": "f", "entries": {"a": "1", "b": "2", "c": "3"#;
+        let (object, nested) = read_partial_object(text, Some("entries"));
+        assert_eq!(object.members, vec![("format".to_string(), json!("f"))]);
+        assert_eq!(object.damaged, Some("entries".to_string()));
+        let nested = nested.unwrap();
+        assert_eq!(nested.members.len(), 2);
+        assert_eq!(nested.damaged, Some("c".to_string()));
+
+        let text = r#"{"entries": {}, "mac": "abc"}"#;
+        let (object, nested) = read_partial_object(text, Some("entries"));
+        assert!(object.complete && nested.unwrap().complete);
+        assert_eq!(object.members, vec![("mac".to_string(), json!("abc"))]);
+    }
+
+    #[test]
+    fn garbage_reads_nothing() {
+        for text in ["", "not json", "[1, 2]", "{,}"] {
+            let (object, _) = read_partial_object(text, None);
+            assert!(object.members.is_empty());
+            assert!(!object.complete);
+        }
+    }
+}
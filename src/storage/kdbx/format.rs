@@ -0,0 +1,715 @@
+use aes::cipher::block_padding::Pkcs7;
+use aes::cipher::{BlockDecryptMut, BlockEncryptMut, KeyIvInit, StreamCipher};
+use argon2::{Algorithm, Argon2, Params, Version};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use hmac::{Hmac, Mac};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use sha2::{Digest, Sha256, Sha512};
+use std::fmt;
+use std::io::{Read, Write};
+
+const SIGNATURE_1: u32 = 0x9AA2_D903;
+const SIGNATURE_2: u32 = 0xB54B_FB67;
+const VERSION_MAJOR: u16 = 4;
+
+const AES256_UUID: [u8; 16] = [
+    0x31, 0xc1, 0xf2, 0xe6, 0xbf, 0x71, 0x43, 0x50, 0xbe, 0x58, 0x05, 0x21, 0x6a, 0xfc, 0x5a, 0xff,
+];
+const CHACHA20_UUID: [u8; 16] = [
+    0xd6, 0x03, 0x8a, 0x2b, 0x8b, 0x6f, 0x4c, 0xb5, 0xa5, 0x24, 0x33, 0x9a, 0x31, 0xdb, 0xb5, 0x9a,
+];
+const ARGON2D_UUID: [u8; 16] = [
+    0xef, 0x63, 0x6d, 0xdf, 0x8c, 0x29, 0x44, 0x4b, 0x91, 0xf7, 0xa9, 0xa4, 0x03, 0xe3, 0x0a, 0x0c,
+];
+const ARGON2ID_UUID: [u8; 16] = [
+    0x9e, 0x29, 0x8b, 0x19, 0x56, 0xdb, 0x47, 0x73, 0xb2, 0x3d, 0xfc, 0x3e, 0xc6, 0xf0, 0xa1, 0xe6,
+];
+
+const HEADER_END: u8 = 0;
+const HEADER_CIPHER: u8 = 2;
+const HEADER_COMPRESSION: u8 = 3;
+const HEADER_MASTER_SEED: u8 = 4;
+const HEADER_IV: u8 = 7;
+const HEADER_KDF: u8 = 11;
+const HEADER_PUBLIC_CUSTOM_DATA: u8 = 12;
+
+const INNER_END: u8 = 0;
+const INNER_STREAM_ID: u8 = 1;
+const INNER_STREAM_KEY: u8 = 2;
+const INNER_BINARY: u8 = 3;
+/// The only inner stream cipher KDBX 4 writers use; Salsa20 is a KDBX 3 leftover.
+const INNER_STREAM_CHACHA20: u32 = 3;
+
+const VARIANT_VERSION: u16 = 0x0100;
+const VARIANT_UINT32: u8 = 0x04;
+const VARIANT_UINT64: u8 = 0x05;
+const VARIANT_BYTES: u8 = 0x42;
+
+/// Payload block size used when writing, the same as KeePass.
+const BLOCK_SIZE: usize = 1024 * 1024;
+
+type HmacSha256 = Hmac<Sha256>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cipher {
+    Aes256,
+    ChaCha20,
+}
+
+impl Cipher {
+    fn iv_len(&self) -> usize {
+        match self {
+            Cipher::Aes256 => 16,
+            Cipher::ChaCha20 => 12,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Argon2Variant {
+    Argon2d,
+    Argon2id,
+}
+
+/// Argon2 parameters from the KDF header field. KDBX stores memory in bytes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Argon2Kdf {
+    pub variant: Argon2Variant,
+    pub salt: Vec<u8>,
+    pub memory_bytes: u64,
+    pub iterations: u64,
+    pub parallelism: u32,
+    pub version: u32,
+}
+
+impl Argon2Kdf {
+    /// Parameters for a new database, with a fresh salt.
+    pub fn new(variant: Argon2Variant, memory_kib: u32, iterations: u32, parallelism: u32) -> Self {
+        let mut salt = vec![0u8; 32];
+        OsRng.fill_bytes(&mut salt);
+        Self {
+            variant,
+            salt,
+            memory_bytes: u64::from(memory_kib) * 1024,
+            iterations: u64::from(iterations),
+            parallelism,
+            version: 0x13,
+        }
+    }
+
+    /// Runs Argon2 over the composite key, giving the key the payload keys are derived from.
+    pub fn transform(&self, composite_key: &[u8; 32]) -> Result<[u8; 32], KdbxError> {
+        let invalid =
+            |reason: String| KdbxError::InvalidHeader(format!("KDF parameters: {}", reason));
+        let memory_kib = u32::try_from(self.memory_bytes / 1024)
+            .map_err(|_| invalid("memory cost is too large".to_string()))?;
+        let iterations = u32::try_from(self.iterations)
+            .map_err(|_| invalid("iteration count is too large".to_string()))?;
+        let version = match self.version {
+            0x10 => Version::V0x10,
+            0x13 => Version::V0x13,
+            other => return Err(invalid(format!("unsupported Argon2 version {:#x}", other))),
+        };
+        let algorithm = match self.variant {
+            Argon2Variant::Argon2d => Algorithm::Argon2d,
+            Argon2Variant::Argon2id => Algorithm::Argon2id,
+        };
+        let params = Params::new(memory_kib, iterations, self.parallelism, Some(32))
+            .map_err(|e| invalid(e.to_string()))?;
+        let mut key = [0u8; 32];
+        Argon2::new(algorithm, version, params)
+            .hash_password_into(composite_key, &self.salt, &mut key)
+            .map_err(|e| invalid(e.to_string()))?;
+        Ok(key)
+    }
+}
+
+/// The unencrypted outer header.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Header {
+    pub minor_version: u16,
+    pub cipher: Cipher,
+    pub compressed: bool,
+    pub kdf: Argon2Kdf,
+    /// Plugin data that pw does not interpret but writes back unchanged.
+    pub public_custom_data: Option<Vec<u8>>,
+}
+
+/// A decrypted database: the outer header, the attachments from the inner header, and the
+/// XML document with protected values still encrypted under `inner_stream_key`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Database {
+    pub header: Header,
+    pub inner_stream_key: Vec<u8>,
+    /// Attachments, each starting with its flags byte, in the order entries refer to them.
+    pub binaries: Vec<Vec<u8>>,
+    pub xml: Vec<u8>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum KdbxError {
+    NotKdbx,
+    UnsupportedVersion(u16, u16),
+    Truncated,
+    InvalidHeader(String),
+    UnsupportedCipher,
+    UnsupportedKdf,
+    UnsupportedInnerStream(u32),
+    /// The header HMAC did not verify: a wrong password or a modified header.
+    WrongKey,
+    Corrupt(String),
+    Xml(String),
+}
+
+impl fmt::Display for KdbxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KdbxError::NotKdbx => write!(f, "not a KeePass database"),
+            KdbxError::UnsupportedVersion(major, minor) => write!(
+                f,
+                "KDBX version {}.{} is not supported; only KDBX 4 is",
+                major, minor
+            ),
+            KdbxError::Truncated => write!(f, "the database file is truncated"),
+            KdbxError::InvalidHeader(reason) => write!(f, "invalid database header: {}", reason),
+            KdbxError::UnsupportedCipher => {
+                write!(
+                    f,
+                    "unsupported cipher; only AES-256 and ChaCha20 are supported"
+                )
+            }
+            KdbxError::UnsupportedKdf => write!(
+                f,
+                "unsupported key derivation; only Argon2d and Argon2id are supported"
+            ),
+            KdbxError::UnsupportedInnerStream(id) => {
+                write!(f, "unsupported inner stream cipher {}", id)
+            }
+            KdbxError::WrongKey => write!(f, "wrong password, or the database header was modified"),
+            KdbxError::Corrupt(reason) => write!(f, "the database is corrupt: {}", reason),
+            KdbxError::Xml(reason) => write!(f, "invalid database XML: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for KdbxError {}
+
+impl From<KdbxError> for std::io::Error {
+    fn from(e: KdbxError) -> Self {
+        std::io::Error::new(std::io::ErrorKind::InvalidData, e)
+    }
+}
+
+/// The key KeePass derives from a password alone: SHA-256 over the SHA-256 of the password.
+pub fn composite_key(password: &str) -> [u8; 32] {
+    let password_hash = Sha256::digest(password.as_bytes());
+    Sha256::digest(password_hash).into()
+}
+
+/// Reads only the outer header, so the KDF can be run (or a cached result reused) before
+/// `decrypt`.
+pub fn read_header(data: &[u8]) -> Result<Header, KdbxError> {
+    Ok(parse_header(data)?.0)
+}
+
+/// Verifies and decrypts a database given the output of `Argon2Kdf::transform`.
+pub fn decrypt(data: &[u8], transformed_key: &[u8; 32]) -> Result<Database, KdbxError> {
+    let (header, header_len, master_seed, iv) = parse_header(data)?;
+    let header_bytes = &data[..header_len];
+    let mut cursor = Cursor::new(&data[header_len..]);
+
+    let header_hash = cursor.take(32)?;
+    if Sha256::digest(header_bytes).as_slice() != header_hash {
+        return Err(KdbxError::Corrupt("header checksum mismatch".to_string()));
+    }
+    let hmac_base = hmac_base_key(&master_seed, transformed_key);
+    let header_mac = cursor.take(32)?;
+    block_mac(&hmac_base, u64::MAX)
+        .chain_update(header_bytes)
+        .verify_slice(header_mac)
+        .map_err(|_| KdbxError::WrongKey)?;
+
+    let mut encrypted = Vec::new();
+    for index in 0u64.. {
+        let mac = cursor.take(32)?;
+        let size_bytes = cursor.take(4)?;
+        let size = i32::from_le_bytes(size_bytes.try_into().unwrap());
+        let size = usize::try_from(size)
+            .map_err(|_| KdbxError::Corrupt("negative block size".to_string()))?;
+        let block = cursor.take(size)?;
+        block_mac(&hmac_base, index)
+            .chain_update(index.to_le_bytes())
+            .chain_update(size_bytes)
+            .chain_update(block)
+            .verify_slice(mac)
+            .map_err(|_| KdbxError::Corrupt(format!("block {} failed authentication", index)))?;
+        if size == 0 {
+            break;
+        }
+        encrypted.extend_from_slice(block);
+    }
+
+    let key = cipher_key(&master_seed, transformed_key);
+    let mut payload = match header.cipher {
+        Cipher::Aes256 => cbc::Decryptor::<aes::Aes256>::new(&key.into(), iv.as_slice().into())
+            .decrypt_padded_vec_mut::<Pkcs7>(&encrypted)
+            .map_err(|_| KdbxError::Corrupt("invalid padding".to_string()))?,
+        Cipher::ChaCha20 => {
+            chacha20::ChaCha20::new(&key.into(), iv.as_slice().into())
+                .apply_keystream(&mut encrypted);
+            encrypted
+        }
+    };
+    if header.compressed {
+        let mut decompressed = Vec::new();
+        GzDecoder::new(payload.as_slice())
+            .read_to_end(&mut decompressed)
+            .map_err(|e| KdbxError::Corrupt(format!("decompression failed: {}", e)))?;
+        payload = decompressed;
+    }
+
+    let mut inner = Cursor::new(&payload);
+    let mut inner_stream_key = None;
+    let mut binaries = Vec::new();
+    loop {
+        let id = inner.take(1)?[0];
+        let len = u32::from_le_bytes(inner.take(4)?.try_into().unwrap()) as usize;
+        let value = inner.take(len)?;
+        match id {
+            INNER_END => break,
+            INNER_STREAM_ID => {
+                let stream = read_u32(value)?;
+                if stream != INNER_STREAM_CHACHA20 {
+                    return Err(KdbxError::UnsupportedInnerStream(stream));
+                }
+            }
+            INNER_STREAM_KEY => inner_stream_key = Some(value.to_vec()),
+            INNER_BINARY => binaries.push(value.to_vec()),
+            _ => {}
+        }
+    }
+    let inner_stream_key = inner_stream_key
+        .ok_or_else(|| KdbxError::Corrupt("missing inner stream key".to_string()))?;
+
+    Ok(Database {
+        header,
+        inner_stream_key,
+        binaries,
+        xml: inner.rest().to_vec(),
+    })
+}
+
+/// Encrypts a database. A fresh master seed and IV are drawn for every write; the KDF salt is
+/// kept, so `transformed_key` stays valid for the database.
+pub fn encrypt(database: &Database, transformed_key: &[u8; 32]) -> Result<Vec<u8>, KdbxError> {
+    let header = &database.header;
+    let mut master_seed = [0u8; 32];
+    OsRng.fill_bytes(&mut master_seed);
+    let mut iv = vec![0u8; header.cipher.iv_len()];
+    OsRng.fill_bytes(&mut iv);
+
+    let mut out = Vec::new();
+    out.extend_from_slice(&SIGNATURE_1.to_le_bytes());
+    out.extend_from_slice(&SIGNATURE_2.to_le_bytes());
+    out.extend_from_slice(&header.minor_version.to_le_bytes());
+    out.extend_from_slice(&VERSION_MAJOR.to_le_bytes());
+    let cipher_uuid = match header.cipher {
+        Cipher::Aes256 => AES256_UUID,
+        Cipher::ChaCha20 => CHACHA20_UUID,
+    };
+    write_field(&mut out, HEADER_CIPHER, &cipher_uuid);
+    write_field(
+        &mut out,
+        HEADER_COMPRESSION,
+        &u32::from(header.compressed).to_le_bytes(),
+    );
+    write_field(&mut out, HEADER_MASTER_SEED, &master_seed);
+    write_field(&mut out, HEADER_IV, &iv);
+    write_field(&mut out, HEADER_KDF, &encode_kdf(&header.kdf));
+    if let Some(data) = &header.public_custom_data {
+        write_field(&mut out, HEADER_PUBLIC_CUSTOM_DATA, data);
+    }
+    write_field(&mut out, HEADER_END, b"\r\n\r\n");
+
+    let hmac_base = hmac_base_key(&master_seed, transformed_key);
+    let header_hash = Sha256::digest(&out);
+    let header_mac = block_mac(&hmac_base, u64::MAX)
+        .chain_update(&out)
+        .finalize()
+        .into_bytes();
+    out.extend_from_slice(&header_hash);
+    out.extend_from_slice(&header_mac);
+
+    let mut payload = Vec::new();
+    write_field(
+        &mut payload,
+        INNER_STREAM_ID,
+        &INNER_STREAM_CHACHA20.to_le_bytes(),
+    );
+    write_field(&mut payload, INNER_STREAM_KEY, &database.inner_stream_key);
+    for binary in &database.binaries {
+        write_field(&mut payload, INNER_BINARY, binary);
+    }
+    write_field(&mut payload, INNER_END, &[]);
+    payload.extend_from_slice(&database.xml);
+    if header.compressed {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder
+            .write_all(&payload)
+            .and_then(|_| encoder.finish())
+            .map(|compressed| payload = compressed)
+            .map_err(|e| KdbxError::Corrupt(format!("compression failed: {}", e)))?;
+    }
+
+    let key = cipher_key(&master_seed, transformed_key);
+    let encrypted = match header.cipher {
+        Cipher::Aes256 => cbc::Encryptor::<aes::Aes256>::new(&key.into(), iv.as_slice().into())
+            .encrypt_padded_vec_mut::<Pkcs7>(&payload),
+        Cipher::ChaCha20 => {
+            chacha20::ChaCha20::new(&key.into(), iv.as_slice().into())
+                .apply_keystream(&mut payload);
+            payload
+        }
+    };
+
+    let blocks = encrypted.chunks(BLOCK_SIZE).chain(std::iter::once(&[][..]));
+    for (index, block) in (0u64..).zip(blocks) {
+        let size = (block.len() as i32).to_le_bytes();
+        let mac = block_mac(&hmac_base, index)
+            .chain_update(index.to_le_bytes())
+            .chain_update(size)
+            .chain_update(block)
+            .finalize()
+            .into_bytes();
+        out.extend_from_slice(&mac);
+        out.extend_from_slice(&size);
+        out.extend_from_slice(block);
+    }
+    Ok(out)
+}
+
+/// The keystream protecting `Protected="True"` values in the XML. Values are encrypted one
+/// after another in document order, continuing the same keystream.
+pub fn inner_stream(inner_stream_key: &[u8]) -> chacha20::ChaCha20 {
+    let hash = Sha512::digest(inner_stream_key);
+    chacha20::ChaCha20::new(hash[..32].into(), hash[32..44].into())
+}
+
+/// Draws a fresh key for the inner stream.
+pub fn new_inner_stream_key() -> Vec<u8> {
+    let mut key = vec![0u8; 64];
+    OsRng.fill_bytes(&mut key);
+    key
+}
+
+type ParsedHeader = (Header, usize, Vec<u8>, Vec<u8>);
+
+fn parse_header(data: &[u8]) -> Result<ParsedHeader, KdbxError> {
+    let mut cursor = Cursor::new(data);
+    let signature_1 = read_u32(cursor.take(4)?)?;
+    let signature_2 = read_u32(cursor.take(4)?)?;
+    if signature_1 != SIGNATURE_1 || signature_2 != SIGNATURE_2 {
+        return Err(KdbxError::NotKdbx);
+    }
+    let minor_version = u16::from_le_bytes(cursor.take(2)?.try_into().unwrap());
+    let major_version = u16::from_le_bytes(cursor.take(2)?.try_into().unwrap());
+    if major_version != VERSION_MAJOR {
+        return Err(KdbxError::UnsupportedVersion(major_version, minor_version));
+    }
+
+    let mut cipher = None;
+    let mut compressed = false;
+    let mut master_seed = None;
+    let mut iv = None;
+    let mut kdf = None;
+    let mut public_custom_data = None;
+    loop {
+        let id = cursor.take(1)?[0];
+        let len = read_u32(cursor.take(4)?)? as usize;
+        let value = cursor.take(len)?;
+        match id {
+            HEADER_END => break,
+            HEADER_CIPHER => {
+                cipher = Some(match value {
+                    v if v == AES256_UUID => Cipher::Aes256,
+                    v if v == CHACHA20_UUID => Cipher::ChaCha20,
+                    _ => return Err(KdbxError::UnsupportedCipher),
+                })
+            }
+            HEADER_COMPRESSION => {
+                compressed = match read_u32(value)? {
+                    0 => false,
+                    1 => true,
+                    other => {
+                        return Err(KdbxError::InvalidHeader(format!(
+                            "unknown compression {}",
+                            other
+                        )))
+                    }
+                }
+            }
+            HEADER_MASTER_SEED => master_seed = Some(value.to_vec()),
+            HEADER_IV => iv = Some(value.to_vec()),
+            HEADER_KDF => kdf = Some(decode_kdf(value)?),
+            HEADER_PUBLIC_CUSTOM_DATA => public_custom_data = Some(value.to_vec()),
+            _ => {}
+        }
+    }
+
+    let missing = |name: &str| KdbxError::InvalidHeader(format!("missing {}", name));
+    let cipher = cipher.ok_or_else(|| missing("cipher"))?;
+    let master_seed = master_seed.ok_or_else(|| missing("master seed"))?;
+    if master_seed.len() != 32 {
+        return Err(KdbxError::InvalidHeader(
+            "master seed must be 32 bytes".to_string(),
+        ));
+    }
+    let iv = iv.ok_or_else(|| missing("encryption IV"))?;
+    if iv.len() != cipher.iv_len() {
+        return Err(KdbxError::InvalidHeader(format!(
+            "encryption IV must be {} bytes",
+            cipher.iv_len()
+        )));
+    }
+    let header = Header {
+        minor_version,
+        cipher,
+        compressed,
+        kdf: kdf.ok_or_else(|| missing("KDF parameters"))?,
+        public_custom_data,
+    };
+    Ok((header, cursor.position, master_seed, iv))
+}
+
+/// Decodes the KDF parameters, a KeePass "variant dictionary" of typed, named values.
+fn decode_kdf(data: &[u8]) -> Result<Argon2Kdf, KdbxError> {
+    let mut cursor = Cursor::new(data);
+    let version = u16::from_le_bytes(cursor.take(2)?.try_into().unwrap());
+    if version & 0xFF00 != VARIANT_VERSION & 0xFF00 {
+        return Err(KdbxError::InvalidHeader(format!(
+            "unsupported KDF parameter version {:#x}",
+            version
+        )));
+    }
+
+    let mut uuid = None;
+    let mut salt = None;
+    let mut memory_bytes = None;
+    let mut iterations = None;
+    let mut parallelism = None;
+    let mut argon_version = None;
+    loop {
+        let kind = cursor.take(1)?[0];
+        if kind == 0 {
+            break;
+        }
+        let name_len = read_u32(cursor.take(4)?)? as usize;
+        let name = cursor.take(name_len)?;
+        let value_len = read_u32(cursor.take(4)?)? as usize;
+        let value = cursor.take(value_len)?;
+        match (name, kind) {
+            (b"$UUID", VARIANT_BYTES) => uuid = Some(value),
+            (b"S", VARIANT_BYTES) => salt = Some(value.to_vec()),
+            (b"M", VARIANT_UINT64) => memory_bytes = Some(read_u64(value)?),
+            (b"I", VARIANT_UINT64) => iterations = Some(read_u64(value)?),
+            (b"P", VARIANT_UINT32) => parallelism = Some(read_u32(value)?),
+            (b"V", VARIANT_UINT32) => argon_version = Some(read_u32(value)?),
+            // A secret key or associated data would change the derived key; KeePass never
+            // sets them, and silently ignoring them would derive the wrong key.
+            (b"K", _) | (b"A", _) => return Err(KdbxError::UnsupportedKdf),
+            _ => {}
+        }
+    }
+
+    let variant = match uuid {
+        Some(v) if v == ARGON2D_UUID => Argon2Variant::Argon2d,
+        Some(v) if v == ARGON2ID_UUID => Argon2Variant::Argon2id,
+        _ => return Err(KdbxError::UnsupportedKdf),
+    };
+    let missing = |name: &str| KdbxError::InvalidHeader(format!("missing Argon2 {}", name));
+    Ok(Argon2Kdf {
+        variant,
+        salt: salt.ok_or_else(|| missing("salt"))?,
+        memory_bytes: memory_bytes.ok_or_else(|| missing("memory cost"))?,
+        iterations: iterations.ok_or_else(|| missing("iterations"))?,
+        parallelism: parallelism.ok_or_else(|| missing("parallelism"))?,
+        version: argon_version.ok_or_else(|| missing("version"))?,
+    })
+}
+
+fn encode_kdf(kdf: &Argon2Kdf) -> Vec<u8> {
+    let uuid = match kdf.variant {
+        Argon2Variant::Argon2d => ARGON2D_UUID,
+        Argon2Variant::Argon2id => ARGON2ID_UUID,
+    };
+    let mut out = VARIANT_VERSION.to_le_bytes().to_vec();
+    let mut item = |kind: u8, name: &str, value: &[u8]| {
+        out.push(kind);
+        out.extend_from_slice(&(name.len() as u32).to_le_bytes());
+        out.extend_from_slice(name.as_bytes());
+        out.extend_from_slice(&(value.len() as u32).to_le_bytes());
+        out.extend_from_slice(value);
+    };
+    item(VARIANT_BYTES, "$UUID", &uuid);
+    item(VARIANT_BYTES, "S", &kdf.salt);
+    item(VARIANT_UINT32, "P", &kdf.parallelism.to_le_bytes());
+    item(VARIANT_UINT64, "M", &kdf.memory_bytes.to_le_bytes());
+    item(VARIANT_UINT64, "I", &kdf.iterations.to_le_bytes());
+    item(VARIANT_UINT32, "V", &kdf.version.to_le_bytes());
+    out.push(0);
+    out
+}
+
+fn write_field(out: &mut Vec<u8>, id: u8, value: &[u8]) {
+    out.push(id);
+    out.extend_from_slice(&(value.len() as u32).to_le_bytes());
+    out.extend_from_slice(value);
+}
+
+fn cipher_key(master_seed: &[u8], transformed_key: &[u8; 32]) -> [u8; 32] {
+    Sha256::new()
+        .chain_update(master_seed)
+        .chain_update(transformed_key)
+        .finalize()
+        .into()
+}
+
+fn hmac_base_key(master_seed: &[u8], transformed_key: &[u8; 32]) -> [u8; 64] {
+    Sha512::new()
+        .chain_update(master_seed)
+        .chain_update(transformed_key)
+        .chain_update([1u8])
+        .finalize()
+        .into()
+}
+
+/// The HMAC for block `index`; the header uses index `u64::MAX`.
+fn block_mac(hmac_base: &[u8; 64], index: u64) -> HmacSha256 {
+    let key = Sha512::new()
+        .chain_update(index.to_le_bytes())
+        .chain_update(hmac_base)
+        .finalize();
+    HmacSha256::new_from_slice(&key).expect("HMAC accepts keys of any length")
+}
+
+fn read_u32(bytes: &[u8]) -> Result<u32, KdbxError> {
+    Ok(u32::from_le_bytes(
+        bytes.try_into().map_err(|_| KdbxError::Truncated)?,
+    ))
+}
+
+fn read_u64(bytes: &[u8]) -> Result<u64, KdbxError> {
+    Ok(u64::from_le_bytes(
+        bytes.try_into().map_err(|_| KdbxError::Truncated)?,
+    ))
+}
+
+struct Cursor<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, position: 0 }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], KdbxError> {
+        let end = self
+            .position
+            .checked_add(len)
+            .filter(|end| *end <= self.data.len())
+            .ok_or(KdbxError::Truncated)?;
+        let slice = &self.data[self.position..end];
+        self.position = end;
+        Ok(slice)
+    }
+
+    fn rest(&self) -> &'a [u8] {
+        &self.data[self.position..]
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    pub(crate) const FIXTURE_PASSWORD: &str = "fixture-password";
+    pub(crate) const AES_ARGON2D: &[u8] = include_bytes!("fixtures/aes_argon2d.kdbx");
+    pub(crate) const CHACHA20_ARGON2ID: &[u8] = include_bytes!("fixtures/chacha20_argon2id.kdbx");
+
+    fn open(data: &[u8], password: &str) -> Result<Database, KdbxError> {
+        let header = read_header(data)?;
+        let transformed = header.kdf.transform(&composite_key(password))?;
+        decrypt(data, &transformed)
+    }
+
+    #[test]
+    fn reads_aes_argon2d_fixture() {
+        let database = open(AES_ARGON2D, FIXTURE_PASSWORD).unwrap();
+        assert_eq!(database.header.cipher, Cipher::Aes256);
+        assert!(database.header.compressed);
+        assert_eq!(database.header.kdf.variant, Argon2Variant::Argon2d);
+        assert_eq!(
+            database.binaries,
+            vec![b"\x01attached file contents".to_vec()]
+        );
+        let xml = String::from_utf8(database.xml).unwrap();
+        assert!(xml.contains("<DatabaseName>fixture</DatabaseName>"));
+    }
+
+    #[test]
+    fn reads_chacha20_argon2id_fixture() {
+        let database = open(CHACHA20_ARGON2ID, FIXTURE_PASSWORD).unwrap();
+        assert_eq!(database.header.cipher, Cipher::ChaCha20);
+        assert!(!database.header.compressed);
+        assert_eq!(database.header.kdf.variant, Argon2Variant::Argon2id);
+        assert!(String::from_utf8(database.xml)
+            .unwrap()
+            .contains("<Name>Work</Name>"));
+    }
+
+    #[test]
+    fn wrong_password_is_rejected() {
+        assert_eq!(
+            open(AES_ARGON2D, "not the password"),
+            Err(KdbxError::WrongKey)
+        );
+    }
+
+    #[test]
+    fn modified_payload_is_rejected() {
+        let mut data = CHACHA20_ARGON2ID.to_vec();
+        let last = data.len() - 40;
+        data[last] ^= 1;
+        assert!(matches!(
+            open(&data, FIXTURE_PASSWORD),
+            Err(KdbxError::Corrupt(_))
+        ));
+    }
+
+    #[test]
+    fn not_a_database() {
+        assert_eq!(read_header(b"{}"), Err(KdbxError::Truncated));
+        assert_eq!(read_header(&[0u8; 64]), Err(KdbxError::NotKdbx));
+    }
+
+    #[test]
+    fn write_and_read_back() {
+        for data in [AES_ARGON2D, CHACHA20_ARGON2ID] {
+            let database = open(data, FIXTURE_PASSWORD).unwrap();
+            let transformed = database
+                .header
+                .kdf
+                .transform(&composite_key(FIXTURE_PASSWORD))
+                .unwrap();
+
+            let written = encrypt(&database, &transformed).unwrap();
+            assert_ne!(written.as_slice(), data);
+            assert_eq!(open(&written, FIXTURE_PASSWORD).unwrap(), database);
+        }
+    }
+}
@@ -0,0 +1,2 @@
+pub(crate) mod format;
+pub(crate) mod xml;
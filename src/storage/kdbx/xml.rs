@@ -0,0 +1,275 @@
+use super::format::KdbxError;
+use aes::cipher::StreamCipher;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use quick_xml::escape::escape;
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use zeroize::{Zeroize, Zeroizing};
+
+/// A minimal element tree. pw only touches the parts of the document it understands, and
+/// everything else (custom icons, auto-type, plugin data, ...) is written back as it was read.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Element {
+    pub name: String,
+    pub attributes: Vec<(String, String)>,
+    pub children: Vec<Node>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Node {
+    Element(Element),
+    Text(String),
+}
+
+/// Unprotected passwords are held as text while the database is open, so text is wiped
+/// when it is dropped.
+impl Drop for Node {
+    fn drop(&mut self) {
+        if let Node::Text(text) = self {
+            text.zeroize();
+        }
+    }
+}
+
+impl Element {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            ..Default::default()
+        }
+    }
+
+    pub fn with_text(name: &str, text: &str) -> Self {
+        let mut element = Self::new(name);
+        element.set_text(text);
+        element
+    }
+
+    pub fn child(&self, name: &str) -> Option<&Element> {
+        self.elements().find(|e| e.name == name)
+    }
+
+    pub fn child_mut(&mut self, name: &str) -> Option<&mut Element> {
+        self.elements_mut().find(|e| e.name == name)
+    }
+
+    /// Returns the child called `name`, appending an empty one if there is none.
+    pub fn child_or_insert(&mut self, name: &str) -> &mut Element {
+        if self.child(name).is_none() {
+            self.push(Element::new(name));
+        }
+        self.child_mut(name).unwrap()
+    }
+
+    pub fn elements(&self) -> impl Iterator<Item = &Element> {
+        self.children.iter().filter_map(|node| match node {
+            Node::Element(e) => Some(e),
+            Node::Text(_) => None,
+        })
+    }
+
+    pub fn elements_mut(&mut self) -> impl Iterator<Item = &mut Element> {
+        self.children.iter_mut().filter_map(|node| match node {
+            Node::Element(e) => Some(e),
+            Node::Text(_) => None,
+        })
+    }
+
+    pub fn push(&mut self, element: Element) {
+        self.children.push(Node::Element(element));
+    }
+
+    pub fn text(&self) -> String {
+        self.children
+            .iter()
+            .filter_map(|node| match node {
+                Node::Text(text) => Some(text.as_str()),
+                Node::Element(_) => None,
+            })
+            .collect()
+    }
+
+    pub fn set_text(&mut self, text: &str) {
+        self.children.clear();
+        if !text.is_empty() {
+            self.children.push(Node::Text(text.to_string()));
+        }
+    }
+
+    pub fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn set_attribute(&mut self, name: &str, value: &str) {
+        match self.attributes.iter_mut().find(|(key, _)| key == name) {
+            Some((_, existing)) => *existing = value.to_string(),
+            None => self.attributes.push((name.to_string(), value.to_string())),
+        }
+    }
+
+    fn is_protected(&self) -> bool {
+        self.attribute("Protected")
+            .is_some_and(|value| value.eq_ignore_ascii_case("true"))
+    }
+}
+
+/// Parses the XML document into its root element.
+pub fn parse(xml: &[u8]) -> Result<Element, KdbxError> {
+    let xml = std::str::from_utf8(xml).map_err(|e| KdbxError::Xml(e.to_string()))?;
+    let mut reader = Reader::from_str(xml);
+    let mut stack: Vec<Element> = Vec::new();
+    let mut root = None;
+    let error = |e: quick_xml::Error| KdbxError::Xml(e.to_string());
+
+    loop {
+        match reader.read_event().map_err(error)? {
+            Event::Start(e) => stack.push(start_element(&e)?),
+            Event::Empty(e) => {
+                let element = start_element(&e)?;
+                close_element(&mut stack, &mut root, element);
+            }
+            Event::End(_) => {
+                let element = stack
+                    .pop()
+                    .ok_or_else(|| KdbxError::Xml("unbalanced end tag".to_string()))?;
+                close_element(&mut stack, &mut root, element);
+            }
+            Event::Text(e) => {
+                if let Some(parent) = stack.last_mut() {
+                    let text = e.unescape().map_err(error)?;
+                    parent.children.push(Node::Text(text.into_owned()));
+                }
+            }
+            Event::CData(e) => {
+                if let Some(parent) = stack.last_mut() {
+                    let text = String::from_utf8_lossy(&e).into_owned();
+                    parent.children.push(Node::Text(text));
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    root.ok_or_else(|| KdbxError::Xml("the document is empty".to_string()))
+}
+
+fn start_element(e: &BytesStart) -> Result<Element, KdbxError> {
+    let mut element = Element::new(&String::from_utf8_lossy(e.name().as_ref()));
+    for attribute in e.attributes() {
+        let attribute = attribute.map_err(|e| KdbxError::Xml(e.to_string()))?;
+        let value = attribute
+            .unescape_value()
+            .map_err(|e| KdbxError::Xml(e.to_string()))?;
+        element.attributes.push((
+            String::from_utf8_lossy(attribute.key.as_ref()).into_owned(),
+            value.into_owned(),
+        ));
+    }
+    Ok(element)
+}
+
+fn close_element(stack: &mut [Element], root: &mut Option<Element>, element: Element) {
+    match stack.last_mut() {
+        Some(parent) => parent.push(element),
+        None => *root = Some(element),
+    }
+}
+
+/// Serializes the document, including the XML declaration.
+pub fn write(root: &Element) -> Vec<u8> {
+    let mut out = String::from("<?xml version=\"1.0\" encoding=\"utf-8\" standalone=\"yes\"?>\n");
+    write_element(root, &mut out);
+    out.push('\n');
+    out.into_bytes()
+}
+
+fn write_element(element: &Element, out: &mut String) {
+    out.push('<');
+    out.push_str(&element.name);
+    for (key, value) in &element.attributes {
+        out.push_str(&format!(" {}=\"{}\"", key, escape(value.as_str())));
+    }
+    if element.children.is_empty() {
+        out.push_str("/>");
+        return;
+    }
+    out.push('>');
+    for child in &element.children {
+        match child {
+            Node::Element(e) => write_element(e, out),
+            Node::Text(text) => out.push_str(&escape(text.as_str())),
+        }
+    }
+    out.push_str(&format!("</{}>", element.name));
+}
+
+/// Decrypts every `Protected="True"` value in document order, leaving the attribute in place
+/// so `protect` encrypts the same values again.
+pub fn unprotect(root: &mut Element, stream: &mut impl StreamCipher) -> Result<(), KdbxError> {
+    if root.is_protected() {
+        let mut value = Zeroizing::new(
+            STANDARD
+                .decode(root.text().trim())
+                .map_err(|e| KdbxError::Xml(format!("invalid protected value: {}", e)))?,
+        );
+        stream.apply_keystream(&mut value);
+        let value = std::str::from_utf8(&value)
+            .map_err(|_| KdbxError::Xml("protected value is not UTF-8".to_string()))?;
+        root.set_text(value);
+    }
+    for child in root.elements_mut() {
+        unprotect(child, stream)?;
+    }
+    Ok(())
+}
+
+/// The inverse of `unprotect`.
+pub fn protect(root: &mut Element, stream: &mut impl StreamCipher) {
+    if root.is_protected() {
+        let mut value = Zeroizing::new(root.text().into_bytes());
+        stream.apply_keystream(&mut value);
+        root.set_text(&STANDARD.encode(&value));
+    }
+    for child in root.elements_mut() {
+        protect(child, stream);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::kdbx::format::inner_stream;
+
+    #[test]
+    fn parse_and_write_round_trip() {
+        let xml =
+            br#"<?xml version="1.0"?><Root a="1 &amp; 2"><Item>x &lt; y</Item><Empty/></Root>"#;
+        let root = parse(xml).unwrap();
+        assert_eq!(root.attribute("a"), Some("1 & 2"));
+        assert_eq!(root.child("Item").unwrap().text(), "x < y");
+
+        let written = write(&root);
+        assert_eq!(parse(&written).unwrap(), root);
+    }
+
+    #[test]
+    fn protect_and_unprotect_in_document_order() {
+        let xml = br#"<Root><Value Protected="True">first</Value><Value>plain</Value><Value Protected="True">second</Value></Root>"#;
+        let original = parse(xml).unwrap();
+        let key = [7u8; 64];
+
+        let mut protected = original.clone();
+        protect(&mut protected, &mut inner_stream(&key));
+        let values: Vec<String> = protected.elements().map(|e| e.text()).collect();
+        assert_ne!(values[0], "first");
+        assert_eq!(values[1], "plain");
+        assert_ne!(values[2], "second");
+
+        unprotect(&mut protected, &mut inner_stream(&key)).unwrap();
+        assert_eq!(protected, original);
+    }
+}
@@ -0,0 +1,903 @@
+use super::entry::{days_from_civil, now, Entry, HistoryItem};
+use super::kdbx::format::{
+    self, composite_key, new_inner_stream_key, Argon2Kdf, Cipher, Database, Header,
+};
+use super::kdbx::xml::{self, Element, Node};
+use super::private_files::private_file_options;
+use super::storage_trait::Storage;
+use crate::crypto::secret::Secret;
+use crate::otp::otpauth::OtpParams;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use std::cell::RefCell;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// Seconds between 0001-01-01, the epoch of KDBX 4 timestamps, and the Unix epoch.
+const KDBX_EPOCH_OFFSET: i64 = 62_135_596_800;
+const NIL_UUID: &str = "AAAAAAAAAAAAAAAAAAAAAA==";
+const FOLDER_ICON: &str = "48";
+/// Where pw keeps an entry's generator profile, in the entry's custom data.
+const PROFILE_DATA_KEY: &str = "pw.profile";
+/// Where pw keeps how an entry's password was generated, as JSON.
+const GENERATOR_DATA_KEY: &str = "pw.generator";
+/// Used when the database does not set `HistoryMaxItems`, as in KeePass.
+const DEFAULT_HISTORY_MAX_ITEMS: usize = 10;
+
+/// Stores entries in a KeePass (KDBX 4) database.
+///
+/// Groups below the root group map to key prefixes, so the entry `GitHub` in the group
+/// `Work` is the key `Work/GitHub`. Entries in the recycle bin are ignored. The database is
+/// decrypted for every operation and re-encrypted for every write, and anything pw does not
+/// understand is written back unchanged.
+pub struct KdbxStorage {
+    path: PathBuf,
+    composite_key: [u8; 32],
+    /// The Argon2 output for the database's KDF parameters, which only change when the
+    /// database is re-keyed, so the slow KDF runs once per process.
+    transformed_key: RefCell<Option<(Argon2Kdf, [u8; 32])>>,
+}
+
+struct Document {
+    database: Database,
+    root: Element,
+}
+
+impl KdbxStorage {
+    /// Opens an existing database, failing if the password is wrong.
+    pub fn open(path: &Path, password: &str) -> io::Result<Self> {
+        let storage = Self {
+            path: path.to_path_buf(),
+            composite_key: composite_key(password),
+            transformed_key: RefCell::new(None),
+        };
+        storage.load()?;
+        Ok(storage)
+    }
+
+    /// Creates an empty database encrypted with ChaCha20 under `kdf`.
+    pub fn create(path: &Path, password: &str, kdf: Argon2Kdf) -> io::Result<Self> {
+        if path.exists() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} already exists", path.display()),
+            ));
+        }
+        let storage = Self {
+            path: path.to_path_buf(),
+            composite_key: composite_key(password),
+            transformed_key: RefCell::new(None),
+        };
+        let database = Database {
+            header: Header {
+                minor_version: 0,
+                cipher: Cipher::ChaCha20,
+                compressed: true,
+                kdf,
+                public_custom_data: None,
+            },
+            inner_stream_key: new_inner_stream_key(),
+            binaries: Vec::new(),
+            xml: Vec::new(),
+        };
+        storage.save(Document {
+            database,
+            root: empty_document(),
+        })?;
+        Ok(storage)
+    }
+
+    fn transformed_key(&self, kdf: &Argon2Kdf) -> io::Result<[u8; 32]> {
+        if let Some((cached_kdf, key)) = self.transformed_key.borrow().as_ref() {
+            if cached_kdf == kdf {
+                return Ok(*key);
+            }
+        }
+        let key = kdf.transform(&self.composite_key)?;
+        *self.transformed_key.borrow_mut() = Some((kdf.clone(), key));
+        Ok(key)
+    }
+
+    fn load(&self) -> io::Result<Document> {
+        let data = fs::read(&self.path)?;
+        let header = format::read_header(&data)?;
+        let key = self.transformed_key(&header.kdf)?;
+        let database = format::decrypt(&data, &key)?;
+        let mut root = xml::parse(&database.xml)?;
+        xml::unprotect(
+            &mut root,
+            &mut format::inner_stream(&database.inner_stream_key),
+        )?;
+        Ok(Document { database, root })
+    }
+
+    /// Writes the database to a temporary file next to it and renames it into place, so an
+    /// interrupted write cannot leave a truncated database behind.
+    fn save(&self, mut document: Document) -> io::Result<()> {
+        let database = &mut document.database;
+        database.inner_stream_key = new_inner_stream_key();
+        xml::protect(
+            &mut document.root,
+            &mut format::inner_stream(&database.inner_stream_key),
+        );
+        database.xml = xml::write(&document.root);
+        let key = self.transformed_key(&database.header.kdf)?;
+        let data = format::encrypt(database, &key)?;
+
+        let mut temp_name = self.path.as_os_str().to_owned();
+        temp_name.push(".tmp");
+        let temp_path = PathBuf::from(temp_name);
+        private_file_options()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&temp_path)?
+            .write_all(&data)?;
+        if let Ok(metadata) = fs::metadata(&self.path) {
+            fs::set_permissions(&temp_path, metadata.permissions())?;
+        }
+        fs::rename(&temp_path, &self.path)
+    }
+}
+
+impl Storage for KdbxStorage {
+    fn set(&self, key: String, value: Secret) -> io::Result<()> {
+        let mut entry = self.get_entry(key.clone())?.unwrap_or_default();
+        entry.replace_value(value);
+        self.set_entry(key, entry)
+    }
+
+    fn get(&self, key: String) -> io::Result<Option<Secret>> {
+        Ok(self.get_entry(key)?.map(|entry| entry.value))
+    }
+
+    fn get_all(&self) -> io::Result<Option<Vec<(String, Secret)>>> {
+        let document = self.load()?;
+        let entries = entry_paths(&document.root)?;
+        if entries.is_empty() {
+            return Ok(None);
+        }
+        let group = root_group(&document.root)?;
+        let values = entries
+            .iter()
+            .filter_map(|(key, path)| {
+                string_field(element_at(group, path), "Password")
+                    .filter(|value| !value.is_empty())
+                    .map(|value| (key.clone(), Secret::from(value)))
+            })
+            .collect();
+        Ok(Some(values))
+    }
+
+    fn get_entry(&self, key: String) -> io::Result<Option<Entry>> {
+        let document = self.load()?;
+        let entries = entry_paths(&document.root)?;
+        let group = root_group(&document.root)?;
+        Ok(entries
+            .iter()
+            .find(|(entry_key, _)| *entry_key == key)
+            .map(|(_, path)| to_entry(element_at(group, path))))
+    }
+
+    fn set_entry(&self, key: String, entry: Entry) -> io::Result<()> {
+        self.set_entries(vec![(key, entry)])
+    }
+
+    fn set_entries(&self, entries: Vec<(String, Entry)>) -> io::Result<()> {
+        let mut document = self.load()?;
+        for (key, entry) in entries {
+            put_entry(&mut document.root, &key, &entry)?;
+        }
+        self.save(document)
+    }
+
+    fn list_keys(&self) -> io::Result<Vec<String>> {
+        let document = self.load()?;
+        let mut keys: Vec<String> = entry_paths(&document.root)?
+            .into_iter()
+            .map(|(key, _)| key)
+            .collect();
+        keys.sort();
+        keys.dedup();
+        Ok(keys)
+    }
+}
+
+/// Updates the entry for `key` in the document, or adds it with the groups it needs.
+fn put_entry(root: &mut Element, key: &str, entry: &Entry) -> io::Result<()> {
+    let entries = entry_paths(root)?;
+    let history_max_items = root
+        .child("Meta")
+        .and_then(|meta| meta.child("HistoryMaxItems"))
+        .and_then(|max| max.text().trim().parse::<i64>().ok())
+        .map(|max| usize::try_from(max).unwrap_or(usize::MAX))
+        .unwrap_or(DEFAULT_HISTORY_MAX_ITEMS);
+    let recycle_bin = recycle_bin_uuid(root);
+    let group = root_group_mut(root)?;
+
+    match entries.into_iter().find(|(entry_key, _)| entry_key == key) {
+        Some((_, path)) => apply_entry(element_at_mut(group, &path), entry, history_max_items),
+        None => {
+            let (groups, title) = match key.rsplit_once('/') {
+                Some((groups, title)) => (groups.split('/').collect(), title),
+                None => (Vec::new(), key),
+            };
+            let parent = ensure_groups(group, &groups, recycle_bin.as_deref());
+            parent.push(new_entry(title, entry));
+        }
+    }
+    Ok(())
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+fn root_group(root: &Element) -> io::Result<&Element> {
+    root.child("Root")
+        .and_then(|r| r.child("Group"))
+        .ok_or_else(|| invalid_data("the database has no root group"))
+}
+
+fn root_group_mut(root: &mut Element) -> io::Result<&mut Element> {
+    root.child_mut("Root")
+        .and_then(|r| r.child_mut("Group"))
+        .ok_or_else(|| invalid_data("the database has no root group"))
+}
+
+fn recycle_bin_uuid(root: &Element) -> Option<String> {
+    root.child("Meta")
+        .and_then(|meta| meta.child("RecycleBinUUID"))
+        .map(|uuid| uuid.text().trim().to_string())
+        .filter(|uuid| !uuid.is_empty() && uuid != NIL_UUID)
+}
+
+fn is_recycle_bin(group: &Element, recycle_bin: Option<&str>) -> bool {
+    recycle_bin.is_some_and(|uuid| group.child("UUID").is_some_and(|u| u.text().trim() == uuid))
+}
+
+/// Every entry's key, with the path of child indexes leading to it from the root group.
+/// When two entries share a key, the first one in document order wins.
+fn entry_paths(root: &Element) -> io::Result<Vec<(String, Vec<usize>)>> {
+    fn collect(
+        group: &Element,
+        prefix: &str,
+        path: &mut Vec<usize>,
+        recycle_bin: Option<&str>,
+        out: &mut Vec<(String, Vec<usize>)>,
+    ) {
+        for (index, node) in group.children.iter().enumerate() {
+            let Node::Element(child) = node else {
+                continue;
+            };
+            path.push(index);
+            match child.name.as_str() {
+                "Entry" => {
+                    let title = string_field(child, "Title").unwrap_or_default();
+                    out.push((format!("{}{}", prefix, title), path.clone()));
+                }
+                "Group" if !is_recycle_bin(child, recycle_bin) => {
+                    let name = child.child("Name").map(|n| n.text()).unwrap_or_default();
+                    collect(
+                        child,
+                        &format!("{}{}/", prefix, name),
+                        path,
+                        recycle_bin,
+                        out,
+                    );
+                }
+                _ => {}
+            }
+            path.pop();
+        }
+    }
+
+    let recycle_bin = recycle_bin_uuid(root);
+    let mut out = Vec::new();
+    collect(
+        root_group(root)?,
+        "",
+        &mut Vec::new(),
+        recycle_bin.as_deref(),
+        &mut out,
+    );
+    Ok(out)
+}
+
+fn element_at<'a>(mut element: &'a Element, path: &[usize]) -> &'a Element {
+    for &index in path {
+        match &element.children[index] {
+            Node::Element(child) => element = child,
+            Node::Text(_) => unreachable!("paths only lead through elements"),
+        }
+    }
+    element
+}
+
+fn element_at_mut<'a>(mut element: &'a mut Element, path: &[usize]) -> &'a mut Element {
+    for &index in path {
+        match &mut element.children[index] {
+            Node::Element(child) => element = child,
+            Node::Text(_) => unreachable!("paths only lead through elements"),
+        }
+    }
+    element
+}
+
+/// Returns the group at `names` below `group`, creating any groups that do not exist.
+fn ensure_groups<'a>(
+    group: &'a mut Element,
+    names: &[&str],
+    recycle_bin: Option<&str>,
+) -> &'a mut Element {
+    let Some((name, rest)) = names.split_first() else {
+        return group;
+    };
+    let existing = group.children.iter().position(|node| {
+        matches!(node, Node::Element(child)
+            if child.name == "Group"
+                && !is_recycle_bin(child, recycle_bin)
+                && child.child("Name").is_some_and(|n| n.text() == *name))
+    });
+    let index = existing.unwrap_or_else(|| {
+        let mut new_group = Element::new("Group");
+        new_group.push(Element::with_text("UUID", &new_uuid()));
+        new_group.push(Element::with_text("Name", name));
+        new_group.push(Element::with_text("IconID", FOLDER_ICON));
+        new_group.push(new_times());
+        // Entries and groups are expected to come before subgroups, so insert the new group
+        // at the end, which is always a valid position.
+        group.push(new_group);
+        group.children.len() - 1
+    });
+    match &mut group.children[index] {
+        Node::Element(child) => ensure_groups(child, rest, recycle_bin),
+        Node::Text(_) => unreachable!("index points at a group element"),
+    }
+}
+
+fn string_field(entry: &Element, key: &str) -> Option<String> {
+    string_element(entry, key)
+        .and_then(|s| s.child("Value"))
+        .map(|v| v.text())
+}
+
+fn string_element<'a>(entry: &'a Element, key: &str) -> Option<&'a Element> {
+    entry
+        .elements()
+        .find(|e| e.name == "String" && e.child("Key").is_some_and(|k| k.text() == key))
+}
+
+fn password(entry: &Element) -> Secret {
+    string_field(entry, "Password").unwrap_or_default().into()
+}
+
+fn set_string_field(entry: &mut Element, key: &str, value: &str, protected: bool) {
+    let existing = entry
+        .elements_mut()
+        .find(|e| e.name == "String" && e.child("Key").is_some_and(|k| k.text() == key));
+    let field = match existing {
+        Some(field) => field,
+        None => {
+            let mut field = Element::new("String");
+            field.push(Element::with_text("Key", key));
+            field.push(Element::new("Value"));
+            // Keep the String fields together, ahead of history and other elements.
+            let position = entry
+                .children
+                .iter()
+                .rposition(|n| matches!(n, Node::Element(e) if e.name == "String"))
+                .map_or(entry.children.len(), |i| i + 1);
+            entry.children.insert(position, Node::Element(field));
+            match &mut entry.children[position] {
+                Node::Element(field) => field,
+                Node::Text(_) => unreachable!("a String element was just inserted"),
+            }
+        }
+    };
+    let value_element = field.child_or_insert("Value");
+    value_element.set_text(value);
+    if protected {
+        value_element.set_attribute("Protected", "True");
+    }
+}
+
+fn remove_string_field(entry: &mut Element, key: &str) {
+    entry.children.retain(|node| {
+        !matches!(node, Node::Element(e)
+            if e.name == "String" && e.child("Key").is_some_and(|k| k.text() == key))
+    });
+}
+
+fn custom_data(entry: &Element, key: &str) -> Option<String> {
+    entry
+        .child("CustomData")?
+        .elements()
+        .find(|item| item.child("Key").is_some_and(|k| k.text() == key))
+        .and_then(|item| item.child("Value"))
+        .map(|value| value.text())
+}
+
+fn set_custom_data(entry: &mut Element, key: &str, value: Option<&str>) {
+    if custom_data(entry, key).as_deref() == value {
+        return;
+    }
+    let data = entry.child_or_insert("CustomData");
+    data.children.retain(|node| {
+        !matches!(node, Node::Element(item) if item.child("Key").is_some_and(|k| k.text() == key))
+    });
+    if let Some(value) = value {
+        let mut item = Element::new("Item");
+        item.push(Element::with_text("Key", key));
+        item.push(Element::with_text("Value", value));
+        data.push(item);
+    }
+}
+
+fn modification_time(entry: &Element) -> Option<u64> {
+    entry
+        .child("Times")?
+        .child("LastModificationTime")
+        .and_then(|time| parse_time(time.text().trim()))
+}
+
+fn to_entry(element: &Element) -> Entry {
+    let non_empty = |key: &str| string_field(element, key).filter(|v| !v.is_empty());
+
+    // KeePass snapshots the whole entry on every edit, so consecutive snapshots often share
+    // a password. Only snapshots whose password was replaced become history items.
+    let mut versions: Vec<(Secret, Option<u64>)> = element
+        .child("History")
+        .map(|history| {
+            history
+                .elements()
+                .filter(|e| e.name == "Entry")
+                .map(|e| (password(e), modification_time(e)))
+                .collect()
+        })
+        .unwrap_or_default();
+    let value = password(element);
+    versions.push((value.clone(), modification_time(element)));
+    let history = versions
+        .windows(2)
+        .filter(|pair| !pair[0].0.is_empty() && pair[0].0 != pair[1].0)
+        .map(|pair| HistoryItem {
+            value: pair[0].0.clone(),
+            replaced_at: pair[1].1.unwrap_or_default(),
+        })
+        .collect();
+
+    Entry {
+        value,
+        username: non_empty("UserName"),
+        url: non_empty("URL"),
+        notes: non_empty("Notes"),
+        profile: custom_data(element, PROFILE_DATA_KEY),
+        generator: custom_data(element, GENERATOR_DATA_KEY)
+            .and_then(|generator| serde_json::from_str(&generator).ok()),
+        history,
+        otp: non_empty("otp").and_then(|otp| OtpParams::parse(&otp).ok()),
+    }
+}
+
+/// Updates an existing entry element, first moving a snapshot of it into its history when
+/// the password changes, as KeePass does.
+fn apply_entry(element: &mut Element, entry: &Entry, history_max_items: usize) {
+    let current = password(element);
+    if entry.value != current && !current.is_empty() {
+        let mut snapshot = element.clone();
+        snapshot
+            .children
+            .retain(|node| !matches!(node, Node::Element(e) if e.name == "History"));
+        let history = element.child_or_insert("History");
+        history.push(snapshot);
+        let snapshots: Vec<usize> = history
+            .children
+            .iter()
+            .enumerate()
+            .filter(|(_, node)| matches!(node, Node::Element(e) if e.name == "Entry"))
+            .map(|(index, _)| index)
+            .collect();
+        let excess = snapshots.len().saturating_sub(history_max_items);
+        for index in snapshots[..excess].iter().rev() {
+            history.children.remove(*index);
+        }
+    }
+
+    set_string_field(
+        element,
+        "UserName",
+        entry.username.as_deref().unwrap_or_default(),
+        false,
+    );
+    set_string_field(element, "Password", entry.value.expose(), true);
+    set_string_field(
+        element,
+        "URL",
+        entry.url.as_deref().unwrap_or_default(),
+        false,
+    );
+    set_string_field(
+        element,
+        "Notes",
+        entry.notes.as_deref().unwrap_or_default(),
+        false,
+    );
+
+    // Rewriting an unchanged otp field would replace the user's URI with pw's rendering of it.
+    let current_otp = string_field(element, "otp").and_then(|otp| OtpParams::parse(&otp).ok());
+    match &entry.otp {
+        Some(otp) if current_otp.as_ref() != Some(otp) => {
+            set_string_field(element, "otp", otp.to_uri().expose(), true)
+        }
+        Some(_) => {}
+        None => remove_string_field(element, "otp"),
+    }
+    set_custom_data(element, PROFILE_DATA_KEY, entry.profile.as_deref());
+    let generator = entry
+        .generator
+        .as_ref()
+        .and_then(|generator| serde_json::to_string(generator).ok());
+    set_custom_data(element, GENERATOR_DATA_KEY, generator.as_deref());
+
+    let now = format_time(now());
+    let times = element.child_or_insert("Times");
+    times.child_or_insert("LastModificationTime").set_text(&now);
+    times.child_or_insert("LastAccessTime").set_text(&now);
+}
+
+fn new_entry(title: &str, entry: &Entry) -> Element {
+    let mut element = Element::new("Entry");
+    element.push(Element::with_text("UUID", &new_uuid()));
+    element.push(Element::with_text("IconID", "0"));
+    element.push(new_times());
+    set_string_field(&mut element, "Title", title, false);
+    apply_entry(&mut element, entry, 0);
+    element
+}
+
+fn new_times() -> Element {
+    let now = format_time(now());
+    let mut times = Element::new("Times");
+    for name in [
+        "CreationTime",
+        "LastModificationTime",
+        "LastAccessTime",
+        "ExpiryTime",
+        "LocationChanged",
+    ] {
+        times.push(Element::with_text(name, &now));
+    }
+    times.push(Element::with_text("Expires", "False"));
+    times.push(Element::with_text("UsageCount", "0"));
+    times
+}
+
+fn empty_document() -> Element {
+    let mut protection = Element::new("MemoryProtection");
+    for (name, protected) in [
+        ("ProtectTitle", "False"),
+        ("ProtectUserName", "False"),
+        ("ProtectPassword", "True"),
+        ("ProtectURL", "False"),
+        ("ProtectNotes", "False"),
+    ] {
+        protection.push(Element::with_text(name, protected));
+    }
+    let mut meta = Element::new("Meta");
+    meta.push(Element::with_text("Generator", "pw"));
+    meta.push(Element::with_text("DatabaseName", "pw"));
+    meta.push(protection);
+    meta.push(Element::with_text("RecycleBinEnabled", "True"));
+    meta.push(Element::with_text("RecycleBinUUID", NIL_UUID));
+    meta.push(Element::with_text(
+        "HistoryMaxItems",
+        &DEFAULT_HISTORY_MAX_ITEMS.to_string(),
+    ));
+
+    let mut group = Element::new("Group");
+    group.push(Element::with_text("UUID", &new_uuid()));
+    group.push(Element::with_text("Name", "pw"));
+    group.push(Element::with_text("IconID", FOLDER_ICON));
+    group.push(new_times());
+    group.push(Element::with_text("IsExpanded", "True"));
+
+    let mut root = Element::new("Root");
+    root.push(group);
+    root.push(Element::new("DeletedObjects"));
+
+    let mut document = Element::new("KeePassFile");
+    document.push(meta);
+    document.push(root);
+    document
+}
+
+fn new_uuid() -> String {
+    STANDARD.encode(uuid::Uuid::new_v4().as_bytes())
+}
+
+/// KDBX 4 stores times as base64 of little-endian seconds since 0001-01-01; older writers
+/// use ISO 8601 (`2024-01-31T12:00:00Z`), which is accepted too.
+fn parse_time(text: &str) -> Option<u64> {
+    if let Some((date, time)) = text.trim_end_matches('Z').split_once('T') {
+        let date: Vec<i64> = date
+            .split('-')
+            .map(|p| p.parse().ok())
+            .collect::<Option<_>>()?;
+        let time: Vec<i64> = time
+            .split(':')
+            .map(|p| p.parse().ok())
+            .collect::<Option<_>>()?;
+        let ([year, month, day], [hour, minute, second]) = (date.as_slice(), time.as_slice())
+        else {
+            return None;
+        };
+        let days = days_from_civil(*year, *month, *day);
+        return u64::try_from(days * 86_400 + hour * 3_600 + minute * 60 + second).ok();
+    }
+    let bytes: [u8; 8] = STANDARD.decode(text).ok()?.try_into().ok()?;
+    u64::try_from(i64::from_le_bytes(bytes) - KDBX_EPOCH_OFFSET).ok()
+}
+
+fn format_time(unix: u64) -> String {
+    let seconds = unix as i64 + KDBX_EPOCH_OFFSET;
+    STANDARD.encode(seconds.to_le_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::otp::otpauth::OtpKind;
+    use crate::password_generator::mode::GenerateMode;
+    use crate::storage::kdbx::format::tests::{AES_ARGON2D, CHACHA20_ARGON2ID, FIXTURE_PASSWORD};
+    use crate::storage::kdbx::format::Argon2Variant;
+    use std::process::{Command, Stdio};
+    use tempfile::{tempdir, TempDir};
+
+    fn fixture(data: &[u8]) -> (TempDir, PathBuf) {
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path().join("fixture.kdbx");
+        fs::write(&path, data).unwrap();
+        (temp_dir, path)
+    }
+
+    fn test_kdf() -> Argon2Kdf {
+        Argon2Kdf::new(Argon2Variant::Argon2id, 64, 1, 1)
+    }
+
+    #[test]
+    fn reads_fixture_entries() {
+        for data in [AES_ARGON2D, CHACHA20_ARGON2ID] {
+            let (_dir, path) = fixture(data);
+            let storage = KdbxStorage::open(&path, FIXTURE_PASSWORD).unwrap();
+
+            assert_eq!(storage.list_keys().unwrap(), vec!["Email", "Work/GitHub"]);
+            let entry = storage.get_entry("Email".to_string()).unwrap().unwrap();
+            assert_eq!(entry.value, "correct horse");
+            assert_eq!(entry.username.as_deref(), Some("alice@example.com"));
+            assert_eq!(entry.url.as_deref(), Some("https://mail.example.com"));
+            assert_eq!(entry.notes.as_deref(), Some("line1\nline2 & more"));
+            assert_eq!(
+                entry.history,
+                vec![HistoryItem {
+                    value: "old password".into(),
+                    replaced_at: 1_700_000_000,
+                }]
+            );
+            let otp = entry.otp.unwrap();
+            assert_eq!(otp.secret, "JBSWY3DPEHPK3PXP");
+            assert!(matches!(otp.kind, OtpKind::Totp { period: 30 }));
+
+            assert_eq!(
+                storage.get("Work/GitHub".to_string()).unwrap(),
+                Some(Secret::from("gh-secret"))
+            );
+            assert_eq!(
+                storage.get("Recycle Bin/Deleted".to_string()).unwrap(),
+                None
+            );
+        }
+    }
+
+    #[test]
+    fn wrong_password_is_rejected() {
+        let (_dir, path) = fixture(AES_ARGON2D);
+        let error = KdbxStorage::open(&path, "wrong").err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(error.to_string().contains("wrong password"));
+    }
+
+    #[test]
+    fn updates_round_trip_and_keep_unknown_data() {
+        let (_dir, path) = fixture(AES_ARGON2D);
+        let storage = KdbxStorage::open(&path, FIXTURE_PASSWORD).unwrap();
+
+        storage
+            .set("Email".to_string(), "new password".into())
+            .unwrap();
+        storage
+            .set("Work/Cloud/AWS".to_string(), "aws-secret".into())
+            .unwrap();
+        drop(storage);
+
+        let storage = KdbxStorage::open(&path, FIXTURE_PASSWORD).unwrap();
+        assert_eq!(
+            storage.list_keys().unwrap(),
+            vec!["Email", "Work/Cloud/AWS", "Work/GitHub"]
+        );
+        let entry = storage.get_entry("Email".to_string()).unwrap().unwrap();
+        assert_eq!(entry.value, "new password");
+        assert_eq!(entry.username.as_deref(), Some("alice@example.com"));
+        let history: Vec<&str> = entry.history.iter().map(|h| h.value.expose()).collect();
+        assert_eq!(history, vec!["old password", "correct horse"]);
+
+        let document = storage.load().unwrap();
+        assert_eq!(
+            document.database.binaries,
+            vec![b"\x01attached file contents".to_vec()]
+        );
+        let group = root_group(&document.root).unwrap();
+        let (_, email_path) = &entry_paths(&document.root).unwrap()[0];
+        let email = element_at(group, email_path);
+        assert_eq!(
+            string_field(email, "Recovery codes").as_deref(),
+            Some("1234-5678")
+        );
+        let recycle_bin = recycle_bin_uuid(&document.root);
+        assert!(group
+            .elements()
+            .any(|g| is_recycle_bin(g, recycle_bin.as_deref())));
+    }
+
+    #[test]
+    fn entry_metadata_round_trip() {
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path().join("new.kdbx");
+        let storage = KdbxStorage::create(&path, "secret", test_kdf()).unwrap();
+        assert!(storage.list_keys().unwrap().is_empty());
+        assert_eq!(storage.get_all().unwrap(), None);
+
+        let entry = Entry {
+            value: "hunter2".into(),
+            username: Some("bob".to_string()),
+            url: Some("https://example.com".to_string()),
+            notes: Some("<notes> & \"quotes\"".to_string()),
+            profile: Some("bank".to_string()),
+            generator: Some(GenerateMode::Profile {
+                name: "bank".to_string(),
+                length: None,
+            }),
+            history: Vec::new(),
+            otp: Some(OtpParams::parse("JBSWY3DPEHPK3PXP").unwrap()),
+        };
+        storage
+            .set_entry("Banking/Example".to_string(), entry.clone())
+            .unwrap();
+        drop(storage);
+
+        let storage = KdbxStorage::open(&path, "secret").unwrap();
+        assert_eq!(
+            storage.get_entry("Banking/Example".to_string()).unwrap(),
+            Some(entry)
+        );
+        assert!(KdbxStorage::open(&path, "wrong").is_err());
+        assert!(KdbxStorage::create(&path, "secret", test_kdf()).is_err());
+    }
+
+    #[test]
+    fn history_is_capped() {
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path().join("new.kdbx");
+        let storage = KdbxStorage::create(&path, "secret", test_kdf()).unwrap();
+
+        for i in 0..=DEFAULT_HISTORY_MAX_ITEMS + 2 {
+            storage
+                .set("key".to_string(), format!("value-{}", i).into())
+                .unwrap();
+        }
+        let entry = storage.get_entry("key".to_string()).unwrap().unwrap();
+        assert_eq!(entry.history.len(), DEFAULT_HISTORY_MAX_ITEMS);
+        assert_eq!(entry.history[0].value, "value-2");
+    }
+
+    #[test]
+    fn parses_both_time_formats() {
+        assert_eq!(parse_time(&format_time(1_700_000_000)), Some(1_700_000_000));
+        assert_eq!(parse_time("2023-11-14T22:13:20Z"), Some(1_700_000_000));
+        assert_eq!(parse_time("1970-01-01T00:00:00Z"), Some(0));
+        assert_eq!(parse_time("garbage"), None);
+    }
+
+    /// Runs `keepassxc-cli`, feeding `input` (the database password and any prompted
+    /// values, one per line) on standard input.
+    fn keepassxc_cli(args: &[&str], input: &str) -> String {
+        let mut child = Command::new("keepassxc-cli")
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .expect("keepassxc-cli is not installed");
+        child
+            .stdin
+            .take()
+            .unwrap()
+            .write_all(input.as_bytes())
+            .unwrap();
+        let output = child.wait_with_output().unwrap();
+        assert!(
+            output.status.success(),
+            "keepassxc-cli {:?} failed: {}",
+            args,
+            String::from_utf8_lossy(&output.stderr)
+        );
+        String::from_utf8(output.stdout).unwrap()
+    }
+
+    #[test]
+    #[ignore = "needs keepassxc-cli; the KeePassXC CI job runs it"]
+    fn keepassxc_databases_round_trip() {
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path().join("keepassxc.kdbx");
+        let database = path.to_str().unwrap();
+        keepassxc_cli(
+            &[
+                "db-create",
+                "--set-password",
+                "--decryption-time",
+                "100",
+                database,
+            ],
+            "secret\nsecret\n",
+        );
+        keepassxc_cli(&["mkdir", database, "Work"], "secret\n");
+        keepassxc_cli(
+            &[
+                "add",
+                "--username",
+                "alice",
+                "--url",
+                "https://github.com",
+                "--password-prompt",
+                database,
+                "Work/GitHub",
+            ],
+            "secret\nhunter2\n",
+        );
+
+        let storage = KdbxStorage::open(&path, "secret").unwrap();
+        assert_eq!(storage.list_keys().unwrap(), vec!["Work/GitHub"]);
+        let entry = storage
+            .get_entry("Work/GitHub".to_string())
+            .unwrap()
+            .unwrap();
+        assert_eq!(entry.value, "hunter2");
+        assert_eq!(entry.username.as_deref(), Some("alice"));
+        assert_eq!(entry.url.as_deref(), Some("https://github.com"));
+
+        storage
+            .set("Work/GitHub".to_string(), "correct horse".into())
+            .unwrap();
+        storage
+            .set("Personal/Mail".to_string(), "mail-password".into())
+            .unwrap();
+        for (key, value) in [
+            ("Work/GitHub", "correct horse"),
+            ("Personal/Mail", "mail-password"),
+        ] {
+            let shown = keepassxc_cli(
+                &[
+                    "show",
+                    "--show-protected",
+                    "--attributes",
+                    "Password",
+                    database,
+                    key,
+                ],
+                "secret\n",
+            );
+            assert_eq!(shown.trim(), value);
+        }
+    }
+}
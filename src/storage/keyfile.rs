@@ -0,0 +1,180 @@
+use super::private_files::private_file_options;
+use crate::error::PwError;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Random bytes in a generated keyfile.
+const KEYFILE_LEN: usize = 64;
+
+const CHECK_LABEL: &[u8] = b"pw-keyfile-check";
+
+/// A second secret kept apart from the vault, e.g. on a removable drive, that is needed
+/// along with the master password to unlock it. Only the SHA-256 of the file is used, so
+/// any file can serve, but it must never change.
+#[derive(Clone)]
+pub struct Keyfile {
+    digest: [u8; 32],
+}
+
+impl Keyfile {
+    /// Writes a new keyfile of random bytes to `path`, with owner-only access. An existing
+    /// file is never overwritten.
+    pub fn generate(path: &Path) -> io::Result<Self> {
+        let mut bytes = [0u8; KEYFILE_LEN];
+        OsRng.fill_bytes(&mut bytes);
+        let contents = format!("{}\n", STANDARD.encode(bytes));
+        let mut file = match private_file_options()
+            .write(true)
+            .create_new(true)
+            .open(path)
+        {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                return Err(PwError::Invalid(format!(
+                    "{} already exists. Choose another path for the new keyfile.",
+                    path.display()
+                ))
+                .into())
+            }
+            Err(e) => return Err(e),
+        };
+        file.write_all(contents.as_bytes())?;
+        file.sync_all()?;
+        Ok(Self::from_contents(contents.as_bytes()))
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        match fs::read(path) {
+            Ok(contents) => Ok(Self::from_contents(&contents)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Err(PwError::NotFound(format!(
+                "Keyfile {} not found. Is the drive it is on mounted?",
+                path.display()
+            ))
+            .into()),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn from_contents(contents: &[u8]) -> Self {
+        Self {
+            digest: Sha256::digest(contents).into(),
+        }
+    }
+
+    fn check_mac(&self, salt: &[u8]) -> HmacSha256 {
+        let mut mac =
+            HmacSha256::new_from_slice(&self.digest).expect("HMAC accepts any key length");
+        mac.update(CHECK_LABEL);
+        mac.update(salt);
+        mac
+    }
+}
+
+/// The secret a vault's wrapping key is derived from: the master password alone, or
+/// combined with the keyfile as KeePass does, `SHA-256(SHA-256(password) || SHA-256(keyfile))`.
+pub(crate) fn composite_secret(password: &str, keyfile: Option<&Keyfile>) -> Vec<u8> {
+    match keyfile {
+        None => password.as_bytes().to_vec(),
+        Some(keyfile) => {
+            let mut hasher = Sha256::new();
+            hasher.update(Sha256::digest(password.as_bytes()));
+            hasher.update(keyfile.digest);
+            hasher.finalize().to_vec()
+        }
+    }
+}
+
+/// Stored with a vault that needs a keyfile, so a wrong keyfile is reported as such rather
+/// than as a wrong password. It reveals nothing useful: a keyfile is far too random to guess.
+pub(crate) fn keyfile_check(keyfile: Option<&Keyfile>, salt: &[u8]) -> Option<Vec<u8>> {
+    keyfile.map(|keyfile| keyfile.check_mac(salt).finalize().into_bytes().to_vec())
+}
+
+/// Checks `keyfile` against the `check` stored with the vault at `path`, which is `None` if
+/// the vault does not use a keyfile.
+pub(crate) fn verify_keyfile(
+    path: &Path,
+    check: Option<&[u8]>,
+    salt: &[u8],
+    keyfile: Option<&Keyfile>,
+) -> io::Result<()> {
+    match (check, keyfile) {
+        (None, None) => Ok(()),
+        (Some(_), None) => Err(PwError::Auth(format!(
+            "{} needs its keyfile. Give it with --keyfile, PW_KEYFILE or the 'unlock.keyfile' setting.",
+            path.display()
+        ))
+        .into()),
+        (None, Some(_)) => Err(PwError::Invalid(format!(
+            "{} does not use a keyfile. Leave out --keyfile, PW_KEYFILE and 'unlock.keyfile' for it.",
+            path.display()
+        ))
+        .into()),
+        (Some(check), Some(keyfile)) => keyfile
+            .check_mac(salt)
+            .verify_slice(check)
+            .map_err(|_| PwError::Auth(format!("wrong keyfile for {}", path.display())).into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn generated_keyfiles_load_and_are_never_overwritten() -> Result<(), PwError> {
+        let temp_dir = tempdir()?;
+        let path = temp_dir.path().join("vault.key");
+        let keyfile = Keyfile::generate(&path)?;
+        assert_eq!(Keyfile::load(&path)?.digest, keyfile.digest);
+        assert!(matches!(
+            Keyfile::generate(&path).map_err(PwError::from),
+            Err(PwError::Invalid(_))
+        ));
+        let other = Keyfile::generate(&temp_dir.path().join("other.key"))?;
+        assert_ne!(other.digest, keyfile.digest);
+        assert!(matches!(
+            Keyfile::load(&temp_dir.path().join("missing.key")).map_err(PwError::from),
+            Err(PwError::NotFound(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn missing_and_wrong_keyfiles_are_told_apart() -> Result<(), PwError> {
+        let temp_dir = tempdir()?;
+        let path = temp_dir.path();
+        let keyfile = Keyfile::generate(&path.join("vault.key"))?;
+        let other = Keyfile::generate(&path.join("other.key"))?;
+        let check = keyfile_check(Some(&keyfile), b"salt").unwrap();
+
+        verify_keyfile(path, Some(&check), b"salt", Some(&keyfile))?;
+        verify_keyfile(path, None, b"salt", None)?;
+        let error = |result: io::Result<()>| PwError::from(result.unwrap_err()).to_string();
+        assert!(
+            error(verify_keyfile(path, Some(&check), b"salt", None)).contains("needs its keyfile")
+        );
+        assert!(
+            error(verify_keyfile(path, Some(&check), b"salt", Some(&other)))
+                .contains("wrong keyfile")
+        );
+        assert!(error(verify_keyfile(path, None, b"salt", Some(&keyfile))).contains("does not use"));
+
+        assert_eq!(composite_secret("master", None), b"master");
+        assert_ne!(
+            composite_secret("master", Some(&keyfile)),
+            composite_secret("master", Some(&other))
+        );
+        Ok(())
+    }
+}
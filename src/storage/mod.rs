@@ -1,2 +1,13 @@
+pub(crate) mod backups;
+pub(crate) mod entry;
 pub(crate) mod file_storage;
+pub(crate) mod json_salvage;
+pub(crate) mod kdbx;
+pub(crate) mod kdbx_storage;
+pub(crate) mod keyfile;
+pub(crate) mod pass_storage;
+pub(crate) mod private_files;
+pub(crate) mod recovery_shares;
+pub(crate) mod sqlite_storage;
 pub(crate) mod storage_trait;
+pub(crate) mod vaults;
@@ -0,0 +1,484 @@
+use super::entry::Entry;
+use super::storage_trait::Storage;
+use crate::crypto::secret::Secret;
+use crate::error::PwError;
+use crate::importers::password_store::parse_pass_contents;
+use crate::otp::otpauth::OtpParams;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use zeroize::Zeroizing;
+
+/// The file listing the key IDs a directory's passwords are encrypted to.
+pub(crate) const GPG_ID_FILE: &str = ".gpg-id";
+
+/// Runs gpg the way pass(1) does.
+#[derive(Debug, Clone, Default)]
+pub struct Gpg {
+    /// A GnuPG home to use instead of `GNUPGHOME` or `~/.gnupg`.
+    pub homedir: Option<PathBuf>,
+}
+
+impl Gpg {
+    fn command(&self) -> Command {
+        let mut command = Command::new("gpg");
+        if let Some(homedir) = &self.homedir {
+            command.arg("--homedir").arg(homedir);
+        }
+        command.args([
+            "--quiet",
+            "--yes",
+            "--batch",
+            "--compress-algo=none",
+            "--no-encrypt-to",
+        ]);
+        command
+    }
+
+    pub fn decrypt(&self, path: &Path) -> io::Result<Zeroizing<String>> {
+        let output = self.command().arg("--decrypt").arg(path).output()?;
+        if !output.status.success() {
+            return Err(io::Error::other(format!(
+                "gpg could not decrypt {}: {}",
+                path.display(),
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+        let stdout = Zeroizing::new(output.stdout);
+        std::str::from_utf8(&stdout)
+            .map(|contents| Zeroizing::new(contents.to_string()))
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// Encrypts `plaintext` to `recipients` and writes it to `output`. gpg writes to a
+    /// temporary file next to `output`, which is renamed into place only once gpg succeeds,
+    /// so a failed or interrupted run never leaves a damaged password file.
+    pub fn encrypt(&self, plaintext: &str, recipients: &[String], output: &Path) -> io::Result<()> {
+        let mut temp_name = output.as_os_str().to_owned();
+        temp_name.push(".tmp");
+        let temp_path = PathBuf::from(temp_name);
+        let result = self.encrypt_to(plaintext, recipients, &temp_path);
+        if result.is_err() {
+            let _ = fs::remove_file(&temp_path);
+            return result;
+        }
+        fs::rename(&temp_path, output)
+    }
+
+    fn encrypt_to(&self, plaintext: &str, recipients: &[String], output: &Path) -> io::Result<()> {
+        let mut command = self.command();
+        command.arg("--encrypt").arg("--output").arg(output);
+        for recipient in recipients {
+            command.arg("--recipient").arg(recipient);
+        }
+        let mut child = command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        child
+            .stdin
+            .take()
+            .expect("stdin is piped")
+            .write_all(plaintext.as_bytes())?;
+        let result = child.wait_with_output()?;
+        if !result.status.success() {
+            return Err(io::Error::other(format!(
+                "gpg could not encrypt {}: {}",
+                output.display(),
+                String::from_utf8_lossy(&result.stderr).trim()
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Stores entries in a pass(1) password store: one gpg-encrypted file per key, encrypted to
+/// the recipients in the nearest `.gpg-id` file. Keys are file paths relative to the store
+/// without the `.gpg` extension, so `pw` and `pass` can be used on the same store.
+///
+/// Files follow the usual pass conventions: the password on the first line, then `login:`
+/// and `url:` lines, an `otpauth://` URI, and notes. pass keeps no history of its own; when
+/// the store is a git repository, every change is committed, as pass does.
+pub struct PassStorage {
+    root: PathBuf,
+    gpg: Gpg,
+}
+
+impl PassStorage {
+    pub fn new(root: &Path, gpg: Gpg) -> io::Result<Self> {
+        if !root.is_dir() {
+            return Err(PwError::NotFound(format!(
+                "{} is not a password store; create one with 'pass init <gpg-id>'",
+                root.display()
+            ))
+            .into());
+        }
+        Ok(Self {
+            root: root.to_path_buf(),
+            gpg,
+        })
+    }
+
+    /// `PASSWORD_STORE_DIR`, or `~/.password-store`, as pass resolves it.
+    pub fn default_root() -> Option<PathBuf> {
+        match std::env::var_os("PASSWORD_STORE_DIR") {
+            Some(dir) => Some(PathBuf::from(dir)),
+            None => dirs::home_dir().map(|home| home.join(".password-store")),
+        }
+    }
+
+    fn file_path(&self, key: &str) -> io::Result<PathBuf> {
+        // Each segment becomes a path component; hidden names (including `.` and `..`) and
+        // empty segments would escape the store or collide with pass's own files.
+        let valid = key
+            .split('/')
+            .all(|segment| !segment.is_empty() && !segment.starts_with('.'));
+        if !valid {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("'{}' is not a valid password store key", key),
+            ));
+        }
+        let mut path = self.root.join(key).into_os_string();
+        path.push(".gpg");
+        Ok(PathBuf::from(path))
+    }
+
+    /// Recipients from the `.gpg-id` file closest to `path`, searching up to the store root.
+    fn recipients(&self, path: &Path) -> io::Result<Vec<String>> {
+        let mut dir = path.parent();
+        while let Some(current) = dir.filter(|d| d.starts_with(&self.root)) {
+            match fs::read_to_string(current.join(GPG_ID_FILE)) {
+                Ok(contents) => {
+                    let recipients: Vec<String> = contents
+                        .lines()
+                        .map(|line| line.split('#').next().unwrap_or_default().trim())
+                        .filter(|line| !line.is_empty())
+                        .map(str::to_string)
+                        .collect();
+                    if recipients.is_empty() {
+                        break;
+                    }
+                    return Ok(recipients);
+                }
+                Err(e) if e.kind() == io::ErrorKind::NotFound => dir = current.parent(),
+                Err(e) => return Err(e),
+            }
+        }
+        Err(PwError::NotFound(format!(
+            "no {} with recipients found for {}; run 'pass init <gpg-id>'",
+            GPG_ID_FILE,
+            path.display()
+        ))
+        .into())
+    }
+
+    fn keys(&self) -> io::Result<Vec<String>> {
+        fn visit(root: &Path, dir: &Path, keys: &mut Vec<String>) -> io::Result<()> {
+            for dir_entry in fs::read_dir(dir)? {
+                let path = dir_entry?.path();
+                let hidden = path
+                    .file_name()
+                    .is_some_and(|name| name.to_string_lossy().starts_with('.'));
+                if hidden {
+                    continue;
+                }
+                if path.is_dir() {
+                    visit(root, &path, keys)?;
+                } else if path.extension().is_some_and(|ext| ext == "gpg") {
+                    let relative = path.strip_prefix(root).unwrap_or(&path).with_extension("");
+                    let components: Vec<String> = relative
+                        .components()
+                        .map(|c| c.as_os_str().to_string_lossy().into_owned())
+                        .collect();
+                    keys.push(components.join("/"));
+                }
+            }
+            Ok(())
+        }
+
+        let mut keys = Vec::new();
+        visit(&self.root, &self.root, &mut keys)?;
+        keys.sort();
+        Ok(keys)
+    }
+
+    fn git_commit(&self, path: &Path, message: &str) -> io::Result<()> {
+        if !self.root.join(".git").is_dir() {
+            return Ok(());
+        }
+        for args in [
+            vec!["add", "--"],
+            vec!["commit", "--quiet", "--message", message, "--"],
+        ] {
+            let output = Command::new("git")
+                .arg("-C")
+                .arg(&self.root)
+                .args(args)
+                .arg(path)
+                .output()?;
+            if !output.status.success() {
+                return Err(io::Error::other(format!(
+                    "git could not commit the change: {}",
+                    String::from_utf8_lossy(&output.stderr).trim()
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Renders an entry in the layout `parse_pass_contents` reads.
+fn pass_contents(entry: &Entry) -> Zeroizing<String> {
+    let otp = entry.otp.as_ref().map(OtpParams::to_uri);
+    let lines: Vec<(&str, &str)> = [
+        Some(("", entry.value.expose())),
+        entry
+            .username
+            .as_deref()
+            .map(|username| ("login: ", username)),
+        entry.url.as_deref().map(|url| ("url: ", url)),
+        otp.as_ref().map(|otp| ("", otp.expose())),
+        entry.notes.as_deref().map(|notes| ("", notes)),
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
+    // Sized up front, so the buffer is never reallocated and leaves no copy behind.
+    let len = lines
+        .iter()
+        .map(|(label, line)| label.len() + line.len() + 1)
+        .sum();
+    let mut contents = Zeroizing::new(String::with_capacity(len));
+    for (label, line) in lines {
+        contents.push_str(label);
+        contents.push_str(line);
+        contents.push('\n');
+    }
+    contents
+}
+
+impl Storage for PassStorage {
+    fn set(&self, key: String, value: Secret) -> io::Result<()> {
+        let mut entry = self.get_entry(key.clone())?.unwrap_or_default();
+        entry.replace_value(value);
+        self.set_entry(key, entry)
+    }
+
+    fn get(&self, key: String) -> io::Result<Option<Secret>> {
+        Ok(self.get_entry(key)?.map(|entry| entry.value))
+    }
+
+    fn get_all(&self) -> io::Result<Option<Vec<(String, Secret)>>> {
+        let keys = self.keys()?;
+        if keys.is_empty() {
+            return Ok(None);
+        }
+        let mut values = Vec::new();
+        for key in keys {
+            if let Some(entry) = self.get_entry(key.clone())? {
+                if !entry.value.is_empty() {
+                    values.push((key, entry.value));
+                }
+            }
+        }
+        Ok(Some(values))
+    }
+
+    fn get_entry(&self, key: String) -> io::Result<Option<Entry>> {
+        let path = self.file_path(&key)?;
+        if !path.is_file() {
+            return Ok(None);
+        }
+        let contents = self.gpg.decrypt(&path)?;
+        let components: Vec<String> = key.split('/').map(str::to_string).collect();
+        Ok(Some(parse_pass_contents(&components, &contents).entry))
+    }
+
+    fn set_entry(&self, key: String, entry: Entry) -> io::Result<()> {
+        let path = self.file_path(&key)?;
+        let existed = path.is_file();
+        let recipients = self.recipients(&path)?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        self.gpg
+            .encrypt(&pass_contents(&entry), &recipients, &path)?;
+
+        let message = if existed {
+            format!("Edit password for {} using pw.", key)
+        } else {
+            format!("Add given password for {} to store.", key)
+        };
+        self.git_commit(&path, &message)
+    }
+
+    fn list_keys(&self) -> io::Result<Vec<String>> {
+        self.keys()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::{tempdir, TempDir};
+
+    /// A throwaway GnuPG home with one passphrase-less key, `pw-test@example.com`.
+    struct TestGpg {
+        home: TempDir,
+    }
+
+    impl TestGpg {
+        /// Panics when gpg is missing or cannot create a key: the pass tests need gpg, and
+        /// skipping them silently would let a broken setup pass.
+        fn new() -> Self {
+            let home = tempdir().unwrap();
+            let output = Command::new("gpg")
+                .arg("--homedir")
+                .arg(home.path())
+                .args([
+                    "--batch",
+                    "--quiet",
+                    "--passphrase",
+                    "",
+                    "--quick-generate-key",
+                    "pw test <pw-test@example.com>",
+                    "future-default",
+                    "default",
+                    "never",
+                ])
+                .output()
+                .expect("the pass storage tests need gpg installed");
+            assert!(
+                output.status.success(),
+                "gpg could not create a test key: {}",
+                String::from_utf8_lossy(&output.stderr)
+            );
+            Self { home }
+        }
+
+        fn gpg(&self) -> Gpg {
+            Gpg {
+                homedir: Some(self.home.path().to_path_buf()),
+            }
+        }
+    }
+
+    impl Drop for TestGpg {
+        fn drop(&mut self) {
+            let _ = Command::new("gpgconf")
+                .arg("--homedir")
+                .arg(self.home.path())
+                .args(["--kill", "all"])
+                .status();
+        }
+    }
+
+    fn store() -> (TestGpg, TempDir, PassStorage) {
+        let gpg = TestGpg::new();
+        let root = tempdir().unwrap();
+        fs::write(root.path().join(GPG_ID_FILE), "pw-test@example.com\n").unwrap();
+        let storage = PassStorage::new(root.path(), gpg.gpg()).unwrap();
+        (gpg, root, storage)
+    }
+
+    #[test]
+    fn set_and_get_round_trip() {
+        let (gpg, root, storage) = store();
+
+        let entry = Entry {
+            value: "hunter2".into(),
+            username: Some("alice".to_string()),
+            url: Some("https://github.com".to_string()),
+            notes: Some("recovery: 1234".to_string()),
+            otp: Some(OtpParams::parse("JBSWY3DPEHPK3PXP").unwrap()),
+            ..Default::default()
+        };
+        storage
+            .set_entry("work/github".to_string(), entry.clone())
+            .unwrap();
+        storage.set("email".to_string(), "mail-pw".into()).unwrap();
+
+        assert_eq!(storage.list_keys().unwrap(), vec!["email", "work/github"]);
+        assert_eq!(
+            storage.get_entry("work/github".to_string()).unwrap(),
+            Some(entry)
+        );
+        assert_eq!(storage.get("missing".to_string()).unwrap(), None);
+
+        // The file is what pass itself would decrypt and show.
+        let decrypted = gpg.gpg().decrypt(&root.path().join("email.gpg")).unwrap();
+        assert_eq!(*decrypted, "mail-pw\n");
+    }
+
+    #[test]
+    fn nearest_gpg_id_is_used() {
+        let (_gpg, root, storage) = store();
+        fs::create_dir_all(root.path().join("shared")).unwrap();
+        fs::write(
+            root.path().join("shared").join(GPG_ID_FILE),
+            "# team key\nteam@example.com\n",
+        )
+        .unwrap();
+
+        let recipients = storage
+            .recipients(&storage.file_path("shared/db").unwrap())
+            .unwrap();
+        assert_eq!(recipients, vec!["team@example.com"]);
+        let recipients = storage
+            .recipients(&storage.file_path("other/db").unwrap())
+            .unwrap();
+        assert_eq!(recipients, vec!["pw-test@example.com"]);
+
+        // No key for team@example.com exists, so gpg refuses and nothing is written.
+        assert!(storage.set("shared/db".to_string(), "x".into()).is_err());
+        assert!(storage.list_keys().unwrap().is_empty());
+        assert!(!root.path().join("shared").join("db.gpg.tmp").exists());
+    }
+
+    #[test]
+    fn rejects_keys_outside_the_store() {
+        let root = tempdir().unwrap();
+        let storage = PassStorage::new(root.path(), Gpg::default()).unwrap();
+        for key in ["../escape", "/etc/passwd", "a/./b", ".git/config", ""] {
+            assert_eq!(
+                storage.get(key.to_string()).unwrap_err().kind(),
+                io::ErrorKind::InvalidInput,
+                "key {:?}",
+                key
+            );
+        }
+    }
+
+    #[test]
+    fn missing_store_is_an_error() {
+        let root = tempdir().unwrap();
+        assert!(PassStorage::new(&root.path().join("missing"), Gpg::default()).is_err());
+    }
+
+    #[test]
+    fn changes_are_committed_to_git() {
+        let (_gpg, root, storage) = store();
+        let git = |args: &[&str]| {
+            Command::new("git")
+                .arg("-C")
+                .arg(root.path())
+                .args(args)
+                .output()
+        };
+        assert!(git(&["init", "--quiet"]).unwrap().status.success());
+        git(&["config", "user.email", "pw-test@example.com"]).unwrap();
+        git(&["config", "user.name", "pw test"]).unwrap();
+
+        storage.set("email".to_string(), "first".into()).unwrap();
+        storage.set("email".to_string(), "second".into()).unwrap();
+
+        let log = git(&["log", "--format=%s"]).unwrap();
+        assert_eq!(
+            String::from_utf8_lossy(&log.stdout),
+            "Edit password for email using pw.\nAdd given password for email to store.\n"
+        );
+    }
+}
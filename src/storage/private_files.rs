@@ -0,0 +1,41 @@
+use std::fs::{DirBuilder, File, OpenOptions};
+use std::io;
+use std::path::Path;
+
+/// Directories holding vaults are only accessible by their owner.
+pub(crate) const PRIVATE_DIR_MODE: u32 = 0o700;
+
+/// Vault files are only readable and writable by their owner.
+pub(crate) const PRIVATE_FILE_MODE: u32 = 0o600;
+
+/// Creates `path` and any missing parents with owner-only access. Existing directories are
+/// left as they are; `pw doctor` reports them if they are too open.
+pub(crate) fn create_private_dir(path: &Path) -> io::Result<()> {
+    let mut builder = DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    std::os::unix::fs::DirBuilderExt::mode(&mut builder, PRIVATE_DIR_MODE);
+    builder.create(path)
+}
+
+/// Options that create files with owner-only access. Like `OpenOptions::new()`, nothing is
+/// enabled yet; the mode only applies when a file is created.
+pub(crate) fn private_file_options() -> OpenOptions {
+    #[allow(unused_mut)]
+    let mut options = OpenOptions::new();
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, PRIVATE_FILE_MODE);
+    options
+}
+
+/// Gives an open file owner-only access, for files that may have existed before they were
+/// opened with `private_file_options`.
+pub(crate) fn restrict_to_owner(file: &File) -> io::Result<()> {
+    #[cfg(unix)]
+    file.set_permissions(std::os::unix::fs::PermissionsExt::from_mode(
+        PRIVATE_FILE_MODE,
+    ))?;
+    #[cfg(not(unix))]
+    let _ = file;
+    Ok(())
+}
@@ -0,0 +1,284 @@
+use crate::crypto::kdf::KEY_LEN;
+use crate::crypto::secret::{LockedKey, Secret};
+use crate::crypto::shamir;
+use crate::error::PwError;
+use data_encoding::BASE32_NOPAD;
+use hmac::{Hmac, Mac};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+use std::fmt;
+use zeroize::Zeroizing;
+
+type HmacSha256 = Hmac<Sha256>;
+
+const SHARE_VERSION: u8 = 1;
+
+const VAULT_ID_LABEL: &[u8] = b"pw-vault-id";
+
+const VAULT_ID_LEN: usize = 8;
+const SPLIT_ID_LEN: usize = 4;
+const CHECKSUM_LEN: usize = 4;
+const SHARE_LEN: usize = 1 + VAULT_ID_LEN + SPLIT_ID_LEN + 2 + KEY_LEN + CHECKSUM_LEN;
+
+/// Characters between the dashes of an encoded share.
+const GROUP_LEN: usize = 5;
+
+/// Identifies the vault a data key belongs to, without revealing the key.
+pub fn vault_id(data_key: &[u8; KEY_LEN]) -> [u8; VAULT_ID_LEN] {
+    let mut mac = HmacSha256::new_from_slice(data_key).expect("HMAC accepts any key length");
+    mac.update(VAULT_ID_LABEL);
+    let digest = mac.finalize().into_bytes();
+    digest[..VAULT_ID_LEN]
+        .try_into()
+        .expect("HMAC-SHA256 is longer than a vault id")
+}
+
+pub fn format_id(id: &[u8]) -> String {
+    id.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// One Shamir share of a vault's data key. Shares carry the id of the vault, so shares of
+/// different vaults are not mixed up, and of the split, since shares of two splits of the
+/// same key do not combine. Encoded, they end in a checksum that catches typos.
+#[derive(Clone, PartialEq)]
+pub struct RecoveryShare {
+    pub vault_id: [u8; VAULT_ID_LEN],
+    split_id: [u8; SPLIT_ID_LEN],
+    pub threshold: u8,
+    pub index: u8,
+    value: Zeroizing<[u8; KEY_LEN]>,
+}
+
+/// Leaves out the key share, which is as sensitive as the key once enough are together.
+impl fmt::Debug for RecoveryShare {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RecoveryShare")
+            .field("vault_id", &format_id(&self.vault_id))
+            .field("threshold", &self.threshold)
+            .field("index", &self.index)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Splits `data_key` into `count` shares, any `threshold` of which recover it.
+pub fn split_data_key(
+    data_key: &[u8; KEY_LEN],
+    threshold: u8,
+    count: u8,
+) -> Result<Vec<RecoveryShare>, PwError> {
+    if threshold < 2 || threshold > count {
+        return Err(PwError::Invalid(format!(
+            "The threshold must be at least 2 and at most the number of shares ({}), got {}",
+            count, threshold
+        )));
+    }
+    let vault_id = vault_id(data_key);
+    let mut split_id = [0u8; SPLIT_ID_LEN];
+    OsRng.fill_bytes(&mut split_id);
+    Ok(shamir::split(data_key, threshold, count)
+        .into_iter()
+        .map(|(index, value)| {
+            let value = Zeroizing::new(value);
+            RecoveryShare {
+                vault_id,
+                split_id,
+                threshold,
+                index,
+                value: Zeroizing::new(
+                    value
+                        .as_slice()
+                        .try_into()
+                        .expect("shares are as long as the key"),
+                ),
+            }
+        })
+        .collect())
+}
+
+/// Recovers the data key from at least `threshold` shares of one split.
+pub fn combine_shares(shares: &[RecoveryShare]) -> Result<LockedKey, PwError> {
+    let Some(first) = shares.first() else {
+        return Err(PwError::Invalid(
+            "No recovery shares were given".to_string(),
+        ));
+    };
+    for share in shares {
+        if share.vault_id != first.vault_id {
+            return Err(PwError::Invalid(format!(
+                "Share {} is for vault {}, but share {} is for vault {}",
+                share.index,
+                format_id(&share.vault_id),
+                first.index,
+                format_id(&first.vault_id)
+            )));
+        }
+        if share.split_id != first.split_id || share.threshold != first.threshold {
+            return Err(PwError::Invalid(format!(
+                "Shares {} and {} come from different splits of the vault. Use shares that \
+                 were printed together.",
+                first.index, share.index
+            )));
+        }
+    }
+    let mut indexes: Vec<u8> = shares.iter().map(|share| share.index).collect();
+    indexes.sort_unstable();
+    indexes.dedup();
+    if indexes.len() != shares.len() {
+        return Err(PwError::Invalid(
+            "The same share was given more than once".to_string(),
+        ));
+    }
+    if shares.len() < usize::from(first.threshold) {
+        return Err(PwError::Invalid(format!(
+            "{} shares are needed, but only {} were given",
+            first.threshold,
+            shares.len()
+        )));
+    }
+
+    let points: Vec<(u8, &[u8])> = shares
+        .iter()
+        .map(|share| (share.index, share.value.as_slice()))
+        .collect();
+    let combined = Zeroizing::new(shamir::combine(&points));
+    let data_key = LockedKey::from_slice(&combined).expect("shares are as long as the key");
+    if vault_id(data_key.expose()) != first.vault_id {
+        return Err(PwError::Corrupt(
+            "The shares do not give back the vault's key; one of them is damaged".to_string(),
+        ));
+    }
+    Ok(data_key)
+}
+
+impl RecoveryShare {
+    /// Base32 in dash-separated groups, e.g. `AEBAG-BAFAY-...`.
+    pub fn encode(&self) -> Secret {
+        let mut bytes = Zeroizing::new(Vec::with_capacity(SHARE_LEN));
+        bytes.push(SHARE_VERSION);
+        bytes.extend_from_slice(&self.vault_id);
+        bytes.extend_from_slice(&self.split_id);
+        bytes.push(self.threshold);
+        bytes.push(self.index);
+        bytes.extend_from_slice(self.value.as_slice());
+        let checksum = Sha256::digest(bytes.as_slice());
+        bytes.extend_from_slice(&checksum[..CHECKSUM_LEN]);
+
+        let text = Zeroizing::new(BASE32_NOPAD.encode(&bytes));
+        // Sized up front, so the buffer is never reallocated and leaves no copy behind.
+        let mut encoded = String::with_capacity(text.len() + text.len() / GROUP_LEN);
+        for (i, group) in text.chars().enumerate() {
+            if i > 0 && i % GROUP_LEN == 0 {
+                encoded.push('-');
+            }
+            encoded.push(group);
+        }
+        encoded.into()
+    }
+
+    /// Reads a share written by `encode`. Case, spaces and dashes do not matter.
+    pub fn decode(text: &str) -> Result<Self, PwError> {
+        let text: Zeroizing<String> = Zeroizing::new(
+            text.chars()
+                .filter(|c| !c.is_whitespace() && *c != '-')
+                .map(|c| c.to_ascii_uppercase())
+                .collect(),
+        );
+        let bytes = BASE32_NOPAD
+            .decode(text.as_bytes())
+            .map(Zeroizing::new)
+            .map_err(|_| PwError::Invalid("This is not a recovery share".to_string()))?;
+        if bytes.len() != SHARE_LEN {
+            return Err(PwError::Invalid(
+                "This recovery share is incomplete or too long".to_string(),
+            ));
+        }
+        let (body, checksum) = bytes.split_at(SHARE_LEN - CHECKSUM_LEN);
+        if Sha256::digest(body)[..CHECKSUM_LEN] != *checksum {
+            return Err(PwError::Invalid(
+                "This recovery share has a typo: its checksum does not match".to_string(),
+            ));
+        }
+        if body[0] != SHARE_VERSION {
+            return Err(PwError::Invalid(format!(
+                "Recovery share version {} is not supported",
+                body[0]
+            )));
+        }
+        let (vault_id, rest) = body[1..].split_at(VAULT_ID_LEN);
+        let (split_id, rest) = rest.split_at(SPLIT_ID_LEN);
+        let share = RecoveryShare {
+            vault_id: vault_id.try_into().expect("split at the id length"),
+            split_id: split_id.try_into().expect("split at the id length"),
+            threshold: rest[0],
+            index: rest[1],
+            value: Zeroizing::new(rest[2..].try_into().expect("the rest is the key share")),
+        };
+        if share.index == 0 || share.threshold == 0 {
+            return Err(PwError::Invalid(
+                "This recovery share is not valid".to_string(),
+            ));
+        }
+        Ok(share)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: [u8; KEY_LEN] = [7; KEY_LEN];
+
+    #[test]
+    fn shares_round_trip_through_text() -> Result<(), PwError> {
+        let shares = split_data_key(&KEY, 3, 5)?;
+        let decoded: Vec<RecoveryShare> = shares
+            .iter()
+            .map(|share| RecoveryShare::decode(&share.encode().expose().to_lowercase()))
+            .collect::<Result<_, _>>()?;
+        assert_eq!(decoded, shares);
+        assert_eq!(combine_shares(&decoded[2..])?.expose(), &KEY);
+        assert_eq!(
+            combine_shares(&[decoded[4].clone(), decoded[0].clone(), decoded[2].clone()])?.expose(),
+            &KEY
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn debug_output_leaves_out_the_key_share() -> Result<(), PwError> {
+        let share = split_data_key(&KEY, 2, 2)?.remove(0);
+        let debug = format!("{:?}", share);
+        assert!(debug.contains("index: 1"), "{}", debug);
+        assert!(!debug.contains("value"), "{}", debug);
+        Ok(())
+    }
+
+    #[test]
+    fn typos_are_caught_by_the_checksum() -> Result<(), PwError> {
+        let text = split_data_key(&KEY, 2, 2)?[0].encode().expose().to_string();
+        let replacement = if text.starts_with('A') { "B" } else { "A" };
+        let typo = format!("{}{}", replacement, &text[1..]);
+        let error = RecoveryShare::decode(&typo).unwrap_err().to_string();
+        assert!(error.contains("typo"), "{}", error);
+        assert!(RecoveryShare::decode(&text[..text.len() - 6]).is_err());
+        assert!(RecoveryShare::decode("not a share!").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn mixed_up_shares_are_refused() -> Result<(), PwError> {
+        let shares = split_data_key(&KEY, 2, 3)?;
+        let other_split = split_data_key(&KEY, 2, 3)?;
+        let other_vault = split_data_key(&[9; KEY_LEN], 2, 3)?;
+
+        let message = |shares: &[RecoveryShare]| combine_shares(shares).unwrap_err().to_string();
+        assert!(message(&[shares[0].clone(), other_vault[1].clone()]).contains("for vault"));
+        assert!(message(&[shares[0].clone(), other_split[1].clone()]).contains("different splits"));
+        assert!(message(&[shares[0].clone(), shares[0].clone()]).contains("more than once"));
+        assert!(message(&shares[..1]).contains("2 shares are needed"));
+        assert!(split_data_key(&KEY, 1, 3).is_err());
+        assert!(split_data_key(&KEY, 4, 3).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,979 @@
+use super::entry::{now, Entry, HistoryItem};
+use super::keyfile::{composite_secret, keyfile_check, verify_keyfile, Keyfile};
+use super::private_files::private_file_options;
+use super::storage_trait::{MasterKey, Storage};
+use crate::crypto::envelope::{open_blob, seal_blob};
+use crate::crypto::kdf::{derive_key, KdfParams, KEY_LEN, SALT_LEN};
+use crate::crypto::secret::{LockedKey, Secret};
+use crate::error::PwError;
+use rand::rngs::OsRng;
+use rand::RngCore;
+use rusqlite::{params, Connection, OpenFlags, OptionalExtension, TransactionBehavior};
+use std::cell::RefCell;
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+
+/// Schema 1 derived the database key straight from the master password. Schema 2 stores a
+/// random data key wrapped under it instead, and schema 3 adds the `generator` column. Older
+/// databases are upgraded when opened.
+const SCHEMA_VERSION: i32 = 3;
+const WRAPPED_KEY_SCHEMA: i32 = 2;
+
+/// Secret columns (`value`, `notes`, `otp` and history values) hold a nonce followed by the
+/// XChaCha20-Poly1305 ciphertext. Everything else is plaintext so it can be indexed and
+/// queried.
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS meta (
+    name TEXT PRIMARY KEY,
+    value BLOB NOT NULL
+);
+CREATE TABLE IF NOT EXISTS entries (
+    id INTEGER PRIMARY KEY,
+    key TEXT NOT NULL UNIQUE,
+    value BLOB NOT NULL,
+    username TEXT,
+    url TEXT,
+    notes BLOB,
+    profile TEXT,
+    generator TEXT,
+    otp BLOB,
+    created_at INTEGER NOT NULL,
+    updated_at INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS entries_username ON entries(username);
+CREATE INDEX IF NOT EXISTS entries_url ON entries(url);
+CREATE TABLE IF NOT EXISTS history (
+    id INTEGER PRIMARY KEY,
+    entry_id INTEGER NOT NULL REFERENCES entries(id) ON DELETE CASCADE,
+    value BLOB NOT NULL,
+    replaced_at INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS history_entry ON history(entry_id);
+";
+
+/// Encrypted under the derived key of schema 1 databases, to tell a wrong master password
+/// apart from a damaged row.
+const KEY_CHECK: &[u8] = b"pw-sqlite-key-check";
+
+/// Associated data of the wrapped data key in `meta`.
+const DATA_KEY_AAD: &str = "meta:data-key";
+
+/// Stores entries in an SQLite database. Every operation is a single indexed query or
+/// transaction, instead of rewriting the whole vault as `FileStorage` does.
+///
+/// Secrets are encrypted with a random data key, which is wrapped under a key derived from
+/// the master password with Argon2id. Each ciphertext is bound to its column and key, so
+/// values cannot be swapped between rows without detection.
+pub struct SqliteStorage {
+    connection: RefCell<Connection>,
+    key: LockedKey,
+    keyfile: Option<Keyfile>,
+}
+
+fn sql_error(e: rusqlite::Error) -> io::Error {
+    io::Error::other(e)
+}
+
+fn connect(path: &Path) -> io::Result<Connection> {
+    let connection = Connection::open(path).map_err(sql_error)?;
+    connection
+        .busy_timeout(Duration::from_secs(5))
+        .map_err(sql_error)?;
+    connection
+        .pragma_update(None, "foreign_keys", true)
+        .map_err(sql_error)?;
+    Ok(connection)
+}
+
+fn schema_version(connection: &Connection) -> io::Result<i32> {
+    connection
+        .pragma_query_value(None, "user_version", |row| row.get(0))
+        .map_err(sql_error)
+}
+
+/// Creates the tables, or brings those of an older database up to date.
+fn migrate(connection: &Connection) -> io::Result<()> {
+    connection.execute_batch(SCHEMA).map_err(sql_error)?;
+    let has_generator: bool = connection
+        .query_row(
+            "SELECT COUNT(*) > 0 FROM pragma_table_info('entries') WHERE name = 'generator'",
+            [],
+            |row| row.get(0),
+        )
+        .map_err(sql_error)?;
+    if !has_generator {
+        connection
+            .execute_batch("ALTER TABLE entries ADD COLUMN generator TEXT")
+            .map_err(sql_error)?;
+    }
+    connection
+        .pragma_update(None, "user_version", SCHEMA_VERSION)
+        .map_err(sql_error)
+}
+
+impl SqliteStorage {
+    /// Opens the database at `path`, creating it if needed. `new_kdf` and `keyfile` only
+    /// apply to a new database; an existing one keeps the parameters it was created with,
+    /// and needs the keyfile it was created with, if any.
+    pub fn open(
+        path: &Path,
+        password: &str,
+        keyfile: Option<&Keyfile>,
+        new_kdf: KdfParams,
+    ) -> io::Result<Self> {
+        // SQLite gives its journal files the database's permissions, so creating the file
+        // ourselves keeps all of them private.
+        match private_file_options()
+            .write(true)
+            .create_new(true)
+            .open(path)
+        {
+            Ok(_) => {}
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+            Err(e) => return Err(e),
+        }
+        let mut connection = connect(path)?;
+        let version = schema_version(&connection)?;
+        if version > SCHEMA_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "{} was created by a newer version of pw (schema {})",
+                    path.display(),
+                    version
+                ),
+            ));
+        }
+
+        let transaction = connection
+            .transaction_with_behavior(TransactionBehavior::Immediate)
+            .map_err(sql_error)?;
+        migrate(&transaction)?;
+        let key = match read_key_params(&transaction)? {
+            Some(KeyParams::Wrapped {
+                kdf,
+                salt,
+                wrapped_key,
+                keyfile_check,
+            }) => {
+                verify_keyfile(path, keyfile_check.as_deref(), &salt, keyfile)?;
+                let secret = composite_secret(password, keyfile);
+                let wrapping_key = derive_key(&secret, &salt, &kdf)?;
+                let key = open_blob(wrapping_key.expose(), &wrapped_key, DATA_KEY_AAD)
+                    .map_err(|_| PwError::Auth("wrong master password".to_string()))?;
+                LockedKey::from_slice(&key).ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::InvalidData, "the data key is damaged")
+                })?
+            }
+            Some(KeyParams::Derived { kdf, salt, check }) => {
+                verify_keyfile(path, None, &salt, keyfile)?;
+                let key = derive_key(password.as_bytes(), &salt, &kdf)?;
+                if open_blob(key.expose(), &check, "meta:check")
+                    .ok()
+                    .as_deref()
+                    .map(Vec::as_slice)
+                    != Some(KEY_CHECK)
+                {
+                    return Err(PwError::Auth("wrong master password".to_string()).into());
+                }
+                // The derived key becomes the data key, so no row has to be re-encrypted.
+                write_wrapped_key(&transaction, key.expose(), password, None, kdf)?;
+                key
+            }
+            None => {
+                let key = LockedKey::random();
+                write_wrapped_key(&transaction, key.expose(), password, keyfile, new_kdf)?;
+                key
+            }
+        };
+        transaction.commit().map_err(sql_error)?;
+
+        Ok(Self {
+            connection: RefCell::new(connection),
+            key,
+            keyfile: keyfile.cloned(),
+        })
+    }
+
+    /// Opens an existing database with its data key, recovered from recovery shares instead
+    /// of unwrapped with the master password. The key is checked against an entry, if there
+    /// is one.
+    pub fn open_with_data_key(
+        path: &Path,
+        data_key: LockedKey,
+        keyfile: Option<&Keyfile>,
+    ) -> io::Result<Self> {
+        if !path.exists() {
+            return Err(PwError::NotFound(format!("{} does not exist", path.display())).into());
+        }
+        let mut connection = connect(path)?;
+        let version = schema_version(&connection)?;
+        if !(WRAPPED_KEY_SCHEMA..=SCHEMA_VERSION).contains(&version) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "{} was not split into recovery shares by this version of pw",
+                    path.display()
+                ),
+            ));
+        }
+        let transaction = connection
+            .transaction_with_behavior(TransactionBehavior::Immediate)
+            .map_err(sql_error)?;
+        migrate(&transaction)?;
+        transaction.commit().map_err(sql_error)?;
+        let sample: Option<(String, Vec<u8>)> = connection
+            .query_row("SELECT key, value FROM entries LIMIT 1", [], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })
+            .optional()
+            .map_err(sql_error)?;
+        let storage = Self {
+            connection: RefCell::new(connection),
+            key: data_key,
+            keyfile: keyfile.cloned(),
+        };
+        if let Some((key, value)) = sample {
+            storage.open_column(&value, "value", &key).map_err(|_| {
+                PwError::Auth(format!(
+                    "The recovery shares do not open {}",
+                    path.display()
+                ))
+            })?;
+        }
+        Ok(storage)
+    }
+
+    /// The Argon2id parameters stored in the database at `path`.
+    pub fn kdf_params(path: &Path) -> io::Result<Option<KdfParams>> {
+        let connection = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+            .map_err(sql_error)?;
+        Ok(read_key_params(&connection)?.map(|params| match params {
+            KeyParams::Derived { kdf, .. } | KeyParams::Wrapped { kdf, .. } => kdf,
+        }))
+    }
+
+    /// Checks the database at `path` without the master password: SQLite's own consistency
+    /// check, the schema version and the key parameters. Returns the number of entries.
+    pub fn check(path: &Path) -> io::Result<usize> {
+        let connection = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+            .map_err(sql_error)?;
+        let result: String = connection
+            .pragma_query_value(None, "quick_check", |row| row.get(0))
+            .map_err(sql_error)?;
+        if result != "ok" {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, result));
+        }
+        let version = schema_version(&connection)?;
+        if !(1..=SCHEMA_VERSION).contains(&version) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unexpected schema version {}", version),
+            ));
+        }
+        if read_key_params(&connection)?.is_none() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "the database key parameters are missing",
+            ));
+        }
+        connection
+            .query_row("SELECT COUNT(*) FROM entries", [], |row| row.get(0))
+            .map_err(sql_error)
+    }
+
+    fn seal_column(&self, plaintext: &str, column: &str, key: &str) -> io::Result<Vec<u8>> {
+        let aad = format!("{}:{}", column, key);
+        Ok(seal_blob(self.key.expose(), plaintext.as_bytes(), &aad)?)
+    }
+
+    fn open_column(&self, blob: &[u8], column: &str, key: &str) -> io::Result<String> {
+        let plaintext = open_blob(self.key.expose(), blob, &format!("{}:{}", column, key))?;
+        std::str::from_utf8(&plaintext)
+            .map(str::to_string)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    fn read_entry(&self, connection: &Connection, key: &str) -> io::Result<Option<Entry>> {
+        type Row = (
+            i64,
+            Vec<u8>,
+            Option<String>,
+            Option<String>,
+            Option<Vec<u8>>,
+            Option<String>,
+            Option<String>,
+            Option<Vec<u8>>,
+        );
+        let row: Option<Row> = connection
+            .query_row(
+                "SELECT id, value, username, url, notes, profile, generator, otp
+                 FROM entries WHERE key = ?1",
+                [key],
+                |row| {
+                    Ok((
+                        row.get(0)?,
+                        row.get(1)?,
+                        row.get(2)?,
+                        row.get(3)?,
+                        row.get(4)?,
+                        row.get(5)?,
+                        row.get(6)?,
+                        row.get(7)?,
+                    ))
+                },
+            )
+            .optional()
+            .map_err(sql_error)?;
+        let Some((id, value, username, url, notes, profile, generator, otp)) = row else {
+            return Ok(None);
+        };
+
+        let mut statement = connection
+            .prepare_cached(
+                "SELECT value, replaced_at FROM history WHERE entry_id = ?1 ORDER BY id",
+            )
+            .map_err(sql_error)?;
+        let rows = statement
+            .query_map([id], |row| {
+                Ok((row.get::<_, Vec<u8>>(0)?, row.get::<_, i64>(1)?))
+            })
+            .map_err(sql_error)?;
+        let mut history = Vec::new();
+        for row in rows {
+            let (value, replaced_at) = row.map_err(sql_error)?;
+            history.push(HistoryItem {
+                value: self.open_column(&value, "history", key)?.into(),
+                replaced_at: replaced_at as u64,
+            });
+        }
+
+        let otp = match otp {
+            Some(blob) => Some(serde_json::from_str(&self.open_column(&blob, "otp", key)?)?),
+            None => None,
+        };
+        Ok(Some(Entry {
+            value: self.open_column(&value, "value", key)?.into(),
+            username,
+            url,
+            notes: notes
+                .map(|blob| self.open_column(&blob, "notes", key))
+                .transpose()?,
+            profile,
+            generator: generator
+                .map(|generator| serde_json::from_str(&generator))
+                .transpose()?,
+            history,
+            otp,
+        }))
+    }
+
+    fn write_entry(&self, connection: &Connection, key: &str, entry: &Entry) -> io::Result<()> {
+        let value = self.seal_column(entry.value.expose(), "value", key)?;
+        let notes = entry
+            .notes
+            .as_deref()
+            .map(|notes| self.seal_column(notes, "notes", key))
+            .transpose()?;
+        let otp = match &entry.otp {
+            Some(otp) => Some(self.seal_column(&serde_json::to_string(otp)?, "otp", key)?),
+            None => None,
+        };
+        let generator = entry
+            .generator
+            .as_ref()
+            .map(serde_json::to_string)
+            .transpose()?;
+        let now = now() as i64;
+        let id: i64 = connection
+            .query_row(
+                "INSERT INTO entries
+                     (key, value, username, url, notes, profile, generator, otp, created_at, updated_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?9)
+                 ON CONFLICT(key) DO UPDATE SET
+                     value = excluded.value,
+                     username = excluded.username,
+                     url = excluded.url,
+                     notes = excluded.notes,
+                     profile = excluded.profile,
+                     generator = excluded.generator,
+                     otp = excluded.otp,
+                     updated_at = excluded.updated_at
+                 RETURNING id",
+                params![
+                    key,
+                    value,
+                    entry.username,
+                    entry.url,
+                    notes,
+                    entry.profile,
+                    generator,
+                    otp,
+                    now
+                ],
+                |row| row.get(0),
+            )
+            .map_err(sql_error)?;
+
+        connection
+            .execute("DELETE FROM history WHERE entry_id = ?1", [id])
+            .map_err(sql_error)?;
+        let mut insert = connection
+            .prepare_cached(
+                "INSERT INTO history (entry_id, value, replaced_at) VALUES (?1, ?2, ?3)",
+            )
+            .map_err(sql_error)?;
+        for item in &entry.history {
+            let value = self.seal_column(item.value.expose(), "history", key)?;
+            insert
+                .execute(params![id, value, item.replaced_at as i64])
+                .map_err(sql_error)?;
+        }
+        Ok(())
+    }
+}
+
+/// How the database key is kept in `meta`.
+enum KeyParams {
+    /// Schema 1: the key is derived from the master password, and `check` is encrypted
+    /// under it.
+    Derived {
+        kdf: KdfParams,
+        salt: Vec<u8>,
+        check: Vec<u8>,
+    },
+    /// A random data key, wrapped under a key derived from the master password and, if
+    /// the database has one, its keyfile.
+    Wrapped {
+        kdf: KdfParams,
+        salt: Vec<u8>,
+        wrapped_key: Vec<u8>,
+        keyfile_check: Option<Vec<u8>>,
+    },
+}
+
+fn read_key_params(connection: &Connection) -> io::Result<Option<KeyParams>> {
+    let meta = |name: &str| -> io::Result<Option<Vec<u8>>> {
+        connection
+            .query_row("SELECT value FROM meta WHERE name = ?1", [name], |row| {
+                row.get(0)
+            })
+            .optional()
+            .map_err(sql_error)
+    };
+    match (
+        meta("kdf")?,
+        meta("salt")?,
+        meta("check")?,
+        meta("wrapped_key")?,
+    ) {
+        (Some(kdf), Some(salt), None, Some(wrapped_key)) => Ok(Some(KeyParams::Wrapped {
+            kdf: serde_json::from_slice(&kdf)?,
+            salt,
+            wrapped_key,
+            keyfile_check: meta("keyfile_check")?,
+        })),
+        (Some(kdf), Some(salt), Some(check), None) => Ok(Some(KeyParams::Derived {
+            kdf: serde_json::from_slice(&kdf)?,
+            salt,
+            check,
+        })),
+        (None, None, None, None) => Ok(None),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "the database key parameters are incomplete",
+        )),
+    }
+}
+
+/// Wraps `data_key` under `password` and `keyfile` with a fresh salt, replacing the key
+/// parameters.
+fn write_wrapped_key(
+    connection: &Connection,
+    data_key: &[u8; KEY_LEN],
+    password: &str,
+    keyfile: Option<&Keyfile>,
+    kdf: KdfParams,
+) -> io::Result<()> {
+    let mut salt = vec![0u8; SALT_LEN];
+    OsRng.fill_bytes(&mut salt);
+    let wrapping_key = derive_key(&composite_secret(password, keyfile), &salt, &kdf)?;
+    let wrapped_key = seal_blob(wrapping_key.expose(), data_key, DATA_KEY_AAD)?;
+    connection
+        .execute(
+            "DELETE FROM meta WHERE name IN ('check', 'keyfile_check')",
+            [],
+        )
+        .map_err(sql_error)?;
+    let mut insert = connection
+        .prepare("INSERT OR REPLACE INTO meta (name, value) VALUES (?1, ?2)")
+        .map_err(sql_error)?;
+    let kdf = serde_json::to_vec(&kdf)?;
+    if let Some(check) = keyfile_check(keyfile, &salt) {
+        insert
+            .execute(params!["keyfile_check", check])
+            .map_err(sql_error)?;
+    }
+    for (name, value) in [("kdf", kdf), ("salt", salt), ("wrapped_key", wrapped_key)] {
+        insert.execute(params![name, value]).map_err(sql_error)?;
+    }
+    Ok(())
+}
+
+fn reseal(
+    old_key: &[u8; KEY_LEN],
+    new_key: &[u8; KEY_LEN],
+    blob: &[u8],
+    aad: &str,
+) -> io::Result<Vec<u8>> {
+    let plaintext = open_blob(old_key, blob, aad)?;
+    Ok(seal_blob(new_key, &plaintext, aad)?)
+}
+
+/// The id, key and encrypted columns of an entry row.
+type SecretColumns = (i64, String, Vec<u8>, Option<Vec<u8>>, Option<Vec<u8>>);
+
+impl MasterKey for SqliteStorage {
+    fn change_password(&mut self, new_password: &str) -> io::Result<()> {
+        let mut connection = self.connection.borrow_mut();
+        let transaction = connection
+            .transaction_with_behavior(TransactionBehavior::Immediate)
+            .map_err(sql_error)?;
+        let Some(KeyParams::Wrapped { kdf, .. }) = read_key_params(&transaction)? else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "the database key parameters are missing",
+            ));
+        };
+        write_wrapped_key(
+            &transaction,
+            self.key.expose(),
+            new_password,
+            self.keyfile.as_ref(),
+            kdf,
+        )?;
+        transaction.commit().map_err(sql_error)
+    }
+
+    fn rekey(&mut self, password: &str, kdf: KdfParams) -> io::Result<usize> {
+        let new_key = LockedKey::random();
+        let mut connection = self.connection.borrow_mut();
+        let transaction = connection
+            .transaction_with_behavior(TransactionBehavior::Immediate)
+            .map_err(sql_error)?;
+
+        let entries: Vec<SecretColumns> = transaction
+            .prepare("SELECT id, key, value, notes, otp FROM entries")
+            .and_then(|mut select| {
+                select
+                    .query_map([], |row| {
+                        Ok((
+                            row.get(0)?,
+                            row.get(1)?,
+                            row.get(2)?,
+                            row.get(3)?,
+                            row.get(4)?,
+                        ))
+                    })?
+                    .collect()
+            })
+            .map_err(sql_error)?;
+        for (id, key, value, notes, otp) in &entries {
+            let reseal_column = |blob: &[u8], column: &str| {
+                reseal(
+                    self.key.expose(),
+                    new_key.expose(),
+                    blob,
+                    &format!("{}:{}", column, key),
+                )
+            };
+            let value = reseal_column(value, "value")?;
+            let notes = notes
+                .as_deref()
+                .map(|notes| reseal_column(notes, "notes"))
+                .transpose()?;
+            let otp = otp
+                .as_deref()
+                .map(|otp| reseal_column(otp, "otp"))
+                .transpose()?;
+            transaction
+                .execute(
+                    "UPDATE entries SET value = ?1, notes = ?2, otp = ?3 WHERE id = ?4",
+                    params![value, notes, otp, id],
+                )
+                .map_err(sql_error)?;
+        }
+
+        let history: Vec<(i64, String, Vec<u8>)> = transaction
+            .prepare(
+                "SELECT history.id, entries.key, history.value
+                 FROM history JOIN entries ON entries.id = history.entry_id",
+            )
+            .and_then(|mut select| {
+                select
+                    .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+                    .collect()
+            })
+            .map_err(sql_error)?;
+        for (id, key, value) in &history {
+            let value = reseal(
+                self.key.expose(),
+                new_key.expose(),
+                value,
+                &format!("history:{}", key),
+            )?;
+            transaction
+                .execute(
+                    "UPDATE history SET value = ?1 WHERE id = ?2",
+                    params![value, id],
+                )
+                .map_err(sql_error)?;
+        }
+
+        write_wrapped_key(
+            &transaction,
+            new_key.expose(),
+            password,
+            self.keyfile.as_ref(),
+            kdf,
+        )?;
+        transaction.commit().map_err(sql_error)?;
+        drop(connection);
+        self.key = new_key;
+        Ok(entries.len())
+    }
+
+    fn kdf_params(&self) -> io::Result<KdfParams> {
+        match read_key_params(&self.connection.borrow())? {
+            Some(KeyParams::Wrapped { kdf, .. }) => Ok(kdf),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "the database key parameters are missing",
+            )),
+        }
+    }
+
+    fn data_key(&self) -> &LockedKey {
+        &self.key
+    }
+}
+
+impl Storage for SqliteStorage {
+    fn set(&self, key: String, value: Secret) -> io::Result<()> {
+        let mut connection = self.connection.borrow_mut();
+        let transaction = connection
+            .transaction_with_behavior(TransactionBehavior::Immediate)
+            .map_err(sql_error)?;
+        let mut entry = self.read_entry(&transaction, &key)?.unwrap_or_default();
+        entry.replace_value(value);
+        self.write_entry(&transaction, &key, &entry)?;
+        transaction.commit().map_err(sql_error)
+    }
+
+    fn get(&self, key: String) -> io::Result<Option<Secret>> {
+        let connection = self.connection.borrow();
+        let value: Option<Vec<u8>> = connection
+            .query_row("SELECT value FROM entries WHERE key = ?1", [&key], |row| {
+                row.get(0)
+            })
+            .optional()
+            .map_err(sql_error)?;
+        value
+            .map(|blob| Ok(Secret::from(self.open_column(&blob, "value", &key)?)))
+            .transpose()
+    }
+
+    fn get_all(&self) -> io::Result<Option<Vec<(String, Secret)>>> {
+        let connection = self.connection.borrow();
+        let mut statement = connection
+            .prepare("SELECT key, value FROM entries ORDER BY key")
+            .map_err(sql_error)?;
+        let rows = statement
+            .query_map([], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, Vec<u8>>(1)?))
+            })
+            .map_err(sql_error)?;
+        let mut any = false;
+        let mut values = Vec::new();
+        for row in rows {
+            let (key, blob) = row.map_err(sql_error)?;
+            any = true;
+            let value = self.open_column(&blob, "value", &key)?;
+            if !value.is_empty() {
+                values.push((key, Secret::from(value)));
+            }
+        }
+        Ok(any.then_some(values))
+    }
+
+    fn get_entry(&self, key: String) -> io::Result<Option<Entry>> {
+        let connection = self.connection.borrow();
+        self.read_entry(&connection, &key)
+    }
+
+    fn set_entry(&self, key: String, entry: Entry) -> io::Result<()> {
+        self.set_entries(vec![(key, entry)])
+    }
+
+    fn set_entries(&self, entries: Vec<(String, Entry)>) -> io::Result<()> {
+        let mut connection = self.connection.borrow_mut();
+        let transaction = connection
+            .transaction_with_behavior(TransactionBehavior::Immediate)
+            .map_err(sql_error)?;
+        for (key, entry) in &entries {
+            self.write_entry(&transaction, key, entry)?;
+        }
+        transaction.commit().map_err(sql_error)
+    }
+
+    fn list_keys(&self) -> io::Result<Vec<String>> {
+        let connection = self.connection.borrow();
+        let mut statement = connection
+            .prepare("SELECT key FROM entries ORDER BY key")
+            .map_err(sql_error)?;
+        let keys = statement
+            .query_map([], |row| row.get(0))
+            .map_err(sql_error)?
+            .collect::<Result<Vec<String>, _>>()
+            .map_err(sql_error)?;
+        Ok(keys)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::kdf::TEST_KDF_PARAMS;
+    use crate::otp::otpauth::OtpParams;
+    use crate::password_generator::mode::GenerateMode;
+    use tempfile::tempdir;
+
+    #[test]
+    fn entry_round_trip() -> io::Result<()> {
+        let temp_dir = tempdir()?;
+        let path = temp_dir.path().join("store.db");
+        let storage = SqliteStorage::open(&path, "master", None, TEST_KDF_PARAMS)?;
+
+        let entry = Entry {
+            value: "hunter2".into(),
+            username: Some("alice".to_string()),
+            url: Some("https://example.com".to_string()),
+            notes: Some("recovery codes".to_string()),
+            profile: Some("bank".to_string()),
+            generator: Some(GenerateMode::Profile {
+                name: "bank".to_string(),
+                length: Some(20),
+            }),
+            history: vec![HistoryItem {
+                value: "old".into(),
+                replaced_at: 1_700_000_000,
+            }],
+            otp: Some(OtpParams::parse("JBSWY3DPEHPK3PXP").unwrap()),
+        };
+        storage.set_entry("work/example".to_string(), entry.clone())?;
+        drop(storage);
+
+        let storage = SqliteStorage::open(&path, "master", None, TEST_KDF_PARAMS)?;
+        assert_eq!(storage.get_entry("work/example".to_string())?, Some(entry));
+        assert_eq!(storage.get_entry("missing".to_string())?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn set_keeps_history_rows() -> io::Result<()> {
+        let temp_dir = tempdir()?;
+        let storage = SqliteStorage::open(
+            &temp_dir.path().join("store.db"),
+            "master",
+            None,
+            TEST_KDF_PARAMS,
+        )?;
+
+        storage.set("key".to_string(), "first".into())?;
+        storage.set("key".to_string(), "second".into())?;
+        storage.set("other".to_string(), "value".into())?;
+
+        assert_eq!(
+            storage.get("key".to_string())?,
+            Some(Secret::from("second"))
+        );
+        let entry = storage.get_entry("key".to_string())?.unwrap();
+        let history: Vec<&str> = entry.history.iter().map(|h| h.value.expose()).collect();
+        assert_eq!(history, vec!["first"]);
+        assert_eq!(storage.list_keys()?, vec!["key", "other"]);
+
+        let values = storage.get_all()?.unwrap();
+        assert_eq!(
+            values,
+            vec![
+                ("key".to_string(), Secret::from("second")),
+                ("other".to_string(), Secret::from("value"))
+            ]
+        );
+
+        let rows: i64 = storage
+            .connection
+            .borrow()
+            .query_row("SELECT COUNT(*) FROM history", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(rows, 1);
+        Ok(())
+    }
+
+    #[test]
+    fn empty_database_has_no_values() -> io::Result<()> {
+        let temp_dir = tempdir()?;
+        let storage = SqliteStorage::open(
+            &temp_dir.path().join("store.db"),
+            "master",
+            None,
+            TEST_KDF_PARAMS,
+        )?;
+        assert_eq!(storage.get_all()?, None);
+        assert!(storage.list_keys()?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn wrong_password_is_rejected() -> io::Result<()> {
+        let temp_dir = tempdir()?;
+        let path = temp_dir.path().join("store.db");
+        SqliteStorage::open(&path, "master", None, TEST_KDF_PARAMS)?;
+
+        let error = SqliteStorage::open(&path, "not master", None, TEST_KDF_PARAMS)
+            .err()
+            .unwrap();
+        assert!(matches!(PwError::from(error), PwError::Auth(_)));
+        Ok(())
+    }
+
+    #[test]
+    fn secrets_are_not_stored_in_plaintext() -> io::Result<()> {
+        let temp_dir = tempdir()?;
+        let path = temp_dir.path().join("store.db");
+        let storage = SqliteStorage::open(&path, "master", None, TEST_KDF_PARAMS)?;
+        let mut entry = Entry::new("very-secret-value".to_string());
+        entry.username = Some("queryable-user".to_string());
+        storage.set_entry("key".to_string(), entry)?;
+        drop(storage);
+
+        let contents = std::fs::read(&path)?;
+        let contains = |needle: &[u8]| contents.windows(needle.len()).any(|w| w == needle);
+        assert!(!contains(b"very-secret-value"));
+        assert!(contains(b"queryable-user"));
+        Ok(())
+    }
+
+    #[test]
+    fn swapped_ciphertexts_are_detected() -> io::Result<()> {
+        let temp_dir = tempdir()?;
+        let storage = SqliteStorage::open(
+            &temp_dir.path().join("store.db"),
+            "master",
+            None,
+            TEST_KDF_PARAMS,
+        )?;
+        storage.set("a".to_string(), "value a".into())?;
+        storage.set("b".to_string(), "value b".into())?;
+
+        storage
+            .connection
+            .borrow()
+            .execute(
+                "UPDATE entries SET value = (SELECT value FROM entries WHERE key = 'b') WHERE key = 'a'",
+                [],
+            )
+            .unwrap();
+        assert!(storage.get("a".to_string()).is_err());
+        assert_eq!(storage.get("b".to_string())?, Some(Secret::from("value b")));
+        Ok(())
+    }
+
+    #[test]
+    fn schema_2_database_gets_the_generator_column() -> io::Result<()> {
+        let temp_dir = tempdir()?;
+        let path = temp_dir.path().join("store.db");
+        let storage = SqliteStorage::open(&path, "master", None, TEST_KDF_PARAMS)?;
+        storage.set("email".to_string(), "hunter2".into())?;
+        storage
+            .connection
+            .borrow()
+            .execute_batch("ALTER TABLE entries DROP COLUMN generator; PRAGMA user_version = 2")
+            .map_err(sql_error)?;
+        drop(storage);
+
+        let storage = SqliteStorage::open(&path, "master", None, TEST_KDF_PARAMS)?;
+        let mut entry = storage.get_entry("email".to_string())?.unwrap();
+        assert_eq!(entry.generator, None);
+        entry.generator = Some(GenerateMode::Strong { length: Some(20) });
+        storage.set_entry("email".to_string(), entry.clone())?;
+        assert_eq!(storage.get_entry("email".to_string())?, Some(entry));
+        Ok(())
+    }
+
+    #[test]
+    fn schema_1_database_is_upgraded_on_open() -> io::Result<()> {
+        let temp_dir = tempdir()?;
+        let path = temp_dir.path().join("store.db");
+        let mut storage = SqliteStorage::open(&path, "master", None, TEST_KDF_PARAMS)?;
+        {
+            // Turn the new database into a schema 1 one, keyed by the derived key.
+            let connection = storage.connection.borrow();
+            let salt: Vec<u8> = connection
+                .query_row("SELECT value FROM meta WHERE name = 'salt'", [], |row| {
+                    row.get(0)
+                })
+                .map_err(sql_error)?;
+            let key = derive_key(b"master", &salt, &TEST_KDF_PARAMS)?;
+            let check = seal_blob(key.expose(), KEY_CHECK, "meta:check")?;
+            connection
+                .execute_batch(
+                    "DELETE FROM meta WHERE name = 'wrapped_key'; PRAGMA user_version = 1",
+                )
+                .map_err(sql_error)?;
+            connection
+                .execute(
+                    "INSERT INTO meta (name, value) VALUES ('check', ?1)",
+                    [check],
+                )
+                .map_err(sql_error)?;
+            storage.key = key;
+        }
+        storage.set("email".to_string(), "hunter2".into())?;
+        drop(storage);
+        assert_eq!(SqliteStorage::check(&path)?, 1);
+
+        let storage = SqliteStorage::open(&path, "master", None, TEST_KDF_PARAMS)?;
+        assert_eq!(
+            storage.get("email".to_string())?,
+            Some(Secret::from("hunter2"))
+        );
+        drop(storage);
+        let connection = Connection::open(&path).map_err(sql_error)?;
+        let version: i32 = connection
+            .query_row("PRAGMA user_version", [], |row| row.get(0))
+            .map_err(sql_error)?;
+        assert_eq!(version, SCHEMA_VERSION);
+        assert!(matches!(
+            read_key_params(&connection)?,
+            Some(KeyParams::Wrapped { .. })
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn check_works_without_the_password() -> io::Result<()> {
+        let temp_dir = tempdir()?;
+        let path = temp_dir.path().join("store.db");
+        let storage = SqliteStorage::open(&path, "master", None, TEST_KDF_PARAMS)?;
+        storage.set("email".to_string(), "hunter2".into())?;
+        drop(storage);
+        assert_eq!(SqliteStorage::check(&path)?, 1);
+
+        let not_a_database = temp_dir.path().join("other.db");
+        std::fs::write(&not_a_database, "not a database, just some text")?;
+        assert!(SqliteStorage::check(&not_a_database).is_err());
+        Ok(())
+    }
+}
@@ -1,5 +1,36 @@
+use super::entry::Entry;
+use crate::crypto::kdf::KdfParams;
+use crate::crypto::secret::{LockedKey, Secret};
+use std::io;
+
 pub trait Storage {
-    fn set(&self, key: String, value: String) -> Result<(), std::io::Error>;
-    fn get(&self, key: String) -> Result<Option<String>, std::io::Error>;
-    fn get_all(&self) -> Result<Option<Vec<String>>, std::io::Error>;
+    fn set(&self, key: String, value: Secret) -> Result<(), std::io::Error>;
+    fn get(&self, key: String) -> Result<Option<Secret>, std::io::Error>;
+    /// Every non-empty password with its key, or `None` if the vault has no entries.
+    fn get_all(&self) -> Result<Option<Vec<(String, Secret)>>, std::io::Error>;
+    fn get_entry(&self, key: String) -> Result<Option<Entry>, std::io::Error>;
+    fn set_entry(&self, key: String, entry: Entry) -> Result<(), std::io::Error>;
+    /// Stores several entries at once. Backends that can write them in one step that either
+    /// completes or leaves the vault as it was do so.
+    fn set_entries(&self, entries: Vec<(String, Entry)>) -> Result<(), std::io::Error> {
+        for (key, entry) in entries {
+            self.set_entry(key, entry)?;
+        }
+        Ok(())
+    }
+    fn list_keys(&self) -> Result<Vec<String>, std::io::Error>;
+}
+
+/// Vaults encrypted under a random data key that is wrapped with the master password.
+pub trait MasterKey {
+    /// Wraps the data key under `new_password`. The entries are not re-encrypted.
+    fn change_password(&mut self, new_password: &str) -> io::Result<()>;
+    /// Re-encrypts every entry under a new data key, wrapped under `password` with `kdf`,
+    /// in one step that either completes or leaves the vault as it was. Returns the number
+    /// of entries.
+    fn rekey(&mut self, password: &str, kdf: KdfParams) -> io::Result<usize>;
+    /// The Argon2id parameters the master password is currently derived with.
+    fn kdf_params(&self) -> io::Result<KdfParams>;
+    /// The data key, to split into recovery shares.
+    fn data_key(&self) -> &LockedKey;
 }
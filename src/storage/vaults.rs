@@ -0,0 +1,214 @@
+use super::private_files::create_private_dir;
+use crate::error::PwError;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The vault used when none is selected. It is set up by the `storage` settings of the
+/// config file, unless a vault of that name is registered.
+pub(crate) const DEFAULT_VAULT: &str = "default";
+
+/// Selects a vault by name when `--vault` is not given.
+pub(crate) const STORE_ENV: &str = "PW_STORE";
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    /// pw's own JSON vault.
+    Json,
+    /// An SQLite database with encrypted secrets.
+    Sqlite,
+    /// A KeePass (KDBX 4) database.
+    Kdbx,
+    /// A pass(1) password store directory.
+    Pass,
+}
+
+impl Backend {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Backend::Json => "json",
+            Backend::Sqlite => "sqlite",
+            Backend::Kdbx => "kdbx",
+            Backend::Pass => "pass",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Vault {
+    pub backend: Backend,
+    pub path: PathBuf,
+}
+
+impl Vault {
+    /// The location of a new vault called `name` when no path is given. pass vaults live
+    /// in their own directory so they do not share `.gpg-id` with the default store.
+    pub fn default_path(pw_dir: &Path, name: &str, backend: Backend) -> PathBuf {
+        let vaults = pw_dir.join("vaults");
+        match backend {
+            Backend::Json => vaults.join(format!("{}.json", name)),
+            Backend::Sqlite => vaults.join(format!("{}.db", name)),
+            Backend::Kdbx => vaults.join(format!("{}.kdbx", name)),
+            Backend::Pass => vaults.join(name),
+        }
+    }
+}
+
+/// The named vaults in `~/.pw/vaults.json`. Each vault has its own backend and master
+/// password; the built-in default vault is always available.
+#[derive(Serialize, Deserialize, Default, Debug, PartialEq)]
+pub struct VaultRegistry {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default: Option<String>,
+    #[serde(default)]
+    pub vaults: BTreeMap<String, Vault>,
+}
+
+impl VaultRegistry {
+    pub fn load(file_path: &Path) -> io::Result<Self> {
+        match fs::read_to_string(file_path) {
+            Ok(contents) => Ok(serde_json::from_str(&contents)?),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn save(&self, file_path: &Path) -> io::Result<()> {
+        if let Some(parent) = file_path.parent() {
+            create_private_dir(parent)?;
+        }
+        fs::write(file_path, serde_json::to_string_pretty(self)? + "\n")
+    }
+
+    /// Looks up a vault by name, falling back to `builtin` for the default vault.
+    pub fn get(&self, name: &str, builtin: &Vault) -> Option<Vault> {
+        match self.vaults.get(name) {
+            Some(vault) => Some(vault.clone()),
+            None if name == DEFAULT_VAULT => Some(builtin.clone()),
+            None => None,
+        }
+    }
+
+    /// The name of the vault to use: the one asked for, otherwise the configured default.
+    pub fn selected<'a>(&'a self, requested: Option<&'a str>) -> &'a str {
+        requested
+            .or(self.default.as_deref())
+            .unwrap_or(DEFAULT_VAULT)
+    }
+
+    pub fn resolve(&self, requested: Option<&str>, builtin: &Vault) -> Result<Vault, PwError> {
+        let name = self.selected(requested);
+        self.get(name, builtin).ok_or_else(|| {
+            PwError::NotFound(format!(
+                "No vault named '{}'. Create it with 'pw vault create {}'.",
+                name, name
+            ))
+        })
+    }
+
+    /// Every vault, including the built-in default vault, sorted by name.
+    pub fn list(&self, builtin: &Vault) -> Vec<(String, Vault)> {
+        let mut vaults: Vec<(String, Vault)> = self
+            .vaults
+            .iter()
+            .map(|(name, vault)| (name.clone(), vault.clone()))
+            .collect();
+        if !self.vaults.contains_key(DEFAULT_VAULT) {
+            vaults.push((DEFAULT_VAULT.to_string(), builtin.clone()));
+            vaults.sort_by(|a, b| a.0.cmp(&b.0));
+        }
+        vaults
+    }
+}
+
+/// Vault names end up in file names, so they are limited to letters, digits, `-` and `_`.
+pub fn validate_name(name: &str) -> Result<(), PwError> {
+    let valid = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if valid {
+        Ok(())
+    } else {
+        Err(PwError::Invalid(format!(
+            "Invalid vault name '{}': use letters, digits, '-' and '_'.",
+            name
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn resolve_prefers_requested_then_default_setting() {
+        let builtin = Vault {
+            backend: Backend::Json,
+            path: PathBuf::from("/home/user/.pw/store.json"),
+        };
+        let mut registry = VaultRegistry::default();
+        let team = Vault {
+            backend: Backend::Sqlite,
+            path: PathBuf::from("/srv/team.db"),
+        };
+        registry.vaults.insert("team".to_string(), team.clone());
+
+        assert_eq!(registry.resolve(None, &builtin).unwrap(), builtin);
+        assert_eq!(registry.resolve(Some("team"), &builtin).unwrap(), team);
+
+        registry.default = Some("team".to_string());
+        assert_eq!(registry.resolve(None, &builtin).unwrap(), team);
+        assert_eq!(
+            registry.resolve(Some("default"), &builtin).unwrap(),
+            builtin
+        );
+        assert!(registry.resolve(Some("missing"), &builtin).is_err());
+    }
+
+    #[test]
+    fn registry_round_trip() -> io::Result<()> {
+        let temp_dir = tempdir()?;
+        let path = temp_dir.path().join("vaults.json");
+        assert_eq!(VaultRegistry::load(&path)?, VaultRegistry::default());
+
+        let mut registry = VaultRegistry {
+            default: Some("team".to_string()),
+            ..Default::default()
+        };
+        registry.vaults.insert(
+            "team".to_string(),
+            Vault {
+                backend: Backend::Kdbx,
+                path: PathBuf::from("/srv/team.kdbx"),
+            },
+        );
+        registry.save(&path)?;
+        assert_eq!(VaultRegistry::load(&path)?, registry);
+
+        let builtin = Vault {
+            backend: Backend::Json,
+            path: temp_dir.path().join("store.json"),
+        };
+        let names: Vec<String> = registry
+            .list(&builtin)
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect();
+        assert_eq!(names, vec!["default", "team"]);
+        Ok(())
+    }
+
+    #[test]
+    fn names_are_validated() {
+        assert!(validate_name("team-2_a").is_ok());
+        for name in ["", "../x", "a/b", ".hidden", "sp ace"] {
+            assert!(validate_name(name).is_err(), "{}", name);
+        }
+    }
+}